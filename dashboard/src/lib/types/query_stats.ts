@@ -0,0 +1,12 @@
+export interface IStatementStats {
+    sql: string,
+    kind: 'read' | 'write',
+    count: number,
+    errors: number,
+    rows: number,
+    total_micros: number,
+    mean_micros: number,
+    p50_micros: number,
+    p99_micros: number,
+    max_micros: number,
+}
@@ -1,7 +1,9 @@
+use crate::network::handshake::accepted_secrets;
 use crate::NodeId;
 use serde::Deserialize;
 use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::time::Duration;
 use tokio::sync::{Mutex, MutexGuard};
 
 #[cfg(feature = "cache")]
@@ -33,7 +35,7 @@ pub enum RaftType {
 }
 
 impl RaftType {
-    pub fn as_str(&self) -> &str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             #[cfg(feature = "sqlite")]
             RaftType::Sqlite => "sqlite",
@@ -55,6 +57,15 @@ pub(crate) struct AppState {
     pub raft_cache: StateRaftCache,
     pub secret_raft: String,
     pub secret_api: String,
+    /// Additional secrets, which are accepted for incoming Raft connections during a rotation
+    pub secret_raft_accepted: Vec<String>,
+    /// Additional secrets, which are accepted for incoming API requests during a rotation
+    pub secret_api_accepted: Vec<String>,
+    /// How long the results of writes with an idempotency key will be remembered
+    pub idempotency_window: Duration,
+    /// Used for direct API requests to other nodes, like a leadership transfer
+    pub api_client: reqwest::Client,
+    pub api_scheme: &'static str,
     #[cfg(feature = "sqlite")]
     pub client_buffers_db: Mutex<HashMap<NodeId, VecDeque<Vec<u8>>>>,
     #[cfg(feature = "cache")]
@@ -68,6 +79,16 @@ pub(crate) struct AppState {
 }
 
 impl AppState {
+    /// All secrets that are valid for incoming Raft connections
+    pub fn secrets_raft(&self) -> Vec<&[u8]> {
+        accepted_secrets(&self.secret_raft, &self.secret_raft_accepted)
+    }
+
+    /// All secrets that are valid for incoming API requests
+    pub fn secrets_api(&self) -> Vec<&[u8]> {
+        accepted_secrets(&self.secret_api, &self.secret_api_accepted)
+    }
+
     pub async fn get_buf_lock(
         &self,
         raft_type: &RaftType,
@@ -98,6 +119,17 @@ pub struct StateRaftDB {
     pub sql_writer: flume::Sender<WriterRequest>,
     pub read_pool: SqlitePool,
     pub log_statements: bool,
+    pub query_stats: std::sync::Arc<crate::query::stats::QueryStats>,
+    pub metrics: std::sync::Arc<crate::store::state_machine::sqlite::metrics::SqliteMetrics>,
+    pub snapshot_metrics: std::sync::Arc<crate::network::snapshot_transfer::SnapshotMetrics>,
+    #[cfg(feature = "backup")]
+    pub log_archive: Option<std::sync::Arc<crate::log_archive::LogArchive>>,
+    #[cfg(feature = "backup")]
+    pub backup_targets: Vec<std::sync::Arc<dyn crate::backup::BackupTarget>>,
+    #[cfg(feature = "backup")]
+    pub path_backups: String,
+    #[cfg(feature = "backup")]
+    pub(crate) online_restore: std::sync::Arc<crate::backup::OnlineRestore>,
 }
 
 #[cfg(feature = "cache")]
@@ -105,6 +137,9 @@ pub struct StateRaftCache {
     pub raft: openraft::Raft<TypeConfigKV>,
     pub lock: tokio::sync::Mutex<()>,
     pub tx_caches: Vec<flume::Sender<CacheRequestHandler>>,
+    /// The `Debug` names of the cache index enum in the same order as `tx_caches`
+    pub cache_names: Vec<String>,
+    pub snapshot_metrics: std::sync::Arc<crate::network::snapshot_transfer::SnapshotMetrics>,
     #[cfg(feature = "listen_notify")]
     pub tx_notify: flume::Sender<NotifyRequest>,
     #[cfg(feature = "listen_notify")]
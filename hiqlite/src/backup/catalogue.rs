@@ -0,0 +1,178 @@
+use crate::backup::BackupTarget;
+use crate::store::state_machine::sqlite::state_machine::BackupMeta;
+use crate::{Error, NodeId};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::fs;
+use tracing::{error, warn};
+
+/// The suffix of the metadata file, which is stored next to each backup.
+pub(crate) const META_SUFFIX: &str = ".meta";
+
+/// The value of `BackupInfo::target` for backups on the local disk of a node
+pub(crate) const BACKUP_TARGET_LOCAL: &str = "local";
+
+/// A single entry of the backup catalogue returned by `Client::list_backups()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// The file or object name, like `backup_node_1_1735689600.sqlite`
+    pub name: String,
+    /// `local` for backups on the disk of the leader, or the name of the `BackupTarget`
+    pub target: String,
+    /// The node that created the backup
+    pub node_id: NodeId,
+    /// Unix timestamp in seconds of the backup creation
+    pub timestamp: i64,
+    /// The size in bytes as it is stored, after encryption for most targets
+    pub size: u64,
+    /// The Raft log index the backup has been created at. `None` for backups without metadata,
+    /// which have been created by older versions.
+    pub log_index: Option<u64>,
+    /// Whether the backup has been verified against the live database at creation time.
+    /// Backups that fail the verification are never stored, so this is only `false` for
+    /// backups without metadata, which have been created by older versions.
+    pub verified: bool,
+}
+
+/// Parses the node id and the creation time from `backup_node_{node_id}_{timestamp}.sqlite`.
+pub(crate) fn parse_backup_name(name: &str) -> Option<(NodeId, DateTime<Utc>)> {
+    let rest = name.strip_prefix("backup_node_")?.strip_suffix(".sqlite")?;
+    let (node_id, ts) = rest.split_once('_')?;
+    let node_id = node_id.parse::<NodeId>().ok()?;
+    let dt = DateTime::from_timestamp(ts.parse::<i64>().ok()?, 0)?;
+    Some((node_id, dt))
+}
+
+#[inline]
+fn info_from(
+    name: String,
+    target: String,
+    size: u64,
+    meta: Option<BackupMeta>,
+) -> Option<BackupInfo> {
+    let (node_id, dt) = parse_backup_name(&name)?;
+    Some(BackupInfo {
+        name,
+        target,
+        node_id,
+        timestamp: dt.timestamp(),
+        size,
+        log_index: meta.as_ref().map(|meta| meta.log_index),
+        verified: meta.is_some(),
+    })
+}
+
+async fn read_meta(path: &str) -> Result<BackupMeta, Error> {
+    let bytes = fs::read(path).await?;
+    serde_json::from_slice(&bytes)
+        .map_err(|err| Error::Backup(format!("Invalid backup metadata: {}", err).into()))
+}
+
+/// Lists all backups inside the local `path_backups` and on all `targets`, newest first.
+///
+/// Targets that cannot be reached will be logged and skipped, so the catalogue is still usable
+/// when a single target is down.
+pub(crate) async fn list_backups(
+    path_backups: &str,
+    targets: &[Arc<dyn BackupTarget>],
+) -> Result<Vec<BackupInfo>, Error> {
+    let mut backups = Vec::new();
+
+    let mut entries = fs::read_dir(path_backups).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if parse_backup_name(&name).is_none() {
+            continue;
+        }
+
+        let size = entry.metadata().await?.len();
+        let path_meta = format!("{}/{}{}", path_backups, name, META_SUFFIX);
+        let meta = read_meta(&path_meta).await.ok();
+        if let Some(info) = info_from(name, BACKUP_TARGET_LOCAL.to_string(), size, meta) {
+            backups.push(info);
+        }
+    }
+
+    for target in targets {
+        match list_target(path_backups, target).await {
+            Ok(mut infos) => backups.append(&mut infos),
+            Err(err) => error!("Cannot list backups on target {}: {}", target.name(), err),
+        }
+    }
+
+    backups.sort_by(|a, b| {
+        b.timestamp
+            .cmp(&a.timestamp)
+            .then_with(|| a.target.cmp(&b.target))
+    });
+    Ok(backups)
+}
+
+async fn list_target(
+    path_backups: &str,
+    target: &Arc<dyn BackupTarget>,
+) -> Result<Vec<BackupInfo>, Error> {
+    let objects = target.list("backup_node_").await?;
+    let metas = objects
+        .iter()
+        .filter_map(|obj| obj.name.strip_suffix(META_SUFFIX))
+        .map(String::from)
+        .collect::<HashSet<_>>();
+
+    let mut infos = Vec::with_capacity(objects.len());
+    for object in objects {
+        if parse_backup_name(&object.name).is_none() {
+            continue;
+        }
+
+        let meta = if metas.contains(&object.name) {
+            // the metadata must be downloaded, because it is encrypted for most targets
+            let name_meta = format!("{}{}", object.name, META_SUFFIX);
+            let path_tmp = format!("{}/catalogue_{}_{}", path_backups, target.name(), name_meta);
+            let res = async {
+                target.download(&name_meta, &path_tmp).await?;
+                read_meta(&path_tmp).await
+            }
+            .await;
+            let _ = fs::remove_file(&path_tmp).await;
+            match res {
+                Ok(meta) => Some(meta),
+                Err(err) => {
+                    warn!(
+                        "Cannot read backup metadata {} from {}: {}",
+                        name_meta,
+                        target.name(),
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(info) = info_from(object.name, target.name().to_string(), object.size, meta) {
+            infos.push(info);
+        }
+    }
+
+    Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backup_name() {
+        let (node_id, dt) = parse_backup_name("backup_node_3_1735689600.sqlite").unwrap();
+        assert_eq!(node_id, 3);
+        assert_eq!(dt.timestamp(), 1735689600);
+
+        assert!(parse_backup_name("backup_node_3_1735689600.sqlite.meta").is_none());
+        assert!(parse_backup_name("backup_node_3.sqlite").is_none());
+        assert!(parse_backup_name("restore.sqlite").is_none());
+    }
+}
@@ -0,0 +1,1014 @@
+use crate::app_state::AppState;
+use crate::helpers::set_path_access;
+use crate::log_archive::{self, LogArchiveConfig, RecoveryTarget};
+use crate::network::HEADER_NAME_SECRET;
+use crate::store::logs;
+#[cfg(feature = "cache")]
+use crate::store::state_machine::memory::state_machine::{CacheRequest, StateMachineMemory};
+use crate::store::state_machine::sqlite::state_machine::{
+    BackupMeta, PathBackups, PathDb, PathLockFile, PathSnapshots, QueryWrite, StateMachineData,
+    StateMachineSqlite,
+};
+use crate::store::state_machine::sqlite::verify::{self, ContentChecksum};
+use crate::{config, trace, Client, Error, Node, NodeConfig, NodeId};
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::ops::Sub;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio::{fs, select, task, time};
+use tracing::{debug, error, info, warn, Instrument};
+
+pub use catalogue::BackupInfo;
+pub use retention::BackupRetention;
+pub use target::{BackupObject, BackupTarget, DirBackupTarget, S3BackupTarget};
+
+pub(crate) mod catalogue;
+mod retention;
+mod target;
+
+pub const BACKUP_DB_NAME: &str = "restore.sqlite";
+/// The local copy of a backup while it is being restored through the Raft
+const BACKUP_DB_NAME_ONLINE: &str = "restore_online.sqlite";
+/// The local copy of a backup while the leader checks it before an online restore
+const BACKUP_DB_NAME_CHECK: &str = "restore_check.sqlite";
+/// The leader's checked copy of the latest online restore, which other nodes fall back to
+const BACKUP_DB_NAME_ONLINE_SRC: &str = "restore_online_src.sqlite";
+/// The local copy of a backup while its cache snapshot is being extracted
+#[cfg(feature = "cache")]
+const BACKUP_DB_NAME_CACHE: &str = "restore_cache.sqlite";
+
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    cron_schedule: cron::Schedule,
+    pub(crate) keep_days: u16,
+    pub(crate) retention: Option<BackupRetention>,
+    pub(crate) cache: bool,
+    pub(crate) log_archive: Option<LogArchiveConfig>,
+    pub(crate) targets: Vec<Arc<dyn BackupTarget>>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            cron_schedule: cron::Schedule::from_str("0 30 2 * * * *").unwrap(),
+            keep_days: 30,
+            retention: None,
+            cache: false,
+            log_archive: None,
+            targets: Vec::default(),
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn new(cron_schedule: &str, keep_days: u16) -> Result<Self, Error> {
+        Ok(Self {
+            cron_schedule: cron::Schedule::from_str(cron_schedule)
+                .map_err(|_| Error::Config("Invalid syntax for cron_schedule".into()))?,
+            keep_days,
+            retention: None,
+            cache: false,
+            log_archive: None,
+            targets: Vec::default(),
+        })
+    }
+
+    /// Adds a target the leader pushes backups to. The S3 bucket from `NodeConfig::s3_config`
+    /// will always be used and does not need to be added here. Retention with `keep_days` or
+    /// `with_retention()` applies to all targets.
+    pub fn with_target(mut self, target: Arc<dyn BackupTarget>) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    /// Replaces the flat `keep_days` for backups on all targets with a grandfather-father-son
+    /// retention. `keep_days` still applies to the log archive.
+    pub fn with_retention(mut self, retention: BackupRetention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Includes a snapshot of all caches in each backup, which can be restored with
+    /// `Client::restore_cache()`. Only has an effect with the `cache` feature enabled.
+    pub fn with_cache(mut self, cache: bool) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Enables the continuous archiving of Raft logs for point-in-time recovery. Archived logs
+    /// will be cleaned up after the same `keep_days` as backups.
+    pub fn with_log_archive(mut self, log_archive: LogArchiveConfig) -> Self {
+        self.log_archive = Some(log_archive);
+        self
+    }
+
+    pub fn from_env() -> Self {
+        Self::parse_env().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn parse_env() -> Result<Self, Error> {
+        let cron_str = env::var("HQL_BACKUP_CRON").unwrap_or_else(|_| "0 30 2 * * * *".to_string());
+        let cron_schedule = cron::Schedule::from_str(&cron_str)
+            .map_err(|_| Error::Config("Invalid syntax for HQL_BACKUP_CRON".into()))?;
+
+        Ok(Self {
+            cron_schedule,
+            keep_days: config::env_parse("HQL_BACKUP_KEEP_DAYS")?.unwrap_or(30),
+            retention: BackupRetention::from_env()?,
+            cache: config::env_parse("HQL_BACKUP_CACHE")?.unwrap_or(false),
+            log_archive: LogArchiveConfig::from_env()?,
+            targets: DirBackupTarget::from_env()?
+                .map(|target| vec![Arc::new(target) as Arc<dyn BackupTarget>])
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Returns all configured backup targets, starting with S3 if an `S3Config` exists.
+pub(crate) fn backup_targets(node_config: &NodeConfig) -> Vec<Arc<dyn BackupTarget>> {
+    let mut targets = Vec::with_capacity(node_config.backup_config.targets.len() + 1);
+    if let Some(s3_config) = &node_config.s3_config {
+        targets.push(Arc::new(S3BackupTarget::new(s3_config.clone())) as Arc<dyn BackupTarget>);
+    }
+    targets.extend(node_config.backup_config.targets.iter().cloned());
+    targets
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BackupSource {
+    S3(String),
+    File(String),
+    /// An object inside the configured `BackupTarget` with the given name, like
+    /// `Target("dir".into(), "backup_node_1_1735689600.sqlite".into())`
+    Target(String, String),
+}
+
+impl BackupSource {
+    /// If this source points to the object `name` inside the given backup target.
+    fn is_object(&self, target: &str, name: &str) -> bool {
+        match self {
+            Self::S3(obj) => target == "s3" && obj == name,
+            Self::Target(t, obj) => t == target && obj == name,
+            Self::File(_) => false,
+        }
+    }
+
+    fn from_env() -> Option<Self> {
+        let var = env::var("HQL_BACKUP_RESTORE").ok()?;
+
+        if let Some(obj) = var.strip_prefix("s3:") {
+            return Some(Self::S3(obj.to_string()));
+        }
+
+        if let Some(file) = var.strip_prefix("file:") {
+            return Some(Self::File(file.to_string()));
+        }
+
+        if let Some((target, obj)) = var.split_once(':') {
+            return Some(Self::Target(target.to_string(), obj.to_string()));
+        }
+
+        error!(
+            "HQL_BACKUP_RESTORE must start with 's3:', 'file:' or the name of a backup target \
+            like 'dir:'. Cannot restore from backup - unknown prefix: {}",
+            var
+        );
+        None
+    }
+}
+
+/// Shared between the SQLite state machine and the API to keep track of online restores.
+#[derive(Debug)]
+pub(crate) struct OnlineRestore {
+    /// The log index and source of the latest online restore applied on this node. The backup
+    /// retention must keep the source until the log entry is included in a snapshot, because
+    /// lagging nodes still need to fetch it.
+    pending: std::sync::Mutex<Option<(u64, BackupSource)>>,
+    api_client: reqwest::Client,
+    api_scheme: &'static str,
+    secret_api: String,
+    /// Stops a retrying fetch, which would block the Raft shutdown otherwise
+    tx_shutdown: watch::Sender<bool>,
+}
+
+impl OnlineRestore {
+    pub(crate) fn new(node_config: &NodeConfig) -> Result<Self, Error> {
+        Ok(Self {
+            pending: Default::default(),
+            api_client: reqwest::Client::builder()
+                .http2_prior_knowledge()
+                .danger_accept_invalid_certs(
+                    node_config
+                        .tls_api
+                        .as_ref()
+                        .map(|c| c.danger_tls_no_verify)
+                        .unwrap_or(false),
+                )
+                .build()?,
+            api_scheme: if node_config.tls_api.is_some() {
+                "https"
+            } else {
+                "http"
+            },
+            secret_api: node_config.secret_api.clone(),
+            tx_shutdown: watch::channel(false).0,
+        })
+    }
+
+    /// Must be called before the SQLite Raft is shut down.
+    pub(crate) fn shutdown(&self) {
+        self.tx_shutdown.send_replace(true);
+    }
+
+    pub(crate) fn set_pending(&self, log_index: u64, src: BackupSource) {
+        *self.pending.lock().unwrap() = Some((log_index, src));
+    }
+
+    /// Returns the source of the latest online restore, as long as the local snapshot does not
+    /// include its log entry yet.
+    fn pending_source(&self, snapshot_index: Option<u64>) -> Option<BackupSource> {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.as_ref() {
+            Some((index, _)) if snapshot_index >= Some(*index) => {
+                *pending = None;
+                None
+            }
+            Some((_, src)) => Some(src.clone()),
+            None => None,
+        }
+    }
+
+    /// Downloads the checked copy of the backup from the leader into the local file `path`.
+    async fn download_from_leader(&self, leader: &Node, path: &str) -> Result<(), Error> {
+        let url = format!(
+            "{}://{}/cluster/restore_src",
+            self.api_scheme, leader.addr_api
+        );
+        let mut resp = self
+            .api_client
+            .get(url)
+            .header(HEADER_NAME_SECRET, &self.secret_api)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Error::Error(
+                format!(
+                    "Leader {} cannot serve the backup: {}",
+                    leader.id,
+                    resp.status()
+                )
+                .into(),
+            ));
+        }
+
+        let mut file = fs::File::create(path).await?;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        Ok(())
+    }
+}
+
+pub fn start_cron(
+    client: Client,
+    targets: Vec<Arc<dyn BackupTarget>>,
+    backup_config: BackupConfig,
+) {
+    task::spawn(async move {
+        info!("Backup cron task started");
+
+        loop {
+            let dur = {
+                let now = chrono::Local::now();
+                let next = backup_config
+                    .cron_schedule
+                    .upcoming(chrono::Local)
+                    .next()
+                    .expect("No next cron task found");
+                if next <= now {
+                    // don't set it to 0 to not go crazy in case of a bad config or timing
+                    Duration::from_secs(1)
+                } else {
+                    Duration::from_secs((next.timestamp() - now.timestamp()) as u64)
+                }
+            };
+            time::sleep(dur).await;
+
+            info!("Executing backup now");
+            let mut success = false;
+            let retries = 5;
+
+            for _ in 0..retries {
+                match backup_cron_job(&client, &targets, &backup_config).await {
+                    Ok(_) => {
+                        info!("Backup task finished successfully");
+                        success = true;
+                        break;
+                    }
+                    Err(err) => {
+                        if err.is_forward_to_leader().is_some() {
+                            warn!(
+                                "Raft currently has no leader - retrying in 10 seconds\n{:?}",
+                                err
+                            );
+                            time::sleep(Duration::from_secs(10)).await;
+                        } else {
+                            error!("Error during backup task execution: {}", err);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            if !success {
+                warn!("Backup task failed after {} retries", retries);
+            }
+        }
+    });
+}
+
+async fn backup_cron_job(
+    client: &Client,
+    targets: &[Arc<dyn BackupTarget>],
+    backup_config: &BackupConfig,
+) -> Result<(), Error> {
+    client.backup().await?;
+
+    // Nodes which have not applied the latest online restore yet still need to fetch its backup.
+    let restore_src = client.inner.state.as_ref().and_then(|state| {
+        let snapshot_index = state
+            .raft_db
+            .raft
+            .metrics()
+            .borrow()
+            .snapshot
+            .map(|log_id| log_id.index);
+        state.raft_db.online_restore.pending_source(snapshot_index)
+    });
+
+    // the backup task will be async in the background, but we can start cleaning up already
+    let threshold = Utc::now().sub(chrono::Duration::days(backup_config.keep_days as i64));
+
+    for target in targets {
+        let objects = target.list("backup_node_").await?;
+        let backups = objects
+            .iter()
+            .filter_map(|obj| {
+                catalogue::parse_backup_name(&obj.name).map(|(_, dt)| (obj.name.clone(), dt))
+            })
+            .collect::<Vec<_>>();
+
+        let expired = match &backup_config.retention {
+            Some(retention) => retention.expired(&backups),
+            None => backups
+                .into_iter()
+                .filter(|(_, dt)| *dt < threshold)
+                .map(|(name, _)| name)
+                .collect(),
+        };
+
+        for name in expired {
+            if restore_src
+                .as_ref()
+                .is_some_and(|src| src.is_object(target.name(), &name))
+            {
+                info!(
+                    "Keeping expired backup {} on {} until all nodes have applied its online \
+                    restore",
+                    name,
+                    target.name()
+                );
+                continue;
+            }
+
+            info!("Deleting expired backup from {}: {}", target.name(), name);
+            target.delete(&name).await?;
+
+            let name_meta = format!("{}{}", name, catalogue::META_SUFFIX);
+            if objects.iter().any(|obj| obj.name == name_meta) {
+                target.delete(&name_meta).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn backup_local_cleanup(backup_path: String) -> Result<(), Error> {
+    // 2024/01/01 00:00:00
+    let ts_min = 1704063600;
+
+    let keep_days = env::var("HQL_BACKUP_KEEP_DAYS_LOCAL")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse::<u32>()
+        .unwrap_or_else(|_| {
+            error!("Error parsing HQL_BACKUP_KEEP_DAYS_LOCAL to u32, using default of 3 days");
+            3
+        });
+    let ts_threshold = Utc::now()
+        .sub(chrono::Duration::days(keep_days as i64))
+        .timestamp();
+
+    let path = Path::new(&backup_path);
+    let mut dir_entries = tokio::fs::read_dir(path).await?;
+
+    while let Ok(Some(entry)) = dir_entries.next_entry().await {
+        if entry.metadata().await?.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        if name == BACKUP_DB_NAME_ONLINE_SRC {
+            // the leader's copy of an online restore, which is only needed for a short time
+            let modified = entry.metadata().await?.modified()?;
+            if chrono::DateTime::<Utc>::from(modified).timestamp() < ts_threshold {
+                debug!("Cleaning up {}", BACKUP_DB_NAME_ONLINE_SRC);
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+            continue;
+        }
+
+        if let Some(s) = name.to_str() {
+            // format!("backup_node_{}_{}.sqlite", node_id, Utc::now().timestamp());
+            // and the metadata next to it, which must expire at the same time
+            let backup = s.strip_suffix(catalogue::META_SUFFIX).unwrap_or(s);
+            if !backup.starts_with("backup_node_") || !backup.ends_with(".sqlite") {
+                continue;
+            }
+
+            let stripped = backup.strip_suffix(".sqlite").unwrap_or(backup);
+            let (_, ts) = stripped.rsplit_once('_').unwrap_or_default();
+
+            match ts.parse::<i64>() {
+                Ok(ts) => {
+                    if ts > ts_min && ts < ts_threshold {
+                        debug!("Cleaning up backup {}", s);
+                        let p = format!("{}/{}", backup_path.trim_end_matches('/'), s);
+                        let _ = tokio::fs::remove_file(p).await;
+                    }
+                }
+                Err(err) => {
+                    error!("Cannot parse ts from file {}: {}", s, err)
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find_target<'a>(
+    targets: &'a [Arc<dyn BackupTarget>],
+    name: &str,
+) -> Result<&'a Arc<dyn BackupTarget>, Error> {
+    match targets.iter().find(|target| target.name() == name) {
+        Some(target) => Ok(target),
+        None if name == "s3" => Err(Error::S3(
+            "No `S3Config` given, cannot restore backup".to_string(),
+        )),
+        None => Err(Error::Config(
+            format!(
+                "No backup target '{}' configured, cannot restore backup",
+                name
+            )
+            .into(),
+        )),
+    }
+}
+
+/// Downloads or copies the backup from `src` into the local file `path`.
+async fn fetch_backup(
+    targets: &[Arc<dyn BackupTarget>],
+    src: &BackupSource,
+    path: &str,
+) -> Result<(), Error> {
+    match src {
+        BackupSource::S3(obj) => find_target(targets, "s3")?.download(obj, path).await,
+        BackupSource::Target(name, obj) => find_target(targets, name)?.download(obj, path).await,
+        BackupSource::File(path_src) => {
+            fs::copy(path_src, path).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Check if the env var `HQL_BACKUP_RESTORE` is set and restores the given backup if so.
+/// Returns `Ok(true)` if backup has been applied.
+/// This will only run if the current node ID is `1`.
+pub(crate) async fn restore_backup_start(node_config: &NodeConfig) -> Result<bool, Error> {
+    if let Some(src) = BackupSource::from_env() {
+        info!("Found backup restore request {:?}", src);
+
+        if node_config.node_id == 1 {
+            restore_backup(node_config, src).await?;
+            return Ok(true);
+        } else {
+            warn!("Cleaning up existing files and start restore cluster join");
+            let _ = fs::remove_dir_all(node_config.data_dir.as_ref()).await;
+        }
+    }
+
+    Ok(false)
+}
+
+/// Apply the given backup from a `BackupTarget` or a local file.
+///
+/// **CAUTION: This function MUST BE CALLED when the Raft is not running!**
+///
+/// You only need to invoke this manually if you want to apply a backup in another
+/// way than with the `HQL_BACKUP_RESTORE` env var, which is being done automatically.
+pub async fn restore_backup(node_config: &NodeConfig, src: BackupSource) -> Result<(), Error> {
+    info!("Starting database restore from backup {:?}", src);
+
+    let targets = backup_targets(node_config);
+    // fail early before any existing data is touched
+    match &src {
+        BackupSource::S3(_) => {
+            find_target(&targets, "s3")?;
+        }
+        BackupSource::Target(name, _) => {
+            find_target(&targets, name)?;
+        }
+        BackupSource::File(_) => {}
+    }
+
+    let (
+        PathDb(path_db),
+        PathBackups(path_backups),
+        PathSnapshots(path_snapshots),
+        PathLockFile(path_lock_file),
+    ) = StateMachineSqlite::build_folders(&node_config.data_dir, false).await;
+    let path_logs = logs::logs_dir(&node_config.data_dir);
+
+    fs::create_dir_all(&path_backups).await?;
+    set_path_access(&path_backups, 0o700).await?;
+
+    let (path_backup, remove_src) = match &src {
+        BackupSource::S3(_) | BackupSource::Target(_, _) => {
+            (format!("{}/{}", path_backups, BACKUP_DB_NAME), true)
+        }
+        BackupSource::File(path_src) => {
+            let (path, filename) = path_src.rsplit_once('/').unwrap_or(("", path_src));
+            debug!(
+                "Given backup path full: '{}', after parsing: '{}' / '{}'",
+                path_src, path, filename
+            );
+            (format!("{}/{}", path_backups, filename), false)
+        }
+    };
+    fetch_backup(&targets, &src, &path_backup).await?;
+
+    verify_backup(path_backup.clone()).await?;
+    debug!("Database backup verification is ok");
+
+    debug!("Removing old data");
+    let _ = fs::remove_dir_all(&path_db).await;
+    // let _ = fs::remove_dir_all(&path_backups).await;
+    let _ = fs::remove_dir_all(&path_snapshots).await;
+    let _ = fs::remove_dir_all(&path_lock_file).await;
+    let _ = fs::remove_dir_all(&path_logs).await;
+
+    fs::create_dir_all(&path_db).await?;
+    set_path_access(&path_db, 0o700).await?;
+
+    let path_db_full = format!("{}/{}", path_db, node_config.filename_db);
+    info!(
+        "Given backup check ok - copying into its final place: {} -> {}",
+        path_backup, path_db_full
+    );
+    fs::copy(&path_backup, &path_db_full).await?;
+    set_path_access(&path_db_full, 0o700).await?;
+
+    if remove_src {
+        info!("Cleaning up downloaded backup from {}", path_backup);
+        fs::remove_file(path_backup).await?;
+    }
+
+    Ok(())
+}
+
+/// Checks the hiqlite metadata and the integrity of the backup. If the backup contains
+/// `BackupMeta`, its content will be compared with the state at backup creation as well.
+async fn verify_backup(path_db: String) -> Result<(), Error> {
+    if env::var("HQL_BACKUP_SKIP_VALIDATION") == Ok("true".to_string()) {
+        return Ok(());
+    }
+
+    task::spawn_blocking(move || {
+        let conn = rusqlite::Connection::open(path_db)?;
+        let mut stmt = conn.prepare_cached("SELECT data FROM _metadata WHERE key = 'meta'")?;
+        let bytes = stmt.query_row((), |row| {
+            let bytes: Vec<u8> = row.get(0)?;
+            Ok(bytes)
+        })?;
+        let _meta: StateMachineData = bincode::deserialize(&bytes).unwrap();
+
+        let mut stmt = conn.prepare_cached("SELECT data FROM _metadata WHERE key = 'backup'")?;
+        let backup_meta = stmt
+            .query_row((), |row| row.get::<_, Vec<u8>>(0))
+            .optional()?;
+        match backup_meta {
+            Some(bytes) => {
+                let backup_meta: BackupMeta = bincode::deserialize(&bytes)?;
+                verify::verify(&conn, &backup_meta.content)
+            }
+            None => {
+                warn!("Backup does not contain a content checksum - running integrity check only");
+                verify::integrity_check(&conn)
+            }
+        }
+    })
+    .await??;
+    Ok(())
+}
+
+/// Restores the given backup on all nodes of the running cluster. Must be executed on the leader.
+///
+/// Each node fetches the backup on its own, which is why only S3 and shared backup targets are
+/// accepted. The leader fetches and verifies the backup first and keeps its copy, which other
+/// nodes fall back to, if they cannot fetch the source. The restore itself is a Raft log entry,
+/// which means each node replaces its database at exactly the same point in the log, while all
+/// later writes will be applied on top of the restored state.
+pub(crate) async fn restore_online(state: &Arc<AppState>, src: BackupSource) -> Result<(), Error> {
+    // fail fast with a `ForwardToLeader` before fetching anything
+    state.raft_db.raft.ensure_linearizable().await?;
+
+    if state.raft_db.metrics.maintenance.load(Ordering::Relaxed) {
+        return Err(Error::Backup(
+            "Another online restore is in progress".into(),
+        ));
+    }
+
+    match &src {
+        BackupSource::File(_) => {
+            return Err(Error::BadRequest(
+                "An online restore from a local file is not possible, because each node \
+                fetches the backup on its own"
+                    .into(),
+            ));
+        }
+        BackupSource::S3(_) => {}
+        BackupSource::Target(name, _) => {
+            if !find_target(&state.raft_db.backup_targets, name)?.is_shared() {
+                return Err(Error::BadRequest(
+                    format!(
+                        "The backup target '{}' is not shared between all nodes and cannot be \
+                        used for an online restore",
+                        name
+                    )
+                    .into(),
+                ));
+            }
+        }
+    }
+
+    let metrics = state.raft_db.raft.metrics().borrow().clone();
+    let leader = metrics
+        .membership_config
+        .nodes()
+        .find(|(id, _)| **id == state.id)
+        .map(|(_, node)| node.clone())
+        .ok_or_else(|| Error::Error("This node is not a member of the cluster".into()))?;
+
+    info!("Checking backup {:?} for an online restore", src);
+    let path = format!("{}/{}", state.raft_db.path_backups, BACKUP_DB_NAME_CHECK);
+    let path_src = format!(
+        "{}/{}",
+        state.raft_db.path_backups, BACKUP_DB_NAME_ONLINE_SRC
+    );
+    let res = async {
+        fetch_backup(&state.raft_db.backup_targets, &src, &path).await?;
+        verify_backup(path.clone()).await?;
+        let path = path.clone();
+        task::spawn_blocking(move || {
+            let conn = rusqlite::Connection::open(path)?;
+            Ok::<_, Error>(ContentChecksum::calculate(&conn)?)
+        })
+        .await?
+    }
+    .await;
+    let content = match res {
+        Ok(content) => content,
+        Err(err) => {
+            let _ = fs::remove_file(&path).await;
+            return Err(err);
+        }
+    };
+    // keep the checked copy for nodes which cannot fetch the source themselves
+    fs::rename(&path, &path_src).await?;
+
+    info!(
+        "Backup check ok - replicating the restore of {} tables / {} rows",
+        content.tables, content.rows
+    );
+    let res = state
+        .raft_db
+        .raft
+        .client_write(QueryWrite::Restore((src, content, leader)).traced())
+        .instrument(trace::commit_span())
+        .await;
+    if let Err(err) = res {
+        let _ = fs::remove_file(&path_src).await;
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Restores all caches from the snapshot inside the given backup on all nodes. Must be executed
+/// on the cache leader, which fetches and checks the snapshot before it is replicated.
+#[cfg(feature = "cache")]
+pub(crate) async fn restore_cache(state: &Arc<AppState>, src: BackupSource) -> Result<(), Error> {
+    // fail fast with a `ForwardToLeader` before fetching anything
+    state.raft_cache.raft.ensure_linearizable().await?;
+
+    let snapshot = fetch_cache_snapshot(state, &src).await?;
+    state
+        .raft_cache
+        .raft
+        .client_write(CacheRequest::Restore(snapshot))
+        .await?;
+    Ok(())
+}
+
+/// Fetches the backup from `src` and extracts the cache snapshot, which is stored inside, if
+/// `BackupConfig::with_cache()` was enabled during its creation.
+#[cfg(feature = "cache")]
+async fn fetch_cache_snapshot(state: &Arc<AppState>, src: &BackupSource) -> Result<Vec<u8>, Error> {
+    let path = format!("{}/{}", state.raft_db.path_backups, BACKUP_DB_NAME_CACHE);
+    let res = async {
+        fetch_backup(&state.raft_db.backup_targets, src, &path).await?;
+        verify_backup(path.clone()).await?;
+        let path = path.clone();
+        task::spawn_blocking(move || {
+            let conn = rusqlite::Connection::open(path)?;
+            conn.query_row(
+                "SELECT data FROM _metadata WHERE key = 'cache'",
+                (),
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .ok_or_else(|| Error::Backup("The backup does not contain a cache snapshot".into()))
+        })
+        .await?
+    }
+    .await;
+    let _ = fs::remove_file(&path).await;
+    let bytes = res?;
+
+    StateMachineMemory::check_backup_snapshot(&bytes, state.raft_cache.tx_caches.len())?;
+    Ok(bytes)
+}
+
+/// The ways a node can fetch the backup for an online restore, in the order they are tried.
+#[derive(Debug, Clone, Copy)]
+enum RestoreFetch {
+    /// The checked copy, if this node was the leader which has started the restore
+    Local,
+    Source,
+    /// The checked copy on the leader, which has started the restore
+    Leader,
+}
+
+/// Fetches the backup for an online restore on this node and makes sure it matches the content
+/// the leader has checked. A node cannot skip this log entry, which is why it falls back to the
+/// leader's copy and keeps retrying until it has the backup, instead of failing the state machine.
+pub(crate) async fn fetch_restore(
+    targets: &[Arc<dyn BackupTarget>],
+    path_backups: &str,
+    online_restore: &OnlineRestore,
+    this_node: NodeId,
+    src: &BackupSource,
+    content: &ContentChecksum,
+    leader: &Node,
+) -> Result<String, Error> {
+    let mut rx_shutdown = online_restore.tx_shutdown.subscribe();
+    let path = format!("{}/{}", path_backups, BACKUP_DB_NAME_ONLINE);
+    let path_src = format!("{}/{}", path_backups, BACKUP_DB_NAME_ONLINE_SRC);
+    let fetches = if leader.id == this_node {
+        [RestoreFetch::Local, RestoreFetch::Source]
+    } else {
+        [RestoreFetch::Source, RestoreFetch::Leader]
+    };
+
+    loop {
+        for fetch in fetches {
+            let res = async {
+                match fetch {
+                    RestoreFetch::Local => {
+                        fs::copy(&path_src, &path).await?;
+                    }
+                    RestoreFetch::Source => fetch_backup(targets, src, &path).await?,
+                    RestoreFetch::Leader => {
+                        online_restore.download_from_leader(leader, &path).await?
+                    }
+                }
+
+                let path = path.clone();
+                let content = content.clone();
+                task::spawn_blocking(move || {
+                    let conn = rusqlite::Connection::open(path)?;
+                    verify::verify(&conn, &content)
+                })
+                .await?
+            }
+            .await;
+
+            match res {
+                Ok(_) => return Ok(path),
+                Err(err) => {
+                    warn!(
+                        "Error fetching backup {:?} for online restore via {:?}: {}",
+                        src, fetch, err
+                    );
+                }
+            }
+        }
+
+        let _ = fs::remove_file(&path).await;
+        error!(
+            "Cannot fetch backup {:?} for online restore - this node cannot continue without \
+            it - retrying in 10 seconds",
+            src
+        );
+        select! {
+            _ = time::sleep(Duration::from_secs(10)) => {}
+            _ = rx_shutdown.wait_for(|shutdown| *shutdown) => {
+                return Err(Error::Error(
+                    "Shutdown requested while fetching a backup for online restore".into(),
+                ));
+            }
+        }
+    }
+}
+
+/// Returns the checked copy of the latest online restore, which this node has started as leader.
+pub(crate) async fn read_restore_src(path_backups: &str) -> Result<Vec<u8>, Error> {
+    let path = format!("{}/{}", path_backups, BACKUP_DB_NAME_ONLINE_SRC);
+    match fs::read(path).await {
+        Ok(bytes) => Ok(bytes),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(Error::BadRequest(
+            "This node has no backup for an online restore".into(),
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// pub fn restore_backup_finish(state: Arc<AppState>, nodes_count: usize) {
+//     task::spawn(restore_backup_cleanup_task(state, nodes_count));
+// }
+
+/// Finishes a restore on node 1. If `HQL_BACKUP_RESTORE_UNTIL` is set, archived logs will be
+/// replayed on top of the backup up to the given `RecoveryTarget`.
+#[tracing::instrument(level = "debug", skip_all)]
+#[cfg(feature = "backup")]
+pub async fn restore_backup_finish(state: &Arc<AppState>) -> Result<(), Error> {
+    loop {
+        match state.raft_db.raft.is_initialized().await {
+            Ok(res) => {
+                if res {
+                    break;
+                }
+            }
+            Err(err) => {
+                error!("{}", err);
+            }
+        }
+        debug!("Waiting for Raft init");
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    while state.raft_db.raft.current_leader().await.is_none() {
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    debug_assert!(
+        state.raft_db.raft.current_leader().await == Some(state.id),
+        "It should never happen that node 1 is not the raft leader during backup restore"
+    );
+
+    let reqs = 10;
+    for _ in 0..reqs {
+        let start = Instant::now();
+        match state.raft_db.raft.client_write(QueryWrite::RTT).await {
+            Ok(_) => {
+                info!("Raft RTT: {} micros", start.elapsed().as_micros());
+            }
+            Err(err) => {
+                error!("Raft RTT request error: {}", err);
+            }
+        }
+    }
+
+    let mut last_log;
+    loop {
+        let metrics = state.raft_db.raft.metrics().borrow().clone();
+        if let Some(last_applied) = metrics.last_applied {
+            if last_applied.index >= reqs {
+                debug!("Found high enough last_applied log id");
+                last_log = last_applied.index;
+                break;
+            }
+        }
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let recovery_target = RecoveryTarget::from_env()?;
+    match &state.raft_db.log_archive {
+        Some(archive) => {
+            if let Some(target) = recovery_target {
+                let base = read_backup_meta(state).await?;
+                if let Some(index) = log_archive::replay(state, archive, base, target).await? {
+                    last_log = index;
+                }
+            }
+
+            // the restored cluster starts with fresh log indexes
+            archive.start_generation().await?;
+        }
+        None => {
+            if recovery_target.is_some() {
+                return Err(Error::Config(
+                    "HQL_BACKUP_RESTORE_UNTIL is set, but no log archive is configured".into(),
+                ));
+            }
+        }
+    }
+
+    debug!("Taking snapshot now");
+    state
+        .raft_db
+        .raft
+        .trigger()
+        .snapshot()
+        .await
+        .expect("Snapshot trigger to always succeed at this point");
+
+    // while let Err(_err) = state.raft_db.raft.trigger().snapshot().await {
+    //     debug_assert!("")
+    //     time::sleep(Duration::from_millis(500)).await;
+    // }
+
+    // wait until snapshot has been built
+    while state.raft_db.raft.metrics().borrow().snapshot.is_none() {
+        info!("Waiting for snapshot build to finish");
+        time::sleep(Duration::from_millis(100)).await;
+    }
+
+    debug!("Purging logs");
+    while let Err(err) = state.raft_db.raft.trigger().purge_log(last_log).await {
+        error!("Error during logs purge: {}", err);
+    }
+
+    info!("restore_backup_finish task successful");
+    Ok(())
+}
+
+async fn read_backup_meta(state: &Arc<AppState>) -> Result<BackupMeta, Error> {
+    let conn = state.raft_db.read_pool.get().await?;
+    let bytes = conn
+        .query_row(
+            "SELECT data FROM _metadata WHERE key = 'backup'",
+            (),
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .map_err(|_| {
+            Error::Error("The restored backup does not contain any backup metadata".into())
+        })?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+// pub async fn snapshot_after_restore(
+//     tx_writer: &flume::Sender<WriterRequest>,
+//     data_dir: &str,
+// ) -> Result<(), Error> {
+//     let snapshot_id = Uuid::now_v7();
+//     fs::create_dir_all(&self.path_snapshots)
+//         .await
+//         .map_err(|err| StorageError::IO {
+//             source: StorageIOError::write(&err),
+//         })?;
+//
+//     let path = format!("{}/{}", self.path_snapshots, snapshot_id);
+//     let (ack, rx) = oneshot::channel();
+//     let req = WriterRequest::Snapshot(SnapshotRequest {
+//         snapshot_id,
+//         // last_membership: self.last_membership.clone(),
+//         path: path.clone(),
+//         ack,
+//     });
+//     self.write_tx
+//         .send_async(req)
+//         .await
+//         .expect("Sender to always be listening");
+// }
@@ -0,0 +1,162 @@
+use crate::{config, Error};
+use chrono::{DateTime, Datelike, Utc};
+use std::collections::HashSet;
+
+/// Grandfather-father-son retention for backups on all `BackupTarget`s.
+///
+/// For each of the last `daily` days, `weekly` ISO weeks and `monthly` months that contain at
+/// least one backup, the newest backup of that period will be kept. A single backup can count
+/// for multiple periods at once. All other backups will be deleted, apart from the newest one,
+/// which is always kept.
+///
+/// For instance, `BackupRetention::new(7, 4, 12)` keeps a year of monthly backups with at most
+/// 23 copies instead of 365 with a flat `keep_days`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupRetention {
+    pub daily: u16,
+    pub weekly: u16,
+    pub monthly: u16,
+}
+
+impl BackupRetention {
+    pub fn new(daily: u16, weekly: u16, monthly: u16) -> Self {
+        Self {
+            daily,
+            weekly,
+            monthly,
+        }
+    }
+
+    /// Reads `HQL_BACKUP_KEEP_DAILY`, `HQL_BACKUP_KEEP_WEEKLY` and `HQL_BACKUP_KEEP_MONTHLY`.
+    /// Returns `None` if none of them is set, which means a flat `keep_days` will be used.
+    pub(crate) fn from_env() -> Result<Option<Self>, Error> {
+        let daily = config::env_parse::<u16>("HQL_BACKUP_KEEP_DAILY")?;
+        let weekly = config::env_parse::<u16>("HQL_BACKUP_KEEP_WEEKLY")?;
+        let monthly = config::env_parse::<u16>("HQL_BACKUP_KEEP_MONTHLY")?;
+        if daily.is_none() && weekly.is_none() && monthly.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            daily: daily.unwrap_or(0),
+            weekly: weekly.unwrap_or(0),
+            monthly: monthly.unwrap_or(0),
+        }))
+    }
+
+    /// Returns the names of all `backups` which are not covered by this retention.
+    pub(crate) fn expired(&self, backups: &[(String, DateTime<Utc>)]) -> Vec<String> {
+        let mut sorted = backups.iter().collect::<Vec<_>>();
+        sorted.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let mut keep = HashSet::with_capacity(sorted.len());
+        if let Some((name, _)) = sorted.first() {
+            keep.insert(name.as_str());
+        }
+        keep_newest_per_period(&sorted, self.daily, |dt| dt.date_naive(), &mut keep);
+        keep_newest_per_period(&sorted, self.weekly, |dt| dt.iso_week(), &mut keep);
+        keep_newest_per_period(
+            &sorted,
+            self.monthly,
+            |dt| (dt.year(), dt.month()),
+            &mut keep,
+        );
+
+        sorted
+            .into_iter()
+            .filter(|(name, _)| !keep.contains(name.as_str()))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// `sorted` must be ordered from newest to oldest.
+fn keep_newest_per_period<'a, K, F>(
+    sorted: &[&'a (String, DateTime<Utc>)],
+    count: u16,
+    period: F,
+    keep: &mut HashSet<&'a str>,
+) where
+    K: PartialEq,
+    F: Fn(&DateTime<Utc>) -> K,
+{
+    let mut kept = 0;
+    let mut last = None;
+
+    for (name, dt) in sorted {
+        if kept >= count {
+            break;
+        }
+
+        let key = period(dt);
+        if last.as_ref() != Some(&key) {
+            keep.insert(name.as_str());
+            kept += 1;
+            last = Some(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_gfs_retention() {
+        // one backup each day at 02:30 for 2 years, the newest one on Wed, 2025-01-15
+        let newest = Utc.with_ymd_and_hms(2025, 1, 15, 2, 30, 0).unwrap();
+        let backups = (0..730)
+            .map(|days| {
+                let dt = newest - Duration::days(days);
+                (format!("backup_node_1_{}.sqlite", dt.timestamp()), dt)
+            })
+            .collect::<Vec<_>>();
+
+        let expired = BackupRetention::new(7, 4, 12).expired(&backups);
+        let mut kept = backups
+            .iter()
+            .filter(|(name, _)| !expired.contains(name))
+            .map(|(_, dt)| dt.date_naive().to_string())
+            .collect::<Vec<_>>();
+        kept.sort();
+
+        let expected = vec![
+            // monthly - the last day of each month
+            "2024-02-29",
+            "2024-03-31",
+            "2024-04-30",
+            "2024-05-31",
+            "2024-06-30",
+            "2024-07-31",
+            "2024-08-31",
+            "2024-09-30",
+            "2024-10-31",
+            "2024-11-30",
+            // weekly - the last day of each ISO week is a Sunday
+            "2024-12-29",
+            // monthly
+            "2024-12-31",
+            // weekly
+            "2025-01-05",
+            // daily
+            "2025-01-09",
+            "2025-01-10",
+            "2025-01-11",
+            // daily + weekly
+            "2025-01-12",
+            // daily
+            "2025-01-13",
+            "2025-01-14",
+            // daily + weekly + monthly
+            "2025-01-15",
+        ];
+        assert_eq!(kept, expected);
+        assert_eq!(expired.len(), backups.len() - expected.len());
+
+        // the newest backup survives even without any retention
+        let expired = BackupRetention::new(0, 0, 0).expired(&backups);
+        assert_eq!(expired.len(), backups.len() - 1);
+        assert!(!expired.contains(&backups[0].0));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::s3::S3Config;
+use crate::{config, Error};
+use async_trait::async_trait;
+use cryptr::{EncValue, FileReader, FileWriter, StreamReader, StreamWriter};
+use std::env;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::fs;
+use tracing::info;
+
+/// An object stored inside a `BackupTarget`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupObject {
+    /// The name relative to the target root, like `backup_node_1_1735689600.sqlite`
+    pub name: String,
+    /// The size in bytes as it is stored on the target, after encryption
+    pub size: u64,
+}
+
+/// A remote location for backups and archived Raft logs.
+///
+/// Object names may contain `/` to group them, like `hiqlite_logs/00000001/start`.
+/// The built-in targets are `S3BackupTarget` and `DirBackupTarget`. You can add your own
+/// implementation with `BackupConfig::with_target()`.
+#[async_trait]
+pub trait BackupTarget: Debug + Send + Sync {
+    /// A short and unique name, which is used in logs, metrics and `BackupSource::Target`.
+    fn name(&self) -> &str;
+
+    /// Lists all objects whose name starts with `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<BackupObject>, Error>;
+
+    /// Uploads the local file at `path` as `name` and overwrites an existing object.
+    async fn upload(&self, path: &str, name: &str) -> Result<(), Error>;
+
+    /// Downloads the object `name` into the local file at `path`.
+    async fn download(&self, name: &str, path: &str) -> Result<(), Error>;
+
+    async fn delete(&self, name: &str) -> Result<(), Error>;
+
+    /// If all nodes can reach the same objects, like inside a bucket or on a network volume.
+    /// An online restore is only possible from a shared target, because each node fetches the
+    /// backup on its own.
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
+
+/// The S3 bucket from `NodeConfig::s3_config`. All objects are encrypted.
+#[derive(Debug, Clone)]
+pub struct S3BackupTarget {
+    s3_config: Arc<S3Config>,
+}
+
+impl S3BackupTarget {
+    pub fn new(s3_config: Arc<S3Config>) -> Self {
+        Self { s3_config }
+    }
+}
+
+#[async_trait]
+impl BackupTarget for S3BackupTarget {
+    fn name(&self) -> &str {
+        "s3"
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<BackupObject>, Error> {
+        let mut objects = Vec::new();
+
+        for bucket in self.s3_config.bucket.list(prefix, None).await? {
+            if bucket.name != self.s3_config.bucket.name {
+                info!("Found non-configured bucket {} - skipping", bucket.name);
+                continue;
+            }
+
+            for object in bucket.contents {
+                objects.push(BackupObject {
+                    name: object.key,
+                    size: object.size,
+                });
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn upload(&self, path: &str, name: &str) -> Result<(), Error> {
+        self.s3_config.push(path, name).await
+    }
+
+    async fn download(&self, name: &str, path: &str) -> Result<(), Error> {
+        self.s3_config.pull(name, path).await
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), Error> {
+        self.s3_config.bucket.delete(name.to_string()).await?;
+        Ok(())
+    }
+}
+
+/// A local directory, which should be a mounted network volume like NFS in most cases.
+/// If `encrypt` is set, objects will be encrypted with the same keys as S3 backups.
+#[derive(Debug, Clone)]
+pub struct DirBackupTarget {
+    path: String,
+    encrypt: bool,
+    shared: bool,
+}
+
+impl DirBackupTarget {
+    pub fn new<S: Into<String>>(path: S, encrypt: bool) -> Self {
+        let path: String = path.into();
+        Self {
+            path: path.trim_end_matches('/').to_string(),
+            encrypt,
+            shared: false,
+        }
+    }
+
+    /// Marks the directory as shared between all nodes, like a mounted network volume, which
+    /// makes it usable for online restores.
+    pub fn with_shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    pub(crate) fn from_env() -> Result<Option<Self>, Error> {
+        let Ok(path) = env::var("HQL_BACKUP_DIR") else {
+            return Ok(None);
+        };
+        let encrypt = config::env_parse("HQL_BACKUP_DIR_ENCRYPT")?.unwrap_or(true);
+        let shared = config::env_parse("HQL_BACKUP_DIR_SHARED")?.unwrap_or(false);
+        Ok(Some(Self::new(path, encrypt).with_shared(shared)))
+    }
+
+    #[inline]
+    fn path_for(&self, name: &str) -> String {
+        format!("{}/{}", self.path, name)
+    }
+}
+
+#[async_trait]
+impl BackupTarget for DirBackupTarget {
+    fn name(&self) -> &str {
+        "dir"
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<BackupObject>, Error> {
+        let mut objects = Vec::new();
+
+        // walk the tree without recursion, the relative path is empty for the root
+        let mut dirs = vec![String::default()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(mut entries) = fs::read_dir(self.path_for(&dir)).await else {
+                continue;
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let file_name = entry.file_name();
+                let name = if dir.is_empty() {
+                    file_name.to_string_lossy().to_string()
+                } else {
+                    format!("{}/{}", dir, file_name.to_string_lossy())
+                };
+
+                let meta = entry.metadata().await?;
+                if meta.is_dir() {
+                    dirs.push(name);
+                } else if name.starts_with(prefix) && !name.ends_with(".tmp") {
+                    objects.push(BackupObject {
+                        name,
+                        size: meta.len(),
+                    });
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn upload(&self, path: &str, name: &str) -> Result<(), Error> {
+        let path_target = self.path_for(name);
+        if let Some((parent, _)) = path_target.rsplit_once('/') {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // write to a temp file first to never leave a partial object behind
+        let path_tmp = format!("{}.tmp", path_target);
+        if self.encrypt {
+            let reader = StreamReader::File(FileReader {
+                path,
+                print_progress: false,
+            });
+            let writer = StreamWriter::File(FileWriter {
+                path: &path_tmp,
+                overwrite_target: true,
+            });
+            EncValue::encrypt_stream(reader, writer).await?;
+        } else {
+            fs::copy(path, &path_tmp).await?;
+        }
+        fs::rename(path_tmp, path_target).await?;
+
+        Ok(())
+    }
+
+    async fn download(&self, name: &str, path: &str) -> Result<(), Error> {
+        let path_src = self.path_for(name);
+
+        if self.encrypt {
+            let reader = StreamReader::File(FileReader {
+                path: &path_src,
+                print_progress: false,
+            });
+            let writer = StreamWriter::File(FileWriter {
+                path,
+                overwrite_target: true,
+            });
+            EncValue::decrypt_stream(reader, writer).await?;
+        } else {
+            fs::copy(path_src, path).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), Error> {
+        fs::remove_file(self.path_for(name)).await?;
+        Ok(())
+    }
+
+    fn is_shared(&self) -> bool {
+        self.shared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_backup_target() {
+        let root = format!(
+            "{}/hiqlite_test_dir_backup_target",
+            env::temp_dir().display()
+        );
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await.unwrap();
+
+        let path_src = format!("{}/src.sqlite", root);
+        fs::write(&path_src, b"backup content").await.unwrap();
+
+        let target = DirBackupTarget::new(format!("{}/target/", root), false);
+        target
+            .upload(&path_src, "backup_node_1_1735689600.sqlite")
+            .await
+            .unwrap();
+        target
+            .upload(&path_src, "hiqlite_logs/00000001/start")
+            .await
+            .unwrap();
+
+        let mut names = target
+            .list("")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|obj| obj.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "backup_node_1_1735689600.sqlite".to_string(),
+                "hiqlite_logs/00000001/start".to_string(),
+            ]
+        );
+        assert_eq!(target.list("hiqlite_logs/").await.unwrap().len(), 1);
+
+        let path_dst = format!("{}/dst.sqlite", root);
+        target
+            .download("backup_node_1_1735689600.sqlite", &path_dst)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&path_dst).await.unwrap(), b"backup content");
+
+        target
+            .delete("backup_node_1_1735689600.sqlite")
+            .await
+            .unwrap();
+        assert_eq!(target.list("backup_node_").await.unwrap().len(), 0);
+
+        let _ = fs::remove_dir_all(&root).await;
+    }
+}
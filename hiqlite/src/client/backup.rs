@@ -1,9 +1,13 @@
-use crate::client::stream::{ClientBackupPayload, ClientStreamReq};
+use crate::backup::{self, BackupInfo, BackupSource};
+use crate::client::stream::{
+    ClientBackupPayload, ClientListBackupsPayload, ClientRestorePayload, ClientStreamReq,
+};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::store::state_machine::sqlite::state_machine::QueryWrite;
-use crate::{Client, Error, Response};
+use crate::{trace, Client, Error, Response, TraceContext};
 use tokio::sync::oneshot;
 use tracing::error;
+use tracing::Instrument;
 
 impl Client {
     /// Create an on-demand backup of the SQLite state machine.
@@ -59,7 +63,8 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::Backup(current_leader))
+                .client_write(QueryWrite::Backup(current_leader).traced())
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -72,6 +77,7 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::Backup(ClientBackupPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
                     node_id: current_leader,
                     ack,
                 }))
@@ -86,4 +92,168 @@ impl Client {
             }
         }
     }
+
+    /// Restore the SQLite state machine from the given backup on all nodes of the running
+    /// cluster, without any restart.
+    ///
+    /// The leader fetches and verifies the backup first. After that, the restore will be
+    /// replicated through the Raft and each node replaces its database at the same log index,
+    /// like installing a snapshot. Nodes expose `hiqlite_maintenance` on the `/metrics`
+    /// endpoint while this is running. Writes which arrive in the meantime will wait and be
+    /// applied on top of the restored data.
+    ///
+    /// Each node fetches the backup on its own, which is why `BackupSource::File` and targets
+    /// which are not shared between all nodes, like a `DirBackupTarget` without
+    /// `with_shared(true)`, are rejected. If a node cannot fetch the backup, it falls back to the
+    /// checked copy of the leader and keeps retrying until it has it or shuts down. A node which
+    /// cannot replace its database stops its SQLite Raft, because it cannot continue with a
+    /// different state than the others.
+    #[cold]
+    pub async fn restore_backup(&self, src: BackupSource) -> Result<(), Error> {
+        match self.restore_backup_execute(src.clone()).await {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self
+                    .was_leader_update_error(&err, &self.inner.leader_db, &self.inner.tx_client_db)
+                    .await
+                {
+                    self.restore_backup_execute(src).await
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    /// Restore all caches from the snapshot inside the given backup, which only exists if it has
+    /// been created with `BackupConfig::with_cache()`.
+    ///
+    /// The content of all caches will be replaced on all nodes through the Raft, including TTLs.
+    /// Entries that have expired in the meantime will be removed right after the restore.
+    /// Distributed locks are not restored, because their owners do not exist anymore after an
+    /// outage. The backup is fetched and checked by the cache leader.
+    #[cfg(feature = "cache")]
+    #[cold]
+    pub async fn restore_cache(&self, src: BackupSource) -> Result<(), Error> {
+        match self.restore_cache_execute(src.clone()).await {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self
+                    .was_leader_update_error(
+                        &err,
+                        &self.inner.leader_cache,
+                        &self.inner.tx_client_cache,
+                    )
+                    .await
+                {
+                    self.restore_cache_execute(src).await
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    async fn restore_cache_execute(&self, src: BackupSource) -> Result<(), Error> {
+        if let Some(state) = self.is_leader_cache_with_state().await {
+            backup::restore_cache(state, src).await
+        } else {
+            let (ack, rx) = oneshot::channel();
+            self.inner
+                .tx_client_cache
+                .send_async(ClientStreamReq::RestoreCache(ClientRestorePayload {
+                    request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    src,
+                    ack,
+                }))
+                .await
+                .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::RestoreCache(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[cold]
+    async fn restore_backup_execute(&self, src: BackupSource) -> Result<(), Error> {
+        if let Some(state) = self.is_leader_db_with_state().await {
+            backup::restore_online(state, src).await
+        } else {
+            let (ack, rx) = oneshot::channel();
+            self.inner
+                .tx_client_db
+                .send_async(ClientStreamReq::Restore(ClientRestorePayload {
+                    request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    src,
+                    ack,
+                }))
+                .await
+                .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::Restore(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// List all backups on the local disk of the leader and on all backup targets, newest first.
+    ///
+    /// Each entry contains the node, creation time, size, Raft log index and verification status.
+    /// The `name` and `target` can be used for a `BackupSource` to restore it. Targets that
+    /// cannot be reached will be logged and skipped.
+    #[cold]
+    pub async fn list_backups(&self) -> Result<Vec<BackupInfo>, Error> {
+        match self.list_backups_execute().await {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self
+                    .was_leader_update_error(&err, &self.inner.leader_db, &self.inner.tx_client_db)
+                    .await
+                {
+                    self.list_backups_execute().await
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    #[cold]
+    async fn list_backups_execute(&self) -> Result<Vec<BackupInfo>, Error> {
+        if let Some(state) = self.is_leader_db_with_state().await {
+            backup::catalogue::list_backups(
+                &state.raft_db.path_backups,
+                &state.raft_db.backup_targets,
+            )
+            .await
+        } else {
+            let (ack, rx) = oneshot::channel();
+            self.inner
+                .tx_client_db
+                .send_async(ClientStreamReq::ListBackups(ClientListBackupsPayload {
+                    request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    ack,
+                }))
+                .await
+                .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::ListBackups(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
 }
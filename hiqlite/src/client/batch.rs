@@ -1,9 +1,10 @@
 use crate::client::stream::{ClientBatchPayload, ClientStreamReq};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::store::state_machine::sqlite::state_machine::QueryWrite;
-use crate::{Client, Error, Response};
+use crate::{trace, Client, Error, Response, TraceContext};
 use std::borrow::Cow;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 
 impl Client {
     /// Takes an arbitrary SQL String with multiple queries and executes all of them as a batch.
@@ -61,7 +62,12 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::Batch(sql))
+                .client_write(
+                    QueryWrite::Batch(sql)
+                        .idempotent(self.idempotency(state))
+                        .traced(),
+                )
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -74,6 +80,8 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::Batch(ClientBatchPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: self.idempotency_key.clone(),
                     sql,
                     ack,
                 }))
@@ -2,7 +2,7 @@ use crate::client::stream::{ClientKVPayload, ClientStreamReq};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::store::state_machine::memory::kv_handler::CacheRequestHandler;
 use crate::store::state_machine::memory::state_machine::{CacheRequest, CacheResponse};
-use crate::{Client, Error};
+use crate::{Client, Error, TraceContext};
 use chrono::Utc;
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
@@ -224,6 +224,7 @@ impl Client {
         is_remote_get: bool,
     ) -> Result<CacheResponse, Error> {
         if let Some(state) = self.is_leader_cache_with_state().await {
+            let cache_req = cache_req.idempotent(self.idempotency(state));
             let res = state.raft_cache.raft.client_write(cache_req).await?;
             Ok(res.data)
         } else {
@@ -231,12 +232,16 @@ impl Client {
             let payload = if is_remote_get {
                 ClientStreamReq::KVGet(ClientKVPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: None,
                     cache_req,
                     ack,
                 })
             } else {
                 ClientStreamReq::KV(ClientKVPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: self.idempotency_key.clone(),
                     cache_req,
                     ack,
                 })
@@ -252,7 +257,6 @@ impl Client {
                 .expect("To always receive an answer from Client Stream Manager")?;
             match res {
                 ApiStreamResponsePayload::KV(res) => res,
-                #[cfg(any(feature = "sqlite", feature = "dlock", feature = "listen_notify"))]
                 _ => unreachable!(),
             }
         }
@@ -58,6 +58,7 @@ impl Client {
 
         let slf = Self {
             inner: Arc::new(db_client),
+            idempotency_key: None,
         };
 
         slf.find_set_active_leader().await;
@@ -164,6 +165,7 @@ impl Client {
 
         let slf = Self {
             inner: Arc::new(db_client),
+            idempotency_key: None,
         };
 
         // It should be enough to check for DB proxy here. When running, the forward to leader
@@ -4,7 +4,7 @@ use crate::store::state_machine::memory::dlock_handler::{
     LockAwaitPayload, LockRequest, LockState,
 };
 use crate::store::state_machine::memory::state_machine::{CacheRequest, CacheResponse};
-use crate::{Client, Error};
+use crate::{Client, Error, TraceContext};
 use std::borrow::Cow;
 use tokio::sync::oneshot;
 use tokio::task;
@@ -162,12 +162,16 @@ impl Client {
             let payload = if is_remote_await {
                 ClientStreamReq::LockAwait(ClientKVPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: None,
                     cache_req,
                     ack,
                 })
             } else {
                 ClientStreamReq::KV(ClientKVPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: None,
                     cache_req,
                     ack,
                 })
@@ -2,9 +2,10 @@ use crate::client::stream::{ClientExecutePayload, ClientStreamReq};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::query::rows::RowOwned;
 use crate::store::state_machine::sqlite::state_machine::{Query, QueryWrite};
-use crate::{Client, Error, Params, Response};
+use crate::{trace, Client, Error, Params, Response, TraceContext};
 use std::borrow::Cow;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 
 impl Client {
     /// Execute any modifying / non-read-only query on the database.
@@ -48,7 +49,12 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::Execute(sql))
+                .client_write(
+                    QueryWrite::Execute(sql)
+                        .idempotent(self.idempotency(state))
+                        .traced(),
+                )
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -61,6 +67,8 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::Execute(ClientExecutePayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: self.idempotency_key.clone(),
                     sql,
                     ack,
                 }))
@@ -188,7 +196,12 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::ExecuteReturning(sql))
+                .client_write(
+                    QueryWrite::ExecuteReturning(sql)
+                        .idempotent(self.idempotency(state))
+                        .traced(),
+                )
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -201,6 +214,8 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::ExecuteReturning(ClientExecutePayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: self.idempotency_key.clone(),
                     sql,
                     ack,
                 }))
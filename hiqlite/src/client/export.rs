@@ -0,0 +1,542 @@
+use crate::export::{
+    blob_param, csv_write_field, csv_write_text, insert_sql, is_skipped_stmt, json_param,
+    json_value, param_size, quote_ident, CsvReader, ExportFormat, SqlSplitter, EXPORT_PAGE_SIZE,
+    IMPORT_CHUNK_BYTES, IMPORT_CHUNK_ROWS, INTERNAL_TABLES,
+};
+use crate::query::rows::ValueOwned;
+use crate::{params, Client, Error, Param, Params, Row};
+use std::mem;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+impl Client {
+    /// Exports the whole database or only the given `tables` into `writer`.
+    ///
+    /// An empty `tables` exports all tables apart from the internal ones. `ExportFormat::Csv`
+    /// and `ExportFormat::Jsonl` always need exactly one table. The data is read and written
+    /// page by page, so the memory usage stays low even for big tables. Returns the number of
+    /// exported rows.
+    ///
+    /// The export reads from the local replica for local clients, like any other query. It
+    /// does not run inside a single transaction, which means you should pause writes during the
+    /// export if you need a fully consistent dump. Use `backup()` otherwise.
+    ///
+    /// ```rust, notest
+    /// let mut file = tokio::fs::File::create("dump.sql").await?;
+    /// let rows = client.export(ExportFormat::Sql, &[], &mut file).await?;
+    /// ```
+    pub async fn export<W>(
+        &self,
+        format: ExportFormat,
+        tables: &[&str],
+        writer: &mut W,
+    ) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let whole_db = tables.is_empty();
+        let tables = if whole_db {
+            self.export_table_names().await?
+        } else {
+            tables.iter().map(|t| t.to_string()).collect()
+        };
+        if format != ExportFormat::Sql && tables.len() != 1 {
+            return Err(Error::Config(
+                "CSV and JSONL exports need exactly one table".into(),
+            ));
+        }
+
+        if format == ExportFormat::Sql {
+            writer.write_all(b"-- Hiqlite SQL dump\n\n").await?;
+        }
+
+        let mut rows = 0;
+        // indexes, triggers and views will be created after all the data has been inserted
+        let mut schema_after = Vec::new();
+
+        for table in &tables {
+            let mut schema = self
+                .query_raw(
+                    "SELECT type, sql FROM sqlite_master WHERE tbl_name = $1 AND sql IS NOT NULL",
+                    params!(table.as_str()),
+                )
+                .await?
+                .into_iter()
+                .map(|mut row| (row.get::<String>("type"), row.get::<String>("sql")))
+                .collect::<Vec<_>>();
+            let Some(pos) = schema.iter().position(|(typ, _)| typ == "table") else {
+                return Err(Error::Config(
+                    format!("Table '{}' does not exist", table).into(),
+                ));
+            };
+            let (_, table_sql) = schema.remove(pos);
+            let without_rowid = table_sql.to_uppercase().contains("WITHOUT ROWID");
+
+            let columns = self
+                .query_raw(
+                    "SELECT name FROM pragma_table_info($1) ORDER BY cid",
+                    params!(table.as_str()),
+                )
+                .await?
+                .into_iter()
+                .map(|mut row| row.get::<String>("name"))
+                .collect::<Vec<_>>();
+
+            match format {
+                ExportFormat::Sql => {
+                    writer
+                        .write_all(format!("{};\n", table_sql).as_bytes())
+                        .await?;
+
+                    let exprs = columns
+                        .iter()
+                        .map(|col| format!("quote({})", quote_ident(col)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let insert = format!(
+                        "INSERT INTO {} ({}) VALUES (",
+                        quote_ident(table),
+                        columns
+                            .iter()
+                            .map(|col| quote_ident(col))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+
+                    rows += self
+                        .export_rows(table, &exprs, without_rowid, writer, |buf, values| {
+                            buf.push_str(&insert);
+                            for (i, value) in values.into_iter().enumerate() {
+                                if i > 0 {
+                                    buf.push_str(", ");
+                                }
+                                if let ValueOwned::Text(literal) = value {
+                                    buf.push_str(&literal);
+                                }
+                            }
+                            buf.push_str(");\n");
+                        })
+                        .await?;
+
+                    schema_after.extend(schema.into_iter().map(|(_, sql)| sql));
+                }
+
+                ExportFormat::Csv => {
+                    let mut header = String::new();
+                    for (i, col) in columns.iter().enumerate() {
+                        if i > 0 {
+                            header.push(',');
+                        }
+                        csv_write_text(&mut header, col);
+                    }
+                    header.push('\n');
+                    writer.write_all(header.as_bytes()).await?;
+
+                    // the unary `+` makes these expressions, which keeps the exact value types
+                    let exprs = columns
+                        .iter()
+                        .map(|col| format!("+{}", quote_ident(col)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    rows += self
+                        .export_rows(table, &exprs, without_rowid, writer, |buf, values| {
+                            for (i, value) in values.iter().enumerate() {
+                                if i > 0 {
+                                    buf.push(',');
+                                }
+                                csv_write_field(buf, value);
+                            }
+                            buf.push('\n');
+                        })
+                        .await?;
+                }
+
+                ExportFormat::Jsonl => {
+                    // the keys are built manually to keep the column order
+                    let keys = columns
+                        .iter()
+                        .map(|col| serde_json::Value::from(col.as_str()).to_string())
+                        .collect::<Vec<_>>();
+                    let exprs = columns
+                        .iter()
+                        .map(|col| format!("+{}", quote_ident(col)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    rows += self
+                        .export_rows(table, &exprs, without_rowid, writer, |buf, values| {
+                            buf.push('{');
+                            for (i, value) in values.into_iter().enumerate() {
+                                if i > 0 {
+                                    buf.push(',');
+                                }
+                                buf.push_str(&keys[i]);
+                                buf.push(':');
+                                buf.push_str(&json_value(value).to_string());
+                            }
+                            buf.push_str("}\n");
+                        })
+                        .await?;
+                }
+            }
+        }
+
+        if format == ExportFormat::Sql {
+            if whole_db {
+                let views = self
+                    .query_raw(
+                        "SELECT sql FROM sqlite_master WHERE type = 'view' AND sql IS NOT NULL",
+                        params!(),
+                    )
+                    .await?;
+                schema_after.extend(views.into_iter().map(|mut row| row.get::<String>("sql")));
+            }
+
+            for sql in schema_after {
+                writer.write_all(format!("{};\n", sql).as_bytes()).await?;
+            }
+        }
+
+        writer.flush().await?;
+        Ok(rows)
+    }
+
+    /// Imports an export created with `export()` from `reader` and returns the number of
+    /// inserted rows.
+    ///
+    /// `ExportFormat::Sql` executes all statements from the dump, which means the `table` must
+    /// be `None`. Transaction control and `PRAGMA`s, like in a dump from the `sqlite3` CLI,
+    /// will be skipped. `ExportFormat::Csv` needs a header line with the column names.
+    /// `ExportFormat::Csv` and `ExportFormat::Jsonl` insert into the given `table`, which must
+    /// exist already. Their hex-encoded values are decoded for columns with the declared type
+    /// `BLOB`.
+    ///
+    /// The input is split into chunks of at most 1000 rows or 1 MiB, and each chunk is written
+    /// through the Raft as a single log entry. CSV and JSONL chunks are executed as a `txn()`,
+    /// SQL chunks as a `batch()`. The import stops at the first error, while all chunks before
+    /// it have been applied already.
+    ///
+    /// ```rust, notest
+    /// let file = tokio::fs::File::open("users.csv").await?;
+    /// let mut reader = tokio::io::BufReader::new(file);
+    /// let rows = client
+    ///     .import(ExportFormat::Csv, Some("users"), &mut reader)
+    ///     .await?;
+    /// ```
+    pub async fn import<R>(
+        &self,
+        format: ExportFormat,
+        table: Option<&str>,
+        reader: &mut R,
+    ) -> Result<u64, Error>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        match (format, table) {
+            (ExportFormat::Sql, None) => self.import_sql(reader).await,
+            (ExportFormat::Sql, Some(_)) => Err(Error::Config(
+                "SQL imports cannot be limited to a table".into(),
+            )),
+            (_, None) => Err(Error::Config("CSV and JSONL imports need a table".into())),
+            (ExportFormat::Csv, Some(table)) => self.import_csv(table, reader).await,
+            (ExportFormat::Jsonl, Some(table)) => self.import_jsonl(table, reader).await,
+        }
+    }
+
+    pub(crate) async fn export_table_names(&self) -> Result<Vec<String>, Error> {
+        let names = self
+            .query_raw(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' \
+                ORDER BY rowid",
+                params!(),
+            )
+            .await?
+            .into_iter()
+            .map(|mut row| row.get::<String>("name"))
+            .filter(|name| !INTERNAL_TABLES.contains(&name.as_str()))
+            .collect();
+        Ok(names)
+    }
+
+    /// Reads all rows of `table` page by page and passes the values of `exprs` to `write_row`.
+    async fn export_rows<W, F>(
+        &self,
+        table: &str,
+        exprs: &str,
+        without_rowid: bool,
+        writer: &mut W,
+        mut write_row: F,
+    ) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&mut String, Vec<ValueOwned>),
+    {
+        let table = quote_ident(table);
+        let mut count = 0;
+        let mut last_rowid = i64::MIN;
+        let mut buf = String::new();
+
+        loop {
+            // Keyset pagination is much faster for big tables, but it needs a rowid.
+            let rows = if without_rowid {
+                self.query_raw(
+                    format!("SELECT {} FROM {} LIMIT $1 OFFSET $2", exprs, table),
+                    params!(EXPORT_PAGE_SIZE, count as i64),
+                )
+                .await?
+            } else {
+                self.query_raw(
+                    format!(
+                        "SELECT _rowid_, {} FROM {} WHERE _rowid_ > $1 ORDER BY _rowid_ LIMIT $2",
+                        exprs, table
+                    ),
+                    params!(last_rowid, EXPORT_PAGE_SIZE),
+                )
+                .await?
+            };
+
+            let len = rows.len();
+            for row in rows {
+                let mut values = match row {
+                    Row::Owned(row) => row
+                        .columns
+                        .into_iter()
+                        .map(|col| col.value)
+                        .collect::<Vec<_>>(),
+                    Row::Borrowed(_) => unreachable!("query_raw() always returns owned rows"),
+                };
+                if !without_rowid {
+                    if let ValueOwned::Integer(rowid) = values.remove(0) {
+                        last_rowid = rowid;
+                    }
+                }
+                write_row(&mut buf, values);
+            }
+
+            writer.write_all(buf.as_bytes()).await?;
+            buf.clear();
+
+            count += len as u64;
+            if len < EXPORT_PAGE_SIZE as usize {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn import_sql<R>(&self, reader: &mut R) -> Result<u64, Error>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut splitter = SqlSplitter::default();
+        let mut line = String::new();
+        let mut chunk = String::new();
+        // `true` for each `INSERT` inside the chunk
+        let mut chunk_inserts = Vec::new();
+        let mut rows = 0;
+
+        loop {
+            line.clear();
+            let eof = reader.read_line(&mut line).await? == 0;
+            let stmts = if eof {
+                mem::take(&mut splitter).finish().into_iter().collect()
+            } else {
+                splitter.push(&line)
+            };
+
+            for stmt in stmts {
+                if is_skipped_stmt(&stmt) {
+                    continue;
+                }
+                chunk.push_str(&stmt);
+                chunk.push('\n');
+                chunk_inserts.push(
+                    stmt.get(..6)
+                        .is_some_and(|s| s.eq_ignore_ascii_case("INSERT")),
+                );
+
+                if chunk_inserts.len() >= IMPORT_CHUNK_ROWS || chunk.len() >= IMPORT_CHUNK_BYTES {
+                    rows += self
+                        .import_batch(mem::take(&mut chunk), mem::take(&mut chunk_inserts))
+                        .await?;
+                }
+            }
+
+            if eof {
+                break;
+            }
+        }
+
+        if !chunk.is_empty() {
+            rows += self.import_batch(chunk, chunk_inserts).await?;
+        }
+        Ok(rows)
+    }
+
+    async fn import_csv<R>(&self, table: &str, reader: &mut R) -> Result<u64, Error>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let blob_columns = self.import_blob_columns(table).await?;
+        let mut csv = CsvReader::default();
+        let mut line = String::new();
+        let mut insert: Option<(String, usize)> = None;
+        let mut blobs = Vec::new();
+        let mut chunk = Vec::new();
+        let mut chunk_size = 0;
+        let mut rows = 0;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                break;
+            }
+            let Some(record) = csv.push_line(&line)? else {
+                continue;
+            };
+
+            let Some((sql, len)) = &insert else {
+                let columns = record
+                    .into_iter()
+                    .map(Option::unwrap_or_default)
+                    .collect::<Vec<_>>();
+                blobs = columns
+                    .iter()
+                    .map(|col| blob_columns.contains(col))
+                    .collect();
+                insert = Some((insert_sql(table, &columns), columns.len()));
+                continue;
+            };
+
+            if record.len() != *len {
+                return Err(Error::Config(
+                    format!(
+                        "Invalid CSV - record with {} fields instead of {}: {}",
+                        record.len(),
+                        len,
+                        line.trim_end()
+                    )
+                    .into(),
+                ));
+            }
+            let params = record
+                .into_iter()
+                .zip(&blobs)
+                .map(|(field, is_blob)| {
+                    let param = field.map(Param::Text).unwrap_or(Param::Null);
+                    if *is_blob {
+                        blob_param(param)
+                    } else {
+                        Ok(param)
+                    }
+                })
+                .collect::<Result<Params, Error>>()?;
+
+            chunk_size += sql.len() + params.iter().map(param_size).sum::<usize>();
+            chunk.push((sql.clone(), params));
+            if chunk.len() >= IMPORT_CHUNK_ROWS || chunk_size >= IMPORT_CHUNK_BYTES {
+                rows += self.import_txn(mem::take(&mut chunk)).await?;
+                chunk_size = 0;
+            }
+        }
+        csv.finish()?;
+
+        if !chunk.is_empty() {
+            rows += self.import_txn(chunk).await?;
+        }
+        Ok(rows)
+    }
+
+    async fn import_jsonl<R>(&self, table: &str, reader: &mut R) -> Result<u64, Error>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let blob_columns = self.import_blob_columns(table).await?;
+        let mut line = String::new();
+        let mut line_no = 0;
+        let mut columns = Vec::new();
+        let mut sql = String::new();
+        let mut chunk = Vec::new();
+        let mut chunk_size = 0;
+        let mut rows = 0;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                break;
+            }
+            line_no += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let object = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&line)
+                .map_err(|err| {
+                    Error::Config(format!("Invalid JSON in line {}: {}", line_no, err).into())
+                })?;
+
+            // the statement only needs to be rebuilt if the keys differ from the last line
+            if !object.keys().eq(columns.iter()) {
+                columns = object.keys().cloned().collect::<Vec<_>>();
+                sql = insert_sql(table, &columns);
+            }
+            let params = object
+                .into_iter()
+                .map(|(k, v)| {
+                    if blob_columns.contains(&k) {
+                        blob_param(json_param(v))
+                    } else {
+                        Ok(json_param(v))
+                    }
+                })
+                .collect::<Result<Params, Error>>()?;
+
+            chunk_size += sql.len() + params.iter().map(param_size).sum::<usize>();
+            chunk.push((sql.clone(), params));
+            if chunk.len() >= IMPORT_CHUNK_ROWS || chunk_size >= IMPORT_CHUNK_BYTES {
+                rows += self.import_txn(mem::take(&mut chunk)).await?;
+                chunk_size = 0;
+            }
+        }
+
+        if !chunk.is_empty() {
+            rows += self.import_txn(chunk).await?;
+        }
+        Ok(rows)
+    }
+
+    /// Returns the columns of `table` with the declared type `BLOB`, which contain hex-encoded
+    /// values in CSV and JSONL imports.
+    async fn import_blob_columns(&self, table: &str) -> Result<Vec<String>, Error> {
+        let columns = self
+            .query_raw(
+                "SELECT name FROM pragma_table_info($1) WHERE upper(type) = 'BLOB'",
+                params!(table),
+            )
+            .await?
+            .into_iter()
+            .map(|mut row| row.get::<String>("name"))
+            .collect();
+        Ok(columns)
+    }
+
+    /// SQLite keeps the changes of the last DML statement for DDL statements, which means only
+    /// the results of `INSERT`s can be counted.
+    async fn import_batch(&self, sql: String, inserts: Vec<bool>) -> Result<u64, Error> {
+        let mut rows = 0;
+        for (res, is_insert) in self.batch(sql).await?.into_iter().zip(inserts) {
+            let changes = res?;
+            if is_insert {
+                rows += changes as u64;
+            }
+        }
+        Ok(rows)
+    }
+
+    async fn import_txn(&self, queries: Vec<(String, Params)>) -> Result<u64, Error> {
+        let mut rows = 0;
+        for res in self.txn(queries).await? {
+            rows += res? as u64;
+        }
+        Ok(rows)
+    }
+}
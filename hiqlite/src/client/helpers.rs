@@ -1,5 +1,6 @@
 use crate::app_state::AppState;
 use crate::client::stream::ClientStreamReq;
+use crate::idempotency::Idempotency;
 use crate::{Client, Error, Node, NodeId};
 use openraft::RaftMetrics;
 use std::clone::Clone;
@@ -205,6 +206,14 @@ impl Client {
         None
     }
 
+    /// Creates the `Idempotency` for a write, which is proposed from this node as the leader.
+    #[inline]
+    pub(crate) fn idempotency(&self, state: &AppState) -> Option<Idempotency> {
+        self.idempotency_key
+            .clone()
+            .map(|key| Idempotency::new(key, state.idempotency_window))
+    }
+
     #[cfg(not(feature = "dashboard"))]
     #[inline(always)]
     pub(crate) fn new_request_id(&self) -> usize {
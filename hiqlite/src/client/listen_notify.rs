@@ -2,7 +2,7 @@ use crate::client::stream::{ClientKVPayload, ClientStreamReq};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::network::HEADER_NAME_SECRET;
 use crate::store::state_machine::memory::state_machine::CacheRequest;
-use crate::{Client, Error, NodeId};
+use crate::{Client, Error, NodeId, TraceContext};
 use chrono::Utc;
 use cryptr::utils::b64_decode;
 use eventsource_client::{Client as ClientES, SSE};
@@ -202,6 +202,8 @@ impl Client {
                 .tx_client_cache
                 .send_async(ClientStreamReq::Notify(ClientKVPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: None,
                     cache_req,
                     ack,
                 }))
@@ -0,0 +1,244 @@
+use crate::app_state::{AppState, RaftType};
+use crate::client::stream::{
+    ClientMembershipPayload, ClientStreamReq, ClientTransferLeadershipPayload,
+};
+use crate::network::api::ApiStreamResponsePayload;
+use crate::network::management::{self, MembershipChange};
+use crate::{Client, Error, Node, NodeId, TraceContext};
+use std::sync::Arc;
+use tokio::sync::{oneshot, RwLock};
+
+impl Client {
+    /// Adds a new voting member to the database and cache Rafts at runtime.
+    ///
+    /// The node is added as a learner first, which blocks until it has caught up with the
+    /// leader. Only after that, it will be promoted to a voter via joint consensus. The node must
+    /// be up and running already. Adding a node which is a voter already is a no-op, while an
+    /// existing learner will be promoted.
+    ///
+    /// The database Raft is changed first and the cache Raft afterward. These are 2 independent
+    /// changes, so if the cache Raft fails, the database Raft keeps its new membership and the
+    /// returned error says so. The call is safe to retry, because a Raft which has the node as a
+    /// voter already is skipped.
+    pub async fn add_node(&self, node: Node) -> Result<(), Error> {
+        self.membership_change(MembershipChange::Add(node)).await
+    }
+
+    /// Removes a node from the database and cache Rafts at runtime.
+    ///
+    /// This is what you want when you scale down, to not leave a dead voter behind, which would
+    /// count towards the quorum. Removing a node which is not a member is a no-op, while removing
+    /// the last voter will return an error.
+    ///
+    /// Like `add_node()`, this may be applied to the database Raft only, when the cache Raft
+    /// fails. A retry is safe and skips the Raft where the node is not a member anymore.
+    pub async fn remove_node(&self, id: NodeId) -> Result<(), Error> {
+        self.membership_change(MembershipChange::Remove(id)).await
+    }
+
+    /// Replaces the node `old` with the given `new` one for the database and cache Rafts.
+    ///
+    /// The new node will be added as a learner and catch up with the leader first. After that,
+    /// `old` is swapped for `new` in a single joint consensus membership change, so the amount
+    /// of voters never changes. If `old` is a learner, `new` will stay a learner as well.
+    ///
+    /// Like `add_node()`, this may be applied to the database Raft only, when the cache Raft
+    /// fails. Unlike the other changes, a retry is not a no-op in this case: the database Raft
+    /// will reject it with an `Error::BadRequest`, because `old` is not a member anymore.
+    pub async fn replace_node(&self, old: NodeId, new: Node) -> Result<(), Error> {
+        self.membership_change(MembershipChange::Replace(old, new))
+            .await
+    }
+
+    /// Transfers the leadership of the database and cache Rafts to another voter.
+    ///
+    /// If `to` is `None`, the voter with the most replicated logs will be chosen. The leader
+    /// waits until the target has caught up and stops its heartbeats. As soon as the leader lease
+    /// on the followers has run out, it makes the target start an election and returns when the
+    /// target has been confirmed as the new leader. This avoids the write stall of a normal election after
+    /// a leader went away, for instance during rolling releases. `Client::shutdown()` will
+    /// trigger a transfer automatically if this node is the leader.
+    pub async fn transfer_leadership(&self, to: Option<NodeId>) -> Result<(), Error> {
+        #[cfg(feature = "sqlite")]
+        self.transfer_leadership_raft(
+            RaftType::Sqlite,
+            to,
+            &self.inner.leader_db,
+            &self.inner.tx_client_db,
+        )
+        .await?;
+
+        #[cfg(feature = "cache")]
+        self.transfer_leadership_raft(
+            RaftType::Cache,
+            to,
+            &self.inner.leader_cache,
+            &self.inner.tx_client_cache,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn transfer_leadership_raft(
+        &self,
+        raft_type: RaftType,
+        to: Option<NodeId>,
+        leader: &Arc<RwLock<(NodeId, String)>>,
+        tx: &flume::Sender<ClientStreamReq>,
+    ) -> Result<(), Error> {
+        let res = match self
+            .transfer_leadership_execute(&raft_type, to, leader, tx)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self.was_leader_update_error(&err, leader, tx).await {
+                    self.transfer_leadership_execute(&raft_type, to, leader, tx)
+                        .await
+                } else {
+                    Err(err)
+                }
+            }
+        };
+
+        // The next request will receive a `ForwardToLeader` and update the leader on its own.
+        res.map(|_| ())
+    }
+
+    async fn transfer_leadership_execute(
+        &self,
+        raft_type: &RaftType,
+        to: Option<NodeId>,
+        leader: &Arc<RwLock<(NodeId, String)>>,
+        tx: &flume::Sender<ClientStreamReq>,
+    ) -> Result<NodeId, Error> {
+        if let Some(state) = self.is_leader_with_state(leader).await {
+            management::transfer_leadership(state, raft_type, to).await
+        } else {
+            let (ack, rx) = oneshot::channel();
+            tx.send_async(ClientStreamReq::TransferLeadership(
+                ClientTransferLeadershipPayload {
+                    request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    to,
+                    ack,
+                },
+            ))
+            .await
+            .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::TransferLeadership(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    async fn membership_change(&self, change: MembershipChange) -> Result<(), Error> {
+        #[cfg(feature = "sqlite")]
+        self.membership_change_raft(
+            RaftType::Sqlite,
+            change.clone(),
+            &self.inner.leader_db,
+            &self.inner.tx_client_db,
+        )
+        .await?;
+
+        #[cfg(feature = "cache")]
+        {
+            let res = self
+                .membership_change_raft(
+                    RaftType::Cache,
+                    change,
+                    &self.inner.leader_cache,
+                    &self.inner.tx_client_cache,
+                )
+                .await;
+
+            // the Rafts have different memberships now, which the caller must know about
+            #[cfg(feature = "sqlite")]
+            let res = res.map_err(|err| {
+                Error::Error(
+                    format!(
+                        "The database Raft membership has been changed, but the cache Raft \
+                        failed: {}",
+                        err
+                    )
+                    .into(),
+                )
+            });
+
+            res?;
+        }
+
+        Ok(())
+    }
+
+    async fn membership_change_raft(
+        &self,
+        raft_type: RaftType,
+        change: MembershipChange,
+        leader: &Arc<RwLock<(NodeId, String)>>,
+        tx: &flume::Sender<ClientStreamReq>,
+    ) -> Result<(), Error> {
+        match self
+            .membership_change_execute(&raft_type, change.clone(), leader, tx)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self.was_leader_update_error(&err, leader, tx).await {
+                    self.membership_change_execute(&raft_type, change, leader, tx)
+                        .await
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    async fn membership_change_execute(
+        &self,
+        raft_type: &RaftType,
+        change: MembershipChange,
+        leader: &Arc<RwLock<(NodeId, String)>>,
+        tx: &flume::Sender<ClientStreamReq>,
+    ) -> Result<(), Error> {
+        if let Some(state) = self.is_leader_with_state(leader).await {
+            management::apply_membership_change(state, raft_type, change).await
+        } else {
+            let (ack, rx) = oneshot::channel();
+            tx.send_async(ClientStreamReq::Membership(ClientMembershipPayload {
+                request_id: self.new_request_id(),
+                trace: TraceContext::current(),
+                change,
+                ack,
+            }))
+            .await
+            .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::Membership(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[inline(always)]
+    async fn is_leader_with_state(
+        &self,
+        leader: &Arc<RwLock<(NodeId, String)>>,
+    ) -> Option<&Arc<AppState>> {
+        if let Some(state) = &self.inner.state {
+            if state.id == leader.read().await.0 {
+                return Some(state);
+            }
+        }
+        None
+    }
+}
@@ -1,21 +1,29 @@
 use crate::app_state::AppState;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
+use crate::app_state::RaftType;
 use crate::client::stream::ClientStreamReq;
 use crate::network::HEADER_NAME_SECRET;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
+use crate::{helpers, network::management};
 use crate::{Client, Error};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::watch;
 use tokio::time;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
+#[cfg(feature = "sqlite")]
+use crate::query::stats::StatementStats;
 #[cfg(feature = "sqlite")]
 use crate::store::{logs::rocksdb::ActionWrite, state_machine::sqlite::writer::WriterRequest};
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 use crate::{Node, NodeId};
-#[cfg(any(feature = "sqlite", feature = "cache"))]
-use openraft::RaftMetrics;
 use openraft::ServerState;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
+use openraft::{LogId, RaftMetrics};
+#[cfg(any(feature = "sqlite", feature = "cache"))]
 use std::clone::Clone;
 
 impl Client {
@@ -29,7 +37,38 @@ impl Client {
             let url = self
                 .build_addr("/cluster/metrics/sqlite", &self.inner.leader_db)
                 .await;
-            self.get_metrics_remote(url).await
+            self.get_remote(url).await
+        }
+    }
+
+    /// Get the per-statement latency statistics for the database, sorted by total execution
+    /// time descending.
+    ///
+    /// Stats are collected on each node separately. A local client returns the stats for its own
+    /// node, while a remote client returns the stats of the current leader.
+    #[cfg(feature = "sqlite")]
+    pub async fn query_stats(&self) -> Result<Vec<StatementStats>, Error> {
+        if let Some(state) = &self.inner.state {
+            Ok(state.raft_db.query_stats.get())
+        } else {
+            let url = self
+                .build_addr("/cluster/query_stats", &self.inner.leader_db)
+                .await;
+            self.get_remote(url).await
+        }
+    }
+
+    /// Resets the statement latency statistics for this node.
+    /// Works on local clients only.
+    #[cfg(feature = "sqlite")]
+    pub fn query_stats_reset(&self) -> Result<(), Error> {
+        if let Some(state) = &self.inner.state {
+            state.raft_db.query_stats.reset();
+            Ok(())
+        } else {
+            Err(Error::Config(
+                "`query_stats_reset()` only works for local clients".into(),
+            ))
         }
     }
 
@@ -43,16 +82,62 @@ impl Client {
             let url = self
                 .build_addr("/cluster/metrics/cache", &self.inner.leader_cache)
                 .await;
-            self.get_metrics_remote(url).await
+            self.get_remote(url).await
+        }
+    }
+
+    /// Builds a new snapshot for the database Raft and waits until it has been finished.
+    /// Returns the last log id included in the snapshot, or `None` if nothing has been applied
+    /// yet.
+    ///
+    /// Snapshots are built on each node separately. A local client builds it on its own node,
+    /// while a remote client builds it on the current leader.
+    #[cfg(feature = "sqlite")]
+    pub async fn trigger_snapshot_db(&self) -> Result<Option<LogId<NodeId>>, Error> {
+        if let Some(state) = &self.inner.state {
+            helpers::trigger_snapshot(state, &RaftType::Sqlite).await
+        } else {
+            let url = self
+                .build_addr("/cluster/snapshot/sqlite", &self.inner.leader_db)
+                .await;
+            self.request_remote(Method::POST, url).await
         }
     }
 
+    /// Builds a new snapshot for the cache Raft and waits until it has been finished.
+    /// Returns the last log id included in the snapshot, or `None` if nothing has been applied
+    /// yet.
+    ///
+    /// Snapshots are built on each node separately. A local client builds it on its own node,
+    /// while a remote client builds it on the current leader.
+    #[cfg(feature = "cache")]
+    pub async fn trigger_snapshot_cache(&self) -> Result<Option<LogId<NodeId>>, Error> {
+        if let Some(state) = &self.inner.state {
+            helpers::trigger_snapshot(state, &RaftType::Cache).await
+        } else {
+            let url = self
+                .build_addr("/cluster/snapshot/cache", &self.inner.leader_cache)
+                .await;
+            self.request_remote(Method::POST, url).await
+        }
+    }
+
+    async fn get_remote<T>(&self, url: String) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.request_remote(Method::GET, url).await
+    }
+
     // This is separated from the `self.send_with_retry_db()` to avoid recursion on leader unreachable
-    async fn get_metrics_remote(&self, url: String) -> Result<RaftMetrics<NodeId, Node>, Error> {
+    async fn request_remote<T>(&self, method: Method, url: String) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
         // This should never be called if we have a local client with its own replicated data
         debug_assert!(
             self.inner.state.is_none(),
-            "get_metrics_remote should never be called with local state"
+            "request_remote should never be called with local state"
         );
         debug_assert!(
             self.inner.api_secret.is_some(),
@@ -64,7 +149,7 @@ impl Client {
             .client
             .as_ref()
             .unwrap()
-            .get(url)
+            .request(method, url)
             .header(HEADER_NAME_SECRET, self.inner.api_secret.as_ref().unwrap())
             .send()
             .await?;
@@ -164,11 +249,13 @@ impl Client {
     /// Perform a graceful shutdown for this Raft node.
     /// Works on local clients only and can't shut down remote nodes.
     ///
-    /// The shutdown adds a 10 delay on purpose for smoothing out Kubernetes rolling releases and
-    /// make the whole process more graceful, because a whole new leader election might be necessary.
+    /// If this node is the current leader, the leadership will be transferred to another voter
+    /// first, and the shutdown waits until the new leader has been confirmed. This makes
+    /// Kubernetes rolling releases smooth without a write stall caused by a new leader election.
     ///
-    /// In future versions, there will be the possibility to trigger a graceful leader election
-    /// upfront, but this has not been stabilized in this version.
+    /// If no transfer could be confirmed, or if this node is not the leader, the shutdown adds a
+    /// 10 s delay on purpose for smoothing out rolling releases, because a whole new leader
+    /// election might be necessary.
     pub async fn shutdown(&self) -> Result<(), Error> {
         if let Some(state) = &self.inner.state {
             Self::shutdown_execute(
@@ -194,6 +281,19 @@ impl Client {
         #[cfg(feature = "sqlite")] tx_client_db: &flume::Sender<ClientStreamReq>,
         tx_shutdown: &Option<watch::Sender<bool>>,
     ) -> Result<(), Error> {
+        // The delay can only be skipped if this node has handed off all of its leaderships.
+        #[allow(unused_mut)]
+        let mut transferred = true;
+        #[cfg(feature = "sqlite")]
+        {
+            transferred &=
+                Self::transfer_leadership_before_shutdown(state, &RaftType::Sqlite).await;
+        }
+        #[cfg(feature = "cache")]
+        {
+            transferred &= Self::transfer_leadership_before_shutdown(state, &RaftType::Cache).await;
+        }
+
         #[cfg(feature = "cache")]
         {
             info!("Shutting down raft cache layer");
@@ -203,6 +303,8 @@ impl Client {
         #[cfg(feature = "sqlite")]
         {
             info!("Shutting down raft sqlite layer");
+            #[cfg(feature = "backup")]
+            state.raft_db.online_restore.shutdown();
             match state.raft_db.raft.shutdown().await {
                 Ok(_) => {
                     let (tx, rx) = tokio::sync::oneshot::channel();
@@ -243,20 +345,52 @@ impl Client {
             let _ = tx.send(true);
         }
 
-        // We need to do a short sleep only to avoid race conditions during rolling releases.
-        // This also helps to make re-joins after a restart smoother.
-        //
-        // TODO for some very weird reason, the process sometimes gets stuck during
-        // this sleep await. I only saw this behavior in integration tests though, where alle nodes
-        // are started from the same `tokio::test` task.
-        //
-        // Note: The issue is "something blocking" in `openraft` but only in some conditions that
-        // don't make sense to me yet - needs further investigation until this sleep can be removed
-        // safely.
-        info!("Shutting down in 10 s ...");
-        time::sleep(Duration::from_secs(10)).await;
+        if !transferred {
+            // We need to do a short sleep only to avoid race conditions during rolling releases.
+            // Without a confirmed leadership transfer, a whole new leader election might be
+            // necessary, and this also helps to make re-joins after a restart smoother.
+            info!("Shutting down in 10 s ...");
+            time::sleep(Duration::from_secs(10)).await;
+        }
 
         info!("Shutdown complete");
         Ok(())
     }
+
+    /// Transfers the leadership to another voter, if this node is the current leader.
+    /// Returns `true` only if a new leader has been confirmed.
+    #[cfg(any(feature = "sqlite", feature = "cache"))]
+    async fn transfer_leadership_before_shutdown(
+        state: &Arc<AppState>,
+        raft_type: &RaftType,
+    ) -> bool {
+        if helpers::get_raft_leader(state, raft_type).await != Some(state.id) {
+            return false;
+        }
+
+        let metrics = helpers::get_raft_metrics(state, raft_type).await;
+        if metrics
+            .membership_config
+            .voter_ids()
+            .all(|id| id == state.id)
+        {
+            return false;
+        }
+
+        info!(
+            "This node is the {} leader - transferring leadership before shutdown",
+            raft_type.as_str()
+        );
+        match management::transfer_leadership(state, raft_type, None).await {
+            Ok(_) => true,
+            Err(err) => {
+                warn!(
+                    "Error transferring the {} leadership before shutdown: {}",
+                    raft_type.as_str(),
+                    err
+                );
+                false
+            }
+        }
+    }
 }
@@ -2,9 +2,10 @@ use crate::client::stream::{ClientMigratePayload, ClientStreamReq};
 use crate::migration::{Migration, Migrations};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::store::state_machine::sqlite::state_machine::QueryWrite;
-use crate::{params, AppliedMigration, Client, Error, Response};
+use crate::{params, trace, AppliedMigration, Client, Error, Response, TraceContext};
 use rust_embed::RustEmbed;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 use tracing::{info, warn};
 
 impl Client {
@@ -89,7 +90,8 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::Migration(migrations))
+                .client_write(QueryWrite::Migration(migrations).traced())
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -102,6 +104,7 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::Migrate(ClientMigratePayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
                     migrations,
                     ack,
                 }))
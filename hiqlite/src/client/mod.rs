@@ -1,5 +1,6 @@
 use crate::app_state::AppState;
 use crate::NodeId;
+use std::borrow::Cow;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use stream::ClientStreamReq;
@@ -16,9 +17,12 @@ mod create;
 pub mod dlock;
 #[cfg(feature = "sqlite")]
 mod execute;
+#[cfg(feature = "sqlite")]
+mod export;
 mod helpers;
 #[cfg(feature = "listen_notify")]
 mod listen_notify;
+mod membership;
 mod mgmt;
 #[cfg(feature = "sqlite")]
 mod migrate;
@@ -38,6 +42,40 @@ mod transaction;
 #[derive(Clone)]
 pub struct Client {
     pub(crate) inner: Arc<DbClient>,
+    pub(crate) idempotency_key: Option<Cow<'static, str>>,
+}
+
+impl Client {
+    /// Returns a copy of this client, which attaches the given idempotency key to each write.
+    ///
+    /// If a connection breaks after the leader has executed a write, you cannot know if it
+    /// has been applied or not. With an idempotency key, you can simply retry the write. If the
+    /// original one has been applied successfully, you will get its result back, and it will
+    /// not be applied a 2nd time. The results are remembered in the state machines for
+    /// `NodeConfig::idempotency_window_secs`.
+    ///
+    /// Use a new key for each logical write, because all writes with the same key will return
+    /// the result of the first successful one. This works for `execute*()`, `txn*()`, `batch()`
+    /// and the cache `put*()`, `delete()` and `clear_cache*()`. A `batch()` is not applied
+    /// inside a single transaction, which means its result will only be remembered after all
+    /// statements have been executed.
+    ///
+    /// ```rust, notest
+    /// let key = format!("order-{}", order_id);
+    /// let client = client.with_idempotency_key(key);
+    ///
+    /// let res = match client.execute(sql, params.clone()).await {
+    ///     Ok(res) => res,
+    ///     // retrying is safe, the insert will never be applied twice
+    ///     Err(_) => client.execute(sql, params).await?,
+    /// };
+    /// ```
+    pub fn with_idempotency_key<K: Into<Cow<'static, str>>>(&self, key: K) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            idempotency_key: Some(key.into()),
+        }
+    }
 }
 
 pub(crate) struct DbClient {
@@ -2,7 +2,7 @@ use crate::client::stream::{ClientQueryPayload, ClientStreamReq};
 use crate::network::api::ApiStreamResponsePayload;
 use crate::query::rows::RowOwned;
 use crate::store::state_machine::sqlite::state_machine::Query;
-use crate::{query, Client, Error, Params, Row};
+use crate::{query, Client, Error, Params, Row, TraceContext};
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
 use tokio::sync::oneshot;
@@ -231,6 +231,7 @@ impl Client {
         if let Some(state) = &self.inner.state {
             let rows = query::query_owned_local(
                 state.raft_db.log_statements,
+                state.raft_db.query_stats.clone(),
                 state.raft_db.read_pool.clone(),
                 stmt,
                 params,
@@ -329,12 +330,14 @@ impl Client {
         let payload = if consistent {
             ClientStreamReq::QueryConsistent(ClientQueryPayload {
                 request_id: self.new_request_id(),
+                trace: TraceContext::current(),
                 ack,
                 query,
             })
         } else {
             ClientStreamReq::Query(ClientQueryPayload {
                 request_id: self.new_request_id(),
+                trace: TraceContext::current(),
                 ack,
                 query,
             })
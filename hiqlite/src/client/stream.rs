@@ -1,6 +1,6 @@
 use crate::network::api::{ApiStreamResponse, ApiStreamResponsePayload};
 use crate::network::handshake::HandshakeSecret;
-use crate::{tls, Client, Error, Node, NodeId};
+use crate::{tls, Client, Error, Node, NodeId, TraceContext};
 use axum::http::header::{CONNECTION, UPGRADE};
 use axum::http::Request;
 use bytes::Bytes;
@@ -8,6 +8,7 @@ use fastwebsockets::{FragmentCollectorRead, Frame, OpCode, Payload, WebSocket, W
 use http_body_util::Empty;
 use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 use std::ops::Deref;
@@ -24,11 +25,16 @@ use tracing::{debug, error, info, warn};
 #[cfg(feature = "cache")]
 use crate::store::state_machine::memory::state_machine::CacheRequest;
 
+use crate::network::management::MembershipChange;
+
 use crate::app_state::RaftType;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 use crate::network::api::{ApiStreamRequest, ApiStreamRequestPayload};
 #[cfg(feature = "sqlite")]
-use crate::{migration::Migration, store::state_machine::sqlite::state_machine::Query};
+use crate::{
+    migration::Migration,
+    store::state_machine::sqlite::state_machine::{Query, TxnStep},
+};
 
 #[derive(Debug)]
 pub(crate) enum ClientStreamReq {
@@ -40,6 +46,8 @@ pub(crate) enum ClientStreamReq {
     #[cfg(feature = "sqlite")]
     Transaction(ClientTransactionPayload),
     #[cfg(feature = "sqlite")]
+    TransactionGuarded(ClientTransactionGuardedPayload),
+    #[cfg(feature = "sqlite")]
     Query(ClientQueryPayload),
     #[cfg(feature = "sqlite")]
     QueryConsistent(ClientQueryPayload),
@@ -50,6 +58,15 @@ pub(crate) enum ClientStreamReq {
 
     #[cfg(feature = "backup")]
     Backup(ClientBackupPayload),
+    #[cfg(feature = "backup")]
+    Restore(ClientRestorePayload),
+    #[cfg(all(feature = "backup", feature = "cache"))]
+    RestoreCache(ClientRestorePayload),
+    #[cfg(feature = "backup")]
+    ListBackups(ClientListBackupsPayload),
+
+    Membership(ClientMembershipPayload),
+    TransferLeadership(ClientTransferLeadershipPayload),
 
     #[cfg(feature = "cache")]
     KV(ClientKVPayload),
@@ -76,6 +93,8 @@ pub(crate) enum ClientStreamReq {
 #[derive(Debug)]
 pub struct ClientExecutePayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<Cow<'static, str>>,
     pub sql: Query,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
@@ -84,14 +103,27 @@ pub struct ClientExecutePayload {
 #[derive(Debug)]
 pub struct ClientTransactionPayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<Cow<'static, str>>,
     pub queries: Vec<Query>,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
 
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct ClientTransactionGuardedPayload {
+    pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<Cow<'static, str>>,
+    pub steps: Vec<TxnStep>,
+    pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
+}
+
 #[cfg(feature = "sqlite")]
 #[derive(Debug)]
 pub struct ClientQueryPayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
     pub query: Query,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
@@ -100,7 +132,9 @@ pub struct ClientQueryPayload {
 #[derive(Debug)]
 pub struct ClientBatchPayload {
     pub request_id: usize,
-    pub sql: std::borrow::Cow<'static, str>,
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<Cow<'static, str>>,
+    pub sql: Cow<'static, str>,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
 
@@ -108,6 +142,7 @@ pub struct ClientBatchPayload {
 #[derive(Debug)]
 pub struct ClientMigratePayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
     pub migrations: Vec<Migration>,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
@@ -116,14 +151,50 @@ pub struct ClientMigratePayload {
 #[derive(Debug)]
 pub struct ClientBackupPayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
     pub node_id: NodeId,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
 
+#[cfg(feature = "backup")]
+#[derive(Debug)]
+pub struct ClientRestorePayload {
+    pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub src: crate::backup::BackupSource,
+    pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
+}
+
+#[cfg(feature = "backup")]
+#[derive(Debug)]
+pub struct ClientListBackupsPayload {
+    pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
+}
+
+#[derive(Debug)]
+pub struct ClientMembershipPayload {
+    pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub change: MembershipChange,
+    pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
+}
+
+#[derive(Debug)]
+pub struct ClientTransferLeadershipPayload {
+    pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub to: Option<NodeId>,
+    pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
+}
+
 #[cfg(feature = "cache")]
 #[derive(Debug)]
 pub struct ClientKVPayload {
     pub request_id: usize,
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<Cow<'static, str>>,
     pub cache_req: CacheRequest,
     pub ack: oneshot::Sender<Result<ApiStreamResponsePayload, Error>>,
 }
@@ -239,11 +310,15 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::Execute(ClientExecutePayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     sql,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::Execute(sql),
                     };
                     Some((
@@ -256,11 +331,15 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::ExecuteReturning(ClientExecutePayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     sql,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::ExecuteReturning(sql),
                     };
                     Some((
@@ -273,11 +352,15 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::Transaction(ClientTransactionPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     queries,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::Transaction(queries),
                     };
                     Some((
@@ -287,14 +370,38 @@ async fn client_stream(
                     ))
                 }
 
+                #[cfg(feature = "sqlite")]
+                ClientStreamReq::TransactionGuarded(ClientTransactionGuardedPayload {
+                    request_id,
+                    trace,
+                    idempotency_key,
+                    steps,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key,
+                        payload: ApiStreamRequestPayload::TransactionGuarded(steps),
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::Query(ClientQueryPayload {
                     request_id,
+                    trace,
                     query,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key: None,
                         payload: ApiStreamRequestPayload::Query(query),
                     };
                     Some((
@@ -307,11 +414,14 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::QueryConsistent(ClientQueryPayload {
                     request_id,
+                    trace,
                     query,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key: None,
                         payload: ApiStreamRequestPayload::QueryConsistent(query),
                     };
                     Some((
@@ -324,11 +434,15 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::Batch(ClientBatchPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     sql,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::Batch(sql),
                     };
                     Some((
@@ -341,11 +455,14 @@ async fn client_stream(
                 #[cfg(feature = "sqlite")]
                 ClientStreamReq::Migrate(ClientMigratePayload {
                     request_id,
+                    trace,
                     migrations,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key: None,
                         payload: ApiStreamRequestPayload::Migrate(migrations),
                     };
                     Some((
@@ -358,11 +475,14 @@ async fn client_stream(
                 #[cfg(feature = "backup")]
                 ClientStreamReq::Backup(ClientBackupPayload {
                     request_id,
+                    trace,
                     node_id,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key: None,
                         payload: ApiStreamRequestPayload::Backup(node_id),
                     };
                     Some((
@@ -372,14 +492,115 @@ async fn client_stream(
                     ))
                 }
 
+                #[cfg(feature = "backup")]
+                ClientStreamReq::Restore(ClientRestorePayload {
+                    request_id,
+                    trace,
+                    src,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key: None,
+                        payload: ApiStreamRequestPayload::Restore(src),
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
+                #[cfg(all(feature = "backup", feature = "cache"))]
+                ClientStreamReq::RestoreCache(ClientRestorePayload {
+                    request_id,
+                    trace,
+                    src,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key: None,
+                        payload: ApiStreamRequestPayload::RestoreCache(src),
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
+                #[cfg(feature = "backup")]
+                ClientStreamReq::ListBackups(ClientListBackupsPayload {
+                    request_id,
+                    trace,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key: None,
+                        payload: ApiStreamRequestPayload::ListBackups,
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
+                ClientStreamReq::Membership(ClientMembershipPayload {
+                    request_id,
+                    trace,
+                    change,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key: None,
+                        payload: ApiStreamRequestPayload::Membership(change),
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
+                ClientStreamReq::TransferLeadership(ClientTransferLeadershipPayload {
+                    request_id,
+                    trace,
+                    to,
+                    ack,
+                }) => {
+                    let req = ApiStreamRequest {
+                        request_id,
+                        trace,
+                        idempotency_key: None,
+                        payload: ApiStreamRequestPayload::TransferLeadership(to),
+                    };
+                    Some((
+                        WritePayload::Payload(bincode::serialize(&req).unwrap()),
+                        request_id,
+                        ack,
+                    ))
+                }
+
                 #[cfg(feature = "cache")]
                 ClientStreamReq::KV(ClientKVPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     cache_req,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::KV(cache_req),
                     };
                     Some((
@@ -392,11 +613,15 @@ async fn client_stream(
                 #[cfg(feature = "cache")]
                 ClientStreamReq::KVGet(ClientKVPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     cache_req,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::KVGet(cache_req),
                     };
                     Some((
@@ -409,11 +634,15 @@ async fn client_stream(
                 #[cfg(feature = "dlock")]
                 ClientStreamReq::LockAwait(ClientKVPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     cache_req,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::LockAwait(cache_req),
                     };
                     Some((
@@ -426,11 +655,15 @@ async fn client_stream(
                 #[cfg(feature = "listen_notify")]
                 ClientStreamReq::Notify(ClientKVPayload {
                     request_id,
+                    trace,
+                    idempotency_key,
                     cache_req,
                     ack,
                 }) => {
                     let req = ApiStreamRequest {
                         request_id,
+                        trace,
+                        idempotency_key,
                         payload: ApiStreamRequestPayload::Notify(cache_req),
                     };
                     Some((
@@ -524,6 +757,12 @@ async fn client_stream(
                     )
                 }
                 #[cfg(feature = "sqlite")]
+                ClientStreamReq::TransactionGuarded(_) => {
+                    unreachable!(
+                        "we should never receive ClientStreamReq::TransactionGuarded from WS reader"
+                    )
+                }
+                #[cfg(feature = "sqlite")]
                 ClientStreamReq::Query(_) => {
                     unreachable!(
                         "we should never receive ClientStreamReq::QueryConsistent from WS reader"
@@ -547,6 +786,32 @@ async fn client_stream(
                 ClientStreamReq::Backup(_) => {
                     unreachable!("we should never receive ClientStreamReq::Backup from WS reader")
                 }
+                #[cfg(feature = "backup")]
+                ClientStreamReq::Restore(_) => {
+                    unreachable!("we should never receive ClientStreamReq::Restore from WS reader")
+                }
+                #[cfg(all(feature = "backup", feature = "cache"))]
+                ClientStreamReq::RestoreCache(_) => {
+                    unreachable!(
+                        "we should never receive ClientStreamReq::RestoreCache from WS reader"
+                    )
+                }
+                #[cfg(feature = "backup")]
+                ClientStreamReq::ListBackups(_) => {
+                    unreachable!(
+                        "we should never receive ClientStreamReq::ListBackups from WS reader"
+                    )
+                }
+                ClientStreamReq::Membership(_) => {
+                    unreachable!(
+                        "we should never receive ClientStreamReq::Membership from WS reader"
+                    )
+                }
+                ClientStreamReq::TransferLeadership(_) => {
+                    unreachable!(
+                        "we should never receive ClientStreamReq::TransferLeadership from WS reader"
+                    )
+                }
                 #[cfg(feature = "cache")]
                 ClientStreamReq::KV(_) => {
                     unreachable!("we should never receive ClientStreamReq::KV from WS reader")
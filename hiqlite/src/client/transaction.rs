@@ -1,9 +1,14 @@
-use crate::client::stream::{ClientStreamReq, ClientTransactionPayload};
+use crate::client::stream::{
+    ClientStreamReq, ClientTransactionGuardedPayload, ClientTransactionPayload,
+};
 use crate::network::api::ApiStreamResponsePayload;
-use crate::store::state_machine::sqlite::state_machine::{Query, QueryWrite};
-use crate::{Client, Error, Params, Response};
+use crate::store::state_machine::sqlite::state_machine::{
+    Query, QueryWrite, TxnStep, TxnStepOutput, TxnStepResult,
+};
+use crate::{trace, Client, Error, Params, Response, TraceContext};
 use std::borrow::Cow;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 
 impl Client {
     /// Takes multiple queries and executes all of them in a single transaction.
@@ -68,7 +73,12 @@ impl Client {
             let res = state
                 .raft_db
                 .raft
-                .client_write(QueryWrite::Transaction(queries))
+                .client_write(
+                    QueryWrite::Transaction(queries)
+                        .idempotent(self.idempotency(state))
+                        .traced(),
+                )
+                .instrument(trace::commit_span())
                 .await?;
             let resp: Response = res.data;
             match resp {
@@ -81,6 +91,8 @@ impl Client {
                 .tx_client_db
                 .send_async(ClientStreamReq::Transaction(ClientTransactionPayload {
                     request_id: self.new_request_id(),
+                    trace: TraceContext::current(),
+                    idempotency_key: self.idempotency_key.clone(),
                     queries,
                     ack,
                 }))
@@ -95,4 +107,142 @@ impl Client {
             }
         }
     }
+
+    /// Executes multiple steps in a single transaction, which may contain preconditions.
+    ///
+    /// Each `TxnStep` is evaluated inside the same transaction on the leader. If any query
+    /// returns an error or a precondition is not met, the whole transaction will be rolled back.
+    /// A failed precondition returns an `Error::GuardFailed` with the index of the failing step,
+    /// which makes this a good fit for optimistic concurrency control.
+    ///
+    /// On success, you get the affected rows for each step in the same order. Assertion steps
+    /// always return `0`. If you need the rows from a `TxnStep::ExecuteReturning`, use
+    /// `txn_returning()` instead.
+    ///
+    /// ```rust, notest
+    /// let res = client
+    ///     .txn_guarded([
+    ///         TxnStep::assert_exists(
+    ///             "SELECT 1 FROM accounts WHERE id = $1 AND balance >= $2",
+    ///             params!(1, 100),
+    ///         ),
+    ///         TxnStep::execute_expect(
+    ///             "UPDATE accounts SET balance = balance - $1 WHERE id = $2",
+    ///             params!(100, 1),
+    ///             1,
+    ///         ),
+    ///         TxnStep::execute(
+    ///             "UPDATE accounts SET balance = balance + $1 WHERE id = $2",
+    ///             params!(100, 2),
+    ///         ),
+    ///     ])
+    ///     .await;
+    ///
+    /// match res {
+    ///     Ok(rows_affected) => assert_eq!(rows_affected, vec![0, 1, 1]),
+    ///     Err(Error::GuardFailed { step, reason }) => {
+    ///         eprintln!("insufficient balance - step {step}: {reason}");
+    ///     }
+    ///     Err(err) => return Err(err),
+    /// }
+    /// ```
+    pub async fn txn_guarded<S>(&self, steps: S) -> Result<Vec<usize>, Error>
+    where
+        S: IntoIterator<Item = TxnStep>,
+    {
+        let res = self.txn_steps(steps.into_iter().collect()).await?;
+        Ok(res.into_iter().map(|out| out.rows_affected).collect())
+    }
+
+    /// Executes multiple steps in a single transaction and returns the result for each of them,
+    /// including the rows from `RETURNING` clauses.
+    ///
+    /// This works exactly like `txn_guarded()`, but each `TxnStep::execute_returning()` gives you
+    /// its rows back. This makes it possible to insert a parent row and use its generated id
+    /// in the same atomic call. Inside the transaction, you can access the id of the last
+    /// inserted row via `last_insert_rowid()`.
+    ///
+    /// ```rust, notest
+    /// let res = client
+    ///     .txn_returning([
+    ///         TxnStep::execute_returning(
+    ///             "INSERT INTO parent (name) VALUES ($1) RETURNING id",
+    ///             params!("parent"),
+    ///         ),
+    ///         TxnStep::execute(
+    ///             "INSERT INTO child (parent_id, name) VALUES (last_insert_rowid(), $1)",
+    ///             params!("child"),
+    ///         ),
+    ///     ])
+    ///     .await?;
+    ///
+    /// let mut row = res.into_iter().next().unwrap().rows.swap_remove(0);
+    /// let parent_id: i64 = row.get("id");
+    /// ```
+    pub async fn txn_returning<S>(&self, steps: S) -> Result<Vec<TxnStepResult>, Error>
+    where
+        S: IntoIterator<Item = TxnStep>,
+    {
+        let res = self.txn_steps(steps.into_iter().collect()).await?;
+        Ok(res.into_iter().map(TxnStepResult::from).collect())
+    }
+
+    async fn txn_steps(&self, steps: Vec<TxnStep>) -> Result<Vec<TxnStepOutput>, Error> {
+        match self.txn_steps_execute(steps.clone()).await {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                if self
+                    .was_leader_update_error(&err, &self.inner.leader_db, &self.inner.tx_client_db)
+                    .await
+                {
+                    self.txn_steps_execute(steps).await
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    async fn txn_steps_execute(&self, steps: Vec<TxnStep>) -> Result<Vec<TxnStepOutput>, Error> {
+        if let Some(state) = self.is_leader_db_with_state().await {
+            let res = state
+                .raft_db
+                .raft
+                .client_write(
+                    QueryWrite::TransactionGuarded(steps)
+                        .idempotent(self.idempotency(state))
+                        .traced(),
+                )
+                .instrument(trace::commit_span())
+                .await?;
+            let resp: Response = res.data;
+            match resp {
+                Response::TransactionGuarded(res) => res,
+                _ => unreachable!(),
+            }
+        } else {
+            let (ack, rx) = oneshot::channel();
+            self.inner
+                .tx_client_db
+                .send_async(ClientStreamReq::TransactionGuarded(
+                    ClientTransactionGuardedPayload {
+                        request_id: self.new_request_id(),
+                        trace: TraceContext::current(),
+                        idempotency_key: self.idempotency_key.clone(),
+                        steps,
+                        ack,
+                    },
+                ))
+                .await
+                .expect("Client Stream Manager to always be running");
+            let res = rx
+                .await
+                .expect("To always receive an answer from Client Stream Manager")?;
+            match res {
+                ApiStreamResponsePayload::TransactionGuarded(res) => res,
+                _ => unreachable!(),
+            }
+        }
+    }
 }
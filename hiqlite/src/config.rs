@@ -1,8 +1,12 @@
 use crate::tls::ServerTlsConfig;
-use crate::{Error, Node, NodeId};
+use crate::{config_file, Error, Node, NodeId};
 use openraft::SnapshotPolicy;
+use serde::{Deserialize, Serialize};
+use std::any::type_name;
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::str::FromStr;
 use tracing::debug;
 
 pub use openraft::Config as RaftConfig;
@@ -20,6 +24,59 @@ pub enum EncKeysFrom {
     File(String),
 }
 
+/// The role of this node inside the cluster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeRole {
+    /// A full member which votes in elections and may become the leader.
+    #[default]
+    Voter,
+    /// A permanent learner (read replica). It replicates all data and serves local reads and
+    /// cache gets, but it will never vote or become the leader. It does not count towards the
+    /// quorum, so it can be added or removed without affecting the availability of the cluster.
+    Learner,
+}
+
+impl TryFrom<&str> for NodeRole {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim() {
+            "voter" => Ok(Self::Voter),
+            "learner" => Ok(Self::Learner),
+            _ => Err(Error::Config(
+                "'role' must be either 'voter' or 'learner'".into(),
+            )),
+        }
+    }
+}
+
+/// The compression used for snapshots sent over the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnapshotCompression {
+    /// Snapshot chunks are sent as they are.
+    #[default]
+    None,
+    /// Best compression ratio, useful for slow networks or large databases.
+    Zstd,
+    /// Very fast, but with a lower compression ratio than `Zstd`.
+    Lz4,
+}
+
+impl TryFrom<&str> for SnapshotCompression {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim() {
+            "none" => Ok(Self::None),
+            "zstd" => Ok(Self::Zstd),
+            "lz4" => Ok(Self::Lz4),
+            _ => Err(Error::Config(
+                "'snapshot_compression' must be one of 'none', 'zstd' or 'lz4'".into(),
+            )),
+        }
+    }
+}
+
 /// The main Node config.
 ///
 /// Most default values are good for internal, fast networks. If you have a slow or unstable
@@ -32,6 +89,24 @@ pub struct NodeConfig {
     pub node_id: NodeId,
     /// All Raft member nodes
     pub nodes: Vec<Node>,
+    /// Election priorities for the leader placement. The leader will periodically move the
+    /// leadership to the healthy voter with the highest priority, for both the SQLite and cache
+    /// Rafts. Nodes without an entry have a priority of `0`. If all nodes have the same priority,
+    /// the leadership will never be moved.
+    ///
+    /// Can be given as an optional 4th value for each node inside `HQL_NODES`.
+    pub node_priorities: BTreeMap<NodeId, u8>,
+    /// All nodes from `nodes` which are permanent learners. Node 1 uses it to count only voters
+    /// for the quorum it waits for during the cluster bootstrap.
+    ///
+    /// Can be given as an optional `learner` value for each node inside `HQL_NODES`.
+    pub node_learners: BTreeSet<NodeId>,
+    /// The role of this node. Node 1 bootstraps the cluster and must always be a `Voter`.
+    /// If this node is listed in `node_learners`, it must be a `Learner` as well.
+    ///
+    /// default: `NodeRole::Learner` if this node is marked as `learner` inside `HQL_NODES`,
+    /// `NodeRole::Voter` otherwise
+    pub role: NodeRole,
     /// The directory where the replication log, database and snapshots should be stored
     pub data_dir: Cow<'static, str>,
     /// If the SQLite should be written to disk, provide a filename here.
@@ -39,8 +114,25 @@ pub struct NodeConfig {
     /// you can afford this. No data will be lost with an in-memory DB because Raft logs and
     /// snapshots are always persisted and the in-memory DB can be rebuilt quickly after a restart.
     pub filename_db: Cow<'static, str>,
+    /// Path to an existing SQLite database, which should become the initial state of a new
+    /// cluster. Only a pristine node 1 reads it once during the very first cluster init. The file
+    /// will be copied and never modified. It is ignored as soon as the cluster exists.
+    ///
+    /// default: `None`
+    #[cfg(feature = "sqlite")]
+    pub seed_db: Option<Cow<'static, str>>,
     /// Enables statement logging or the SQL writer
     pub log_statements: bool,
+    /// If set, each read or write statement taking longer than this threshold will be logged
+    /// with its SQL, params and duration. Per-statement latency stats are collected either way
+    /// and can be fetched via `Client::query_stats()`.
+    pub slow_query_threshold_ms: Option<u64>,
+    /// The time in seconds for which the result of a write with an idempotency key will be
+    /// remembered. A retry with the same key inside this window will return the original result
+    /// instead of applying the write again. See `Client::with_idempotency_key()`.
+    ///
+    /// default: 600
+    pub idempotency_window_secs: u64,
     /// The internal cache size for prepared statements. The default is `1024` which could be
     /// reduced in very heavily memory-constrained environments.
     pub prepared_statement_cache_capacity: usize,
@@ -78,6 +170,20 @@ pub struct NodeConfig {
     /// a lot more pressure on the disk. If you have lots of writes, it
     /// can pretty quickly kill your SSD for instance.
     pub sync_immediate: bool,
+    /// The compression for snapshots sent to other nodes. Each chunk of
+    /// `RaftConfig::snapshot_max_chunk_size` is compressed on its own. The receiving node always
+    /// accepts any compression, which means this value may differ between nodes.
+    ///
+    /// default: `SnapshotCompression::None`
+    pub snapshot_compression: SnapshotCompression,
+    /// Limits the bandwidth in bytes per second for sending snapshots to other nodes. The limit
+    /// applies to the compressed data and is shared between all nodes that receive a snapshot at
+    /// the same time. This prevents a snapshot installation from starving the normal replication
+    /// traffic. One chunk for each other node must still be sent within
+    /// `RaftConfig::install_snapshot_timeout`.
+    ///
+    /// default: `None`
+    pub snapshot_bandwidth_limit: Option<u64>,
     /// The internal Raft config. This must be the same on each node.
     /// You will get good defaults with `NodeConfig::default_raft_config(_)`.
     pub raft_config: RaftConfig,
@@ -88,6 +194,17 @@ pub struct NodeConfig {
     pub secret_raft: String,
     /// Secret for Raft management and DB API - at least 16 characters long
     pub secret_api: String,
+    /// Additional secrets, which will be accepted for incoming Raft connections. A node always
+    /// uses `secret_raft` itself. This makes it possible to rotate the secret with rolling
+    /// restarts and without any downtime:
+    ///
+    /// 1. add the new secret here on all nodes
+    /// 2. swap it with the old `secret_raft` on all nodes and keep the old one in here
+    /// 3. remove the old secret from this list
+    pub secret_raft_accepted: Vec<String>,
+    /// Additional secrets, which will be accepted for the API. Works the same way as
+    /// `secret_raft_accepted` and makes it possible to update clients step by step.
+    pub secret_api_accepted: Vec<String>,
     /// auto-backup configuration
     #[cfg(feature = "backup")]
     pub backup_config: backup::BackupConfig,
@@ -107,17 +224,28 @@ impl Default for NodeConfig {
         Self {
             node_id: 0,
             nodes: vec![],
+            node_priorities: BTreeMap::default(),
+            node_learners: BTreeSet::default(),
+            role: NodeRole::Voter,
             data_dir: "hiqlite".into(),
             filename_db: "hiqlite.db".into(),
+            #[cfg(feature = "sqlite")]
+            seed_db: None,
             log_statements: false,
+            slow_query_threshold_ms: None,
+            idempotency_window_secs: 600,
             prepared_statement_cache_capacity: 1024,
             read_pool_size: 4,
             sync_immediate: false,
+            snapshot_compression: SnapshotCompression::None,
+            snapshot_bandwidth_limit: None,
             raft_config: Self::default_raft_config(10_000),
             tls_raft: None,
             tls_api: None,
             secret_raft: String::default(),
             secret_api: String::default(),
+            secret_raft_accepted: Vec::default(),
+            secret_api_accepted: Vec::default(),
             #[cfg(feature = "backup")]
             backup_config: backup::BackupConfig::default(),
             #[cfg(feature = "s3")]
@@ -158,35 +286,66 @@ impl NodeConfig {
         Self::from_env_parse()
     }
 
+    /// Same as `from_env_all()`, but returns an error instead of panicking on invalid values.
+    ///
+    /// If `filename` ends with `.toml`, it will be parsed as a TOML config file, and
+    /// `./config` will be skipped. In this case, values from the environment and a `.env` file
+    /// take precedence over the ones from the file.
+    pub fn try_from_env_all(filename: &str) -> Result<Self, Error> {
+        if config_file::is_toml(filename) {
+            dotenvy::dotenv().ok();
+            config_file::load(filename)?;
+        } else {
+            if dotenvy::from_filename("config").is_err() {
+                debug!("config file './config' not found");
+            }
+            if dotenvy::from_filename_override(filename).is_err() {
+                debug!("config file '{}' not found", filename);
+            }
+            dotenvy::dotenv_override().ok();
+        }
+        Self::try_from_env_parse()
+    }
+
+    /// Builds the config from a TOML config file. Values from the environment take precedence
+    /// over the ones from the file, which makes it possible to inject secrets via env vars.
+    pub fn from_toml_file(filename: &str) -> Result<Self, Error> {
+        config_file::load(filename)?;
+        Self::try_from_env_parse()
+    }
+
     fn from_env_parse() -> Self {
+        Self::try_from_env_parse().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_from_env_parse() -> Result<Self, Error> {
         let env_from = env::var("HQL_NODE_ID_FROM").unwrap_or_else(|_| String::default());
         let node_id = if env_from == "k8s" {
-            let binding = hostname::get().expect("Cannot read hostname");
-            let hostname = binding.to_str().expect("Invalid hostname format");
-            match hostname.rsplit_once('-') {
-                None => {
-                    panic!(
-                        "Cannot split off the NODE_ID from the hostname {}",
-                        hostname
-                    );
-                }
-                Some((_, id)) => {
-                    let id_hostname = id.parse::<u64>().expect("Cannot parse HQL_NODE_ID to u64");
-                    // the hostnames for k8s sts always start at 0, but we need to start at 1
-                    id_hostname + 1
-                }
-            }
+            let binding = hostname::get()
+                .map_err(|err| Error::Config(format!("Cannot read hostname: {}", err).into()))?;
+            let hostname = binding
+                .to_str()
+                .ok_or_else(|| Error::Config("Invalid hostname format".into()))?;
+            let id_hostname = hostname
+                .rsplit_once('-')
+                .and_then(|(_, id)| id.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    Error::Config(
+                        format!(
+                            "Cannot split off the NODE_ID from the hostname {}",
+                            hostname
+                        )
+                        .into(),
+                    )
+                })?;
+            // the hostnames for k8s sts always start at 0, but we need to start at 1
+            id_hostname + 1
         } else {
-            env::var("HQL_NODE_ID")
-                .expect("Node ID not found")
-                .parse::<u64>()
-                .expect("Cannot parse HQL_NODE_ID to u64")
+            env_parse("HQL_NODE_ID")?
+                .ok_or_else(|| Error::Config("HQL_NODE_ID not found".into()))?
         };
 
-        let logs_keep = env::var("HQL_LOGS_UNTIL_SNAPSHOT")
-            .unwrap_or_else(|_| "10000".to_string())
-            .parse::<u64>()
-            .expect("Cannot parse HQL_LOGS_UNTIL_SNAPSHOT to u64");
+        let logs_keep = env_parse("HQL_LOGS_UNTIL_SNAPSHOT")?.unwrap_or(10_000);
 
         #[cfg(feature = "s3")]
         let enc_keys_from = env::var("HQL_ENC_KEYS_FROM")
@@ -199,46 +358,57 @@ impl NodeConfig {
             })
             .unwrap_or(EncKeysFrom::Env);
 
+        let nodes_value = env_required("HQL_NODES")?;
+        let node_learners = Node::learners_from_str(&nodes_value)?;
+        let role = match env::var("HQL_NODE_ROLE") {
+            Ok(role) => NodeRole::try_from(role.as_str())?,
+            Err(_) if node_learners.contains(&node_id) => NodeRole::Learner,
+            Err(_) => NodeRole::Voter,
+        };
+
         let slf = Self {
             node_id,
-            nodes: Node::all_from_env(),
+            nodes: Node::all_from_str(&nodes_value)?,
+            node_priorities: Node::priorities_from_str(&nodes_value)?,
+            node_learners,
+            role,
             data_dir: env::var("HQL_DATA_DIR")
                 .unwrap_or_else(|_| "data".to_string())
                 .into(),
             filename_db: env::var("HQL_FILENAME_DB")
                 .unwrap_or_else(|_| "hiqlite.db".to_string())
                 .into(),
-            log_statements: env::var("HQL_LOG_STATEMENTS")
-                .unwrap_or_else(|_| "false".to_string())
-                .parse()
-                .expect("Cannot parse HQL_LOG_STATEMENTS to u64"),
+            #[cfg(feature = "sqlite")]
+            seed_db: env::var("HQL_SEED_DB").ok().map(Cow::from),
+            log_statements: env_parse("HQL_LOG_STATEMENTS")?.unwrap_or(false),
+            slow_query_threshold_ms: env_parse("HQL_SLOW_QUERY_THRESHOLD_MS")?,
+            idempotency_window_secs: env_parse("HQL_IDEMPOTENCY_WINDOW_SECS")?.unwrap_or(600),
             prepared_statement_cache_capacity: 1024,
-            read_pool_size: env::var("HQL_READ_POOL_SIZE")
-                .unwrap_or_else(|_| "4".to_string())
-                .parse()
-                .expect("Cannot parse HQL_READ_POOL_SIZE to usize"),
-            sync_immediate: env::var("HQL_SYNC_IMMEDIATE")
-                .unwrap_or_else(|_| "false".to_string())
-                .parse()
-                .expect("Cannot parse HQL_SYNC_IMMEDIATE as bool"),
+            read_pool_size: env_parse("HQL_READ_POOL_SIZE")?.unwrap_or(4),
+            sync_immediate: env_parse("HQL_SYNC_IMMEDIATE")?.unwrap_or(false),
+            snapshot_compression: env::var("HQL_SNAPSHOT_COMPRESSION")
+                .map(|c| SnapshotCompression::try_from(c.as_str()))
+                .unwrap_or(Ok(SnapshotCompression::None))?,
+            snapshot_bandwidth_limit: env_parse("HQL_SNAPSHOT_BANDWIDTH_LIMIT")?,
             raft_config: Self::default_raft_config(logs_keep),
-            tls_raft: ServerTlsConfig::from_env("RAFT"),
-            tls_api: ServerTlsConfig::from_env("API"),
-            secret_raft: env::var("HQL_SECRET_RAFT").expect("HQL_SECRET_RAFT not found"),
-            secret_api: env::var("HQL_SECRET_API").expect("HQL_SECRET_API not found"),
+            tls_raft: ServerTlsConfig::parse_env("RAFT")?,
+            tls_api: ServerTlsConfig::parse_env("API")?,
+            secret_raft: env_required("HQL_SECRET_RAFT")?,
+            secret_api: env_required("HQL_SECRET_API")?,
+            secret_raft_accepted: env_list("HQL_SECRET_RAFT_ACCEPTED"),
+            secret_api_accepted: env_list("HQL_SECRET_API_ACCEPTED"),
             #[cfg(feature = "backup")]
-            backup_config: backup::BackupConfig::from_env(),
+            backup_config: backup::BackupConfig::parse_env()?,
             #[cfg(feature = "s3")]
             enc_keys_from,
             #[cfg(feature = "s3")]
-            s3_config: crate::s3::S3Config::try_from_env(),
+            s3_config: crate::s3::S3Config::parse_env()?,
             #[cfg(feature = "dashboard")]
-            password_dashboard: DashboardState::from_env().password_dashboard,
+            password_dashboard: DashboardState::parse_env()?.password_dashboard,
         };
 
-        slf.is_valid()
-            .expect("NodeConfig parsed from env is invalid");
-        slf
+        slf.is_valid()?;
+        Ok(slf)
     }
 
     /// Provides good defaults for a `RaftConfig` inside a fast network.
@@ -282,11 +452,86 @@ impl NodeConfig {
             return Err(Error::Config("'node_id' not found in 'nodes'".into()));
         }
 
+        if let Some(id) = self
+            .node_priorities
+            .keys()
+            .find(|id| !self.nodes.iter().any(|node| node.id == **id))
+        {
+            return Err(Error::Config(
+                format!("'node_priorities' contains unknown node {}", id).into(),
+            ));
+        }
+
+        if self.node_id == 1 && self.role == NodeRole::Learner {
+            return Err(Error::Config(
+                "node 1 initializes the cluster and cannot be a 'learner'".into(),
+            ));
+        }
+
+        if let Some(id) = self
+            .node_learners
+            .iter()
+            .find(|id| !self.nodes.iter().any(|node| node.id == **id))
+        {
+            return Err(Error::Config(
+                format!("'node_learners' contains unknown node {}", id).into(),
+            ));
+        }
+
+        if self.node_learners.contains(&1) {
+            return Err(Error::Config(
+                "node 1 initializes the cluster and cannot be a 'learner'".into(),
+            ));
+        }
+
+        if self.node_learners.contains(&self.node_id) && self.role != NodeRole::Learner {
+            return Err(Error::Config(
+                "this node is listed in 'node_learners' but its 'role' is not 'learner'".into(),
+            ));
+        }
+
+        #[cfg(feature = "backup")]
+        if let Some(log_archive) = &self.backup_config.log_archive {
+            if log_archive.target == crate::LogArchiveTarget::S3 && self.s3_config.is_none() {
+                return Err(Error::Config(
+                    "the log archive target 's3' needs an 's3_config'".into(),
+                ));
+            }
+        }
+
+        if let Some(limit) = self.snapshot_bandwidth_limit {
+            // The throttle is shared between all followers. In the worst case, all of them
+            // receive a snapshot at the same time and each chunk has to wait for one chunk of
+            // every other follower, but it must still be sent within the `install_snapshot_timeout`.
+            let followers = self.nodes.len().saturating_sub(1).max(1) as u64;
+            let chunk_millis =
+                self.raft_config.snapshot_max_chunk_size * followers * 1000 / limit.max(1);
+            if chunk_millis >= self.raft_config.install_snapshot_timeout {
+                return Err(Error::Config(
+                    "'snapshot_bandwidth_limit' is too low to send a snapshot chunk to each \
+                    other node within the 'install_snapshot_timeout'"
+                        .into(),
+                ));
+            }
+        }
+
         if self.secret_raft.len() < 16 || self.secret_api.len() < 16 {
             return Err(Error::Config(
                 "'secret_raft' and 'secret_api' should be at least 16 characters long".into(),
             ));
         }
+        if self
+            .secret_raft_accepted
+            .iter()
+            .chain(self.secret_api_accepted.iter())
+            .any(|secret| secret.len() < 16)
+        {
+            return Err(Error::Config(
+                "'secret_raft_accepted' and 'secret_api_accepted' should only contain secrets \
+                with at least 16 characters"
+                    .into(),
+            ));
+        }
 
         #[cfg(feature = "dashboard")]
         if let Some(pwd) = &self.password_dashboard {
@@ -303,43 +548,125 @@ impl NodeConfig {
 
 impl From<&str> for Node {
     fn from(s: &str) -> Self {
-        let (id, rest) = s
-            .trim()
-            .split_once(' ')
-            .expect("invalid format for HQL_NODES");
-        let (addr_raft, addr_api) = rest.split_once(' ').expect("invalid format for HQL_NODES");
-
-        let id = id
-            .parse::<u64>()
-            .expect("Cannot parse Node ID from HQL_NODES to u64");
-
-        Self {
-            id,
-            addr_raft: addr_raft.trim().to_string(),
-            addr_api: addr_api.trim().to_string(),
-        }
+        Self::try_from_line(s).unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 impl Node {
     pub fn all_from_env() -> Vec<Self> {
-        let mut res = Vec::new();
-
         let value = env::var("HQL_NODES").expect("HQL_NODES does not exist");
+        Self::all_from_str(&value).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        for line in value.lines() {
-            if !line.is_empty() {
-                res.push(Self::from(line))
-            }
-        }
+    /// Parses all nodes from the `HQL_NODES` format with one node per line.
+    pub(crate) fn all_from_str(value: &str) -> Result<Vec<Self>, Error> {
+        value
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Self::try_from_line)
+            .collect()
+    }
 
-        res
+    fn try_from_line(line: &str) -> Result<Self, Error> {
+        let mut values = line.split_whitespace();
+        let (Some(id), Some(addr_raft), Some(addr_api)) =
+            (values.next(), values.next(), values.next())
+        else {
+            return Err(Error::Config(
+                format!(
+                    "invalid format for HQL_NODES, expected 'id addr_raft addr_api': {}",
+                    line.trim()
+                )
+                .into(),
+            ));
+        };
+
+        let id = id.parse::<u64>().map_err(|_| {
+            Error::Config(format!("Cannot parse Node ID from HQL_NODES to u64: {}", id).into())
+        })?;
+
+        Ok(Self {
+            id,
+            addr_raft: addr_raft.to_string(),
+            addr_api: addr_api.to_string(),
+        })
+    }
+
+    /// Parses the optional election priorities, which can be given as a 4th value per node
+    /// inside `HQL_NODES`.
+    fn priorities_from_str(value: &str) -> Result<BTreeMap<NodeId, u8>, Error> {
+        value
+            .lines()
+            .filter_map(|line| {
+                let mut values = line.split_whitespace();
+                let id = values.next()?;
+                let priority = values.skip(2).find(|value| *value != "learner")?;
+                Some((id, priority))
+            })
+            .map(|(id, priority)| {
+                let id = id.parse::<u64>().map_err(|_| {
+                    Error::Config(
+                        format!("Cannot parse Node ID from HQL_NODES to u64: {}", id).into(),
+                    )
+                })?;
+                let priority = priority.parse::<u8>().map_err(|_| {
+                    Error::Config(
+                        format!(
+                            "Cannot parse Node priority from HQL_NODES to u8: {}",
+                            priority
+                        )
+                        .into(),
+                    )
+                })?;
+                Ok((id, priority))
+            })
+            .collect()
+    }
+
+    /// Parses the permanent learners, which are marked with an additional `learner` value
+    /// after the addresses inside `HQL_NODES`.
+    fn learners_from_str(value: &str) -> Result<BTreeSet<NodeId>, Error> {
+        value
+            .lines()
+            .filter(|line| line.split_whitespace().skip(3).any(|v| v == "learner"))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|id| {
+                id.parse::<u64>().map_err(|_| {
+                    Error::Config(
+                        format!("Cannot parse Node ID from HQL_NODES to u64: {}", id).into(),
+                    )
+                })
+            })
+            .collect()
     }
 }
 
+/// Parses the env var `key` into `T`, if it is set.
+pub(crate) fn env_parse<T: FromStr>(key: &str) -> Result<Option<T>, Error> {
+    match env::var(key) {
+        Ok(value) => value.trim().parse::<T>().map(Some).map_err(|_| {
+            Error::Config(format!("Cannot parse {} to {}", key, type_name::<T>()).into())
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads the env var `key` as a list of values separated by whitespace or new lines.
+pub(crate) fn env_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|value| value.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Reads the env var `key`, which must be set.
+pub(crate) fn env_required(key: &str) -> Result<String, Error> {
+    env::var(key).map_err(|_| Error::Config(format!("{} not found", key).into()))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{Node, NodeConfig};
+    use std::collections::BTreeSet;
 
     #[test]
     fn test_config_from_env() {
@@ -362,4 +689,74 @@ mod tests {
         assert_eq!(c.secret_raft, "SuperSecureSecret1337");
         assert_eq!(c.secret_api, "SuperSecureSecret1337");
     }
+
+    #[test]
+    fn test_snapshot_bandwidth_limit() {
+        let nodes = Node::all_from_str(
+            r#"
+            1 localhost:8100 localhost:8200
+            2 localhost:8101 localhost:8201
+            3 localhost:8102 localhost:8202
+            "#,
+        )
+        .unwrap();
+        let mut config = NodeConfig {
+            node_id: 1,
+            nodes,
+            secret_raft: "SuperSecureSecret1337".to_string(),
+            secret_api: "SuperSecureSecret1337".to_string(),
+            ..Default::default()
+        };
+        config.raft_config.snapshot_max_chunk_size = 1024 * 1024;
+        config.raft_config.install_snapshot_timeout = 1000;
+
+        // 2 followers with a chunk of 1 MiB each need more than 2 MiB/s
+        config.snapshot_bandwidth_limit = Some(2 * 1024 * 1024);
+        assert!(config.is_valid().is_err());
+        config.snapshot_bandwidth_limit = Some(3 * 1024 * 1024);
+        assert!(config.is_valid().is_ok());
+
+        // a single follower only needs a single chunk
+        config.nodes.pop();
+        config.snapshot_bandwidth_limit = Some(2 * 1024 * 1024);
+        assert!(config.is_valid().is_ok());
+    }
+
+    #[test]
+    fn test_node_priorities() {
+        let value = r#"
+            1 localhost:8100 localhost:8200 10
+            2 localhost:8101 localhost:8201
+            3 localhost:8102 localhost:8202 5
+        "#;
+
+        let node = Node::from(value.lines().nth(1).unwrap());
+        assert_eq!(node.addr_api, "localhost:8200");
+
+        let priorities = Node::priorities_from_str(value).unwrap();
+        assert_eq!(priorities.len(), 2);
+        assert_eq!(priorities.get(&1), Some(&10));
+        assert_eq!(priorities.get(&2), None);
+        assert_eq!(priorities.get(&3), Some(&5));
+    }
+
+    #[test]
+    fn test_node_learners() {
+        let value = r#"
+            1 localhost:8100 localhost:8200 10
+            2 localhost:8101 localhost:8201 learner
+            3 localhost:8102 localhost:8202 5 learner
+        "#;
+
+        let nodes = Node::all_from_str(value).unwrap();
+        assert_eq!(nodes.len(), 3);
+
+        let learners = Node::learners_from_str(value).unwrap();
+        assert_eq!(learners, BTreeSet::from([2, 3]));
+
+        let priorities = Node::priorities_from_str(value).unwrap();
+        assert_eq!(priorities.len(), 2);
+        assert_eq!(priorities.get(&1), Some(&10));
+        assert_eq!(priorities.get(&3), Some(&5));
+    }
 }
@@ -0,0 +1,579 @@
+use crate::config::{NodeRole, SnapshotCompression};
+use crate::Error;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use toml::Spanned;
+
+/// Returns `true` if the config file at `path` should be parsed as TOML.
+pub(crate) fn is_toml(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == "toml")
+}
+
+/// Parses and validates the TOML config file at `path` and applies all values to their `HQL_*`
+/// env vars. Vars which exist already will not be touched, which means the environment always
+/// takes precedence over the file.
+pub(crate) fn load(path: &str) -> Result<(), Error> {
+    let content = std::fs::read_to_string(path).map_err(|err| {
+        Error::Config(format!("Cannot read config file {}: {}", path, err).into())
+    })?;
+    let config = ConfigFile::parse(&content).map_err(|err| {
+        Error::Config(format!("Invalid config file {} at line {}", path, err).into())
+    })?;
+
+    for (key, value) in config.env_vars() {
+        if env::var_os(&key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
+
+/// The typed TOML config file. Each value maps to the env var of the same name with the
+/// section as prefix, like `backup.keep_days` -> `HQL_BACKUP_KEEP_DAYS`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    node_id: Option<Spanned<u64>>,
+    node_id_from: Option<Spanned<String>>,
+    role: Option<Spanned<String>>,
+    data_dir: Option<String>,
+    filename_db: Option<String>,
+    seed_db: Option<String>,
+    log_statements: Option<bool>,
+    slow_query_threshold_ms: Option<u64>,
+    idempotency_window_secs: Option<u64>,
+    read_pool_size: Option<usize>,
+    sync_immediate: Option<bool>,
+    logs_until_snapshot: Option<u64>,
+    snapshot_compression: Option<Spanned<String>>,
+    snapshot_bandwidth_limit: Option<u64>,
+    health_check_delay_secs: Option<u16>,
+    secret_raft: Option<String>,
+    secret_api: Option<String>,
+    #[serde(default)]
+    secret_raft_accepted: Vec<String>,
+    #[serde(default)]
+    secret_api_accepted: Vec<String>,
+    #[serde(default)]
+    nodes: Vec<Spanned<NodeEntry>>,
+    #[serde(default)]
+    tls: TlsSection,
+    #[serde(default)]
+    backup: BackupSection,
+    s3: Option<S3Section>,
+    #[serde(default)]
+    encryption: EncryptionSection,
+    #[serde(default)]
+    dashboard: DashboardSection,
+    #[serde(default)]
+    proxy: ProxySection,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NodeEntry {
+    id: u64,
+    addr_raft: Spanned<String>,
+    addr_api: Spanned<String>,
+    priority: Option<u8>,
+    role: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSection {
+    raft: Option<TlsEntry>,
+    api: Option<TlsEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsEntry {
+    key: String,
+    cert: String,
+    danger_tls_no_verify: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackupSection {
+    cron: Option<Spanned<String>>,
+    keep_days: Option<u16>,
+    keep_daily: Option<u16>,
+    keep_weekly: Option<u16>,
+    keep_monthly: Option<u16>,
+    keep_days_local: Option<u32>,
+    dir: Option<String>,
+    dir_encrypt: Option<bool>,
+    dir_shared: Option<bool>,
+    cache: Option<bool>,
+    log_archive: Option<Spanned<String>>,
+    log_archive_interval_secs: Option<u64>,
+    restore: Option<Spanned<String>>,
+    restore_until: Option<Spanned<String>>,
+    skip_validation: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct S3Section {
+    url: Spanned<String>,
+    bucket: String,
+    region: String,
+    #[serde(default)]
+    path_style: bool,
+    key: String,
+    secret: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncryptionSection {
+    keys_from: Option<Spanned<String>>,
+    #[serde(default)]
+    keys: Vec<Spanned<String>>,
+    key_active: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DashboardSection {
+    password: Option<String>,
+    insecure_cookie: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProxySection {
+    listen_port: Option<u16>,
+}
+
+impl ConfigFile {
+    /// Parses and validates the file content. Errors start with the line number they occurred.
+    fn parse(content: &str) -> Result<Self, String> {
+        let slf = toml::from_str::<Self>(content).map_err(|err| match err.span() {
+            Some(span) => format!("{}: {}", line_of(content, span), err.message()),
+            None => err.message().to_string(),
+        })?;
+        slf.validate()
+            .map_err(|(span, msg)| format!("{}: {}", line_of(content, span), msg))?;
+        Ok(slf)
+    }
+
+    fn validate(&self) -> Result<(), (Range<usize>, String)> {
+        if let Some(from) = &self.node_id_from {
+            if from.get_ref() != "k8s" {
+                return Err(invalid(from, "'node_id_from' can only be 'k8s'"));
+            }
+        }
+        if let Some(role) = &self.role {
+            NodeRole::try_from(role.get_ref().as_str()).map_err(|err| invalid(role, err))?;
+        }
+        if let Some(compression) = &self.snapshot_compression {
+            SnapshotCompression::try_from(compression.get_ref().as_str())
+                .map_err(|err| invalid(compression, err))?;
+        }
+
+        let mut ids = BTreeSet::new();
+        for node in &self.nodes {
+            let entry = node.get_ref();
+            if !ids.insert(entry.id) {
+                return Err(invalid(node, format!("duplicate node id {}", entry.id)));
+            }
+            for addr in [&entry.addr_raft, &entry.addr_api] {
+                if addr.get_ref().is_empty() || addr.get_ref().contains(char::is_whitespace) {
+                    return Err(invalid(
+                        addr,
+                        "node addresses must not be empty or contain spaces",
+                    ));
+                }
+            }
+            if let Some(role) = &entry.role {
+                let parsed = NodeRole::try_from(role.get_ref().as_str())
+                    .map_err(|err| invalid(role, err))?;
+                if entry.id == 1 && parsed == NodeRole::Learner {
+                    return Err(invalid(
+                        role,
+                        "node 1 initializes the cluster and cannot be a 'learner'",
+                    ));
+                }
+            }
+        }
+        if let Some(id) = &self.node_id {
+            if !self.nodes.is_empty() && !ids.contains(id.get_ref()) {
+                return Err(invalid(id, "'node_id' not found in 'nodes'"));
+            }
+        }
+
+        #[cfg(feature = "backup")]
+        {
+            use std::str::FromStr;
+
+            if let Some(cron) = &self.backup.cron {
+                cron::Schedule::from_str(cron.get_ref())
+                    .map_err(|err| invalid(cron, format!("invalid cron syntax: {}", err)))?;
+            }
+            if let Some(archive) = &self.backup.log_archive {
+                crate::LogArchiveTarget::try_from(archive.get_ref().as_str())
+                    .map_err(|err| invalid(archive, err))?;
+            }
+            if let Some(until) = &self.backup.restore_until {
+                crate::log_archive::RecoveryTarget::try_from(until.get_ref().as_str())
+                    .map_err(|err| invalid(until, err))?;
+            }
+        }
+        if let Some(restore) = &self.backup.restore {
+            if !restore.get_ref().contains(':') {
+                return Err(invalid(
+                    restore,
+                    "'restore' must start with 's3:', 'file:' or the name of a backup target \
+                    like 'dir:'",
+                ));
+            }
+        }
+
+        if let Some(s3) = &self.s3 {
+            reqwest::Url::parse(s3.url.get_ref())
+                .map_err(|err| invalid(&s3.url, format!("invalid S3 url: {}", err)))?;
+        }
+
+        let enc = &self.encryption;
+        if let Some(from) = &enc.keys_from {
+            if from.get_ref() != "env" && !from.get_ref().starts_with("file:") {
+                return Err(invalid(
+                    from,
+                    "'keys_from' must be either 'env' or start with 'file:'",
+                ));
+            }
+        }
+        let mut key_ids = BTreeSet::new();
+        for key in &enc.keys {
+            match key.get_ref().split_once('/') {
+                Some((id, _)) if !id.is_empty() => {
+                    key_ids.insert(id);
+                }
+                _ => {
+                    return Err(invalid(
+                        key,
+                        "encryption keys must have the format 'id/key'",
+                    ))
+                }
+            }
+        }
+        if let Some(active) = &enc.key_active {
+            if !enc.keys.is_empty() && !key_ids.contains(active.get_ref().as_str()) {
+                return Err(invalid(active, "'key_active' not found in 'keys'"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps all given values to their env vars.
+    fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        let mut push = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((key.to_string(), value));
+            }
+        };
+
+        push(
+            "HQL_NODE_ID",
+            self.node_id.as_ref().map(|v| v.get_ref().to_string()),
+        );
+        push(
+            "HQL_NODE_ID_FROM",
+            self.node_id_from.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push(
+            "HQL_NODE_ROLE",
+            self.role.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push("HQL_DATA_DIR", self.data_dir.clone());
+        push("HQL_FILENAME_DB", self.filename_db.clone());
+        push("HQL_SEED_DB", self.seed_db.clone());
+        push("HQL_LOG_STATEMENTS", to_string(self.log_statements));
+        push(
+            "HQL_SLOW_QUERY_THRESHOLD_MS",
+            to_string(self.slow_query_threshold_ms),
+        );
+        push(
+            "HQL_IDEMPOTENCY_WINDOW_SECS",
+            to_string(self.idempotency_window_secs),
+        );
+        push("HQL_READ_POOL_SIZE", to_string(self.read_pool_size));
+        push("HQL_SYNC_IMMEDIATE", to_string(self.sync_immediate));
+        push(
+            "HQL_LOGS_UNTIL_SNAPSHOT",
+            to_string(self.logs_until_snapshot),
+        );
+        push(
+            "HQL_SNAPSHOT_COMPRESSION",
+            self.snapshot_compression
+                .as_ref()
+                .map(|v| v.get_ref().clone()),
+        );
+        push(
+            "HQL_SNAPSHOT_BANDWIDTH_LIMIT",
+            to_string(self.snapshot_bandwidth_limit),
+        );
+        push(
+            "HQL_HEALTH_CHECK_DELAY_SECS",
+            to_string(self.health_check_delay_secs),
+        );
+        push("HQL_SECRET_RAFT", self.secret_raft.clone());
+        push("HQL_SECRET_API", self.secret_api.clone());
+        for (key, secrets) in [
+            ("HQL_SECRET_RAFT_ACCEPTED", &self.secret_raft_accepted),
+            ("HQL_SECRET_API_ACCEPTED", &self.secret_api_accepted),
+        ] {
+            if !secrets.is_empty() {
+                push(key, Some(secrets.join("\n")));
+            }
+        }
+
+        if !self.nodes.is_empty() {
+            let nodes = self
+                .nodes
+                .iter()
+                .map(|node| {
+                    let node = node.get_ref();
+                    let line = format!(
+                        "{} {} {}",
+                        node.id,
+                        node.addr_raft.get_ref(),
+                        node.addr_api.get_ref()
+                    );
+                    let line = match node.priority {
+                        Some(priority) => format!("{} {}", line, priority),
+                        None => line,
+                    };
+                    match node.role.as_ref().map(|role| role.get_ref().trim()) {
+                        Some("learner") => format!("{} learner", line),
+                        _ => line,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            push("HQL_NODES", Some(nodes));
+        }
+
+        for (variant, tls) in [("RAFT", &self.tls.raft), ("API", &self.tls.api)] {
+            if let Some(tls) = tls {
+                push(&format!("HQL_TLS_{}_KEY", variant), Some(tls.key.clone()));
+                push(&format!("HQL_TLS_{}_CERT", variant), Some(tls.cert.clone()));
+                push(
+                    &format!("HQL_TLS_{}_DANGER_TLS_NO_VERIFY", variant),
+                    to_string(tls.danger_tls_no_verify),
+                );
+            }
+        }
+
+        let backup = &self.backup;
+        push(
+            "HQL_BACKUP_CRON",
+            backup.cron.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push("HQL_BACKUP_KEEP_DAYS", to_string(backup.keep_days));
+        push("HQL_BACKUP_KEEP_DAILY", to_string(backup.keep_daily));
+        push("HQL_BACKUP_KEEP_WEEKLY", to_string(backup.keep_weekly));
+        push("HQL_BACKUP_KEEP_MONTHLY", to_string(backup.keep_monthly));
+        push(
+            "HQL_BACKUP_KEEP_DAYS_LOCAL",
+            to_string(backup.keep_days_local),
+        );
+        push("HQL_BACKUP_DIR", backup.dir.clone());
+        push("HQL_BACKUP_DIR_ENCRYPT", to_string(backup.dir_encrypt));
+        push("HQL_BACKUP_DIR_SHARED", to_string(backup.dir_shared));
+        push("HQL_BACKUP_CACHE", to_string(backup.cache));
+        push(
+            "HQL_BACKUP_LOG_ARCHIVE",
+            backup.log_archive.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push(
+            "HQL_BACKUP_LOG_ARCHIVE_INTERVAL_SECS",
+            to_string(backup.log_archive_interval_secs),
+        );
+        push(
+            "HQL_BACKUP_RESTORE",
+            backup.restore.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push(
+            "HQL_BACKUP_RESTORE_UNTIL",
+            backup.restore_until.as_ref().map(|v| v.get_ref().clone()),
+        );
+        push(
+            "HQL_BACKUP_SKIP_VALIDATION",
+            to_string(backup.skip_validation),
+        );
+
+        if let Some(s3) = &self.s3 {
+            push("HQL_S3_URL", Some(s3.url.get_ref().clone()));
+            push("HQL_S3_BUCKET", Some(s3.bucket.clone()));
+            push("HQL_S3_REGION", Some(s3.region.clone()));
+            push("HQL_S3_PATH_STYLE", Some(s3.path_style.to_string()));
+            push("HQL_S3_KEY", Some(s3.key.clone()));
+            push("HQL_S3_SECRET", Some(s3.secret.clone()));
+        }
+
+        let enc = &self.encryption;
+        push(
+            "HQL_ENC_KEYS_FROM",
+            enc.keys_from.as_ref().map(|v| v.get_ref().clone()),
+        );
+        if !enc.keys.is_empty() {
+            let keys = enc
+                .keys
+                .iter()
+                .map(|key| key.get_ref().as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            push("ENC_KEYS", Some(keys));
+        }
+        push(
+            "ENC_KEY_ACTIVE",
+            enc.key_active.as_ref().map(|v| v.get_ref().clone()),
+        );
+
+        push("HQL_PASSWORD_DASHBOARD", self.dashboard.password.clone());
+        push(
+            "HQL_INSECURE_COOKIE",
+            to_string(self.dashboard.insecure_cookie),
+        );
+
+        push("LISTEN_PORT", to_string(self.proxy.listen_port));
+
+        vars
+    }
+}
+
+#[inline]
+fn to_string<T: ToString>(value: Option<T>) -> Option<String> {
+    value.map(|v| v.to_string())
+}
+
+#[inline]
+fn invalid<T>(value: &Spanned<T>, msg: impl Display) -> (Range<usize>, String) {
+    (value.span(), msg.to_string())
+}
+
+/// Returns the 1-based line number for the start of `span`.
+fn line_of(content: &str, span: Range<usize>) -> usize {
+    let start = span.start.min(content.len());
+    content[..start].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+node_id = 2
+data_dir = "data"
+log_statements = true
+secret_raft = "SuperSecureSecret1337"
+secret_api = "SuperSecureSecret1337"
+secret_api_accepted = ["SuperSecureSecret1338", "SuperSecureSecret1339"]
+
+[[nodes]]
+id = 1
+addr_raft = "localhost:8100"
+addr_api = "localhost:8200"
+priority = 10
+
+[[nodes]]
+id = 2
+addr_raft = "localhost:8101"
+addr_api = "localhost:8201"
+
+[tls.api]
+key = "tls/key.pem"
+cert = "tls/cert-chain.pem"
+
+[backup]
+keep_days = 7
+dir = "/mnt/backups"
+
+[encryption]
+keys = ["bVCyTsGaggVy5yqQ/UzluN29DZW41M3hTSkx6Y3NtZmRuQkR2TnJxUTYzcjQ="]
+key_active = "bVCyTsGaggVy5yqQ"
+"#;
+
+    #[test]
+    fn test_config_file_env_vars() {
+        let config = ConfigFile::parse(CONFIG).unwrap();
+        let vars = config.env_vars();
+        let get = |key: &str| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("HQL_NODE_ID"), Some("2"));
+        assert_eq!(get("HQL_LOG_STATEMENTS"), Some("true"));
+        assert_eq!(
+            get("HQL_NODES"),
+            Some("1 localhost:8100 localhost:8200 10\n2 localhost:8101 localhost:8201")
+        );
+        assert_eq!(
+            get("HQL_SECRET_API_ACCEPTED"),
+            Some("SuperSecureSecret1338\nSuperSecureSecret1339")
+        );
+        assert_eq!(get("HQL_SECRET_RAFT_ACCEPTED"), None);
+        assert_eq!(get("HQL_TLS_API_KEY"), Some("tls/key.pem"));
+        assert_eq!(get("HQL_TLS_API_DANGER_TLS_NO_VERIFY"), None);
+        assert_eq!(get("HQL_TLS_RAFT_KEY"), None);
+        assert_eq!(get("HQL_BACKUP_KEEP_DAYS"), Some("7"));
+        assert_eq!(get("HQL_BACKUP_DIR"), Some("/mnt/backups"));
+        assert_eq!(get("ENC_KEY_ACTIVE"), Some("bVCyTsGaggVy5yqQ"));
+        assert_eq!(get("HQL_S3_URL"), None);
+        assert_eq!(get("HQL_READ_POOL_SIZE"), None);
+
+        let config = ConfigFile::parse(&CONFIG.replace(
+            "addr_api = \"localhost:8201\"",
+            "addr_api = \"localhost:8201\"\nrole = \"learner\"",
+        ))
+        .unwrap();
+        let vars = config.env_vars();
+        let get = |key: &str| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(
+            get("HQL_NODES"),
+            Some("1 localhost:8100 localhost:8200 10\n2 localhost:8101 localhost:8201 learner")
+        );
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let err = ConfigFile::parse("node_id = 1\nunknown = true\n").unwrap_err();
+        assert!(err.starts_with("2: unknown field `unknown`"), "{}", err);
+
+        let err = ConfigFile::parse("node_id = \"one\"\n").unwrap_err();
+        assert!(err.starts_with("1: invalid type"), "{}", err);
+
+        let err = ConfigFile::parse("data_dir = \"data\"\nrole = \"leader\"\n").unwrap_err();
+        assert!(err.starts_with("2: "), "{}", err);
+        assert!(err.contains("'voter' or 'learner'"), "{}", err);
+
+        let err = ConfigFile::parse(&CONFIG.replace("id = 2", "id = 1")).unwrap_err();
+        assert!(err.starts_with("15: duplicate node id 1"), "{}", err);
+
+        let err = ConfigFile::parse(&CONFIG.replace("node_id = 2", "node_id = 3")).unwrap_err();
+        assert!(err.starts_with("2: 'node_id' not found"), "{}", err);
+
+        let err = ConfigFile::parse(&CONFIG.replace("\"localhost:8201\"", "\"localhost 8201\""))
+            .unwrap_err();
+        assert!(err.starts_with("18: node addresses"), "{}", err);
+
+        let err =
+            ConfigFile::parse(&CONFIG.replace("priority = 10", "role = \"learner\"")).unwrap_err();
+        assert!(err.starts_with("13: node 1 initializes"), "{}", err);
+
+        let err = ConfigFile::parse(&CONFIG.replace(
+            "key_active = \"bVCyTsGaggVy5yqQ\"",
+            "key_active = \"other\"",
+        ))
+        .unwrap_err();
+        assert!(err.starts_with("30: 'key_active' not found"), "{}", err);
+    }
+}
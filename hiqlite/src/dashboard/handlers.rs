@@ -3,6 +3,7 @@ use crate::dashboard::table::Table;
 use crate::dashboard::{query, session};
 use crate::network::AppStateExt;
 use crate::query::rows::RowOwned;
+use crate::query::stats::StatementStats;
 use crate::{Error, Node};
 use axum::body::Body;
 use axum::extract::Path;
@@ -101,6 +102,10 @@ pub async fn post_query(
     Ok(Json(res))
 }
 
+pub async fn get_query_stats(state: AppStateExt, _: Session) -> Json<Vec<StatementStats>> {
+    Json(state.raft_db.query_stats.get())
+}
+
 pub async fn get_metrics(state: AppStateExt, _: Session) -> Json<RaftMetrics<u64, Node>> {
     let metrics = state.raft_db.raft.metrics().borrow().clone();
     Json(metrics)
@@ -21,18 +21,29 @@ pub struct DashboardState {
 
 impl DashboardState {
     pub fn from_env() -> Self {
+        Self::parse_env().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn parse_env() -> Result<Self, Error> {
         match env::var("HQL_PASSWORD_DASHBOARD") {
             Ok(b64) => {
-                let hash = String::from_utf8(b64_decode(&b64).unwrap()).unwrap();
-                Self {
+                let hash = b64_decode(&b64)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .ok_or_else(|| {
+                        Error::Config(
+                            "HQL_PASSWORD_DASHBOARD must be a base64 encoded Argon2ID hash".into(),
+                        )
+                    })?;
+                Ok(Self {
                     password_dashboard: Some(hash),
-                }
+                })
             }
             Err(_) => {
                 warn!("HQL_PASSWORD_DASHBOARD has not been set and the dashboard will be disabled");
-                Self {
+                Ok(Self {
                     password_dashboard: None,
-                }
+                })
             }
         }
     }
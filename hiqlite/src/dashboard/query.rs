@@ -105,6 +105,8 @@ async fn execute_dynamic(state: &AppStateExt, sql: Query) -> Result<usize, Error
             .send_async(crate::client::stream::ClientStreamReq::Execute(
                 crate::client::stream::ClientExecutePayload {
                     request_id: state.new_request_id(),
+                    trace: None,
+                    idempotency_key: None,
                     sql,
                     ack,
                 },
@@ -19,6 +19,10 @@ use crate::store::state_machine::memory::notify_handler::NotifyRequest;
 pub enum Error {
     #[error("BadRequest: {0}")]
     BadRequest(Cow<'static, str>),
+    /// A backup could not be verified after it has been written or before it is restored
+    #[cfg(feature = "sqlite")]
+    #[error("Backup: {0}")]
+    Backup(Cow<'static, str>),
     /// Serialization / Deserialization errors from `bincode`
     #[error("Bincode: {0}")]
     Bincode(String),
@@ -44,6 +48,13 @@ pub enum Error {
     Cryptr(String),
     #[error("Error: {0}")]
     Error(Cow<'static, str>),
+    /// A guarded transaction has been rolled back, because the precondition of the step with
+    /// the index `step` has not been met.
+    #[error("GuardFailed: step {step}: {reason}")]
+    GuardFailed {
+        step: usize,
+        reason: Cow<'static, str>,
+    },
     #[error("InitializeError: {0}")]
     InitializeError(RaftInitError),
     /// Error informing about a Raft leader change
@@ -117,6 +128,8 @@ impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         let status = match &self {
             Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "sqlite")]
+            Error::Backup(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Bincode(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Cache(_) => StatusCode::BAD_REQUEST,
             Error::Channel(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -138,6 +151,7 @@ impl IntoResponse for Error {
             Error::Config(_) => StatusCode::BAD_REQUEST,
             Error::Connect(_) => StatusCode::SERVICE_UNAVAILABLE,
             Error::Error(_) => StatusCode::BAD_REQUEST,
+            Error::GuardFailed { .. } => StatusCode::CONFLICT,
             Error::InitializeError(_) => StatusCode::BAD_REQUEST,
             Error::RaftError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::RaftErrorFatal(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,475 @@
+use crate::query::rows::ValueOwned;
+use crate::{Error, Param};
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Rows are read with this page size during an export.
+pub(crate) const EXPORT_PAGE_SIZE: i64 = 1000;
+/// An import sends at most this many rows inside a single Raft log entry.
+pub(crate) const IMPORT_CHUNK_ROWS: usize = 1000;
+/// An import sends at most this many bytes of SQL and params inside a single Raft log entry.
+/// A single statement that is bigger than this will still be sent on its own.
+pub(crate) const IMPORT_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Internal tables, which are skipped during an export of the whole database.
+pub(crate) const INTERNAL_TABLES: [&str; 3] = ["_idempotency", "_metadata", "_migrations"];
+
+/// The format for `Client::export()` and `Client::import()`.
+///
+/// - `Sql` is a plain SQL dump with the schema, data, indexes and triggers, which can be used
+///   for the whole database or selected tables.
+/// - `Csv` writes a header line with column names and one record per row for a single table.
+///   `NULL` values are empty fields, while empty strings are quoted.
+/// - `Jsonl` writes one JSON object per line and row for a single table.
+///
+/// BLOBs are hex-encoded in `Csv` and `Jsonl`. An import decodes them again for all columns with
+/// the declared type `BLOB`. Inside columns with any other type, they will be imported as TEXT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Sql,
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sql" => Ok(Self::Sql),
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "ndjson" => Ok(Self::Jsonl),
+            _ => Err(Error::Config(
+                format!("Unknown export format '{}', expected sql, csv or jsonl", s).into(),
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// The typical file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Sql => "sql",
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+pub(crate) fn csv_write_field(buf: &mut String, value: &ValueOwned) {
+    match value {
+        ValueOwned::Null => {}
+        ValueOwned::Integer(i) => write!(buf, "{}", i).unwrap(),
+        ValueOwned::Real(r) => write!(buf, "{}", r).unwrap(),
+        ValueOwned::Text(t) => csv_write_text(buf, t),
+        // an empty BLOB must be quoted like an empty string
+        ValueOwned::Blob(b) => csv_write_text(buf, &hex::encode(b)),
+    }
+}
+
+pub(crate) fn csv_write_text(buf: &mut String, text: &str) {
+    // an empty string must be quoted to distinguish it from `NULL`
+    if text.is_empty() || text.contains([',', '"', '\n', '\r']) {
+        buf.push('"');
+        buf.push_str(&text.replace('"', "\"\""));
+        buf.push('"');
+    } else {
+        buf.push_str(text);
+    }
+}
+
+pub(crate) fn json_value(value: ValueOwned) -> serde_json::Value {
+    match value {
+        ValueOwned::Null => serde_json::Value::Null,
+        ValueOwned::Integer(i) => serde_json::Value::from(i),
+        // SQLite never stores NaN, which is the only case `from` maps to `Null`
+        ValueOwned::Real(r) => serde_json::Value::from(r),
+        ValueOwned::Text(t) => serde_json::Value::String(t),
+        ValueOwned::Blob(b) => serde_json::Value::String(hex::encode(b)),
+    }
+}
+
+pub(crate) fn json_param(value: serde_json::Value) -> Param {
+    match value {
+        serde_json::Value::Null => Param::Null,
+        serde_json::Value::Bool(b) => Param::Integer(b as i64),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Param::Integer(i)
+            } else {
+                Param::Real(n.as_f64().unwrap_or_default())
+            }
+        }
+        serde_json::Value::String(s) => Param::Text(s),
+        value => Param::Text(value.to_string()),
+    }
+}
+
+/// Decodes a hex-encoded BLOB from a CSV or JSONL import for a column with the declared type
+/// `BLOB`. All other params are inserted as they are.
+pub(crate) fn blob_param(param: Param) -> Result<Param, Error> {
+    match param {
+        Param::Text(t) => hex::decode(&t).map(Param::Blob).map_err(|err| {
+            Error::Config(format!("Invalid hex for a BLOB column '{}': {}", t, err).into())
+        }),
+        param => Ok(param),
+    }
+}
+
+/// Approximate size of a param inside a Raft log entry
+#[inline]
+pub(crate) fn param_size(param: &Param) -> usize {
+    match param {
+        Param::Null => 1,
+        Param::Integer(_) | Param::Real(_) => 8,
+        Param::Text(t) => t.len(),
+        Param::Blob(b) => b.len(),
+    }
+}
+
+/// Parses CSV records, which may span multiple lines because of quoted line breaks.
+#[derive(Debug, Default)]
+pub(crate) struct CsvReader {
+    fields: Vec<Option<String>>,
+    field: String,
+    in_quotes: bool,
+    /// `true` if the current field has been quoted, which makes an empty field `""` instead of
+    /// `NULL`
+    quoted: bool,
+}
+
+impl CsvReader {
+    /// Feeds a single line including its line break. Returns a record as soon as it is complete.
+    pub(crate) fn push_line(&mut self, line: &str) -> Result<Option<Vec<Option<String>>>, Error> {
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if self.in_quotes {
+                if c == '"' {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        self.field.push('"');
+                    } else {
+                        self.in_quotes = false;
+                    }
+                } else {
+                    self.field.push(c);
+                }
+                continue;
+            }
+
+            match c {
+                '"' if self.field.is_empty() && !self.quoted => {
+                    self.in_quotes = true;
+                    self.quoted = true;
+                }
+                '"' => {
+                    return Err(Error::Config(
+                        format!(
+                            "Invalid CSV - unexpected quote in line: {}",
+                            line.trim_end()
+                        )
+                        .into(),
+                    ));
+                }
+                ',' => self.finish_field(),
+                '\r' | '\n' => {
+                    // line endings are only allowed at the very end outside of quotes
+                    if chars.any(|c| c != '\n') {
+                        return Err(Error::Config(
+                            format!("Invalid CSV - line break inside line: {}", line.trim_end())
+                                .into(),
+                        ));
+                    }
+                    break;
+                }
+                c => self.field.push(c),
+            }
+        }
+
+        if self.in_quotes {
+            return Ok(None);
+        }
+
+        // a completely empty line is no record
+        if self.fields.is_empty() && self.field.is_empty() && !self.quoted {
+            return Ok(None);
+        }
+
+        self.finish_field();
+        Ok(Some(std::mem::take(&mut self.fields)))
+    }
+
+    /// Returns an error if a quoted field has not been closed at the end of the input.
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        if self.in_quotes {
+            Err(Error::Config(
+                "Invalid CSV - unterminated quoted field at the end of the input".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn finish_field(&mut self) {
+        let field = std::mem::take(&mut self.field);
+        if field.is_empty() && !self.quoted {
+            self.fields.push(None);
+        } else {
+            self.fields.push(Some(field));
+        }
+        self.quoted = false;
+    }
+}
+
+/// Transaction control and `PRAGMA`s, like they are written by the `sqlite3` CLI `.dump`, cannot
+/// be executed inside a batch. Each chunk of an import is running in its own transaction anyway.
+pub(crate) fn is_skipped_stmt(stmt: &str) -> bool {
+    let upper = stmt.trim_end_matches(';').trim().to_uppercase();
+    matches!(
+        upper.as_str(),
+        "BEGIN" | "BEGIN TRANSACTION" | "COMMIT" | "COMMIT TRANSACTION" | "END" | "END TRANSACTION"
+    ) || upper.starts_with("PRAGMA ")
+}
+
+/// Builds the `INSERT` for a CSV or JSONL import.
+pub(crate) fn insert_sql(table: &str, columns: &[String]) -> String {
+    let mut sql = format!("INSERT INTO {} (", quote_ident(table));
+    for (i, col) in columns.iter().enumerate() {
+        if i > 0 {
+            sql.push_str(", ");
+        }
+        sql.push_str(&quote_ident(col));
+    }
+    sql.push_str(") VALUES (");
+    for i in 1..=columns.len() {
+        if i > 1 {
+            sql.push_str(", ");
+        }
+        write!(sql, "${}", i).unwrap();
+    }
+    sql.push(')');
+    sql
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SqlState {
+    Normal,
+    Quoted(char),
+    LineComment,
+    BlockComment,
+}
+
+/// Splits an SQL dump into single statements. The input may be fed in any pieces.
+#[derive(Debug)]
+pub(crate) struct SqlSplitter {
+    stmt: String,
+    state: SqlState,
+}
+
+impl Default for SqlSplitter {
+    fn default() -> Self {
+        Self {
+            stmt: String::default(),
+            state: SqlState::Normal,
+        }
+    }
+}
+
+impl SqlSplitter {
+    /// Feeds the next piece of the input and returns all statements that are complete.
+    pub(crate) fn push(&mut self, input: &str) -> Vec<String> {
+        let mut stmts = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match self.state {
+                SqlState::Normal => match c {
+                    '\'' | '"' | '`' => self.state = SqlState::Quoted(c),
+                    '[' => self.state = SqlState::Quoted(']'),
+                    '-' if chars.peek() == Some(&'-') => {
+                        chars.next();
+                        self.state = SqlState::LineComment;
+                        continue;
+                    }
+                    '/' if chars.peek() == Some(&'*') => {
+                        chars.next();
+                        self.state = SqlState::BlockComment;
+                        continue;
+                    }
+                    ';' if !self.is_unfinished_trigger() => {
+                        let stmt = std::mem::take(&mut self.stmt);
+                        let stmt = stmt.trim();
+                        if !stmt.is_empty() {
+                            stmts.push(format!("{};", stmt));
+                        }
+                        continue;
+                    }
+                    _ => {}
+                },
+                SqlState::Quoted(end) => {
+                    // a doubled quote is an escaped one and will toggle the state twice
+                    if c == end {
+                        self.state = SqlState::Normal;
+                    }
+                }
+                SqlState::LineComment => {
+                    if c == '\n' {
+                        self.state = SqlState::Normal;
+                        self.stmt.push(c);
+                    }
+                    continue;
+                }
+                SqlState::BlockComment => {
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        self.state = SqlState::Normal;
+                        self.stmt.push(' ');
+                    }
+                    continue;
+                }
+            }
+
+            self.stmt.push(c);
+        }
+
+        stmts
+    }
+
+    /// Returns `true` if there is no unfinished statement left.
+    #[cfg(feature = "server")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.state == SqlState::Normal && self.stmt.trim().is_empty()
+    }
+
+    /// Returns the rest of the input, which has not been terminated with a `;`.
+    pub(crate) fn finish(self) -> Option<String> {
+        let stmt = self.stmt.trim();
+        if stmt.is_empty() {
+            None
+        } else {
+            Some(format!("{};", stmt))
+        }
+    }
+
+    /// The body of a trigger contains statements terminated with `;` and ends with `END`.
+    fn is_unfinished_trigger(&self) -> bool {
+        let mut words = self
+            .stmt
+            .split_whitespace()
+            .take(4)
+            .map(|w| w.to_uppercase());
+        let is_trigger =
+            words.next().as_deref() == Some("CREATE") && words.take(2).any(|w| w == "TRIGGER");
+        if !is_trigger {
+            return false;
+        }
+
+        let end = self.stmt.trim_end();
+        let Some(last) = end.rsplit(|c: char| c.is_whitespace() || c == ';').next() else {
+            return true;
+        };
+        !last.eq_ignore_ascii_case("END")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql_splitter() {
+        let mut splitter = SqlSplitter::default();
+        let mut stmts = splitter.push(
+            r#"-- a comment; with a semicolon
+CREATE TABLE "a;b" (id INTEGER PRIMARY KEY, v TEXT);
+INSERT INTO "a;b" VALUES (1, 'it''s; here'), (2, 'multi
+line');
+CREATE TRIGGER t AFTER INSERT ON "a;b" BEGIN
+    DELETE FROM "a;b" WHERE id = 0; /* ; */
+END;
+INSERT INTO "a;b" VALUES (3"#,
+        );
+        stmts.append(&mut splitter.push(", NULL);\nSELECT 1"));
+
+        assert_eq!(stmts.len(), 4);
+        assert_eq!(
+            stmts[0],
+            r#"CREATE TABLE "a;b" (id INTEGER PRIMARY KEY, v TEXT);"#
+        );
+        assert_eq!(
+            stmts[1],
+            "INSERT INTO \"a;b\" VALUES (1, 'it''s; here'), (2, 'multi\nline');"
+        );
+        assert!(stmts[2].starts_with("CREATE TRIGGER t"));
+        assert!(stmts[2].ends_with("END;"));
+        assert_eq!(stmts[3], r#"INSERT INTO "a;b" VALUES (3, NULL);"#);
+        assert_eq!(splitter.finish().as_deref(), Some("SELECT 1;"));
+
+        assert!(is_skipped_stmt("BEGIN TRANSACTION;"));
+        assert!(is_skipped_stmt("PRAGMA foreign_keys=OFF;"));
+        assert!(!is_skipped_stmt(&stmts[0]));
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let values = [
+            ValueOwned::Integer(1),
+            ValueOwned::Null,
+            ValueOwned::Text(String::default()),
+            ValueOwned::Text("with \"quotes\", commas\nand lines".to_string()),
+            ValueOwned::Real(1.5),
+            ValueOwned::Blob(vec![0, 1, 255]),
+            ValueOwned::Blob(Vec::default()),
+        ];
+        let mut line = String::new();
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            csv_write_field(&mut line, value);
+        }
+        line.push('\n');
+
+        let mut reader = CsvReader::default();
+        let mut record = None;
+        for l in line.split_inclusive('\n') {
+            record = reader.push_line(l).unwrap();
+        }
+        reader.finish().unwrap();
+
+        assert_eq!(
+            record.unwrap(),
+            vec![
+                Some("1".to_string()),
+                None,
+                Some(String::default()),
+                Some("with \"quotes\", commas\nand lines".to_string()),
+                Some("1.5".to_string()),
+                Some("0001ff".to_string()),
+                Some(String::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blob_param() {
+        assert_eq!(
+            blob_param(Param::Text("0001ff".to_string())).unwrap(),
+            Param::Blob(vec![0, 1, 255])
+        );
+        assert_eq!(
+            blob_param(Param::Text(String::default())).unwrap(),
+            Param::Blob(Vec::default())
+        );
+        assert_eq!(blob_param(Param::Null).unwrap(), Param::Null);
+        assert!(blob_param(Param::Text("no hex".to_string())).is_err());
+    }
+}
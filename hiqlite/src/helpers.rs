@@ -1,9 +1,8 @@
 use crate::app_state::{AppState, RaftType};
-use crate::{Error, Node};
-use openraft::RaftMetrics;
-use std::collections::BTreeSet;
+use crate::{Error, Node, NodeId};
+use openraft::{ChangeMembers, LogId, RaftMetrics};
 use std::sync::Arc;
-use tokio::sync::MutexGuard;
+use tokio::sync::{watch, MutexGuard};
 
 pub async fn is_raft_initialized(
     state: &Arc<AppState>,
@@ -96,6 +95,93 @@ pub async fn lock_raft<'a>(
     }
 }
 
+pub fn get_raft_metrics_watch(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+) -> watch::Receiver<RaftMetrics<u64, Node>> {
+    match raft_type {
+        #[cfg(feature = "sqlite")]
+        RaftType::Sqlite => state.raft_db.raft.metrics(),
+        #[cfg(feature = "cache")]
+        RaftType::Cache => state.raft_cache.raft.metrics(),
+        RaftType::Unknown => panic!("neither `sqlite` nor `cache` feature enabled"),
+    }
+}
+
+pub fn get_raft_config(state: &Arc<AppState>, raft_type: &RaftType) -> Arc<openraft::Config> {
+    match raft_type {
+        #[cfg(feature = "sqlite")]
+        RaftType::Sqlite => state.raft_db.raft.config().clone(),
+        #[cfg(feature = "cache")]
+        RaftType::Cache => state.raft_cache.raft.config().clone(),
+        RaftType::Unknown => panic!("neither `sqlite` nor `cache` feature enabled"),
+    }
+}
+
+/// Enables or disables the heartbeats this node sends while it is the leader.
+pub fn set_heartbeat(state: &Arc<AppState>, raft_type: &RaftType, enabled: bool) {
+    match raft_type {
+        #[cfg(feature = "sqlite")]
+        RaftType::Sqlite => state.raft_db.raft.runtime_config().heartbeat(enabled),
+        #[cfg(feature = "cache")]
+        RaftType::Cache => state.raft_cache.raft.runtime_config().heartbeat(enabled),
+        RaftType::Unknown => panic!("neither `sqlite` nor `cache` feature enabled"),
+    }
+}
+
+/// Makes this node start an election immediately.
+pub async fn trigger_elect(state: &Arc<AppState>, raft_type: &RaftType) -> Result<(), Error> {
+    match raft_type {
+        #[cfg(feature = "sqlite")]
+        RaftType::Sqlite => state.raft_db.raft.trigger().elect().await?,
+        #[cfg(feature = "cache")]
+        RaftType::Cache => state.raft_cache.raft.trigger().elect().await?,
+        RaftType::Unknown => panic!("neither `sqlite` nor `cache` feature enabled"),
+    }
+    Ok(())
+}
+
+/// Makes this node build a new snapshot and waits until it has been finished.
+/// Returns the last log id included in the snapshot, or `None` if nothing has been applied yet.
+pub async fn trigger_snapshot(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+) -> Result<Option<LogId<NodeId>>, Error> {
+    let mut rx = get_raft_metrics_watch(state, raft_type);
+    let (last_applied, snapshot) = {
+        let metrics = rx.borrow();
+        (metrics.last_applied, metrics.snapshot)
+    };
+    let Some(last_applied) = last_applied else {
+        return Ok(None);
+    };
+    if snapshot == Some(last_applied) {
+        return Ok(snapshot);
+    }
+
+    match raft_type {
+        #[cfg(feature = "sqlite")]
+        RaftType::Sqlite => state.raft_db.raft.trigger().snapshot().await?,
+        #[cfg(feature = "cache")]
+        RaftType::Cache => state.raft_cache.raft.trigger().snapshot().await?,
+        RaftType::Unknown => panic!("neither `sqlite` nor `cache` feature enabled"),
+    }
+
+    loop {
+        let snapshot = rx.borrow().snapshot;
+        if let Some(snapshot) = snapshot {
+            if snapshot.index >= last_applied.index {
+                return Ok(Some(snapshot));
+            }
+        }
+        if rx.changed().await.is_err() {
+            return Err(Error::Error(
+                "Raft has been shut down while building the snapshot".into(),
+            ));
+        }
+    }
+}
+
 pub async fn add_new_learner(
     state: &Arc<AppState>,
     raft_type: &RaftType,
@@ -120,12 +206,15 @@ pub async fn add_new_learner(
     }
 }
 
-pub async fn change_membership(
+pub async fn change_membership<C>(
     state: &Arc<AppState>,
     raft_type: &RaftType,
-    members: BTreeSet<u64>,
+    members: C,
     retain: bool,
-) -> Result<(), Error> {
+) -> Result<(), Error>
+where
+    C: Into<ChangeMembers<NodeId, Node>>,
+{
     match raft_type {
         #[cfg(feature = "sqlite")]
         RaftType::Sqlite => {
@@ -0,0 +1,138 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+#[cfg(feature = "cache")]
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// An idempotency key attached to a write inside the Raft log, see
+/// `Client::with_idempotency_key()`.
+///
+/// The timestamps are created by the leader when it proposes the write. This makes sure that
+/// each node will deduplicate in exactly the same way, even if their clocks or configs differ.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Idempotency {
+    pub key: Cow<'static, str>,
+    /// Unix timestamp in milliseconds of the proposal
+    pub ts: i64,
+    /// Unix timestamp in milliseconds after which the result will be forgotten
+    pub expires: i64,
+}
+
+impl Idempotency {
+    pub fn new(key: Cow<'static, str>, window: Duration) -> Self {
+        let ts = Utc::now().timestamp_millis();
+        Self {
+            key,
+            ts,
+            expires: ts + window.as_millis() as i64,
+        }
+    }
+}
+
+/// In-memory store for the results of idempotent writes, used by the cache state machine.
+#[cfg(feature = "cache")]
+#[derive(Debug)]
+pub struct IdempotencyStore<T> {
+    results: HashMap<String, (i64, T)>,
+    expiries: BTreeSet<(i64, String)>,
+}
+
+#[cfg(feature = "cache")]
+impl<T> Default for IdempotencyStore<T> {
+    fn default() -> Self {
+        Self {
+            results: HashMap::default(),
+            expiries: BTreeSet::default(),
+        }
+    }
+}
+
+#[cfg(feature = "cache")]
+impl<T: Clone> IdempotencyStore<T> {
+    /// Returns the result for the given key, if it has not expired yet.
+    pub fn get(&self, idempotency: &Idempotency) -> Option<T> {
+        self.results
+            .get(idempotency.key.as_ref())
+            .filter(|(expires, _)| *expires >= idempotency.ts)
+            .map(|(_, res)| res.clone())
+    }
+
+    pub fn insert(&mut self, idempotency: Idempotency, res: T) {
+        let key = idempotency.key.into_owned();
+        if let Some((expires, _)) = self.results.insert(key.clone(), (idempotency.expires, res)) {
+            self.expiries.remove(&(expires, key.clone()));
+        }
+        self.expiries.insert((idempotency.expires, key));
+    }
+
+    /// Removes all results which expired before `ts`.
+    pub fn purge(&mut self, ts: i64) {
+        while let Some((expires, _)) = self.expiries.first() {
+            if *expires >= ts {
+                break;
+            }
+            let (_, key) = self.expiries.pop_first().unwrap();
+            self.results.remove(&key);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<(String, i64, T)> {
+        self.results
+            .iter()
+            .map(|(key, (expires, res))| (key.clone(), *expires, res.clone()))
+            .collect()
+    }
+
+    pub fn install_snapshot(&mut self, snapshot: Vec<(String, i64, T)>) {
+        self.results.clear();
+        self.expiries.clear();
+        for (key, expires, res) in snapshot {
+            self.expiries.insert((expires, key.clone()));
+            self.results.insert(key, (expires, res));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "cache"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idempotency_store() {
+        let mut store = IdempotencyStore::default();
+
+        let first = Idempotency {
+            key: "first".into(),
+            ts: 100,
+            expires: 200,
+        };
+        assert!(store.get(&first).is_none());
+        store.insert(first.clone(), 1);
+        assert_eq!(store.get(&first), Some(1));
+
+        let second = Idempotency {
+            key: "second".into(),
+            ts: 150,
+            expires: 250,
+        };
+        store.insert(second.clone(), 2);
+
+        // an expired result must never be returned, even if it has not been purged yet
+        let retry = Idempotency {
+            key: "first".into(),
+            ts: 201,
+            expires: 301,
+        };
+        assert!(store.get(&retry).is_none());
+
+        store.purge(201);
+        assert_eq!(store.snapshot(), vec![("second".to_string(), 250, 2)]);
+
+        let mut installed = IdempotencyStore::default();
+        installed.install_snapshot(store.snapshot());
+        assert_eq!(installed.get(&second), Some(2));
+        installed.purge(251);
+        assert!(installed.snapshot().is_empty());
+    }
+}
@@ -1,15 +1,17 @@
 use crate::app_state::{AppState, RaftType};
+use crate::config::NodeRole;
 use crate::network::management::LearnerReq;
 use crate::network::HEADER_NAME_SECRET;
 use crate::{helpers, Error, Node, NodeId};
 use openraft::Membership;
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
 use tracing::{debug, error};
 
 #[cfg(feature = "sqlite")]
-use crate::store::state_machine::sqlite::TypeConfigSqlite;
+use crate::store::state_machine::sqlite::{seed, writer::WriterRequest, TypeConfigSqlite};
 
 #[cfg(feature = "cache")]
 use crate::store::state_machine::memory::TypeConfigKV;
@@ -22,11 +24,20 @@ use tracing::info;
 pub type IsPristineNode1 = bool;
 
 /// Initializes a fresh node 1, if it has not been set up yet.
+///
+/// If a `seed_db` is given, the pristine database will be replaced with a copy of it before the
+/// init, and the initial snapshot will be built from it right after. This happens before the
+/// API is started, which means no other node can join before the logs have been purged.
 #[cfg(feature = "sqlite")]
+#[allow(clippy::too_many_arguments)]
 pub async fn init_pristine_node_1_db(
     raft: &openraft::Raft<TypeConfigSqlite>,
+    sql_writer: &flume::Sender<WriterRequest>,
+    data_dir: &str,
+    seed_db: Option<&str>,
     this_node: u64,
     nodes: &[Node],
+    learners: &BTreeSet<NodeId>,
     secret_api: &str,
     tls: bool,
     tls_no_verify: bool,
@@ -36,19 +47,41 @@ pub async fn init_pristine_node_1_db(
 
         if is_initialized_timeout_sqlite(raft).await? {
             info!("node 1 raft is already initialized");
+            // A crash right after the init may have interrupted the initial snapshot. A seeded
+            // node 1 always purges its logs, which means nothing has been purged only in this case.
+            if seed_db.is_some() && raft.metrics().borrow().purged.is_none() {
+                seed::build_initial_snapshot(raft).await?;
+            }
             return Ok(());
         }
 
-        if should_node_1_skip_init(&RaftType::Sqlite, nodes, secret_api, tls, tls_no_verify).await?
+        if should_node_1_skip_init(
+            &RaftType::Sqlite,
+            nodes,
+            learners,
+            secret_api,
+            tls,
+            tls_no_verify,
+        )
+        .await?
         {
             info!("node 1 (DB) should skip its own init - found existing cluster on remotes");
             return Ok(());
         }
 
+        if let Some(path) = seed_db {
+            info!("seeding pristine node 1 from {}", path);
+            seed::apply_seed(sql_writer, data_dir, path).await?;
+        }
+
         info!("initializing pristine node 1 raft");
         let mut nodes_set = BTreeMap::new();
         nodes_set.insert(this_node.id, this_node);
         raft.initialize(nodes_set).await?;
+
+        if seed_db.is_some() {
+            seed::build_initial_snapshot(raft).await?;
+        }
     }
 
     Ok(())
@@ -61,6 +94,7 @@ pub async fn init_pristine_node_1_cache(
     raft: &openraft::Raft<TypeConfigKV>,
     this_node: u64,
     nodes: &[Node],
+    learners: &BTreeSet<NodeId>,
     secret_api: &str,
     tls: bool,
     tls_no_verify: bool,
@@ -70,7 +104,16 @@ pub async fn init_pristine_node_1_cache(
 
         // in case of cache raft, a node will never be initialized after start up
 
-        if should_node_1_skip_init(&RaftType::Cache, nodes, secret_api, tls, tls_no_verify).await? {
+        if should_node_1_skip_init(
+            &RaftType::Cache,
+            nodes,
+            learners,
+            secret_api,
+            tls,
+            tls_no_verify,
+        )
+        .await?
+        {
             info!("node 1 (cache) should skip its own init - found existing cluster on remotes");
             return Ok(false);
         }
@@ -100,6 +143,7 @@ fn get_this_node(this_node: u64, nodes: &[Node]) -> Node {
 async fn should_node_1_skip_init(
     raft_type: &RaftType,
     nodes: &[Node],
+    learners: &BTreeSet<NodeId>,
     secret_api: &str,
     tls: bool,
     tls_no_verify: bool,
@@ -113,8 +157,14 @@ async fn should_node_1_skip_init(
         .danger_accept_invalid_certs(tls_no_verify)
         .build()?;
 
+    // Permanent learners are asked for an existing cluster as well, but they never vote and
+    // therefore don't count towards the quorum.
     // no need for +1 since this very node is the +1
-    let quorum = nodes.len() / 2;
+    let voters = nodes
+        .iter()
+        .filter(|node| !learners.contains(&node.id))
+        .count();
+    let quorum = voters / 2;
 
     let scheme = if tls { "https" } else { "http" };
     let mut skip_nodes = vec![1];
@@ -174,7 +224,11 @@ async fn should_node_1_skip_init(
                 }
             }
 
-            if skip_nodes.len() >= quorum {
+            let skip_voters = skip_nodes
+                .iter()
+                .filter(|id| !learners.contains(id))
+                .count();
+            if skip_voters >= quorum {
                 return Ok(false);
             }
         }
@@ -190,13 +244,14 @@ enum SkipBecome {
 }
 
 /// If this node is not a cluster member, it will try to become a learner and
-/// a voting member afterward.
+/// a voting member afterward. A node with `NodeRole::Learner` stays a learner.
 #[tracing::instrument(skip(state, nodes, tls, tls_no_verify))]
 #[allow(clippy::too_many_arguments)]
 pub async fn become_cluster_member(
     state: Arc<AppState>,
     raft_type: &RaftType,
     this_node: u64,
+    role: NodeRole,
     nodes: &[Node],
     is_pristine_cache_node_1: IsPristineNode1,
     election_timeout_max: u64,
@@ -270,6 +325,14 @@ pub async fn become_cluster_member(
         return Ok(());
     }
 
+    if role == NodeRole::Learner {
+        info!(
+            "Node role is 'learner' - staying a permanent {} raft learner",
+            raft_type.as_str()
+        );
+        return Ok(());
+    }
+
     info!("Trying to become {} raft member", raft_type.as_str());
     try_become(
         &state,
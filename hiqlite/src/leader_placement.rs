@@ -0,0 +1,160 @@
+use crate::app_state::{AppState, RaftType};
+use crate::network::management;
+use crate::{helpers, NodeId};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::{task, time};
+use tracing::{debug, info, warn};
+
+/// How often the leader checks if there is a healthy voter with a higher priority.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// The maximum time the leader placement pauses after failed transfers.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 10);
+
+/// Pauses the leader placement after failed transfers, because each attempt stops the heartbeats
+/// for a short time. The pause doubles with each failure in a row, up to `MAX_BACKOFF`.
+#[derive(Debug, Default)]
+struct Backoff {
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    fn is_active(&self) -> bool {
+        self.until.is_some_and(|until| Instant::now() < until)
+    }
+
+    fn delay(failures: u32) -> Duration {
+        CHECK_INTERVAL
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(MAX_BACKOFF)
+    }
+
+    fn failure(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let delay = Self::delay(self.failures);
+        self.until = Some(Instant::now() + delay);
+        delay
+    }
+
+    fn success(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
+
+/// Spawns the leader placement task, if any election priorities have been configured.
+pub fn spawn(state: Arc<AppState>, priorities: BTreeMap<NodeId, u8>) {
+    if priorities.is_empty() {
+        return;
+    }
+
+    task::spawn(async move {
+        #[cfg(feature = "sqlite")]
+        let mut backoff_db = Backoff::default();
+        #[cfg(feature = "cache")]
+        let mut backoff_cache = Backoff::default();
+
+        loop {
+            time::sleep(CHECK_INTERVAL).await;
+
+            #[cfg(feature = "sqlite")]
+            place_leader(&state, &RaftType::Sqlite, &priorities, &mut backoff_db).await;
+            #[cfg(feature = "cache")]
+            place_leader(&state, &RaftType::Cache, &priorities, &mut backoff_cache).await;
+        }
+    });
+}
+
+async fn place_leader(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+    priorities: &BTreeMap<NodeId, u8>,
+    backoff: &mut Backoff,
+) {
+    if helpers::get_raft_leader(state, raft_type).await != Some(state.id) {
+        return;
+    }
+
+    if backoff.is_active() {
+        debug!(
+            "{} leader placement is paused after a failed transfer",
+            raft_type.as_str()
+        );
+        return;
+    }
+
+    let metrics = helpers::get_raft_metrics(state, raft_type).await;
+    let Some(replication) = metrics.replication else {
+        return;
+    };
+
+    let priority = |id: &NodeId| priorities.get(id).copied().unwrap_or(0);
+    let own_priority = priority(&state.id);
+
+    // Only voters which have (almost) caught up are healthy candidates. Anything else would
+    // only result in a failed transfer.
+    let max_lag = helpers::get_raft_config(state, raft_type).max_payload_entries;
+    let last_log_index = metrics.last_log_index.unwrap_or(0);
+    let candidate = metrics
+        .membership_config
+        .voter_ids()
+        .filter(|id| *id != state.id && priority(id) > own_priority)
+        .filter_map(|id| {
+            let matched = replication.get(&id)?.as_ref()?.index;
+            (matched + max_lag >= last_log_index).then_some((priority(&id), matched, id))
+        })
+        .max()
+        .map(|(_, _, id)| id);
+
+    let Some(to) = candidate else {
+        debug!(
+            "No healthy {} voter with a higher priority than this leader",
+            raft_type.as_str()
+        );
+        return;
+    };
+
+    info!(
+        "Moving {} leadership to node {} with a higher priority",
+        raft_type.as_str(),
+        to
+    );
+    match management::transfer_leadership(state, raft_type, Some(to)).await {
+        Ok(_) => backoff.success(),
+        Err(err) => {
+            let delay = backoff.failure();
+            warn!(
+                "Error moving the {} leadership to node {}, next try in {} s: {}",
+                raft_type.as_str(),
+                to,
+                delay.as_secs(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(Backoff::delay(1), Duration::from_secs(60));
+        assert_eq!(Backoff::delay(2), Duration::from_secs(120));
+        assert_eq!(Backoff::delay(5), MAX_BACKOFF);
+        assert_eq!(Backoff::delay(u32::MAX), MAX_BACKOFF);
+
+        let mut backoff = Backoff::default();
+        assert!(!backoff.is_active());
+        backoff.failure();
+        backoff.failure();
+        assert_eq!(backoff.failures, 2);
+        assert!(backoff.is_active());
+        backoff.success();
+        assert!(!backoff.is_active());
+    }
+}
@@ -2,6 +2,9 @@
 
 #![doc = include_str!("../README.md")]
 #![forbid(unsafe_code)]
+// `Error` is large because of the wrapped `openraft` errors. Boxing them would break each
+// `ForwardToLeader` match for users, and errors are never returned on any hot path.
+#![allow(clippy::result_large_err)]
 #![cfg_attr(doc, feature(doc_auto_cfg))]
 
 #[cfg(feature = "sqlite")]
@@ -13,9 +16,11 @@ use std::fmt::{Debug, Display};
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 pub use crate::{client::Client, error::Error};
 #[cfg(any(feature = "sqlite", feature = "cache"))]
-pub use config::{NodeConfig, RaftConfig};
+pub use config::{NodeConfig, NodeRole, RaftConfig, SnapshotCompression};
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 pub use tls::ServerTlsConfig;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
+pub use trace::TraceContext;
 
 #[cfg(feature = "cache")]
 pub use num_derive::ToPrimitive;
@@ -25,13 +30,28 @@ pub use strum::EnumIter;
 #[cfg(feature = "dlock")]
 pub use client::dlock::Lock;
 
+#[cfg(feature = "sqlite")]
+pub use crate::export::ExportFormat;
 #[cfg(feature = "sqlite")]
 pub use crate::query::rows::Row;
 #[cfg(feature = "sqlite")]
-pub use crate::store::state_machine::sqlite::{param::Param, state_machine::Params};
+pub use crate::query::stats::{StatementKind, StatementStats};
+#[cfg(feature = "sqlite")]
+pub use crate::store::state_machine::sqlite::{
+    param::{Json, Param},
+    state_machine::{Params, TxnStep, TxnStepResult},
+};
 #[cfg(feature = "sqlite")]
 pub use migration::AppliedMigration;
 
+#[cfg(feature = "backup")]
+pub use backup::{
+    BackupConfig, BackupInfo, BackupObject, BackupRetention, BackupSource, BackupTarget,
+    DirBackupTarget, S3BackupTarget,
+};
+#[cfg(feature = "backup")]
+pub use log_archive::{LogArchiveConfig, LogArchiveTarget, RecoveryTarget};
+
 // TODO remove after enough crash testing and making sure we can never get into a
 // split brain situation
 #[cfg(any(feature = "sqlite", feature = "cache"))]
@@ -44,12 +64,20 @@ mod client;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 mod config;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
+mod config_file;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
 mod error;
+#[cfg(feature = "sqlite")]
+mod export;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 mod helpers;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
+mod idempotency;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
 mod init;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
+mod leader_placement;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
 mod network;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 mod start;
@@ -57,11 +85,15 @@ mod start;
 mod store;
 #[cfg(any(feature = "sqlite", feature = "cache"))]
 mod tls;
+#[cfg(any(feature = "sqlite", feature = "cache"))]
+mod trace;
 
 #[cfg(feature = "backup")]
 mod backup;
 #[cfg(feature = "dashboard")]
 mod dashboard;
+#[cfg(feature = "backup")]
+mod log_archive;
 #[cfg(feature = "sqlite")]
 mod migration;
 #[cfg(feature = "sqlite")]
@@ -0,0 +1,802 @@
+use crate::app_state::{AppState, RaftType};
+use crate::backup::{BackupTarget, DirBackupTarget, S3BackupTarget};
+use crate::helpers::set_path_access;
+use crate::s3::S3Config;
+use crate::store::state_machine::sqlite::state_machine::{BackupMeta, QueryWrite};
+use crate::{config, helpers, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::{fs, select, task, time};
+use tracing::{debug, error, info, warn};
+
+/// The prefix for all archived log segments inside the `BackupTarget`.
+const PREFIX: &str = "hiqlite_logs/";
+/// The max amount of log entries inside a single segment.
+const SEGMENT_MAX_ENTRIES: usize = 10_000;
+/// The max amount of buffered entries while the archive target is unreachable. The oldest ones
+/// will be dropped after that, which leaves a gap inside the archive.
+const BUF_MAX_ENTRIES: usize = SEGMENT_MAX_ENTRIES * 10;
+/// The maximum time uploads pause after failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 5);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Where archived Raft log segments should be stored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogArchiveTarget {
+    /// The S3 bucket from `NodeConfig::s3_config`. Segments will be encrypted like backups.
+    S3,
+    /// A local directory, which should be a mounted network volume in most cases.
+    Dir(String),
+}
+
+impl LogArchiveTarget {
+    fn backup_target(
+        &self,
+        s3_config: &Option<Arc<S3Config>>,
+    ) -> Result<Arc<dyn BackupTarget>, Error> {
+        match self {
+            Self::S3 => {
+                let s3_config = s3_config.clone().ok_or_else(|| {
+                    Error::S3("No `S3Config` given, cannot use the S3 log archive".to_string())
+                })?;
+                Ok(Arc::new(S3BackupTarget::new(s3_config)))
+            }
+            Self::Dir(dir) => Ok(Arc::new(DirBackupTarget::new(dir.as_str(), false))),
+        }
+    }
+}
+
+impl TryFrom<&str> for LogArchiveTarget {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value == "s3" {
+            Ok(Self::S3)
+        } else if let Some(dir) = value.strip_prefix("file:") {
+            Ok(Self::Dir(dir.trim_end_matches('/').to_string()))
+        } else {
+            Err(Error::Config(
+                "the log archive must be either 's3' or start with 'file:'".into(),
+            ))
+        }
+    }
+}
+
+/// Continuous archiving of committed SQLite Raft logs for point-in-time recovery.
+///
+/// The leader uploads all logs it applied since the last upload as a new segment every
+/// `interval`, which means the `interval` is your max data loss (RPO) in case of a disaster.
+/// Together with a base backup, the database can be restored to any log index or timestamp
+/// covered by the archive, see `RecoveryTarget`.
+#[derive(Debug, Clone)]
+pub struct LogArchiveConfig {
+    pub target: LogArchiveTarget,
+    pub interval: Duration,
+}
+
+impl LogArchiveConfig {
+    pub fn new(target: LogArchiveTarget, interval: Duration) -> Self {
+        Self { target, interval }
+    }
+
+    pub(crate) fn from_env() -> Result<Option<Self>, Error> {
+        let Ok(target) = env::var("HQL_BACKUP_LOG_ARCHIVE") else {
+            return Ok(None);
+        };
+        let target = LogArchiveTarget::try_from(target.as_str())?;
+        let interval =
+            config::env_parse::<u64>("HQL_BACKUP_LOG_ARCHIVE_INTERVAL_SECS")?.unwrap_or(60);
+
+        Ok(Some(Self {
+            target,
+            interval: Duration::from_secs(interval.max(1)),
+        }))
+    }
+}
+
+/// Up to which point archived logs should be replayed on top of a restored backup.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecoveryTarget {
+    /// Replays all logs available in the archive.
+    Latest,
+    /// Replays all logs up to and including this log index.
+    LogIndex(u64),
+    /// Replays all logs which have been applied up to this point in time.
+    Timestamp(DateTime<Utc>),
+}
+
+impl RecoveryTarget {
+    /// Reads the target from `HQL_BACKUP_RESTORE_UNTIL`, which can be either `latest`,
+    /// `index:<log_index>` or an RFC3339 timestamp.
+    pub(crate) fn from_env() -> Result<Option<Self>, Error> {
+        match env::var("HQL_BACKUP_RESTORE_UNTIL") {
+            Ok(value) => Self::try_from(value.as_str()).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn includes(&self, entry: &ArchivedEntry) -> bool {
+        match self {
+            Self::Latest => true,
+            Self::LogIndex(index) => entry.index <= *index,
+            Self::Timestamp(ts) => entry.timestamp <= ts.timestamp_millis(),
+        }
+    }
+}
+
+impl TryFrom<&str> for RecoveryTarget {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        if value == "latest" {
+            Ok(Self::Latest)
+        } else if let Some(index) = value.strip_prefix("index:") {
+            index
+                .parse::<u64>()
+                .map(Self::LogIndex)
+                .map_err(|_| Error::Config("Cannot parse recovery target log index".into()))
+        } else {
+            DateTime::parse_from_rfc3339(value)
+                .map(|ts| Self::Timestamp(ts.to_utc()))
+                .map_err(|_| {
+                    Error::Config(
+                        "recovery target must be 'latest', 'index:<log_index>' or an RFC3339 \
+                        timestamp"
+                            .into(),
+                    )
+                })
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedEntry {
+    index: u64,
+    /// Unix timestamp in millis when the entry has been applied
+    timestamp: i64,
+    /// `None` for blank and membership entries, which are archived to detect gaps
+    payload: Option<QueryWrite>,
+}
+
+/// An archived segment with the key format `<generation>/<first>_<last>_<ts_last>.seg`.
+///
+/// A new generation is started with each restore, because the restored cluster will start with
+/// fresh log indexes again.
+#[derive(Debug)]
+struct Segment {
+    key: String,
+    generation: u64,
+    first: u64,
+    last: u64,
+    /// Unix timestamp in seconds of the last entry
+    ts_last: i64,
+}
+
+impl Segment {
+    fn parse(key: &str) -> Option<Self> {
+        let (generation, name) = key.split_once('/')?;
+        let mut parts = name.strip_suffix(".seg")?.split('_');
+        Some(Self {
+            key: key.to_string(),
+            generation: generation.parse().ok()?,
+            first: parts.next()?.parse().ok()?,
+            last: parts.next()?.parse().ok()?,
+            ts_last: parts.next()?.parse().ok()?,
+        })
+    }
+
+    fn key(generation: u64, first: u64, last: u64, ts_last: i64) -> String {
+        format!(
+            "{:08}/{:020}_{:020}_{}.seg",
+            generation, first, last, ts_last
+        )
+    }
+}
+
+#[inline]
+fn generation_of(key: &str) -> Option<u64> {
+    key.split_once('/')?.0.parse().ok()
+}
+
+#[derive(Debug)]
+pub(crate) struct LogArchive {
+    config: LogArchiveConfig,
+    target: Arc<dyn BackupTarget>,
+    path_tmp: String,
+    keep_days: u16,
+    generation: AtomicU64,
+    tx: flume::Sender<ArchivedEntry>,
+    rx: flume::Receiver<ArchivedEntry>,
+}
+
+impl LogArchive {
+    pub fn new(
+        config: LogArchiveConfig,
+        s3_config: &Option<Arc<S3Config>>,
+        data_dir: &str,
+        keep_days: u16,
+    ) -> Result<Arc<Self>, Error> {
+        let target = config.target.backup_target(s3_config)?;
+        let (tx, rx) = flume::unbounded();
+        Ok(Arc::new(Self {
+            config,
+            target,
+            path_tmp: format!("{}/log_archive", data_dir),
+            keep_days,
+            generation: AtomicU64::new(0),
+            tx,
+            rx,
+        }))
+    }
+
+    /// Adds an applied entry to the archive buffer. Must be called for every applied entry in
+    /// order, with `None` for anything that is not a `QueryWrite`.
+    #[inline]
+    pub fn push(&self, index: u64, payload: Option<&QueryWrite>) {
+        let payload = payload.map(|query| match query {
+            // the trace context is meaningless for a later replay
+            QueryWrite::Traced((_, query)) => query.as_ref().clone(),
+            query => query.clone(),
+        });
+        let _ = self.tx.send(ArchivedEntry {
+            index,
+            timestamp: Utc::now().timestamp_millis(),
+            payload,
+        });
+    }
+
+    /// The current archive generation, `0` if it is not known yet.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Starts a new generation after a restore. Each generation has its own log indexes.
+    pub async fn start_generation(&self) -> Result<u64, Error> {
+        let generation = self
+            .list()
+            .await?
+            .iter()
+            .filter_map(|key| generation_of(key))
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.write(&format!("{:08}/start", generation), Vec::default())
+            .await?;
+        self.generation.store(generation, Ordering::Relaxed);
+        info!("Started new log archive generation {}", generation);
+        Ok(generation)
+    }
+
+    /// Returns all keys relative to the archive root.
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .target
+            .list(PREFIX)
+            .await?
+            .into_iter()
+            .filter_map(|object| object.name.strip_prefix(PREFIX).map(String::from))
+            .collect())
+    }
+
+    async fn write(&self, key: &str, bytes: Vec<u8>) -> Result<(), Error> {
+        let path = self.path_tmp_for(key).await?;
+        fs::write(&path, bytes).await?;
+        let res = self
+            .target
+            .upload(&path, &format!("{}{}", PREFIX, key))
+            .await;
+        let _ = fs::remove_file(&path).await;
+        res
+    }
+
+    async fn read(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let path = self.path_tmp_for(key).await?;
+        self.target
+            .download(&format!("{}{}", PREFIX, key), &path)
+            .await?;
+        let bytes = fs::read(&path).await;
+        let _ = fs::remove_file(&path).await;
+        Ok(bytes?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.target.delete(&format!("{}{}", PREFIX, key)).await
+    }
+
+    async fn path_tmp_for(&self, key: &str) -> Result<String, Error> {
+        fs::create_dir_all(&self.path_tmp).await?;
+        set_path_access(&self.path_tmp, 0o700).await?;
+        Ok(format!("{}/{}", self.path_tmp, key.replace('/', "_")))
+    }
+
+    /// Returns the current generation and the last archived log index inside of it.
+    async fn find_last_archived(&self) -> Result<(u64, u64), Error> {
+        let keys = self.list().await?;
+        let generation = keys
+            .iter()
+            .filter_map(|key| generation_of(key))
+            .max()
+            .unwrap_or(1);
+        let last = keys
+            .iter()
+            .filter_map(|key| Segment::parse(key))
+            .filter(|segment| segment.generation == generation)
+            .map(|segment| segment.last)
+            .max()
+            .unwrap_or(0);
+        Ok((generation, last))
+    }
+
+    /// Deletes all segments whose newest entry is older than `keep_days`.
+    async fn cleanup(&self) -> Result<(), Error> {
+        let threshold = Utc::now().timestamp() - self.keep_days as i64 * 24 * 60 * 60;
+        for segment in self
+            .list()
+            .await?
+            .iter()
+            .filter_map(|key| Segment::parse(key))
+        {
+            if segment.ts_last < threshold {
+                info!("Deleting expired log archive segment {}", segment.key);
+                self.delete(&segment.key).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pauses uploads after the archive target failed. Otherwise, each new entry would trigger
+/// another attempt as soon as the buffer holds a full segment. The pause starts at the archive
+/// interval and doubles with each failure in a row, up to `MAX_BACKOFF`.
+#[derive(Debug)]
+struct Backoff {
+    interval: Duration,
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            failures: 0,
+            until: None,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.until.is_some_and(|until| Instant::now() < until)
+    }
+
+    fn delay(&self) -> Duration {
+        self.interval
+            .saturating_mul(2u32.saturating_pow(self.failures.saturating_sub(1)))
+            .min(MAX_BACKOFF)
+    }
+
+    fn failure(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let delay = self.delay();
+        self.until = Some(Instant::now() + delay);
+        delay
+    }
+
+    fn success(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
+
+/// Appends the entry and drops the oldest one, if the buffer is full.
+fn push_capped(buf: &mut VecDeque<ArchivedEntry>, entry: ArchivedEntry) -> Option<ArchivedEntry> {
+    buf.push_back(entry);
+    if buf.len() > BUF_MAX_ENTRIES {
+        buf.pop_front()
+    } else {
+        None
+    }
+}
+
+fn warn_gap(last_archived: u64, first: u64) {
+    warn!(
+        "Gap in the log archive between index {} and {} - a point-in-time recovery will only be \
+        possible up to index {}",
+        last_archived, first, last_archived
+    );
+}
+
+/// Spawns the archiver task. Each node buffers the entries it applied, but only the leader
+/// uploads them. Followers keep their entries for a few intervals to be able to continue
+/// seamlessly in case of a leader change.
+pub(crate) fn spawn(state: Arc<AppState>, archive: Arc<LogArchive>) {
+    task::spawn(async move {
+        info!("Raft log archive task started");
+
+        match archive.find_last_archived().await {
+            Ok((generation, _)) => archive.generation.store(generation, Ordering::Relaxed),
+            Err(err) => error!("Error reading the log archive: {}", err),
+        }
+
+        let mut archiver = Archiver {
+            state,
+            archive: archive.clone(),
+            buf: VecDeque::new(),
+            last_archived: None,
+            last_cleanup: Instant::now(),
+            backoff: Backoff::new(archive.config.interval),
+            dropping: false,
+        };
+        let mut interval = time::interval(archive.config.interval);
+
+        loop {
+            select! {
+                res = archive.rx.recv_async() => {
+                    let Ok(entry) = res else {
+                        break;
+                    };
+                    archiver.push(entry);
+                    if archiver.buf.len() >= SEGMENT_MAX_ENTRIES && !archiver.backoff.is_active() {
+                        archiver.flush().await;
+                    }
+                }
+                _ = interval.tick() => archiver.flush().await,
+            }
+        }
+
+        warn!("Raft log archive task exiting");
+    });
+}
+
+struct Archiver {
+    state: Arc<AppState>,
+    archive: Arc<LogArchive>,
+    buf: VecDeque<ArchivedEntry>,
+    /// Only `Some(_)` while this node is the leader
+    last_archived: Option<u64>,
+    last_cleanup: Instant,
+    backoff: Backoff,
+    /// `true` while entries are dropped because of a full buffer
+    dropping: bool,
+}
+
+impl Archiver {
+    fn push(&mut self, entry: ArchivedEntry) {
+        let Some(dropped) = push_capped(&mut self.buf, entry) else {
+            return;
+        };
+        // followers drop old entries anyway, this only matters for the leader
+        if let Some(last_archived) = self.last_archived {
+            if !self.dropping && dropped.index > last_archived {
+                self.dropping = true;
+                let first = self.buf.front().map(|entry| entry.index).unwrap_or(0);
+                warn_gap(last_archived, first);
+            }
+        }
+    }
+
+    async fn flush(&mut self) {
+        if helpers::get_raft_leader(&self.state, &RaftType::Sqlite).await != Some(self.state.id) {
+            self.last_archived = None;
+            let threshold =
+                Utc::now().timestamp_millis() - self.archive.config.interval.as_millis() as i64 * 3;
+            while self
+                .buf
+                .front()
+                .is_some_and(|entry| entry.timestamp < threshold)
+            {
+                self.buf.pop_front();
+            }
+            return;
+        }
+
+        if self.backoff.is_active() {
+            debug!("Log archive uploads are paused after a failure");
+            return;
+        }
+
+        let mut last_archived = match self.last_archived {
+            Some(last) => last,
+            None => match self.archive.find_last_archived().await {
+                Ok((generation, last)) => {
+                    debug!(
+                        "Log archive generation {} is archived up to index {}",
+                        generation, last
+                    );
+                    self.archive.generation.store(generation, Ordering::Relaxed);
+                    last
+                }
+                Err(err) => {
+                    let delay = self.backoff.failure();
+                    error!(
+                        "Error reading the log archive, next try in {} s: {}",
+                        delay.as_secs(),
+                        err
+                    );
+                    return;
+                }
+            },
+        };
+        self.last_archived = Some(last_archived);
+
+        while self
+            .buf
+            .front()
+            .is_some_and(|entry| entry.index <= last_archived)
+        {
+            self.buf.pop_front();
+        }
+
+        while !self.buf.is_empty() {
+            let first = self.buf.front().unwrap().index;
+            if last_archived > 0 && first != last_archived + 1 && !self.dropping {
+                warn_gap(last_archived, first);
+            }
+
+            let len = self.buf.len().min(SEGMENT_MAX_ENTRIES);
+            let entries = self.buf.range(..len).collect::<Vec<_>>();
+            let last = entries[len - 1];
+            let key = Segment::key(
+                self.archive.generation(),
+                first,
+                last.index,
+                last.timestamp / 1000,
+            );
+            let bytes = bincode::serialize(&entries).unwrap();
+
+            if let Err(err) = self.archive.write(&key, bytes).await {
+                let delay = self.backoff.failure();
+                error!(
+                    "Error archiving Raft logs into {}, next try in {} s: {}",
+                    key,
+                    delay.as_secs(),
+                    err
+                );
+                return;
+            }
+            debug!("Archived Raft logs segment {}", key);
+            self.backoff.success();
+            self.dropping = false;
+
+            last_archived = last.index;
+            self.last_archived = Some(last_archived);
+            self.buf.drain(..len);
+        }
+
+        if self.last_cleanup.elapsed() > CLEANUP_INTERVAL {
+            self.last_cleanup = Instant::now();
+            if let Err(err) = self.archive.cleanup().await {
+                error!("Error during log archive cleanup: {}", err);
+            }
+        }
+    }
+}
+
+/// What to do with an archived entry during a replay.
+#[derive(Debug)]
+enum ReplayStep {
+    /// The entry is included in the restored backup already
+    Skip,
+    /// The entry does not change the database
+    Ignore,
+    Apply(QueryWrite),
+    Stop,
+}
+
+fn replay_step(entry: ArchivedEntry, next: u64, target: &RecoveryTarget) -> ReplayStep {
+    if entry.index < next {
+        return ReplayStep::Skip;
+    }
+    if entry.index > next {
+        warn!(
+            "Gap in the log archive after index {} - stopping the replay",
+            next - 1
+        );
+        return ReplayStep::Stop;
+    }
+    if !target.includes(&entry) {
+        return ReplayStep::Stop;
+    }
+
+    match entry.payload {
+        None | Some(QueryWrite::Backup(_)) | Some(QueryWrite::RTT) => ReplayStep::Ignore,
+        // All following entries were written on top of the restored backup, which cannot be
+        // replayed reliably, and the source may not even exist anymore.
+        Some(QueryWrite::Restore(_)) => {
+            error!(
+                "The original cluster has been restored from a backup at index {} - stopping the \
+                replay at index {}. To go further, restore the backup used at that time.",
+                entry.index,
+                next - 1
+            );
+            ReplayStep::Stop
+        }
+        Some(query) => ReplayStep::Apply(query),
+    }
+}
+
+/// Replays archived logs on top of a freshly restored backup through the Raft.
+///
+/// Returns the Raft log index of the last replayed entry, if any.
+pub(crate) async fn replay(
+    state: &Arc<AppState>,
+    archive: &LogArchive,
+    base: BackupMeta,
+    target: RecoveryTarget,
+) -> Result<Option<u64>, Error> {
+    if base.archive_generation == 0 {
+        return Err(Error::Error(
+            "The restored backup has been created without a log archive".into(),
+        ));
+    }
+    info!(
+        "Replaying archived logs after index {} up to {:?}",
+        base.log_index, target
+    );
+
+    let mut segments = archive
+        .list()
+        .await?
+        .iter()
+        .filter_map(|key| Segment::parse(key))
+        .filter(|s| s.generation == base.archive_generation && s.last > base.log_index)
+        .collect::<Vec<_>>();
+    segments.sort_by_key(|s| s.first);
+
+    let mut next = base.log_index + 1;
+    let mut last_log = None;
+    'segments: for segment in segments {
+        let bytes = archive.read(&segment.key).await?;
+        let entries: Vec<ArchivedEntry> = bincode::deserialize(&bytes)?;
+
+        for entry in entries {
+            match replay_step(entry, next, &target) {
+                ReplayStep::Skip => continue,
+                ReplayStep::Stop => break 'segments,
+                ReplayStep::Ignore => next += 1,
+                ReplayStep::Apply(query) => {
+                    next += 1;
+                    let res = state.raft_db.raft.client_write(query).await?;
+                    last_log = Some(res.log_id.index);
+                }
+            }
+        }
+    }
+
+    info!(
+        "Replayed archived logs up to index {} of the original cluster",
+        next - 1
+    );
+    Ok(last_log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_key() {
+        let key = Segment::key(3, 1, 10_000, 1700000000);
+        assert_eq!(
+            key,
+            "00000003/00000000000000000001_00000000000000010000_1700000000.seg"
+        );
+
+        let segment = Segment::parse(&key).unwrap();
+        assert_eq!(segment.generation, 3);
+        assert_eq!(segment.first, 1);
+        assert_eq!(segment.last, 10_000);
+        assert_eq!(segment.ts_last, 1700000000);
+
+        assert!(Segment::parse("00000003/start").is_none());
+        assert_eq!(generation_of("00000003/start"), Some(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(10));
+        assert!(!backoff.is_active());
+        assert_eq!(backoff.failure(), Duration::from_secs(10));
+        assert_eq!(backoff.failure(), Duration::from_secs(20));
+        assert!(backoff.is_active());
+        time::advance(Duration::from_secs(21)).await;
+        assert!(!backoff.is_active());
+
+        backoff.failures = u32::MAX - 1;
+        assert_eq!(backoff.failure(), MAX_BACKOFF);
+        backoff.success();
+        assert!(!backoff.is_active());
+    }
+
+    #[test]
+    fn test_push_capped() {
+        let entry = |index| ArchivedEntry {
+            index,
+            timestamp: 0,
+            payload: None,
+        };
+        let mut buf = VecDeque::new();
+        for index in 1..=BUF_MAX_ENTRIES as u64 {
+            assert!(push_capped(&mut buf, entry(index)).is_none());
+        }
+        let dropped = push_capped(&mut buf, entry(BUF_MAX_ENTRIES as u64 + 1)).unwrap();
+        assert_eq!(dropped.index, 1);
+        assert_eq!(buf.len(), BUF_MAX_ENTRIES);
+        assert_eq!(buf.front().unwrap().index, 2);
+    }
+
+    #[test]
+    fn test_replay_step() {
+        let entry = |index, payload| ArchivedEntry {
+            index,
+            timestamp: 0,
+            payload,
+        };
+        let target = RecoveryTarget::Latest;
+
+        assert!(matches!(
+            replay_step(entry(4, None), 5, &target),
+            ReplayStep::Skip
+        ));
+        assert!(matches!(
+            replay_step(entry(6, None), 5, &target),
+            ReplayStep::Stop
+        ));
+        assert!(matches!(
+            replay_step(entry(5, Some(QueryWrite::RTT)), 5, &target),
+            ReplayStep::Ignore
+        ));
+        assert!(matches!(
+            replay_step(
+                entry(5, Some(QueryWrite::Batch("SELECT 1;".into()))),
+                5,
+                &target
+            ),
+            ReplayStep::Apply(QueryWrite::Batch(_))
+        ));
+        assert!(matches!(
+            replay_step(
+                entry(5, Some(QueryWrite::RTT)),
+                5,
+                &RecoveryTarget::LogIndex(4)
+            ),
+            ReplayStep::Stop
+        ));
+
+        // an online restore in the original cluster cannot be replayed
+        let restore = QueryWrite::Restore((
+            crate::backup::BackupSource::S3("backup_node_1_1735689600.sqlite".into()),
+            Default::default(),
+            Default::default(),
+        ));
+        assert!(matches!(
+            replay_step(entry(5, Some(restore)), 5, &target),
+            ReplayStep::Stop
+        ));
+    }
+
+    #[test]
+    fn test_recovery_target() {
+        assert_eq!(
+            RecoveryTarget::try_from("latest").unwrap(),
+            RecoveryTarget::Latest
+        );
+        assert_eq!(
+            RecoveryTarget::try_from("index:42").unwrap(),
+            RecoveryTarget::LogIndex(42)
+        );
+        assert!(matches!(
+            RecoveryTarget::try_from("2025-01-01T12:00:00Z").unwrap(),
+            RecoveryTarget::Timestamp(_)
+        ));
+        assert!(RecoveryTarget::try_from("yesterday").is_err());
+    }
+}
@@ -1,17 +1,21 @@
 use crate::app_state::RaftType;
+use crate::idempotency::Idempotency;
 use crate::network::handshake::HandshakeSecret;
+use crate::network::management::{self, MembershipChange};
 use crate::network::{validate_secret, AppStateExt, Error};
+use crate::{trace, TraceContext};
 use axum::extract::Path;
 use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use fastwebsockets::{upgrade, FragmentCollectorRead, Frame, OpCode, Payload};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::{Deref, Sub};
 use std::time::Duration;
 use tokio::{task, time};
-use tracing::{error, info, warn};
+use tracing::{error, field, info, info_span, warn, Instrument};
 
 #[cfg(feature = "cache")]
 use crate::store::state_machine::memory::{
@@ -28,7 +32,7 @@ use crate::store::state_machine::memory::dlock_handler::{
 use crate::{
     migration::Migration,
     query::{query_consistent_local, query_owned_local, rows::RowOwned},
-    store::state_machine::sqlite::state_machine::{Query, QueryWrite},
+    store::state_machine::sqlite::state_machine::{Query, QueryWrite, TxnStep, TxnStepOutput},
 };
 
 #[cfg(feature = "listen_notify")]
@@ -280,6 +284,8 @@ pub async fn stream(
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct ApiStreamRequest {
     pub(crate) request_id: usize,
+    pub(crate) trace: Option<TraceContext>,
+    pub(crate) idempotency_key: Option<Cow<'static, str>>,
     pub(crate) payload: ApiStreamRequestPayload,
 }
 
@@ -292,6 +298,8 @@ pub(crate) enum ApiStreamRequestPayload {
     #[cfg(feature = "sqlite")]
     Transaction(Vec<Query>),
     #[cfg(feature = "sqlite")]
+    TransactionGuarded(Vec<TxnStep>),
+    #[cfg(feature = "sqlite")]
     QueryConsistent(Query),
     #[cfg(feature = "sqlite")]
     Batch(std::borrow::Cow<'static, str>),
@@ -300,6 +308,15 @@ pub(crate) enum ApiStreamRequestPayload {
 
     #[cfg(feature = "backup")]
     Backup(crate::NodeId),
+    #[cfg(feature = "backup")]
+    Restore(crate::backup::BackupSource),
+    #[cfg(all(feature = "backup", feature = "cache"))]
+    RestoreCache(crate::backup::BackupSource),
+    #[cfg(feature = "backup")]
+    ListBackups,
+
+    Membership(MembershipChange),
+    TransferLeadership(Option<crate::NodeId>),
 
     #[cfg(feature = "cache")]
     KV(CacheRequest),
@@ -330,6 +347,8 @@ pub(crate) enum ApiStreamResponsePayload {
     #[cfg(feature = "sqlite")]
     Transaction(Result<Vec<Result<usize, Error>>, Error>),
     #[cfg(feature = "sqlite")]
+    TransactionGuarded(Result<Vec<TxnStepOutput>, Error>),
+    #[cfg(feature = "sqlite")]
     Query(Result<Vec<RowOwned>, Error>),
     #[cfg(feature = "sqlite")]
     QueryConsistent(Result<Vec<RowOwned>, Error>),
@@ -340,6 +359,15 @@ pub(crate) enum ApiStreamResponsePayload {
 
     #[cfg(feature = "backup")]
     Backup(Result<(), Error>),
+    #[cfg(feature = "backup")]
+    Restore(Result<(), Error>),
+    #[cfg(all(feature = "backup", feature = "cache"))]
+    RestoreCache(Result<(), Error>),
+    #[cfg(feature = "backup")]
+    ListBackups(Result<Vec<crate::backup::BackupInfo>, Error>),
+
+    Membership(Result<(), Error>),
+    TransferLeadership(Result<crate::NodeId, Error>),
 
     #[cfg(feature = "cache")]
     KV(Result<CacheResponse, Error>),
@@ -365,7 +393,7 @@ async fn handle_socket_concurrent(
     let mut ws = socket.await?;
     ws.set_auto_close(true);
 
-    let client_id = match HandshakeSecret::server(&mut ws, state.secret_api.as_bytes()).await {
+    let client_id = match HandshakeSecret::server(&mut ws, &state.secrets_api()).await {
         Ok(id) => id,
         Err(err) => {
             error!("Error during WebSocket handshake: {}", err);
@@ -404,6 +432,8 @@ async fn handle_socket_concurrent(
         }
     }
 
+    let raft_name = raft_type.as_str();
+    let raft_type_req = raft_type.clone();
     let st = state.clone();
     let handle_write = task::spawn(async move {
         let mut buf = VecDeque::default();
@@ -490,10 +520,25 @@ async fn handle_socket_concurrent(
             }
         };
 
+        let span = info_span!(
+            "forwarded_request",
+            raft = raft_name,
+            request_id = req.request_id,
+            trace_id = field::Empty
+        );
+        let req_trace = req.trace;
+        if let Some(trace) = &req_trace {
+            span.record("trace_id", field::display(trace));
+        }
+
         let state = state.clone();
+        let raft_type = raft_type_req.clone();
         let tx_write = tx_write.clone();
-        task::spawn(async move {
+        let fut = async move {
             let request_id = req.request_id;
+            let idempotency = req
+                .idempotency_key
+                .map(|key| Idempotency::new(key, state.idempotency_window));
 
             let res = match req.payload {
                 #[cfg(feature = "sqlite")]
@@ -501,7 +546,8 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::Execute(sql))
+                        .client_write(QueryWrite::Execute(sql).idempotent(idempotency).traced())
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -527,7 +573,12 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::ExecuteReturning(sql))
+                        .client_write(
+                            QueryWrite::ExecuteReturning(sql)
+                                .idempotent(idempotency)
+                                .traced(),
+                        )
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -555,7 +606,12 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::Transaction(queries))
+                        .client_write(
+                            QueryWrite::Transaction(queries)
+                                .idempotent(idempotency)
+                                .traced(),
+                        )
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -576,11 +632,45 @@ async fn handle_socket_concurrent(
                     }
                 }
 
+                #[cfg(feature = "sqlite")]
+                ApiStreamRequestPayload::TransactionGuarded(steps) => {
+                    match state
+                        .raft_db
+                        .raft
+                        .client_write(
+                            QueryWrite::TransactionGuarded(steps)
+                                .idempotent(idempotency)
+                                .traced(),
+                        )
+                        .instrument(trace::commit_span())
+                        .await
+                    {
+                        Ok(resp) => {
+                            let resp: crate::Response = resp.data;
+                            let res = match resp {
+                                crate::Response::TransactionGuarded(res) => res,
+                                _ => unreachable!(),
+                            };
+                            ApiStreamResponse {
+                                request_id,
+                                result: ApiStreamResponsePayload::TransactionGuarded(res),
+                            }
+                        }
+                        Err(err) => ApiStreamResponse {
+                            request_id,
+                            result: ApiStreamResponsePayload::TransactionGuarded(Err(Error::from(
+                                err,
+                            ))),
+                        },
+                    }
+                }
+
                 #[cfg(feature = "sqlite")]
                 ApiStreamRequestPayload::QueryConsistent(Query { sql, params }) => {
                     let res = query_consistent_local(
                         &state.raft_db.raft,
                         state.raft_db.log_statements,
+                        state.raft_db.query_stats.clone(),
                         state.raft_db.read_pool.clone(),
                         sql,
                         params,
@@ -598,7 +688,8 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::Batch(sql))
+                        .client_write(QueryWrite::Batch(sql).idempotent(idempotency).traced())
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -624,7 +715,8 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::Migration(migrations))
+                        .client_write(QueryWrite::Migration(migrations).traced())
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -650,7 +742,8 @@ async fn handle_socket_concurrent(
                     match state
                         .raft_db
                         .raft
-                        .client_write(QueryWrite::Backup(node_id))
+                        .client_write(QueryWrite::Backup(node_id).traced())
+                        .instrument(trace::commit_span())
                         .await
                     {
                         Ok(resp) => {
@@ -671,10 +764,58 @@ async fn handle_socket_concurrent(
                     }
                 }
 
+                #[cfg(feature = "backup")]
+                ApiStreamRequestPayload::Restore(src) => {
+                    let res = crate::backup::restore_online(&state, src).await;
+                    ApiStreamResponse {
+                        request_id,
+                        result: ApiStreamResponsePayload::Restore(res),
+                    }
+                }
+
+                #[cfg(all(feature = "backup", feature = "cache"))]
+                ApiStreamRequestPayload::RestoreCache(src) => {
+                    let res = crate::backup::restore_cache(&state, src).await;
+                    ApiStreamResponse {
+                        request_id,
+                        result: ApiStreamResponsePayload::RestoreCache(res),
+                    }
+                }
+
+                #[cfg(feature = "backup")]
+                ApiStreamRequestPayload::ListBackups => {
+                    let res = crate::backup::catalogue::list_backups(
+                        &state.raft_db.path_backups,
+                        &state.raft_db.backup_targets,
+                    )
+                    .await;
+                    ApiStreamResponse {
+                        request_id,
+                        result: ApiStreamResponsePayload::ListBackups(res),
+                    }
+                }
+
+                ApiStreamRequestPayload::Membership(change) => {
+                    let res = management::apply_membership_change(&state, &raft_type, change).await;
+                    ApiStreamResponse {
+                        request_id,
+                        result: ApiStreamResponsePayload::Membership(res),
+                    }
+                }
+
+                ApiStreamRequestPayload::TransferLeadership(to) => {
+                    let res = management::transfer_leadership(&state, &raft_type, to).await;
+                    ApiStreamResponse {
+                        request_id,
+                        result: ApiStreamResponsePayload::TransferLeadership(res),
+                    }
+                }
+
                 #[cfg(feature = "sqlite")]
                 ApiStreamRequestPayload::Query(Query { sql, params }) => {
                     let res = query_owned_local(
                         state.raft_db.log_statements,
+                        state.raft_db.query_stats.clone(),
                         state.raft_db.read_pool.clone(),
                         sql,
                         params,
@@ -689,18 +830,26 @@ async fn handle_socket_concurrent(
 
                 #[cfg(feature = "cache")]
                 ApiStreamRequestPayload::KV(cache_req) => {
-                    match state.raft_cache.raft.client_write(cache_req).await {
-                        Ok(resp) => {
-                            let resp: CacheResponse = resp.data;
-                            ApiStreamResponse {
-                                request_id,
-                                result: ApiStreamResponsePayload::KV(Ok(resp)),
+                    if let Err(err) = cache_req.check_remote() {
+                        ApiStreamResponse {
+                            request_id,
+                            result: ApiStreamResponsePayload::KV(Err(err)),
+                        }
+                    } else {
+                        let cache_req = cache_req.idempotent(idempotency);
+                        match state.raft_cache.raft.client_write(cache_req).await {
+                            Ok(resp) => {
+                                let resp: CacheResponse = resp.data;
+                                ApiStreamResponse {
+                                    request_id,
+                                    result: ApiStreamResponsePayload::KV(Ok(resp)),
+                                }
                             }
+                            Err(err) => ApiStreamResponse {
+                                request_id,
+                                result: ApiStreamResponsePayload::KV(Err(Error::from(err))),
+                            },
                         }
-                        Err(err) => ApiStreamResponse {
-                            request_id,
-                            result: ApiStreamResponsePayload::KV(Err(Error::from(err))),
-                        },
                     }
                 }
 
@@ -780,7 +929,8 @@ async fn handle_socket_concurrent(
                     err
                 );
             }
-        });
+        };
+        task::spawn(trace::scope_opt(req_trace, fut.instrument(span)));
     }
 
     // ignore the result in case the writer has already exited and drop the channel
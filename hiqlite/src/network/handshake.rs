@@ -46,9 +46,10 @@ impl HandshakeSecret {
         Ok(())
     }
 
+    /// Runs the server side of the handshake. The client may use any of the given `secrets`.
     pub(crate) async fn server(
         ws: &mut WebSocket<TokioIo<Upgraded>>,
-        secret: &[u8],
+        secrets: &[&[u8]],
     ) -> Result<NodeId, Error> {
         let challenge = Challenge::new()?;
 
@@ -62,7 +63,7 @@ impl HandshakeSecret {
             OpCode::Binary => {
                 let bytes = frame.payload.as_ref();
                 let challenge_response: ChallengeResponse = bincode::deserialize(bytes)?;
-                let resp = challenge_response.verify(&challenge, secret)?;
+                let resp = verify_accepted(&challenge_response, &challenge, secrets)?;
                 (challenge_response.node_id, resp)
             }
             _ => {
@@ -78,3 +79,50 @@ impl HandshakeSecret {
         Ok(node_id)
     }
 }
+
+/// `secret` followed by all additionally `accepted` ones, which are valid during a rotation.
+pub(crate) fn accepted_secrets<'a>(secret: &'a str, accepted: &'a [String]) -> Vec<&'a [u8]> {
+    let mut secrets = Vec::with_capacity(accepted.len() + 1);
+    secrets.push(secret.as_bytes());
+    secrets.extend(accepted.iter().map(|s| s.as_bytes()));
+    secrets
+}
+
+/// Verifies the `ChallengeResponse` against each of the accepted `secrets` and returns the final
+/// response for the first matching one.
+fn verify_accepted(
+    challenge_response: &ChallengeResponse,
+    challenge: &Challenge,
+    secrets: &[&[u8]],
+) -> Result<ResponseFinal, Error> {
+    secrets
+        .iter()
+        .find_map(|secret| challenge_response.verify(challenge, secret).ok())
+        .ok_or_else(|| Error::BadRequest("Invalid ChallengeResponse".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_accepted() {
+        let accepted = vec!["OldSecretDuringRotation".to_string()];
+        let secrets = accepted_secrets("CurrentSuperMegaSecure1337", &accepted);
+        let challenge = Challenge::new().unwrap();
+
+        for secret in ["CurrentSuperMegaSecure1337", "OldSecretDuringRotation"] {
+            let challenge_response =
+                ChallengeResponse::new(1, &challenge, secret.as_bytes()).unwrap();
+            let response = verify_accepted(&challenge_response, &challenge, &secrets).unwrap();
+            // the client must be able to verify the server with the secret it has used
+            assert!(response
+                .verify(&challenge_response, secret.as_bytes())
+                .is_ok());
+        }
+
+        let challenge_response =
+            ChallengeResponse::new(1, &challenge, b"UnknownSecretNotAccepted").unwrap();
+        assert!(verify_accepted(&challenge_response, &challenge, &secrets).is_err());
+    }
+}
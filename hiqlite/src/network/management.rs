@@ -1,5 +1,8 @@
+use crate::app_state::AppState;
 use crate::app_state::RaftType;
-use crate::network::{fmt_ok, get_payload, validate_secret, AppStateExt, Error};
+use crate::network::{
+    fmt_ok, get_payload, validate_secret, AppStateExt, Error, HEADER_NAME_SECRET,
+};
 use crate::NodeId;
 use crate::{helpers, Node};
 use axum::body;
@@ -7,11 +10,13 @@ use axum::extract::Path;
 use axum::http::HeaderMap;
 use axum::response::Response;
 use openraft::error::{CheckIsLeaderError, ForwardToLeader, RaftError};
+use openraft::ChangeMembers;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LearnerReq {
@@ -20,21 +25,11 @@ pub struct LearnerReq {
     pub addr_raft: String,
 }
 
-pub(crate) async fn add_learner(
-    state: AppStateExt,
-    headers: HeaderMap,
-    Path(raft_type): Path<RaftType>,
-    body: body::Bytes,
-) -> Result<Response, Error> {
-    validate_secret(&state, &headers)?;
-
-    if !helpers::is_raft_initialized(&state, &raft_type).await? {
-        return Err(Error::Error("Raft is not initialized".into()));
-    }
-
-    if let Some(leader_id) = helpers::get_raft_leader(&state, &raft_type).await {
+/// Returns a `ForwardToLeader` error, if this node is not the current leader.
+async fn check_is_leader(state: &Arc<AppState>, raft_type: &RaftType) -> Result<(), Error> {
+    if let Some(leader_id) = helpers::get_raft_leader(state, raft_type).await {
         if leader_id != state.id {
-            let metrics = helpers::get_raft_metrics(&state, &raft_type).await;
+            let metrics = helpers::get_raft_metrics(state, raft_type).await;
             let members = metrics.membership_config;
             let leader = members
                 .nodes()
@@ -49,9 +44,169 @@ pub(crate) async fn add_learner(
             }));
             return Err(Error::CheckIsLeaderError(err));
         }
+        Ok(())
     } else {
-        return Err(Error::LeaderChange("Leader election in progress".into()));
+        Err(Error::LeaderChange("Leader election in progress".into()))
+    }
+}
+
+/// A runtime membership change, requested via `Client::add_node()` / `remove_node()` /
+/// `replace_node()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MembershipChange {
+    Add(Node),
+    Remove(NodeId),
+    Replace(NodeId, Node),
+}
+
+/// Applies a `MembershipChange` to the given Raft. Must be executed on the leader.
+///
+/// New nodes are always added as a learner first, which blocks until they caught up with the
+/// leader. Only after that, they will be promoted to a voter. Voter changes are done with a single
+/// `change_membership()`, which makes `openraft` go through a joint consensus.
+pub(crate) async fn apply_membership_change(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+    change: MembershipChange,
+) -> Result<(), Error> {
+    check_is_leader(state, raft_type).await?;
+
+    // we want to hold the lock until we finished to not end up with race conditions
+    let _lock = helpers::lock_raft(state, raft_type).await;
+
+    let metrics = helpers::get_raft_metrics(state, raft_type).await;
+    let members = metrics.membership_config;
+    let voters = members.voter_ids().collect::<BTreeSet<_>>();
+    let is_member = |id: NodeId| members.nodes().any(|(nid, _)| *nid == id);
+
+    match change {
+        MembershipChange::Add(node) => {
+            let id = node.id;
+            if voters.contains(&id) {
+                info!("Node {} is a {} voter already", id, raft_type.as_str());
+                return Ok(());
+            }
+
+            if !is_member(id) {
+                info!("Adding node {} as {} learner", id, raft_type.as_str());
+                helpers::add_new_learner(state, raft_type, node).await?;
+            }
+
+            info!("Promoting node {} to {} voter", id, raft_type.as_str());
+            helpers::change_membership(
+                state,
+                raft_type,
+                ChangeMembers::AddVoterIds(BTreeSet::from([id])),
+                false,
+            )
+            .await
+        }
+
+        MembershipChange::Remove(id) => {
+            if voters.contains(&id) {
+                if voters.len() == 1 {
+                    return Err(Error::BadRequest(
+                        "Cannot remove the last voter from the cluster".into(),
+                    ));
+                }
+
+                info!("Removing {} voter {}", raft_type.as_str(), id);
+                // retain false makes sure that the node will be removed completely
+                helpers::change_membership(
+                    state,
+                    raft_type,
+                    ChangeMembers::RemoveVoters(BTreeSet::from([id])),
+                    false,
+                )
+                .await
+            } else if is_member(id) {
+                info!("Removing {} learner {}", raft_type.as_str(), id);
+                helpers::change_membership(
+                    state,
+                    raft_type,
+                    ChangeMembers::RemoveNodes(BTreeSet::from([id])),
+                    false,
+                )
+                .await
+            } else {
+                info!("Node {} is not a {} member", id, raft_type.as_str());
+                Ok(())
+            }
+        }
+
+        MembershipChange::Replace(old, node) => {
+            let id = node.id;
+            if old == id {
+                return Err(Error::BadRequest(
+                    "The new node must have a different id than the old one".into(),
+                ));
+            }
+            if !is_member(old) {
+                return Err(Error::BadRequest(
+                    format!("Node {} is not a {} member", old, raft_type.as_str()).into(),
+                ));
+            }
+            if voters.contains(&id) {
+                return Err(Error::BadRequest(
+                    format!("Node {} is a {} voter already", id, raft_type.as_str()).into(),
+                ));
+            }
+
+            if !is_member(id) {
+                info!("Adding node {} as {} learner", id, raft_type.as_str());
+                helpers::add_new_learner(state, raft_type, node).await?;
+            }
+
+            if voters.contains(&old) {
+                info!(
+                    "Replacing {} voter {} with node {}",
+                    raft_type.as_str(),
+                    old,
+                    id
+                );
+                let mut new_voters = voters;
+                new_voters.remove(&old);
+                new_voters.insert(id);
+                helpers::change_membership(
+                    state,
+                    raft_type,
+                    ChangeMembers::ReplaceAllVoters(new_voters),
+                    false,
+                )
+                .await
+            } else {
+                // the old node is a learner -> the new one stays a learner as well
+                info!(
+                    "Replacing {} learner {} with node {}",
+                    raft_type.as_str(),
+                    old,
+                    id
+                );
+                helpers::change_membership(
+                    state,
+                    raft_type,
+                    ChangeMembers::RemoveNodes(BTreeSet::from([old])),
+                    false,
+                )
+                .await
+            }
+        }
     }
+}
+
+pub(crate) async fn add_learner(
+    state: AppStateExt,
+    headers: HeaderMap,
+    Path(raft_type): Path<RaftType>,
+    body: body::Bytes,
+) -> Result<Response, Error> {
+    validate_secret(&state, &headers)?;
+
+    if !helpers::is_raft_initialized(&state, &raft_type).await? {
+        return Err(Error::Error("Raft is not initialized".into()));
+    }
+
+    check_is_leader(&state, &raft_type).await?;
 
     let LearnerReq {
         node_id,
@@ -178,10 +333,9 @@ pub(crate) async fn become_member(
     let metrics = helpers::get_raft_metrics(&state, &raft_type).await;
     let members = metrics.membership_config;
 
-    let mut nodes_set = BTreeSet::new();
-    for (id, _node) in members.nodes() {
-        nodes_set.insert(*id);
-    }
+    // Only promote the current voters and the new node. Other learners may be permanent
+    // read replicas, and they must never be promoted as a side effect.
+    let mut nodes_set = members.voter_ids().collect::<BTreeSet<_>>();
     nodes_set.insert(payload.id);
 
     let res = helpers::change_membership(&state, &raft_type, nodes_set, true).await;
@@ -261,6 +415,21 @@ pub(crate) async fn post_membership(
 //     }
 // }
 
+/// Get the SQL statement latency stats for this node
+#[cfg(feature = "sqlite")]
+pub(crate) async fn query_stats(state: AppStateExt, headers: HeaderMap) -> Result<Response, Error> {
+    validate_secret(&state, &headers)?;
+    fmt_ok(headers, state.raft_db.query_stats.get())
+}
+
+/// Returns the checked copy of the latest online restore, which this node has started as leader.
+/// Used by nodes which cannot fetch the backup source themselves.
+#[cfg(feature = "backup")]
+pub(crate) async fn restore_src(state: AppStateExt, headers: HeaderMap) -> Result<Vec<u8>, Error> {
+    validate_secret(&state, &headers)?;
+    crate::backup::read_restore_src(&state.raft_db.path_backups).await
+}
+
 /// Get the latest metrics of the cluster
 pub(crate) async fn metrics(
     state: AppStateExt,
@@ -272,3 +441,234 @@ pub(crate) async fn metrics(
     let metrics = helpers::get_raft_metrics(&state, &raft_type).await;
     fmt_ok(headers, &metrics)
 }
+
+/// Makes this node start an election immediately. Used for leadership transfers.
+pub(crate) async fn elect(
+    state: AppStateExt,
+    headers: HeaderMap,
+    Path(raft_type): Path<RaftType>,
+) -> Result<Response, Error> {
+    validate_secret(&state, &headers)?;
+
+    info!(
+        "Received leadership transfer for {} raft",
+        raft_type.as_str()
+    );
+    helpers::trigger_elect(&state, &raft_type).await?;
+    fmt_ok(headers, ())
+}
+
+/// Makes this node build a new snapshot and returns its last log id.
+pub(crate) async fn snapshot(
+    state: AppStateExt,
+    headers: HeaderMap,
+    Path(raft_type): Path<RaftType>,
+) -> Result<Response, Error> {
+    validate_secret(&state, &headers)?;
+
+    info!("Received snapshot request for {} raft", raft_type.as_str());
+    let log_id = helpers::trigger_snapshot(&state, &raft_type).await?;
+    fmt_ok(headers, log_id)
+}
+
+/// Transfers the leadership to another voter and returns its id. Must be executed on the leader.
+///
+/// If no target is given, the voter with the most replicated logs will be chosen. The leader
+/// waits until the target has caught up, stops its heartbeats, makes the target start an election
+/// once the leader lease on the followers has run out, and waits until the target has been
+/// confirmed as the new leader.
+pub(crate) async fn transfer_leadership(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+    to: Option<NodeId>,
+) -> Result<NodeId, Error> {
+    check_is_leader(state, raft_type).await?;
+
+    // we want to hold the lock to not end up with parallel membership changes
+    let _lock = helpers::lock_raft(state, raft_type).await;
+
+    let metrics = helpers::get_raft_metrics(state, raft_type).await;
+    let voters = metrics
+        .membership_config
+        .voter_ids()
+        .filter(|id| *id != state.id)
+        .collect::<BTreeSet<_>>();
+    let replication = metrics.replication.clone().unwrap_or_default();
+    let matched = |id: &NodeId| {
+        replication
+            .get(id)
+            .and_then(|log_id| log_id.map(|log_id| log_id.index))
+    };
+
+    let target = match to {
+        Some(id) if id == state.id => return Ok(id),
+        Some(id) if voters.contains(&id) => id,
+        Some(id) => {
+            return Err(Error::BadRequest(
+                format!("Node {} is not a {} voter", id, raft_type.as_str()).into(),
+            ));
+        }
+        None => *voters.iter().max_by_key(|id| matched(id)).ok_or_else(|| {
+            Error::BadRequest(
+                format!(
+                    "There is no other {} voter to transfer to",
+                    raft_type.as_str()
+                )
+                .into(),
+            )
+        })?,
+    };
+    let node = metrics
+        .membership_config
+        .nodes()
+        .find(|(id, _)| **id == target)
+        .map(|(_, node)| node.clone())
+        .expect("voter to always exist in membership nodes");
+
+    // The target must have caught up before it starts an election, otherwise it would not get
+    // the votes it needs. This should usually only take a few heartbeats.
+    let timeout =
+        Duration::from_millis(helpers::get_raft_config(state, raft_type).election_timeout_max * 4);
+    let last_log_index = metrics.last_log_index;
+    let mut rx = helpers::get_raft_metrics_watch(state, raft_type);
+    let caught_up = time::timeout(timeout, async {
+        loop {
+            let replication = rx.borrow().replication.clone().unwrap_or_default();
+            let matched = replication
+                .get(&target)
+                .and_then(|log_id| log_id.map(|log_id| log_id.index));
+            if matched >= last_log_index {
+                return;
+            }
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    })
+    .await;
+    if caught_up.is_err() {
+        return Err(Error::Timeout(format!(
+            "Node {} did not catch up in time for the {} leadership transfer",
+            target,
+            raft_type.as_str()
+        )));
+    }
+
+    info!(
+        "Transferring {} leadership from {} to {}",
+        raft_type.as_str(),
+        state.id,
+        target
+    );
+
+    // Followers reject any vote request as long as their leader lease is valid, which is renewed
+    // with each heartbeat. The heartbeats are stopped until the transfer is done, so the leases
+    // run out and the target can win its election. It will start it before any other follower
+    // would start its own one after the lease and another election timeout.
+    helpers::set_heartbeat(state, raft_type, false);
+    let res = elect_target(state, raft_type, &node).await;
+    helpers::set_heartbeat(state, raft_type, true);
+    res
+}
+
+/// Makes the `target` start elections until it has been confirmed as the new leader.
+async fn elect_target(
+    state: &Arc<AppState>,
+    raft_type: &RaftType,
+    target: &Node,
+) -> Result<NodeId, Error> {
+    let config = helpers::get_raft_config(state, raft_type);
+    let lease = Duration::from_millis(config.election_timeout_max);
+    let margin = Duration::from_millis(config.election_timeout_min / 4);
+    // give the target up to 3 elections
+    let timeout = (lease * 2 + margin) * 3;
+    let url = format!(
+        "{}://{}/cluster/elect/{}",
+        state.api_scheme,
+        target.addr_api,
+        raft_type.as_str()
+    );
+
+    let mut rx = helpers::get_raft_metrics_watch(state, raft_type);
+    let res = time::timeout(timeout, async {
+        loop {
+            // Client writes still renew the leases, in which case the vote will be rejected and
+            // we need to wait for another lease to run out.
+            time::sleep(lease + margin).await;
+
+            let res = state
+                .api_client
+                .post(&url)
+                .header(HEADER_NAME_SECRET, &state.secret_api)
+                .send()
+                .await?;
+            if !res.status().is_success() {
+                let err = res.json::<Error>().await?;
+                return Err(err);
+            }
+
+            let confirmed = time::timeout(lease, async {
+                loop {
+                    let leader = rx.borrow().current_leader;
+                    match leader {
+                        Some(leader) if leader != state.id => return Some(leader),
+                        _ => {}
+                    }
+                    if rx.changed().await.is_err() {
+                        return None;
+                    }
+                }
+            })
+            .await;
+            match confirmed {
+                Ok(Some(leader)) => return Ok(leader),
+                Ok(None) => {
+                    return Err(Error::Error(
+                        format!("{} raft has been shut down", raft_type.as_str()).into(),
+                    ));
+                }
+                Err(_) => debug!(
+                    "{} leader not yet confirmed - triggering another election on {}",
+                    raft_type.as_str(),
+                    target.id
+                ),
+            }
+        }
+    })
+    .await;
+
+    match res {
+        Ok(Ok(leader)) if leader == target.id => {
+            info!("New {} leader: {}", raft_type.as_str(), leader);
+            Ok(leader)
+        }
+        Ok(Ok(leader)) => {
+            warn!(
+                "{} leadership moved to {} instead of {}",
+                raft_type.as_str(),
+                leader,
+                target.id
+            );
+            Err(Error::Error(
+                format!(
+                    "The {} leadership moved to {} instead of {}",
+                    raft_type.as_str(),
+                    leader,
+                    target.id
+                )
+                .into(),
+            ))
+        }
+        Ok(Err(err)) => Err(err),
+        Err(_) => {
+            warn!(
+                "No new {} leader confirmed after leadership transfer",
+                raft_type.as_str()
+            );
+            Err(Error::Timeout(format!(
+                "No new {} leader has been confirmed in time",
+                raft_type.as_str()
+            )))
+        }
+    }
+}
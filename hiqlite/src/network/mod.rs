@@ -16,10 +16,12 @@ pub(crate) mod api;
 mod challenge_response;
 pub(crate) mod handshake;
 pub(crate) mod management;
+pub(crate) mod prometheus;
 mod raft_client;
 mod raft_client_split;
 pub(crate) mod raft_server;
 pub(crate) mod raft_server_split;
+pub(crate) mod snapshot_transfer;
 
 pub(crate) type AppStateExt = axum::extract::State<Arc<AppState>>;
 // pub(crate) type RaftWriteResponse = ClientWriteResponse<TypeConfigSqlite>;
@@ -57,7 +59,7 @@ fn validate_secret(state: &AppStateExt, headers: &HeaderMap) -> Result<(), Error
     match headers.get(HEADER_NAME_SECRET) {
         None => Err(Error::Token("API Secret missing".into())),
         Some(secret) => {
-            if state.secret_api.as_bytes() != secret.as_bytes() {
+            if !state.secrets_api().contains(&secret.as_bytes()) {
                 Err(Error::Token("Invalid API Secret".into()))
             } else {
                 Ok(())
@@ -0,0 +1,505 @@
+use crate::app_state::RaftType;
+use crate::network::snapshot_transfer::SnapshotMetrics;
+use crate::network::{AppStateExt, Error, HEADER_NAME_SECRET};
+use crate::{helpers, Node, NodeId};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use openraft::{RaftMetrics, ServerState};
+use std::fmt::{Display, Write};
+
+#[cfg(feature = "sqlite")]
+use crate::query::stats::StatementKind;
+use std::sync::atomic::Ordering;
+#[cfg(feature = "cache")]
+use tokio::sync::oneshot;
+
+/// Returns the metrics for this node in the Prometheus text format.
+///
+/// Prometheus cannot set custom headers in older versions. Apart from the usual
+/// `X-API-SECRET`, the secret is accepted as a `Authorization: Bearer <secret>` as well.
+pub(crate) async fn metrics(state: AppStateExt, headers: HeaderMap) -> Result<Response, Error> {
+    validate_secret_prometheus(&state, &headers)?;
+
+    let mut rafts = Vec::with_capacity(2);
+    #[cfg(feature = "sqlite")]
+    rafts.push((
+        RaftType::Sqlite.as_str(),
+        helpers::get_raft_metrics(&state, &RaftType::Sqlite).await,
+    ));
+    #[cfg(feature = "cache")]
+    rafts.push((
+        RaftType::Cache.as_str(),
+        helpers::get_raft_metrics(&state, &RaftType::Cache).await,
+    ));
+
+    let mut p = Prometheus::default();
+    write_raft_metrics(&mut p, &rafts);
+
+    let snapshots: &[(&str, &SnapshotMetrics)] = &[
+        #[cfg(feature = "sqlite")]
+        (RaftType::Sqlite.as_str(), &state.raft_db.snapshot_metrics),
+        #[cfg(feature = "cache")]
+        (RaftType::Cache.as_str(), &state.raft_cache.snapshot_metrics),
+    ];
+    write_snapshot_metrics(&mut p, snapshots);
+
+    #[cfg(feature = "sqlite")]
+    {
+        let db = &state.raft_db;
+
+        p.family(
+            "hiqlite_sqlite_writer_queue_depth",
+            "Requests waiting in the SQL writer queue",
+            "gauge",
+        );
+        p.sample("hiqlite_sqlite_writer_queue_depth", "", db.sql_writer.len());
+
+        p.family(
+            "hiqlite_sqlite_apply_entries_total",
+            "Raft log entries applied to the SQLite state machine",
+            "counter",
+        );
+        p.sample(
+            "hiqlite_sqlite_apply_entries_total",
+            "",
+            db.metrics.apply_entries.load(Ordering::Relaxed),
+        );
+        p.family(
+            "hiqlite_sqlite_apply_seconds_total",
+            "Total time spent applying Raft log entries to the SQLite state machine",
+            "counter",
+        );
+        p.sample(
+            "hiqlite_sqlite_apply_seconds_total",
+            "",
+            micros_to_secs(db.metrics.apply_micros.load(Ordering::Relaxed)),
+        );
+
+        let pool = db.read_pool.status();
+        p.family(
+            "hiqlite_sqlite_read_pool_size",
+            "Max amount of read pool connections",
+            "gauge",
+        );
+        p.sample("hiqlite_sqlite_read_pool_size", "", pool.max_size);
+        p.family(
+            "hiqlite_sqlite_read_pool_available",
+            "Currently idle read pool connections",
+            "gauge",
+        );
+        p.sample("hiqlite_sqlite_read_pool_available", "", pool.available);
+        p.family(
+            "hiqlite_sqlite_read_pool_waiting",
+            "Tasks waiting for a read pool connection",
+            "gauge",
+        );
+        p.sample("hiqlite_sqlite_read_pool_waiting", "", pool.waiting);
+
+        let (mut count, mut micros) = ([0u64; 2], [0u64; 2]);
+        for stat in db.query_stats.get() {
+            let idx = match stat.kind {
+                StatementKind::Read => 0,
+                StatementKind::Write => 1,
+            };
+            count[idx] += stat.count;
+            micros[idx] += stat.total_micros;
+        }
+        p.family(
+            "hiqlite_sqlite_statements_total",
+            "Executed SQL statements",
+            "counter",
+        );
+        p.sample("hiqlite_sqlite_statements_total", "kind=\"read\"", count[0]);
+        p.sample(
+            "hiqlite_sqlite_statements_total",
+            "kind=\"write\"",
+            count[1],
+        );
+        p.family(
+            "hiqlite_sqlite_statements_seconds_total",
+            "Total execution time of SQL statements",
+            "counter",
+        );
+        p.sample(
+            "hiqlite_sqlite_statements_seconds_total",
+            "kind=\"read\"",
+            micros_to_secs(micros[0]),
+        );
+        p.sample(
+            "hiqlite_sqlite_statements_seconds_total",
+            "kind=\"write\"",
+            micros_to_secs(micros[1]),
+        );
+
+        p.family(
+            "hiqlite_backup_last_success_timestamp_seconds",
+            "Unix timestamp of the last successful backup",
+            "gauge",
+        );
+        p.sample(
+            "hiqlite_backup_last_success_timestamp_seconds",
+            "target=\"local\"",
+            db.metrics.backup_last_success.load(Ordering::Relaxed),
+        );
+        for (target, ts) in db
+            .metrics
+            .backup_last_success_targets
+            .lock()
+            .unwrap()
+            .iter()
+        {
+            p.sample(
+                "hiqlite_backup_last_success_timestamp_seconds",
+                &label("target", target),
+                ts,
+            );
+        }
+        p.family(
+            "hiqlite_backup_failures_total",
+            "Failed backups since node start",
+            "counter",
+        );
+        p.sample(
+            "hiqlite_backup_failures_total",
+            "",
+            db.metrics.backups_failed.load(Ordering::Relaxed),
+        );
+        p.family(
+            "hiqlite_maintenance",
+            "1 while this node replaces its database during an online restore",
+            "gauge",
+        );
+        p.sample(
+            "hiqlite_maintenance",
+            "",
+            db.metrics.maintenance.load(Ordering::Relaxed) as u8,
+        );
+        p.family(
+            "hiqlite_backup_verification_failures_total",
+            "Backups which failed the integrity or content check after creation",
+            "counter",
+        );
+        p.sample(
+            "hiqlite_backup_verification_failures_total",
+            "",
+            db.metrics
+                .backup_verifications_failed
+                .load(Ordering::Relaxed),
+        );
+    }
+
+    #[cfg(feature = "cache")]
+    {
+        use crate::store::state_machine::memory::kv_handler::CacheRequestHandler;
+
+        p.family("hiqlite_cache_entries", "Entries per cache", "gauge");
+        for (tx, name) in state
+            .raft_cache
+            .tx_caches
+            .iter()
+            .zip(state.raft_cache.cache_names.iter())
+        {
+            let (ack, rx) = oneshot::channel();
+            if tx.send_async(CacheRequestHandler::Len(ack)).await.is_ok() {
+                if let Ok(len) = rx.await {
+                    p.sample("hiqlite_cache_entries", &label("cache", name), len);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "dlock")]
+    {
+        use crate::store::state_machine::memory::dlock_handler::LockRequest;
+
+        let (ack, rx) = oneshot::channel();
+        if state
+            .raft_cache
+            .tx_dlock
+            .send_async(LockRequest::Count(ack))
+            .await
+            .is_ok()
+        {
+            if let Ok((held, queued)) = rx.await {
+                p.family(
+                    "hiqlite_dlock_held",
+                    "Currently held distributed locks",
+                    "gauge",
+                );
+                p.sample("hiqlite_dlock_held", "", held);
+                p.family(
+                    "hiqlite_dlock_queued",
+                    "Lock requests waiting for a distributed lock",
+                    "gauge",
+                );
+                p.sample("hiqlite_dlock_queued", "", queued);
+            }
+        }
+    }
+
+    Ok((
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        p.buf,
+    )
+        .into_response())
+}
+
+fn write_raft_metrics(p: &mut Prometheus, rafts: &[(&str, RaftMetrics<NodeId, Node>)]) {
+    p.family(
+        "hiqlite_raft_current_term",
+        "The current Raft term",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_current_term",
+            &format!("raft=\"{}\"", raft),
+            m.current_term,
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_current_leader",
+        "The node ID of the current leader, 0 if unknown",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_current_leader",
+            &format!("raft=\"{}\"", raft),
+            m.current_leader.unwrap_or(0),
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_is_leader",
+        "1 if this node is the current leader",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_is_leader",
+            &format!("raft=\"{}\"", raft),
+            (m.state == ServerState::Leader) as u8,
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_server_state",
+        "The current Raft server state of this node",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        for state in [
+            ServerState::Learner,
+            ServerState::Follower,
+            ServerState::Candidate,
+            ServerState::Leader,
+            ServerState::Shutdown,
+        ] {
+            p.sample(
+                "hiqlite_raft_server_state",
+                &format!("raft=\"{}\",state=\"{:?}\"", raft, state),
+                (m.state == state) as u8,
+            );
+        }
+    }
+
+    p.family(
+        "hiqlite_raft_last_log_index",
+        "The last log index appended to this node",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_last_log_index",
+            &format!("raft=\"{}\"", raft),
+            m.last_log_index.unwrap_or(0),
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_last_applied_index",
+        "The last log index applied to the state machine",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_last_applied_index",
+            &format!("raft=\"{}\"", raft),
+            m.last_applied.map(|id| id.index).unwrap_or(0),
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_snapshot_index",
+        "The log index of the last snapshot",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        p.sample(
+            "hiqlite_raft_snapshot_index",
+            &format!("raft=\"{}\"", raft),
+            m.snapshot.map(|id| id.index).unwrap_or(0),
+        );
+    }
+
+    p.family(
+        "hiqlite_raft_millis_since_quorum_ack",
+        "Milliseconds since the last quorum ack, only reported by the leader",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        if let Some(millis) = m.millis_since_quorum_ack {
+            p.sample(
+                "hiqlite_raft_millis_since_quorum_ack",
+                &format!("raft=\"{}\"", raft),
+                millis,
+            );
+        }
+    }
+
+    p.family(
+        "hiqlite_raft_replication_lag",
+        "Log entries each follower is behind the leader, only reported by the leader",
+        "gauge",
+    );
+    for (raft, m) in rafts {
+        if let Some(replication) = &m.replication {
+            let last_log_index = m.last_log_index.unwrap_or(0);
+            for (node_id, matched) in replication {
+                let matched = matched.map(|id| id.index).unwrap_or(0);
+                p.sample(
+                    "hiqlite_raft_replication_lag",
+                    &format!("raft=\"{}\",node_id=\"{}\"", raft, node_id),
+                    last_log_index.saturating_sub(matched),
+                );
+            }
+        }
+    }
+}
+
+fn write_snapshot_metrics(p: &mut Prometheus, snapshots: &[(&str, &SnapshotMetrics)]) {
+    #[allow(clippy::type_complexity)]
+    let families: [(&str, &str, &str, fn(&SnapshotMetrics) -> u64); 6] = [
+        (
+            "hiqlite_snapshot_send_offset_bytes",
+            "Uncompressed progress of the currently sent snapshot, 0 if none is running",
+            "gauge",
+            |m| m.send_offset.load(Ordering::Relaxed),
+        ),
+        (
+            "hiqlite_snapshot_sent_bytes_total",
+            "Snapshot bytes sent over the network after compression",
+            "counter",
+            |m| m.sent_bytes.load(Ordering::Relaxed),
+        ),
+        (
+            "hiqlite_snapshots_sent_total",
+            "Snapshots fully sent to other nodes",
+            "counter",
+            |m| m.sent.load(Ordering::Relaxed),
+        ),
+        (
+            "hiqlite_snapshot_receive_offset_bytes",
+            "Uncompressed progress of the currently received snapshot, 0 if none is running",
+            "gauge",
+            |m| m.receive_offset.load(Ordering::Relaxed),
+        ),
+        (
+            "hiqlite_snapshot_received_bytes_total",
+            "Snapshot bytes received over the network before decompression",
+            "counter",
+            |m| m.received_bytes.load(Ordering::Relaxed),
+        ),
+        (
+            "hiqlite_snapshots_received_total",
+            "Snapshots fully received from the leader",
+            "counter",
+            |m| m.received.load(Ordering::Relaxed),
+        ),
+    ];
+
+    for (name, help, typ, value) in families {
+        p.family(name, help, typ);
+        for (raft, m) in snapshots {
+            p.sample(name, &format!("raft=\"{}\"", raft), value(m));
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[inline]
+fn micros_to_secs(micros: u64) -> f64 {
+    micros as f64 / 1_000_000.0
+}
+
+#[inline]
+fn validate_secret_prometheus(state: &AppStateExt, headers: &HeaderMap) -> Result<(), Error> {
+    let secret = if let Some(secret) = headers.get(HEADER_NAME_SECRET) {
+        secret.as_bytes()
+    } else if let Some(bearer) = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.as_bytes().strip_prefix(b"Bearer "))
+    {
+        bearer
+    } else {
+        return Err(Error::Token("API Secret missing".into()));
+    };
+
+    if !state.secrets_api().contains(&secret) {
+        Err(Error::Token("Invalid API Secret".into()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Builds a single `name="value"` label. Values like cache names or backup target names are
+/// user-defined and must be escaped as the text format requires.
+fn label(name: &str, value: &str) -> String {
+    let mut label = String::with_capacity(name.len() + value.len() + 3);
+    label.push_str(name);
+    label.push_str("=\"");
+    for c in value.chars() {
+        match c {
+            '\\' => label.push_str("\\\\"),
+            '"' => label.push_str("\\\""),
+            '\n' => label.push_str("\\n"),
+            c => label.push(c),
+        }
+    }
+    label.push('"');
+    label
+}
+
+#[derive(Default)]
+struct Prometheus {
+    buf: String,
+}
+
+impl Prometheus {
+    fn family(&mut self, name: &str, help: &str, typ: &str) {
+        let _ = writeln!(self.buf, "# HELP {} {}", name, help);
+        let _ = writeln!(self.buf, "# TYPE {} {}", name, typ);
+    }
+
+    fn sample<V: Display>(&mut self, name: &str, labels: &str, value: V) {
+        if labels.is_empty() {
+            let _ = writeln!(self.buf, "{} {}", name, value);
+        } else {
+            let _ = writeln!(self.buf, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_escape() {
+        assert_eq!(label("cache", "One"), r#"cache="One""#);
+        assert_eq!(label("target", "a\"b\\c\nd"), r#"target="a\"b\\c\nd""#);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::config::SnapshotCompression;
 use crate::network::handshake::HandshakeSecret;
 use crate::network::raft_server_split::{
     RaftStreamRequest, RaftStreamResponse, RaftStreamResponsePayload,
 };
+use crate::network::snapshot_transfer::{SnapshotMetrics, SnapshotThrottle};
 use crate::Node;
 use crate::{tls, NodeId};
 use bytes::Bytes;
@@ -62,6 +64,9 @@ pub struct NetworkStreaming {
     pub secret_raft: Vec<u8>,
     pub raft_type: RaftType,
     pub heartbeat_interval: u64,
+    pub snapshot_compression: SnapshotCompression,
+    pub snapshot_throttle: Option<Arc<SnapshotThrottle>>,
+    pub snapshot_metrics: Arc<SnapshotMetrics>,
     // pub sender: flume::Sender<RaftRequest>,
 }
 
@@ -89,6 +94,9 @@ impl RaftNetworkFactory<TypeConfigKV> for NetworkStreaming {
             node: node.clone(),
             sender,
             task: Some(task),
+            snapshot_compression: self.snapshot_compression,
+            snapshot_throttle: self.snapshot_throttle.clone(),
+            snapshot_metrics: self.snapshot_metrics.clone(),
         }
     }
 }
@@ -117,6 +125,9 @@ impl RaftNetworkFactory<TypeConfigSqlite> for NetworkStreaming {
             node: node.clone(),
             sender,
             task: Some(task),
+            snapshot_compression: self.snapshot_compression,
+            snapshot_throttle: self.snapshot_throttle.clone(),
+            snapshot_metrics: self.snapshot_metrics.clone(),
         }
     }
 }
@@ -141,6 +152,7 @@ enum RaftRequest {
     SnapshotDB(
         (
             oneshot::Sender<Result<RaftStreamResponsePayload, Error>>,
+            SnapshotCompression,
             InstallSnapshotRequest<TypeConfigSqlite>,
         ),
     ),
@@ -163,6 +175,7 @@ enum RaftRequest {
     SnapshotCache(
         (
             oneshot::Sender<Result<RaftStreamResponsePayload, Error>>,
+            SnapshotCompression,
             InstallSnapshotRequest<TypeConfigKV>,
         ),
     ),
@@ -240,13 +253,13 @@ impl NetworkStreaming {
                                 #[cfg(feature = "sqlite")]
                                 RaftRequest::VoteDB((ack, _)) => Some(ack),
                                 #[cfg(feature = "sqlite")]
-                                RaftRequest::SnapshotDB((ack, _)) => Some(ack),
+                                RaftRequest::SnapshotDB((ack, _, _)) => Some(ack),
                                 #[cfg(feature = "cache")]
                                 RaftRequest::AppendCache((ack, _)) => Some(ack),
                                 #[cfg(feature = "cache")]
                                 RaftRequest::VoteCache((ack, _)) => Some(ack),
                                 #[cfg(feature = "cache")]
-                                RaftRequest::SnapshotCache((ack, _)) => Some(ack),
+                                RaftRequest::SnapshotCache((ack, _, _)) => Some(ack),
                                 RaftRequest::StreamResponse(_) => None,
                                 RaftRequest::Shutdown => None,
                             };
@@ -312,8 +325,14 @@ impl NetworkStreaming {
                         Some((ack, RaftStreamRequest::VoteDB((request_id, req))))
                     }
                     #[cfg(feature = "sqlite")]
-                    RaftRequest::SnapshotDB((ack, req)) => {
-                        Some((ack, RaftStreamRequest::SnapshotDB((request_id, req))))
+                    RaftRequest::SnapshotDB((ack, compression, req)) => {
+                        // the uncompressed variant stays compatible with older nodes
+                        let payload = if compression == SnapshotCompression::None {
+                            RaftStreamRequest::SnapshotDB((request_id, req))
+                        } else {
+                            RaftStreamRequest::SnapshotDBCompressed((request_id, compression, req))
+                        };
+                        Some((ack, payload))
                     }
 
                     #[cfg(feature = "cache")]
@@ -325,8 +344,18 @@ impl NetworkStreaming {
                         Some((ack, RaftStreamRequest::VoteCache((request_id, req))))
                     }
                     #[cfg(feature = "cache")]
-                    RaftRequest::SnapshotCache((ack, req)) => {
-                        Some((ack, RaftStreamRequest::SnapshotCache((request_id, req))))
+                    RaftRequest::SnapshotCache((ack, compression, req)) => {
+                        // the uncompressed variant stays compatible with older nodes
+                        let payload = if compression == SnapshotCompression::None {
+                            RaftStreamRequest::SnapshotCache((request_id, req))
+                        } else {
+                            RaftStreamRequest::SnapshotCacheCompressed((
+                                request_id,
+                                compression,
+                                req,
+                            ))
+                        };
+                        Some((ack, payload))
                     }
 
                     RaftRequest::StreamResponse(resp) => {
@@ -514,6 +543,9 @@ pub struct NetworkConnectionStreaming {
     node: Node,
     sender: flume::Sender<RaftRequest>,
     task: Option<JoinHandle<()>>,
+    snapshot_compression: SnapshotCompression,
+    snapshot_throttle: Option<Arc<SnapshotThrottle>>,
+    snapshot_metrics: Arc<SnapshotMetrics>,
 }
 
 impl Drop for NetworkConnectionStreaming {
@@ -551,6 +583,34 @@ impl NetworkConnectionStreaming {
             .map_err(|err| RPCError::Unreachable(Unreachable::new(&err)))?
             .map_err(|err| RPCError::Unreachable(Unreachable::new(&err)))
     }
+
+    /// Compresses and throttles a snapshot chunk, if configured, and records the progress.
+    #[cfg(any(feature = "cache", feature = "sqlite"))]
+    async fn prepare_snapshot_chunk<Err>(
+        &self,
+        offset: u64,
+        data: &mut Vec<u8>,
+        done: bool,
+    ) -> Result<(), RPCError<NodeId, Node, Err>>
+    where
+        Err: std::error::Error + 'static + Clone,
+    {
+        let len = data.len();
+        if self.snapshot_compression != SnapshotCompression::None {
+            *data = self
+                .snapshot_compression
+                .compress(data)
+                .map_err(|err| RPCError::Unreachable(Unreachable::new(&err)))?;
+        }
+
+        if let Some(throttle) = &self.snapshot_throttle {
+            throttle.wait(data.len()).await;
+        }
+
+        self.snapshot_metrics
+            .record_send(offset, len, data.len(), done);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "sqlite")]
@@ -573,14 +633,21 @@ impl RaftNetwork<TypeConfigSqlite> for NetworkConnectionStreaming {
     #[tracing::instrument(level = "debug", skip_all, err(Debug))]
     async fn install_snapshot(
         &mut self,
-        req: InstallSnapshotRequest<TypeConfigSqlite>,
+        mut req: InstallSnapshotRequest<TypeConfigSqlite>,
         _option: RPCOption,
     ) -> Result<
         InstallSnapshotResponse<NodeId>,
         RPCError<NodeId, Node, RaftError<NodeId, InstallSnapshotError>>,
     > {
+        self.prepare_snapshot_chunk(req.offset, &mut req.data, req.done)
+            .await?;
+
         let (ack, rx) = oneshot::channel();
-        match self.send(RaftRequest::SnapshotDB((ack, req)), rx).await? {
+        let compression = self.snapshot_compression;
+        match self
+            .send(RaftRequest::SnapshotDB((ack, compression, req)), rx)
+            .await?
+        {
             RaftStreamResponsePayload::SnapshotDB(resp) => {
                 resp.map_err(|err| RPCError::Unreachable(Unreachable::new(&err)))
             }
@@ -624,15 +691,19 @@ impl RaftNetwork<TypeConfigKV> for NetworkConnectionStreaming {
     #[tracing::instrument(level = "debug", skip_all, err(Debug))]
     async fn install_snapshot(
         &mut self,
-        req: InstallSnapshotRequest<TypeConfigKV>,
+        mut req: InstallSnapshotRequest<TypeConfigKV>,
         _option: RPCOption,
     ) -> Result<
         InstallSnapshotResponse<NodeId>,
         RPCError<NodeId, Node, RaftError<NodeId, InstallSnapshotError>>,
     > {
+        self.prepare_snapshot_chunk(req.offset, &mut req.data, req.done)
+            .await?;
+
         let (ack, rx) = oneshot::channel();
+        let compression = self.snapshot_compression;
         match self
-            .send(RaftRequest::SnapshotCache((ack, req)), rx)
+            .send(RaftRequest::SnapshotCache((ack, compression, req)), rx)
             .await?
         {
             RaftStreamResponsePayload::SnapshotCache(resp) => {
@@ -1,3 +1,4 @@
+use crate::config::SnapshotCompression;
 use crate::network::handshake::HandshakeSecret;
 use crate::network::{AppStateExt, Error};
 use axum::response::IntoResponse;
@@ -6,7 +7,7 @@ use openraft::error::{InstallSnapshotError, RaftError};
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
 use tokio::task;
-use tracing::{error, warn};
+use tracing::{debug_span, error, warn, Instrument};
 
 #[cfg(feature = "cache")]
 use crate::store::state_machine::memory::TypeConfigKV;
@@ -36,6 +37,78 @@ pub enum RaftStreamRequest {
     VoteCache((usize, VoteRequest<u64>)),
     #[cfg(feature = "cache")]
     SnapshotCache((usize, InstallSnapshotRequest<TypeConfigKV>)),
+
+    // The compressed snapshots are appended at the end to keep the existing variants compatible.
+    #[cfg(feature = "sqlite")]
+    SnapshotDBCompressed(
+        (
+            usize,
+            SnapshotCompression,
+            InstallSnapshotRequest<TypeConfigSqlite>,
+        ),
+    ),
+    #[cfg(feature = "cache")]
+    SnapshotCacheCompressed(
+        (
+            usize,
+            SnapshotCompression,
+            InstallSnapshotRequest<TypeConfigKV>,
+        ),
+    ),
+}
+
+impl RaftStreamRequest {
+    /// Decompresses snapshot chunks and records the receive progress. Any compressed snapshot
+    /// will be converted into its uncompressed variant.
+    fn prepare_snapshot(self, state: &AppStateExt, wire_len: usize) -> Result<Self, Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SnapshotDB((request_id, req)) => {
+                state.raft_db.snapshot_metrics.record_receive(
+                    req.offset,
+                    req.data.len(),
+                    wire_len,
+                    req.done,
+                );
+                Ok(Self::SnapshotDB((request_id, req)))
+            }
+            #[cfg(feature = "sqlite")]
+            Self::SnapshotDBCompressed((request_id, compression, mut req)) => {
+                req.data = compression.decompress(&req.data)?;
+                state.raft_db.snapshot_metrics.record_receive(
+                    req.offset,
+                    req.data.len(),
+                    wire_len,
+                    req.done,
+                );
+                Ok(Self::SnapshotDB((request_id, req)))
+            }
+
+            #[cfg(feature = "cache")]
+            Self::SnapshotCache((request_id, req)) => {
+                state.raft_cache.snapshot_metrics.record_receive(
+                    req.offset,
+                    req.data.len(),
+                    wire_len,
+                    req.done,
+                );
+                Ok(Self::SnapshotCache((request_id, req)))
+            }
+            #[cfg(feature = "cache")]
+            Self::SnapshotCacheCompressed((request_id, compression, mut req)) => {
+                req.data = compression.decompress(&req.data)?;
+                state.raft_cache.snapshot_metrics.record_receive(
+                    req.offset,
+                    req.data.len(),
+                    wire_len,
+                    req.done,
+                );
+                Ok(Self::SnapshotCache((request_id, req)))
+            }
+
+            req => Ok(req),
+        }
+    }
 }
 
 impl From<&[u8]> for RaftStreamRequest {
@@ -108,7 +181,7 @@ async fn handle_socket(
     let mut ws = socket.await?;
     ws.set_auto_close(true);
 
-    if let Err(err) = HandshakeSecret::server(&mut ws, state.secret_raft.as_bytes()).await {
+    if let Err(err) = HandshakeSecret::server(&mut ws, &state.secrets_raft()).await {
         error!("Error during WebSocket handshake: {}", err);
         ws.write_frame(Frame::close(1000, b"Invalid Handshake"))
             .await?;
@@ -162,7 +235,13 @@ async fn handle_socket(
             OpCode::Binary => {
                 let bytes = frame.payload.deref();
                 match bincode::deserialize::<RaftStreamRequest>(bytes) {
-                    Ok(req) => req,
+                    Ok(req) => match req.prepare_snapshot(&state, bytes.len()) {
+                        Ok(req) => req,
+                        Err(err) => {
+                            error!("Error decompressing snapshot chunk: {}", err);
+                            break;
+                        }
+                    },
                     Err(err) => {
                         error!("Error deserializing RaftStreamRequest: {:?}", err);
                         // let _ = tx_write.send_async(WsWriteMsg::Break).await;
@@ -184,7 +263,18 @@ async fn handle_socket(
             let bytes = match req {
                 #[cfg(feature = "sqlite")]
                 RaftStreamRequest::AppendDB((request_id, req)) => {
-                    let res = state.raft_db.raft.append_entries(req).await;
+                    let span = debug_span!(
+                        "raft_append",
+                        raft = "sqlite",
+                        leader = req.vote.leader_id().node_id,
+                        entries = req.entries.len(),
+                    );
+                    let res = state
+                        .raft_db
+                        .raft
+                        .append_entries(req)
+                        .instrument(span)
+                        .await;
                     let resp = RaftStreamResponse {
                         request_id,
                         payload: RaftStreamResponsePayload::AppendDB(res),
@@ -212,7 +302,18 @@ async fn handle_socket(
 
                 #[cfg(feature = "cache")]
                 RaftStreamRequest::AppendCache((request_id, req)) => {
-                    let res = state.raft_cache.raft.append_entries(req).await;
+                    let span = debug_span!(
+                        "raft_append",
+                        raft = "cache",
+                        leader = req.vote.leader_id().node_id,
+                        entries = req.entries.len(),
+                    );
+                    let res = state
+                        .raft_cache
+                        .raft
+                        .append_entries(req)
+                        .instrument(span)
+                        .await;
                     let resp = RaftStreamResponse {
                         request_id,
                         payload: RaftStreamResponsePayload::AppendCache(res),
@@ -237,6 +338,15 @@ async fn handle_socket(
                     };
                     bincode::serialize(&resp).unwrap()
                 }
+
+                #[cfg(feature = "sqlite")]
+                RaftStreamRequest::SnapshotDBCompressed(_) => {
+                    unreachable!("compressed snapshots are converted in `prepare_snapshot()`")
+                }
+                #[cfg(feature = "cache")]
+                RaftStreamRequest::SnapshotCacheCompressed(_) => {
+                    unreachable!("compressed snapshots are converted in `prepare_snapshot()`")
+                }
             };
 
             if let Err(err) = tx_write.send_async(WsWriteMsg::Payload(bytes)).await {
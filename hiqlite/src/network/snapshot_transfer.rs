@@ -0,0 +1,154 @@
+use crate::config::SnapshotCompression;
+use crate::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+
+impl SnapshotCompression {
+    pub(crate) fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Zstd => zstd::bulk::compress(data, 3)
+                .map_err(|err| Error::Error(format!("zstd compression: {}", err).into())),
+            Self::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+
+    pub(crate) fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Zstd => zstd::decode_all(data)
+                .map_err(|err| Error::Error(format!("zstd decompression: {}", err).into())),
+            Self::Lz4 => lz4_flex::decompress_size_prepended(data)
+                .map_err(|err| Error::Error(format!("lz4 decompression: {}", err).into())),
+        }
+    }
+}
+
+/// Limits the bandwidth of outgoing snapshot chunks. It is shared between all connections of a
+/// Raft, which means the limit applies to the sum of all snapshots sent at the same time.
+#[derive(Debug)]
+pub(crate) struct SnapshotThrottle {
+    bytes_per_sec: u64,
+    next: Mutex<Instant>,
+}
+
+impl SnapshotThrottle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `bytes` may be sent without exceeding the limit.
+    pub async fn wait(&self, bytes: usize) {
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            start
+        };
+        time::sleep_until(start).await;
+    }
+}
+
+/// Progress of snapshot transfers, which will be exposed on the `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotMetrics {
+    /// Uncompressed offset of the currently running outgoing snapshot
+    pub send_offset: AtomicU64,
+    /// Bytes sent over the network, after compression
+    pub sent_bytes: AtomicU64,
+    pub sent: AtomicU64,
+    /// Uncompressed offset of the currently running incoming snapshot
+    pub receive_offset: AtomicU64,
+    /// Bytes received over the network, before decompression
+    pub received_bytes: AtomicU64,
+    pub received: AtomicU64,
+}
+
+impl SnapshotMetrics {
+    #[inline]
+    pub fn record_send(&self, offset: u64, len: usize, wire_len: usize, done: bool) {
+        self.sent_bytes
+            .fetch_add(wire_len as u64, Ordering::Relaxed);
+        if done {
+            self.send_offset.store(0, Ordering::Relaxed);
+            self.sent.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.send_offset
+                .store(offset + len as u64, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn record_receive(&self, offset: u64, len: usize, wire_len: usize, done: bool) {
+        self.received_bytes
+            .fetch_add(wire_len as u64, Ordering::Relaxed);
+        if done {
+            self.receive_offset.store(0, Ordering::Relaxed);
+            self.received.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.receive_offset
+                .store(offset + len as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_compression() {
+        let data = "hiqlite snapshot chunk ".repeat(1024).into_bytes();
+
+        for compression in [
+            SnapshotCompression::None,
+            SnapshotCompression::Zstd,
+            SnapshotCompression::Lz4,
+        ] {
+            let compressed = compression.compress(&data).unwrap();
+            if compression != SnapshotCompression::None {
+                assert!(compressed.len() < data.len());
+            }
+            assert_eq!(compression.decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_snapshot_throttle_shared() {
+        let throttle = std::sync::Arc::new(SnapshotThrottle::new(1000));
+        let start = Instant::now();
+
+        // 3 followers sending a chunk at the same time share the limit
+        let handles = (0..3)
+            .map(|_| {
+                let throttle = throttle.clone();
+                tokio::spawn(async move {
+                    throttle.wait(500).await;
+                    Instant::now() - start
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut elapsed = Vec::with_capacity(handles.len());
+        for handle in handles {
+            elapsed.push(handle.await.unwrap());
+        }
+        elapsed.sort();
+        assert_eq!(
+            elapsed,
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(500),
+                Duration::from_millis(1000)
+            ]
+        );
+
+        // the next chunk has to wait for the bytes which have been sent already
+        throttle.wait(1000).await;
+        assert_eq!(Instant::now() - start, Duration::from_millis(1500));
+    }
+}
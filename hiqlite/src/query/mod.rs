@@ -1,5 +1,6 @@
 use crate::app_state::AppState;
 use crate::query::rows::{ColumnOwned, RowOwned};
+use crate::query::stats::{QueryStats, StatementKind};
 use crate::store::state_machine::sqlite::state_machine::SqlitePool;
 use crate::store::state_machine::sqlite::TypeConfigSqlite;
 use crate::{Error, Params};
@@ -7,10 +8,12 @@ use openraft::Raft;
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::task;
 use tracing::info;
 
 pub mod rows;
+pub mod stats;
 
 // pub(crate) async fn query_columns<S>(
 //     read_pool: &Arc<SqlitePool>,
@@ -33,6 +36,7 @@ pub mod rows;
 pub(crate) async fn query_consistent_local<S>(
     raft: &Raft<TypeConfigSqlite>,
     log_statements: bool,
+    query_stats: Arc<QueryStats>,
     read_pool: SqlitePool,
     stmt: S,
     params: Params,
@@ -41,11 +45,12 @@ where
     S: Into<Cow<'static, str>>,
 {
     let _ = raft.ensure_linearizable().await?;
-    query_owned_local(log_statements, read_pool, stmt, params).await
+    query_owned_local(log_statements, query_stats, read_pool, stmt, params).await
 }
 
 pub(crate) async fn query_owned_local<S>(
     log_statements: bool,
+    query_stats: Arc<QueryStats>,
     read_pool: SqlitePool,
     stmt: S,
     params: Params,
@@ -61,26 +66,44 @@ where
     let conn = read_pool.get().await?;
 
     task::spawn_blocking(move || {
-        let mut stmt = conn.prepare_cached(stmt.as_ref())?;
-        let columns = ColumnOwned::mapping_cols_from_stmt(stmt.columns())?;
-
-        let mut idx = 1;
-        for param in params {
-            stmt.raw_bind_parameter(idx, param.into_sql())?;
-            idx += 1;
-        }
-
-        let mut rows = stmt.raw_query();
-        let mut rows_owned = Vec::new();
-        while let Ok(Some(row)) = rows.next() {
-            rows_owned.push(RowOwned::from_row_column(row, &columns));
-        }
-
-        Ok::<Vec<RowOwned>, Error>(rows_owned)
+        let start = Instant::now();
+        let res = rows_owned(&conn, stmt.as_ref(), &params);
+        query_stats.record(
+            StatementKind::Read,
+            &stmt,
+            &params,
+            start.elapsed(),
+            res.as_ref().map(|rows| rows.len()),
+        );
+        res
     })
     .await?
 }
 
+#[inline]
+fn rows_owned(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &Params,
+) -> Result<Vec<RowOwned>, Error> {
+    let mut stmt = conn.prepare_cached(sql)?;
+    let columns = ColumnOwned::mapping_cols_from_stmt(stmt.columns())?;
+
+    let mut idx = 1;
+    for param in params {
+        stmt.raw_bind_parameter(idx, param)?;
+        idx += 1;
+    }
+
+    let mut rows = stmt.raw_query();
+    let mut rows_owned = Vec::new();
+    while let Ok(Some(row)) = rows.next() {
+        rows_owned.push(RowOwned::from_row_column(row, &columns));
+    }
+
+    Ok(rows_owned)
+}
+
 #[inline(always)]
 pub(crate) async fn query_map<T, S>(
     state: &Arc<AppState>,
@@ -97,25 +120,43 @@ where
     }
 
     let conn = state.raft_db.read_pool.get().await?;
+    let query_stats = state.raft_db.query_stats.clone();
     task::spawn_blocking(move || {
-        let mut stmt = conn.prepare_cached(stmt.as_ref())?;
-
-        let mut idx = 1;
-        for param in params {
-            stmt.raw_bind_parameter(idx, param.into_sql())?;
-            idx += 1;
-        }
-
-        let mut rows = stmt.raw_query();
-        let mut res = Vec::new();
-        while let Ok(Some(row)) = rows.next() {
-            res.push(T::from(rows::Row::Borrowed(row)));
-        }
-        Ok::<Vec<T>, Error>(res)
+        let start = Instant::now();
+        let res = rows_map(&conn, stmt.as_ref(), &params);
+        query_stats.record(
+            StatementKind::Read,
+            &stmt,
+            &params,
+            start.elapsed(),
+            res.as_ref().map(|rows| rows.len()),
+        );
+        res
     })
     .await?
 }
 
+#[inline]
+fn rows_map<T>(conn: &rusqlite::Connection, sql: &str, params: &Params) -> Result<Vec<T>, Error>
+where
+    T: for<'r> From<rows::Row<'r>>,
+{
+    let mut stmt = conn.prepare_cached(sql)?;
+
+    let mut idx = 1;
+    for param in params {
+        stmt.raw_bind_parameter(idx, param)?;
+        idx += 1;
+    }
+
+    let mut rows = stmt.raw_query();
+    let mut res = Vec::new();
+    while let Ok(Some(row)) = rows.next() {
+        res.push(T::from(rows::Row::Borrowed(row)));
+    }
+    Ok(res)
+}
+
 #[inline]
 pub(crate) async fn query_map_one<T, S>(
     state: &Arc<AppState>,
@@ -172,25 +213,43 @@ where
     }
 
     let conn = state.raft_db.read_pool.get().await?;
+    let query_stats = state.raft_db.query_stats.clone();
     task::spawn_blocking(move || {
-        let mut stmt = conn.prepare_cached(stmt.as_ref())?;
-
-        let mut idx = 1;
-        for param in params {
-            stmt.raw_bind_parameter(idx, param.into_sql())?;
-            idx += 1;
-        }
-
-        let mut rows = serde_rusqlite::from_rows::<T>(stmt.raw_query());
-        let mut res = Vec::new();
-        while let Some(Ok(ty)) = rows.next() {
-            res.push(ty);
-        }
-        Ok::<Vec<T>, Error>(res)
+        let start = Instant::now();
+        let res = rows_as(&conn, stmt.as_ref(), &params);
+        query_stats.record(
+            StatementKind::Read,
+            &stmt,
+            &params,
+            start.elapsed(),
+            res.as_ref().map(|rows| rows.len()),
+        );
+        res
     })
     .await?
 }
 
+#[inline]
+fn rows_as<T>(conn: &rusqlite::Connection, sql: &str, params: &Params) -> Result<Vec<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut stmt = conn.prepare_cached(sql)?;
+
+    let mut idx = 1;
+    for param in params {
+        stmt.raw_bind_parameter(idx, param)?;
+        idx += 1;
+    }
+
+    let mut rows = serde_rusqlite::from_rows::<T>(stmt.raw_query());
+    let mut res = Vec::new();
+    while let Some(Ok(ty)) = rows.next() {
+        res.push(ty);
+    }
+    Ok(res)
+}
+
 #[inline]
 pub(crate) async fn query_as_one<T, S>(
     state: &Arc<AppState>,
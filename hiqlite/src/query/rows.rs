@@ -1,6 +1,10 @@
-use crate::Error;
+use crate::{Error, Json};
 use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use rusqlite::types::ValueRef;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum Row<'a> {
@@ -10,22 +14,23 @@ pub enum Row<'a> {
 }
 
 impl Row<'_> {
+    /// # Panics
+    /// If the column does not exist or the type cannot be converted correctly
     pub fn get<T>(&mut self, idx: &str) -> T
     where
-        T: TryFrom<ValueOwned, Error = crate::Error> + rusqlite::types::FromSql,
+        T: TryFrom<ValueOwned, Error = crate::Error>,
     {
-        match self {
-            Row::Borrowed(b) => b.get_unwrap(idx),
-            Row::Owned(o) => o.try_get(idx).unwrap(),
-        }
+        self.try_get(idx).unwrap()
     }
 
+    /// Borrowed and owned rows use the same conversion from `ValueOwned`, which means a type
+    /// will always behave the same, no matter if the query was executed locally or remote.
     pub fn try_get<T>(&mut self, idx: &str) -> Result<T, Error>
     where
-        T: TryFrom<ValueOwned, Error = crate::Error> + rusqlite::types::FromSql,
+        T: TryFrom<ValueOwned, Error = crate::Error>,
     {
         match self {
-            Self::Borrowed(r) => r.get(idx).map_err(Error::from),
+            Self::Borrowed(r) => T::try_from(ValueOwned::try_from(r.get_ref(idx)?)?),
             Self::Owned(o) => o.try_get(idx),
         }
     }
@@ -228,6 +233,40 @@ pub enum ValueOwned {
     Blob(Vec<u8>),
 }
 
+impl TryFrom<ValueRef<'_>> for ValueOwned {
+    type Error = crate::Error;
+
+    /// Fails for `TEXT` with invalid UTF-8 instead of silently replacing it.
+    #[inline]
+    fn try_from(value: ValueRef<'_>) -> Result<Self, Self::Error> {
+        match value {
+            ValueRef::Null => Ok(Self::Null),
+            ValueRef::Integer(i) => Ok(Self::Integer(i)),
+            ValueRef::Real(r) => Ok(Self::Real(r)),
+            ValueRef::Text(t) => String::from_utf8(t.to_vec())
+                .map(Self::Text)
+                .map_err(|err| Error::Sqlite(format!("Invalid UTF-8 in TEXT: {}", err).into())),
+            ValueRef::Blob(b) => Ok(Self::Blob(b.to_vec())),
+        }
+    }
+}
+
+/// Implements `TryFrom<ValueOwned> for Option<T>` for any `T: TryFrom<ValueOwned>`.
+macro_rules! try_from_opt(
+    ($t:ty) => (
+        impl TryFrom<ValueOwned> for Option<$t> {
+            type Error = crate::Error;
+
+            fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+                match value {
+                    ValueOwned::Null => Ok(None),
+                    v => <$t>::try_from(v).map(Some),
+                }
+            }
+        }
+    )
+);
+
 impl ValueOwned {
     fn try_as_str(&self) -> Result<&str, Error> {
         match self {
@@ -259,12 +298,57 @@ impl TryFrom<ValueOwned> for Option<i64> {
     }
 }
 
+impl TryFrom<ValueOwned> for u64 {
+    type Error = crate::Error;
+
+    /// Accepts an `INTEGER`, or the 8 byte big endian `BLOB` for values bigger than `i64::MAX`.
+    /// The `TEXT` representation is accepted as well.
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        match value {
+            ValueOwned::Integer(i) => u64::try_from(i)
+                .map_err(|_| Error::Sqlite(format!("Cannot convert {} into u64", i).into())),
+            ValueOwned::Blob(b) => <[u8; 8]>::try_from(b.as_slice())
+                .map(u64::from_be_bytes)
+                .map_err(|_| {
+                    Error::Sqlite(format!("Cannot convert {} bytes into u64", b.len()).into())
+                }),
+            ValueOwned::Text(s) => s
+                .parse()
+                .map_err(|_| Error::Sqlite(format!("Cannot convert '{}' into u64", s).into())),
+            _ => Err(Error::Sqlite("Cannot convert into u64".into())),
+        }
+    }
+}
+
+try_from_opt!(u64);
+
+impl TryFrom<ValueOwned> for i128 {
+    type Error = crate::Error;
+
+    /// Accepts the biased 16 byte `BLOB` `i128` is stored as, or an `INTEGER`.
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        match value {
+            ValueOwned::Integer(i) => Ok(i as i128),
+            ValueOwned::Blob(b) => {
+                let bytes = <[u8; 16]>::try_from(b.as_slice()).map_err(|_| {
+                    Error::Sqlite(format!("Cannot convert {} bytes into i128", b.len()).into())
+                })?;
+                Ok(i128::from_be_bytes(bytes) ^ (1_i128 << 127))
+            }
+            _ => Err(Error::Sqlite("Cannot convert into i128".into())),
+        }
+    }
+}
+
+try_from_opt!(i128);
+
 impl TryFrom<ValueOwned> for f64 {
     type Error = Error;
 
     fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
         match value {
             ValueOwned::Real(r) => Ok(r),
+            ValueOwned::Integer(i) => Ok(i as f64),
             _ => Err(Error::Sqlite("Cannot convert into f64".into())),
         }
     }
@@ -286,7 +370,7 @@ impl TryFrom<ValueOwned> for bool {
 
     fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
         match value {
-            ValueOwned::Integer(i) => Ok(i == 1),
+            ValueOwned::Integer(i) => Ok(i != 0),
             ValueOwned::Real(i) => Ok(i == 1.0),
             ValueOwned::Text(s) => Ok(s.as_str() == "true"),
             _ => Err(Error::Sqlite("Cannot convert into bool".into())),
@@ -349,6 +433,62 @@ impl TryFrom<ValueOwned> for Option<Vec<u8>> {
     }
 }
 
+impl TryFrom<ValueOwned> for Decimal {
+    type Error = crate::Error;
+
+    /// Accepts `TEXT` without any loss of precision, as well as `INTEGER` and `REAL`.
+    ///
+    /// A `REAL` is only accepted with at most 15 significant digits, which is the precision an
+    /// `f64` can always represent. Anything longer has most likely been converted by a column
+    /// with `NUMERIC` affinity and lost precision already.
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        match value {
+            ValueOwned::Text(s) => Decimal::from_str_exact(&s)
+                .or_else(|_| Decimal::from_scientific(&s))
+                .map_err(|err| Error::Sqlite(err.to_string().into())),
+            ValueOwned::Integer(i) => Ok(Decimal::from(i)),
+            ValueOwned::Real(r) => {
+                let d = Decimal::try_from(r)
+                    .map_err(|err| Error::Sqlite(err.to_string().into()))?
+                    .normalize();
+                if d.mantissa().unsigned_abs() >= 10_u128.pow(f64::DIGITS) {
+                    return Err(Error::Sqlite(
+                        format!(
+                            "Cannot convert REAL {} into Decimal without losing precision - \
+                            use a TEXT column",
+                            r
+                        )
+                        .into(),
+                    ));
+                }
+                Ok(d)
+            }
+            _ => Err(Error::Sqlite("Cannot convert into Decimal".into())),
+        }
+    }
+}
+
+try_from_opt!(Decimal);
+
+impl TryFrom<ValueOwned> for Uuid {
+    type Error = crate::Error;
+
+    /// Accepts the raw 16 bytes `BLOB`, as well as any `TEXT` format `Uuid` can parse.
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        match value {
+            ValueOwned::Blob(b) => {
+                Uuid::from_slice(&b).map_err(|err| Error::Sqlite(err.to_string().into()))
+            }
+            ValueOwned::Text(s) => {
+                Uuid::try_parse(&s).map_err(|err| Error::Sqlite(err.to_string().into()))
+            }
+            _ => Err(Error::Sqlite("Cannot convert into Uuid".into())),
+        }
+    }
+}
+
+try_from_opt!(Uuid);
+
 impl TryFrom<ValueOwned> for NaiveDate {
     type Error = crate::Error;
 
@@ -466,3 +606,163 @@ impl TryFrom<ValueOwned> for serde_json::Value {
         Ok(slf)
     }
 }
+
+impl<T: DeserializeOwned> TryFrom<ValueOwned> for Json<T> {
+    type Error = crate::Error;
+
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        let slf = match value {
+            ValueOwned::Text(s) => serde_json::from_str(s.as_str()),
+            ValueOwned::Blob(b) => serde_json::from_slice(&b),
+            _ => return Err(Error::Sqlite("Cannot convert into Json<T>".into())),
+        };
+        slf.map(Json)
+            .map_err(|err| Error::Sqlite(err.to_string().into()))
+    }
+}
+
+impl<T: DeserializeOwned> TryFrom<ValueOwned> for Option<Json<T>> {
+    type Error = crate::Error;
+
+    fn try_from(value: ValueOwned) -> Result<Self, Self::Error> {
+        match value {
+            ValueOwned::Null => Ok(None),
+            v => Json::try_from(v).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Param;
+    use std::str::FromStr;
+
+    fn value(param: Param) -> ValueOwned {
+        match param {
+            Param::Null => ValueOwned::Null,
+            Param::Integer(i) => ValueOwned::Integer(i),
+            Param::Real(r) => ValueOwned::Real(r),
+            Param::Text(t) => ValueOwned::Text(t),
+            Param::Blob(b) => ValueOwned::Blob(b),
+        }
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        for i in [0, 13, i64::MAX as u64, i64::MAX as u64 + 1, u64::MAX] {
+            assert_eq!(u64::try_from(value(Param::from(i))).unwrap(), i);
+        }
+        assert!(u64::try_from(ValueOwned::Integer(-1)).is_err());
+        assert!(u64::try_from(ValueOwned::Text(u64::MAX.to_string())).is_ok());
+
+        let ints = [i128::MIN, -1, 0, 1, i128::MAX];
+        for i in ints {
+            assert_eq!(i128::try_from(value(Param::from(i))).unwrap(), i);
+        }
+        // the blob must keep the numeric ordering
+        for w in ints.windows(2) {
+            let (Param::Blob(a), Param::Blob(b)) = (Param::from(w[0]), Param::from(w[1])) else {
+                panic!("i128 must be a blob");
+            };
+            assert!(a < b);
+        }
+
+        let d = Decimal::from_str("-1234567890.0987654321").unwrap();
+        assert_eq!(Decimal::try_from(value(Param::from(d))).unwrap(), d);
+        assert_eq!(
+            Decimal::try_from(ValueOwned::Integer(7)).unwrap(),
+            Decimal::from(7)
+        );
+
+        let a = Uuid::from_u128(1);
+        let b = Uuid::now_v7();
+        assert_eq!(Uuid::try_from(value(Param::from(a))).unwrap(), a);
+        assert_eq!(
+            Uuid::try_from(value(Param::from(b.hyphenated()))).unwrap(),
+            b
+        );
+        let (Param::Blob(blob_a), Param::Blob(blob_b)) = (Param::from(a), Param::from(b)) else {
+            panic!("Uuid must be a blob");
+        };
+        assert!(blob_a < blob_b);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Data {
+            id: i64,
+            tags: Vec<String>,
+        }
+        let data = Data {
+            id: 1,
+            tags: vec!["a".to_string()],
+        };
+        let json = Json::<Data>::try_from(value(Param::from(Json(&data)))).unwrap();
+        assert_eq!(json.0, data);
+        assert!(Option::<Json<Data>>::try_from(ValueOwned::Null)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_sqlite_column_round_trip() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, u INTEGER, d_text TEXT, d_num DECIMAL)",
+        )
+        .unwrap();
+
+        let read = |id: i64, col: &str| {
+            conn.query_row(
+                &format!("SELECT {} FROM test WHERE id = $1", col),
+                [id],
+                |row| Ok(ValueOwned::try_from(row.get_ref(0)?)),
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        let ints = [0, 13, i64::MAX as u64, i64::MAX as u64 + 1, u64::MAX];
+        let decimals = ["12345678901234567890.123", "-0.000000001", "1.5"];
+        for (id, (i, d)) in ints.iter().zip(decimals.iter().cycle()).enumerate() {
+            let d = Decimal::from_str(d).unwrap();
+            conn.execute(
+                "INSERT INTO test (id, u, d_text, d_num) VALUES ($1, $2, $3, $4)",
+                [
+                    Param::from(id as i64),
+                    Param::from(*i),
+                    Param::from(d),
+                    Param::from(d),
+                ],
+            )
+            .unwrap();
+
+            let id = id as i64;
+            assert_eq!(u64::try_from(read(id, "u")).unwrap(), *i);
+            assert_eq!(Decimal::try_from(read(id, "d_text")).unwrap(), d);
+        }
+
+        // the numeric ordering must be kept, even for values bigger than `i64::MAX`
+        let mut stmt = conn.prepare("SELECT u FROM test ORDER BY u").unwrap();
+        let sorted = stmt
+            .query_map([], |row| Ok(ValueOwned::try_from(row.get_ref(0)?)))
+            .unwrap()
+            .map(|v| u64::try_from(v.unwrap().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, ints);
+
+        // a NUMERIC column converts the text into a REAL -> must not lose precision silently
+        assert!(Decimal::try_from(read(0, "d_num")).is_err());
+        assert_eq!(
+            Decimal::try_from(read(2, "d_num")).unwrap(),
+            Decimal::from_str("1.5").unwrap()
+        );
+
+        // invalid UTF-8 inside TEXT must be an error instead of being replaced silently
+        let invalid = conn
+            .query_row("SELECT CAST(x'ff' AS TEXT)", [], |row| {
+                Ok(ValueOwned::try_from(row.get_ref(0)?))
+            })
+            .unwrap();
+        assert!(invalid.is_err());
+    }
+}
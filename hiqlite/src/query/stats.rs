@@ -0,0 +1,235 @@
+use crate::{Error, Params};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// The max amount of distinct statements we keep stats for. Dynamic SQL (e.g. with values
+/// formatted directly into the statement) could otherwise grow the map without bounds.
+const MAX_STATEMENTS: usize = 1024;
+/// The amount of latest samples per statement used for calculating percentiles.
+const MAX_SAMPLES: usize = 1024;
+
+/// Where a statement has been executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementKind {
+    /// Local read from the read pool
+    Read,
+    /// Write applied by the state machine writer
+    Write,
+}
+
+/// Aggregated latency statistics for a single SQL statement on this node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementStats {
+    pub sql: String,
+    pub kind: StatementKind,
+    /// How often this statement has been executed since node start or the last reset
+    pub count: u64,
+    /// How many executions have returned an error
+    pub errors: u64,
+    /// Rows affected for writes, returned rows for reads
+    pub rows: u64,
+    pub total_micros: u64,
+    pub mean_micros: u64,
+    /// Calculated from the latest 1024 executions
+    pub p50_micros: u64,
+    /// Calculated from the latest 1024 executions
+    pub p99_micros: u64,
+    pub max_micros: u64,
+}
+
+#[derive(Debug, Default)]
+struct Acc {
+    count: u64,
+    errors: u64,
+    rows: u64,
+    total_micros: u64,
+    max_micros: u64,
+    samples: Vec<u64>,
+    samples_idx: usize,
+}
+
+impl Acc {
+    #[inline]
+    fn add(&mut self, micros: u64, rows: Option<usize>) {
+        self.count += 1;
+        match rows {
+            Some(rows) => self.rows += rows as u64,
+            None => self.errors += 1,
+        }
+        self.total_micros += micros;
+        if micros > self.max_micros {
+            self.max_micros = micros;
+        }
+
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(micros);
+        } else {
+            self.samples[self.samples_idx] = micros;
+            self.samples_idx = (self.samples_idx + 1) % MAX_SAMPLES;
+        }
+    }
+
+    fn to_stats(&self, sql: &str, kind: StatementKind) -> StatementStats {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let percentile = |p: usize| {
+            if sorted.is_empty() {
+                0
+            } else {
+                sorted[(sorted.len() - 1) * p / 100]
+            }
+        };
+
+        StatementStats {
+            sql: sql.to_string(),
+            kind,
+            count: self.count,
+            errors: self.errors,
+            rows: self.rows,
+            total_micros: self.total_micros,
+            mean_micros: self.total_micros.checked_div(self.count).unwrap_or(0),
+            p50_micros: percentile(50),
+            p99_micros: percentile(99),
+            max_micros: self.max_micros,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Statements {
+    reads: HashMap<String, Acc>,
+    writes: HashMap<String, Acc>,
+}
+
+/// Per-node statement timings for the read and the write path, as well as the slow query log.
+#[derive(Debug)]
+pub(crate) struct QueryStats {
+    slow_threshold: Option<Duration>,
+    statements: Mutex<Statements>,
+}
+
+impl QueryStats {
+    pub fn new(slow_query_threshold_ms: Option<u64>) -> Self {
+        Self {
+            slow_threshold: slow_query_threshold_ms.map(Duration::from_millis),
+            statements: Default::default(),
+        }
+    }
+
+    /// Records a single statement execution and logs it, if it exceeded the slow query
+    /// threshold. `rows` must be `Err(_)` if the execution failed.
+    pub fn record(
+        &self,
+        kind: StatementKind,
+        sql: &str,
+        params: &Params,
+        elapsed: Duration,
+        rows: Result<usize, &Error>,
+    ) {
+        if let Some(threshold) = self.slow_threshold {
+            if elapsed >= threshold {
+                warn!(
+                    "Slow {:?} query after {} ms:\n{}\n{:?}",
+                    kind,
+                    elapsed.as_millis(),
+                    sql,
+                    params
+                );
+            }
+        }
+
+        let micros = elapsed.as_micros() as u64;
+        let mut lock = self.statements.lock().unwrap();
+        let map = match kind {
+            StatementKind::Read => &mut lock.reads,
+            StatementKind::Write => &mut lock.writes,
+        };
+
+        if let Some(acc) = map.get_mut(sql) {
+            acc.add(micros, rows.ok());
+        } else if map.len() < MAX_STATEMENTS {
+            let mut acc = Acc::default();
+            acc.add(micros, rows.ok());
+            map.insert(sql.to_string(), acc);
+        }
+    }
+
+    /// Returns the stats for all statements, sorted by their total execution time descending.
+    pub fn get(&self) -> Vec<StatementStats> {
+        let lock = self.statements.lock().unwrap();
+        let mut res = Vec::with_capacity(lock.reads.len() + lock.writes.len());
+        for (sql, acc) in lock.reads.iter() {
+            res.push(acc.to_stats(sql, StatementKind::Read));
+        }
+        for (sql, acc) in lock.writes.iter() {
+            res.push(acc.to_stats(sql, StatementKind::Write));
+        }
+        drop(lock);
+
+        res.sort_by_key(|s| Reverse(s.total_micros));
+        res
+    }
+
+    pub fn reset(&self) {
+        let mut lock = self.statements.lock().unwrap();
+        lock.reads.clear();
+        lock.writes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params;
+    use crate::Param;
+
+    #[test]
+    fn test_query_stats() {
+        let stats = QueryStats::new(None);
+        for i in 1..=100 {
+            stats.record(
+                StatementKind::Read,
+                "SELECT 1",
+                &params!(),
+                Duration::from_micros(i),
+                Ok(1),
+            );
+        }
+        let err = Error::Sqlite("test".into());
+        stats.record(
+            StatementKind::Write,
+            "DELETE FROM test",
+            &params!(1),
+            Duration::from_micros(10_000),
+            Err(&err),
+        );
+
+        let res = stats.get();
+        assert_eq!(res.len(), 2);
+
+        // sorted by total time
+        let write = &res[0];
+        assert_eq!(write.kind, StatementKind::Write);
+        assert_eq!(write.count, 1);
+        assert_eq!(write.errors, 1);
+        assert_eq!(write.rows, 0);
+
+        let read = &res[1];
+        assert_eq!(read.kind, StatementKind::Read);
+        assert_eq!(read.count, 100);
+        assert_eq!(read.rows, 100);
+        assert_eq!(read.total_micros, 5050);
+        assert_eq!(read.mean_micros, 50);
+        assert_eq!(read.p50_micros, 50);
+        assert_eq!(read.p99_micros, 99);
+        assert_eq!(read.max_micros, 100);
+
+        stats.reset();
+        assert!(stats.get().is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Error;
+use crate::{config, Error};
 use cryptr::{EncValue, FileReader, FileWriter, S3Reader, S3Writer, StreamReader, StreamWriter};
 use std::env;
 use std::sync::Arc;
@@ -42,36 +42,36 @@ impl S3Config {
     }
 
     pub fn try_from_env() -> Option<Arc<Self>> {
-        if let Ok(url) = env::var("HQL_S3_URL") {
-            // we assume that all values exist when we can read the url successfully
-
-            let url = reqwest::Url::parse(&url).expect("Cannot parse HQL_S3_URL as URL");
-            let bucket_name = env::var("HQL_S3_BUCKET").expect("HQL_S3_BUCKET not found");
-            let region = Region(env::var("HQL_S3_REGION").expect("HQL_S3_REGION not found"));
-            let path_style = env::var("HQL_S3_PATH_STYLE")
-                .expect("HQL_S3_PATH_STYLE not found")
-                .parse::<bool>()
-                .expect("Cannot parse HQL_S3_PATH_STYLE as bool");
-
-            let access_key_id = AccessKeyId(env::var("HQL_S3_KEY").expect("HQL_S3_KEY not found"));
-            let access_key_secret =
-                AccessKeySecret(env::var("HQL_S3_SECRET").expect("HQL_S3_SECRET not found"));
-            let credentials = Credentials {
-                access_key_id,
-                access_key_secret,
-            };
-
-            let options = Some(BucketOptions {
-                path_style,
-                list_objects_v2: true,
-            });
-
-            let bucket = Bucket::new(url, bucket_name, region, credentials, options).unwrap();
-
-            Some(Arc::new(S3Config { bucket }))
-        } else {
-            None
-        }
+        Self::parse_env().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn parse_env() -> Result<Option<Arc<Self>>, Error> {
+        let Ok(url) = env::var("HQL_S3_URL") else {
+            return Ok(None);
+        };
+
+        // all other values must exist when the url is set
+        let url = reqwest::Url::parse(&url)
+            .map_err(|_| Error::Config("Cannot parse HQL_S3_URL as URL".into()))?;
+        let bucket_name = config::env_required("HQL_S3_BUCKET")?;
+        let region = Region(config::env_required("HQL_S3_REGION")?);
+        let path_style = config::env_parse::<bool>("HQL_S3_PATH_STYLE")?
+            .ok_or_else(|| Error::Config("HQL_S3_PATH_STYLE not found".into()))?;
+
+        let credentials = Credentials {
+            access_key_id: AccessKeyId(config::env_required("HQL_S3_KEY")?),
+            access_key_secret: AccessKeySecret(config::env_required("HQL_S3_SECRET")?),
+        };
+
+        let options = Some(BucketOptions {
+            path_style,
+            list_objects_v2: true,
+        });
+
+        let bucket = Bucket::new(url, bucket_name, region, credentials, options)
+            .map_err(|err| Error::S3(err.to_string()))?;
+
+        Ok(Some(Arc::new(S3Config { bucket })))
     }
 
     pub(crate) async fn push(&self, path: &str, object: &str) -> Result<(), Error> {
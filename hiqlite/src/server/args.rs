@@ -1,4 +1,5 @@
-use clap::{Parser, ValueEnum};
+use crate::ExportFormat;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Clone, Parser)]
 #[clap(author, version, about)]
@@ -12,6 +13,19 @@ pub enum Args {
 
     /// Generate a new default config with safe values for testing
     GenerateConfig(ArgsGenerate),
+
+    /// Export the whole database or selected tables from a running cluster as an SQL dump,
+    /// CSV or JSONL.
+    Export(ArgsExport),
+
+    /// Import an SQL dump, CSV or JSONL into a running cluster.
+    Import(ArgsImport),
+
+    /// Start an interactive SQL shell connected to a running cluster.
+    Shell(ArgsShell),
+
+    /// Inspect and administer a running cluster.
+    Cluster(ArgsCluster),
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -55,6 +69,122 @@ pub struct ArgsGenerate {
     pub insecure_cookie: bool,
 }
 
+#[derive(Debug, Clone, Parser)]
+pub struct ArgsExport {
+    /// The optional config file name to parse
+    #[clap(short, long, default_value = "$HOME/.hiqlite/config")]
+    pub config_file: String,
+
+    /// The export format: sql, csv or jsonl
+    #[clap(short, long, default_value = "sql", value_parser = parse_format)]
+    pub format: ExportFormat,
+
+    /// The table to export. Can be given multiple times. Exports all tables if not set.
+    #[clap(short, long)]
+    pub table: Vec<String>,
+
+    /// The output file, or a directory for CSV and JSONL exports of multiple tables, which
+    /// will create one file per table. Writes to stdout if not set.
+    #[clap(short, long)]
+    pub out: Option<String>,
+
+    /// Log Level, logs are written to stderr
+    #[clap(short, long, default_value = "warn")]
+    pub log_level: LogLevel,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ArgsImport {
+    /// The optional config file name to parse
+    #[clap(short, long, default_value = "$HOME/.hiqlite/config")]
+    pub config_file: String,
+
+    /// The import format: sql, csv or jsonl
+    #[clap(short, long, default_value = "sql", value_parser = parse_format)]
+    pub format: ExportFormat,
+
+    /// The table to import into. Mandatory for CSV and JSONL.
+    #[clap(short, long)]
+    pub table: Option<String>,
+
+    /// The input file. Reads from stdin if not set.
+    #[clap(short, long)]
+    pub input: Option<String>,
+
+    /// Log Level, logs are written to stderr
+    #[clap(short, long, default_value = "warn")]
+    pub log_level: LogLevel,
+}
+
+fn parse_format(s: &str) -> Result<ExportFormat, String> {
+    s.parse::<ExportFormat>().map_err(|err| err.to_string())
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ArgsShell {
+    /// The optional config file name to parse
+    #[clap(short, long, default_value = "$HOME/.hiqlite/config")]
+    pub config_file: String,
+
+    /// Start with consistent reads through the leader. Can be toggled with `.consistent`.
+    #[clap(long)]
+    pub consistent: bool,
+
+    /// Log Level, logs are written to stderr
+    #[clap(short, long, default_value = "warn")]
+    pub log_level: LogLevel,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ArgsCluster {
+    #[clap(subcommand)]
+    pub cmd: ClusterCmd,
+
+    /// The optional config file name to parse
+    #[clap(short, long, global = true, default_value = "$HOME/.hiqlite/config")]
+    pub config_file: String,
+
+    /// Print the output as JSON
+    #[clap(long, global = true)]
+    pub json: bool,
+
+    /// Log Level, logs are written to stderr
+    #[clap(short, long, global = true, default_value = "warn")]
+    pub log_level: LogLevel,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ClusterCmd {
+    /// Show the leader, term and log state of the database and cache Rafts
+    Status,
+
+    /// List all members with their role and addresses
+    Members,
+
+    /// Show the full Raft metrics including the replication state of each node
+    Metrics,
+
+    /// Create a backup on all nodes, or list the existing backups with `--list`
+    Backup {
+        /// List the backup catalogue instead of creating a new backup
+        #[clap(long)]
+        list: bool,
+    },
+
+    /// Build a new snapshot of the database and cache on the current leader
+    Snapshot,
+
+    /// Transfer the leadership to another voter
+    TransferLeader {
+        /// The target node id. The voter with the most replicated logs is chosen if not set.
+        #[clap(long)]
+        to: Option<u64>,
+    },
+
+    /// Check the cluster health. Exits with an error if any Raft is unhealthy.
+    Health,
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 pub enum LogLevel {
     Info,
@@ -0,0 +1,398 @@
+use crate::backup::BackupInfo;
+use crate::server::args::{ArgsCluster, ClusterCmd};
+use crate::server::config;
+use crate::server::table::print_table;
+use crate::{Client, Error, Node, NodeId};
+use chrono::DateTime;
+use openraft::{LogId, RaftMetrics};
+use serde_json::{json, Value};
+
+type Metrics = RaftMetrics<NodeId, Node>;
+
+pub async fn cluster(args: ArgsCluster) -> Result<(), Error> {
+    let client = config::build_remote_client(args.config_file).await?;
+    let json = args.json;
+
+    match args.cmd {
+        ClusterCmd::Status => status(&client, json).await,
+        ClusterCmd::Members => members(&client, json).await,
+        ClusterCmd::Metrics => metrics(&client, json).await,
+        ClusterCmd::Backup { list: false } => backup(&client, json).await,
+        ClusterCmd::Backup { list: true } => list_backups(&client, json).await,
+        ClusterCmd::Snapshot => snapshot(&client, json).await,
+        ClusterCmd::TransferLeader { to } => transfer_leader(&client, to, json).await,
+        ClusterCmd::Health => health(&client, json).await,
+    }
+}
+
+/// The metrics of the database and cache Raft, fetched from the current leaders.
+async fn fetch_metrics(client: &Client) -> Result<[(&'static str, Metrics); 2], Error> {
+    Ok([
+        ("db", client.metrics_db().await?),
+        ("cache", client.metrics_cache().await?),
+    ])
+}
+
+async fn status(client: &Client, json: bool) -> Result<(), Error> {
+    let rafts = fetch_metrics(client).await?;
+
+    if json {
+        let value = rafts
+            .iter()
+            .map(|(name, metrics)| (name.to_string(), status_json(metrics)))
+            .collect::<serde_json::Map<_, _>>();
+        print_json(&Value::Object(value));
+    } else {
+        for (name, metrics) in &rafts {
+            println!("[{}]", name);
+            print_fields(&status_fields(metrics));
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+async fn members(client: &Client, json: bool) -> Result<(), Error> {
+    let rafts = fetch_metrics(client).await?;
+
+    if json {
+        let value = rafts
+            .iter()
+            .map(|(name, metrics)| {
+                let members = member_rows(metrics)
+                    .into_iter()
+                    .map(|[id, role, addr_api, addr_raft]| {
+                        json!({
+                            "id": id.parse::<NodeId>().unwrap_or_default(),
+                            "role": role,
+                            "addr_api": addr_api,
+                            "addr_raft": addr_raft,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                (name.to_string(), Value::Array(members))
+            })
+            .collect::<serde_json::Map<_, _>>();
+        print_json(&Value::Object(value));
+    } else {
+        for (name, metrics) in &rafts {
+            println!("[{}]", name);
+            print_table(
+                &["id", "role", "addr_api", "addr_raft"],
+                &member_rows(metrics)
+                    .into_iter()
+                    .map(Vec::from)
+                    .collect::<Vec<_>>(),
+            );
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+async fn metrics(client: &Client, json: bool) -> Result<(), Error> {
+    let rafts = fetch_metrics(client).await?;
+
+    if json {
+        let value = rafts
+            .iter()
+            .map(|(name, metrics)| {
+                let value = serde_json::to_value(metrics)
+                    .map_err(|err| Error::Error(err.to_string().into()))?;
+                Ok((name.to_string(), value))
+            })
+            .collect::<Result<serde_json::Map<_, _>, Error>>()?;
+        print_json(&Value::Object(value));
+        return Ok(());
+    }
+
+    for (name, metrics) in &rafts {
+        println!("[{}]", name);
+        let mut fields = status_fields(metrics);
+        fields.push(("vote", metrics.vote.to_string()));
+        fields.push((
+            "running",
+            match &metrics.running_state {
+                Ok(_) => "ok".to_string(),
+                Err(err) => err.to_string(),
+            },
+        ));
+        fields.push((
+            "quorum ack",
+            metrics
+                .millis_since_quorum_ack
+                .map(|ms| format!("{} ms ago", ms))
+                .unwrap_or_else(|| "-".to_string()),
+        ));
+        print_fields(&fields);
+
+        // replication metrics only exist on the leader
+        if let Some(replication) = &metrics.replication {
+            let last_log = metrics.last_log_index.unwrap_or_default();
+            let rows = replication
+                .iter()
+                .map(|(id, matched)| {
+                    let matched = matched.map(|log_id| log_id.index);
+                    vec![
+                        id.to_string(),
+                        fmt_opt(matched),
+                        last_log
+                            .saturating_sub(matched.unwrap_or_default())
+                            .to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            println!();
+            print_table(&["node", "matched", "lag"], &rows);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+async fn backup(client: &Client, json: bool) -> Result<(), Error> {
+    client.backup().await?;
+
+    if json {
+        print_json(&json!({ "triggered": true }));
+    } else {
+        println!("Backup has been triggered on all nodes and runs in the background");
+    }
+
+    Ok(())
+}
+
+async fn list_backups(client: &Client, json: bool) -> Result<(), Error> {
+    let backups = client.list_backups().await?;
+
+    if json {
+        let value =
+            serde_json::to_value(&backups).map_err(|err| Error::Error(err.to_string().into()))?;
+        print_json(&value);
+        return Ok(());
+    }
+
+    let rows = backups
+        .iter()
+        .map(|backup| {
+            let BackupInfo {
+                name,
+                target,
+                node_id,
+                timestamp,
+                size,
+                log_index,
+                verified,
+            } = backup;
+            vec![
+                name.clone(),
+                target.clone(),
+                node_id.to_string(),
+                DateTime::from_timestamp(*timestamp, 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| timestamp.to_string()),
+                size.to_string(),
+                fmt_opt(*log_index),
+                verified.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(
+        &[
+            "name",
+            "target",
+            "node",
+            "created (UTC)",
+            "size",
+            "log_index",
+            "verified",
+        ],
+        &rows,
+    );
+    println!("({} backups)", backups.len());
+
+    Ok(())
+}
+
+async fn snapshot(client: &Client, json: bool) -> Result<(), Error> {
+    let snapshots = [
+        ("db", client.trigger_snapshot_db().await?),
+        ("cache", client.trigger_snapshot_cache().await?),
+    ];
+
+    if json {
+        let value = snapshots
+            .iter()
+            .map(|(name, log_id)| (name.to_string(), json!(log_id.map(|id| id.index))))
+            .collect::<serde_json::Map<_, _>>();
+        print_json(&Value::Object(value));
+    } else {
+        for (name, log_id) in snapshots {
+            match log_id {
+                Some(log_id) => println!("{} snapshot built at log index {}", name, log_id.index),
+                None => println!("{} has no applied logs yet - nothing to snapshot", name),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn transfer_leader(client: &Client, to: Option<NodeId>, json: bool) -> Result<(), Error> {
+    client.transfer_leadership(to).await?;
+    let rafts = fetch_metrics(client).await?;
+
+    if json {
+        let value = rafts
+            .iter()
+            .map(|(name, metrics)| (name.to_string(), json!(metrics.current_leader)))
+            .collect::<serde_json::Map<_, _>>();
+        print_json(&Value::Object(value));
+    } else {
+        for (name, metrics) in &rafts {
+            println!("{} leader: {}", name, fmt_opt(metrics.current_leader));
+        }
+    }
+
+    Ok(())
+}
+
+async fn health(client: &Client, json: bool) -> Result<(), Error> {
+    let checks = [
+        ("db", client.is_healthy_db().await),
+        ("cache", client.is_healthy_cache().await),
+    ];
+    let is_healthy = checks.iter().all(|(_, res)| res.is_ok());
+
+    if json {
+        let value = checks
+            .iter()
+            .map(|(name, res)| {
+                let value = json!({
+                    "healthy": res.is_ok(),
+                    "error": res.as_ref().err().map(|err| err.to_string()),
+                });
+                (name.to_string(), value)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        print_json(&Value::Object(value));
+    } else {
+        for (name, res) in &checks {
+            match res {
+                Ok(_) => println!("{}: ok", name),
+                Err(err) => println!("{}: {}", name, err),
+            }
+        }
+    }
+
+    if is_healthy {
+        Ok(())
+    } else {
+        Err(Error::Connect("The cluster is not healthy".to_string()))
+    }
+}
+
+fn status_fields(metrics: &Metrics) -> Vec<(&'static str, String)> {
+    let membership = metrics.membership_config.membership();
+    let voters = membership.voter_ids().collect::<Vec<_>>();
+    let learners = membership.learner_ids().collect::<Vec<_>>();
+
+    vec![
+        ("node", format!("{} ({:?})", metrics.id, metrics.state)),
+        ("leader", fmt_leader(metrics)),
+        ("term", metrics.current_term.to_string()),
+        ("last log", fmt_opt(metrics.last_log_index)),
+        ("last applied", fmt_log_id(metrics.last_applied)),
+        ("snapshot", fmt_log_id(metrics.snapshot)),
+        ("purged", fmt_log_id(metrics.purged)),
+        ("voters", fmt_ids(&voters)),
+        ("learners", fmt_ids(&learners)),
+    ]
+}
+
+fn status_json(metrics: &Metrics) -> Value {
+    let membership = metrics.membership_config.membership();
+    json!({
+        "node_id": metrics.id,
+        "state": format!("{:?}", metrics.state),
+        "leader": metrics.current_leader,
+        "term": metrics.current_term,
+        "last_log_index": metrics.last_log_index,
+        "last_applied": metrics.last_applied.map(|id| id.index),
+        "snapshot": metrics.snapshot.map(|id| id.index),
+        "purged": metrics.purged.map(|id| id.index),
+        "voters": membership.voter_ids().collect::<Vec<_>>(),
+        "learners": membership.learner_ids().collect::<Vec<_>>(),
+    })
+}
+
+/// `[id, role, addr_api, addr_raft]` for each member
+fn member_rows(metrics: &Metrics) -> Vec<[String; 4]> {
+    let membership = metrics.membership_config.membership();
+    membership
+        .nodes()
+        .map(|(id, node)| {
+            let role = if metrics.current_leader == Some(*id) {
+                "leader"
+            } else if membership.is_voter(id) {
+                "voter"
+            } else {
+                "learner"
+            };
+            [
+                id.to_string(),
+                role.to_string(),
+                node.addr_api.clone(),
+                node.addr_raft.clone(),
+            ]
+        })
+        .collect()
+}
+
+fn fmt_leader(metrics: &Metrics) -> String {
+    let Some(leader) = metrics.current_leader else {
+        return "none - election in progress".to_string();
+    };
+    match metrics.membership_config.membership().get_node(&leader) {
+        Some(node) => format!("{} ({})", leader, node.addr_api),
+        None => leader.to_string(),
+    }
+}
+
+fn fmt_log_id(log_id: Option<LogId<NodeId>>) -> String {
+    fmt_opt(log_id.map(|id| id.index))
+}
+
+fn fmt_opt<T: ToString>(value: Option<T>) -> String {
+    value
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn fmt_ids(ids: &[NodeId]) -> String {
+    if ids.is_empty() {
+        "-".to_string()
+    } else {
+        ids.iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn print_json(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("JSON values to always serialize")
+    );
+}
+
+fn print_fields(fields: &[(&str, String)]) {
+    let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in fields {
+        println!("  {:width$}  {}", key, value, width = width);
+    }
+}
@@ -1,17 +1,17 @@
 use crate::helpers::{read_line_stdin, set_path_access};
 use crate::server::args::{ArgsConfig, ArgsGenerate};
 use crate::server::password;
-use crate::{Error, NodeConfig};
+use crate::{config_file, Client, Error, Node, NodeConfig, ServerTlsConfig};
 use cryptr::{utils, EncKeys};
+use std::path::Path;
 use tokio::fs;
 
+/// The `--config-file` default, which resolves to the files inside `~/.hiqlite`.
+const DEFAULT_CONFIG_FILE: &str = "$HOME/.hiqlite/config";
+
 pub fn build_node_config(args: ArgsConfig) -> Result<NodeConfig, Error> {
-    let config_path = if args.config_file == "$HOME/.hiqlite/config" {
-        default_config_file_path()
-    } else {
-        args.config_file
-    };
-    let mut config = NodeConfig::from_env_all(&config_path);
+    let config_path = resolve_config_path(args.config_file)?;
+    let mut config = NodeConfig::try_from_env_all(&config_path)?;
 
     if let Some(id) = args.node_id {
         config.node_id = id;
@@ -23,12 +23,61 @@ pub fn build_node_config(args: ArgsConfig) -> Result<NodeConfig, Error> {
     Ok(config)
 }
 
+/// Builds a remote client for CLI commands, which only needs `HQL_NODES`, `HQL_SECRET_API`
+/// and the optional API TLS config from the config file.
+pub async fn build_remote_client(config_file: String) -> Result<Client, Error> {
+    let config_path = resolve_config_path(config_file)?;
+    if config_file::is_toml(&config_path) {
+        config_file::load(&config_path)?;
+    } else if dotenvy::from_filename_override(&config_path).is_err() {
+        return Err(Error::Config(
+            format!("Cannot read config file {}", config_path).into(),
+        ));
+    }
+
+    let nodes = Node::all_from_str(&crate::config::env_required("HQL_NODES")?)?
+        .into_iter()
+        .map(|n| n.addr_api)
+        .collect::<Vec<_>>();
+    let secret_api = crate::config::env_required("HQL_SECRET_API")?;
+    let tls_config = ServerTlsConfig::parse_env("API")?;
+    let tls_no_verify = tls_config
+        .as_ref()
+        .map(|tls| tls.danger_tls_no_verify)
+        .unwrap_or(false);
+
+    Client::remote(
+        nodes,
+        tls_config.is_some(),
+        tls_no_verify,
+        secret_api,
+        false,
+    )
+    .await
+}
+
+/// Resolves the `--config-file` default to `~/.hiqlite/config.toml`. The legacy dotenv style
+/// `~/.hiqlite/config` will only be used, if it exists and there is no `config.toml`.
+pub(crate) fn resolve_config_path(config_file: String) -> Result<String, Error> {
+    if config_file != DEFAULT_CONFIG_FILE {
+        return Ok(config_file);
+    }
+
+    let path_toml = default_config_file_path()?;
+    let path_legacy = format!("{}/config", default_config_dir()?);
+    if !Path::new(&path_toml).exists() && Path::new(&path_legacy).exists() {
+        Ok(path_legacy)
+    } else {
+        Ok(path_toml)
+    }
+}
+
 pub async fn generate(args: ArgsGenerate) -> Result<(), Error> {
-    let path = default_config_dir();
+    let path = default_config_dir()?;
     fs::create_dir_all(&path).await?;
     set_path_access(&path, 0o700).await?;
 
-    let path_file = default_config_file_path();
+    let path_file = default_config_file_path()?;
     if fs::File::open(&path_file).await.is_ok() {
         eprint!(
             "Config file {} exists already. Overwrite? (yes): ",
@@ -67,72 +116,94 @@ pub async fn generate(args: ArgsGenerate) -> Result<(), Error> {
 }
 
 #[inline]
-fn home_dir() -> String {
-    let home = home::home_dir().expect("Cannot get current $HOME");
+fn home_dir() -> Result<String, Error> {
+    let home = home::home_dir().ok_or_else(|| Error::Config("Cannot get current $HOME".into()))?;
     home.to_str()
-        .expect("Invalid characters in $HOME")
-        .to_string()
+        .map(String::from)
+        .ok_or_else(|| Error::Config("Invalid characters in $HOME".into()))
 }
 
 #[inline]
-fn default_config_dir() -> String {
-    format!("{}/.hiqlite", home_dir())
+pub(crate) fn default_config_dir() -> Result<String, Error> {
+    Ok(format!("{}/.hiqlite", home_dir()?))
 }
 
 #[inline]
-fn default_config_file_path() -> String {
-    format!("{}/config", default_config_dir())
+fn default_config_file_path() -> Result<String, Error> {
+    Ok(format!("{}/config.toml", default_config_dir()?))
 }
 
 fn default_config(password_dashboard_b64: &str, insecure_cookie: bool) -> Result<String, Error> {
-    let data_dir = format!("{}/data", default_config_dir());
+    let data_dir = format!("{}/data", default_config_dir()?);
     let secret_raft = utils::secure_random_alnum(32);
     let secret_api = utils::secure_random_alnum(32);
     let enc_keys = EncKeys::generate()?;
     let enc_keys_b64 = enc_keys.keys_as_b64()?;
-    let enc_keys_trimmed = enc_keys_b64.trim();
+    let enc_keys_toml = enc_keys_b64
+        .lines()
+        .map(|key| key.trim())
+        .filter(|key| !key.is_empty())
+        .map(|key| format!("    \"{}\",", key))
+        .collect::<Vec<_>>()
+        .join("\n");
     let enc_key_active = enc_keys.enc_key_active;
 
     Ok(format!(
-        r#"# Can be set to 'k8s' to try to split off the node id from the hostname
+        r#"# Each value can be overwritten with the env var from the legacy config,
+# which mostly is the upper case path with an `HQL_` prefix, like
+# `HQL_NODE_ID` or `HQL_BACKUP_KEEP_DAYS`. Env vars always take precedence
+# over this file, which makes it possible to inject secrets.
+
+# Can be set to 'k8s' to try to split off the node id from the hostname
 # when Hiqlite is running as a StatefulSet inside Kubernetes.
-#HQL_NODE_ID_FROM=k8s
+#node_id_from = "k8s"
 
 # The node id must exist in the nodes and there must always be
 # at least a node with ID 1.
-# Will be ignored if `HQL_NODE_ID_FROM=k8s`
-HQL_NODE_ID=1
+# Will be ignored if `node_id_from = "k8s"`
+node_id = 1
 
-# All cluster member nodes.
-# To make setting the env var easy, the values are separated by `\s`
-# while nodes are separated by `\n`
-# in the following format:
-#
-# id addr_raft addr_api
-# id addr_raft addr_api
-# id addr_raft addr_api
-#
-#HQL_NODES="
-#1 localhost:8100 localhost:8200
-#2 localhost:8100 localhost:8200
-#3 localhost:8100 localhost:8200
-#"
-HQL_NODES="
-1 localhost:8100 localhost:8200
-"
+# The role of this node. A `learner` is a permanent, non-voting read
+# replica. It replicates all data and serves local reads and cache
+# gets, but it will never vote or become the leader. Node 1 initializes
+# the cluster and must always be a `voter`.
+# default: "voter"
+#role = "voter"
 
 # The data dir hiqlite will store raft logs and state machine data in.
-# default: hiqlite_data
-HQL_DATA_DIR={data_dir}
+# default: "hiqlite_data"
+data_dir = "{data_dir}"
 
 # The file name of the SQLite database in the state machine folder.
-# default: hiqlite.db
-#HQL_FILENAME_DB=hiqlite.db
+# default: "hiqlite.db"
+#filename_db = "hiqlite.db"
+
+# Path to an existing SQLite database, which should become the initial
+# state of a new cluster. Only a pristine node 1 reads it once during the
+# very first cluster init. The file is copied and never modified, and the
+# other nodes receive it via snapshot install. It is ignored as soon as the
+# cluster exists, but you should remove it after the first start anyway.
+# default: not set
+#seed_db = "/path/to/app.sqlite"
 
 # If set to `true`, all SQL statements will be logged for debugging
 # purposes.
 # default: false
-#HQL_LOG_STATEMENTS=false
+#log_statements = false
+
+# If set, all read and write statements taking longer than this
+# threshold in milliseconds will be logged as a warning, including
+# their params and the duration. Latency statistics per statement
+# are collected independently of this value.
+# default: not set
+#slow_query_threshold_ms = 100
+
+# Writes with an idempotency key will remember their result for
+# this amount of seconds. A retry with the same key inside this
+# window returns the original result instead of applying the write
+# a 2nd time.
+# default: 600
+#idempotency_window_secs = 600
 
 # The size of the pooled connections for local database reads.
 #
@@ -147,7 +218,7 @@ HQL_DATA_DIR={data_dir}
 # travel through the Raft and have their own dedicated connection.
 #
 # default: 4
-#HQL_READ_POOL_SIZE=4
+#read_pool_size = 4
 
 # Enables immediate flush + sync to disk after each Log Store Batch.
 # The situations where you would need this are very rare, and you
@@ -168,7 +239,7 @@ HQL_DATA_DIR={data_dir}
 # `sync_immediate` will greatly reduce the write throughput and put
 # a lot more pressure on the disk. If you have lots of writes, it
 # can pretty quickly kill your SSD for instance.
-#HQL_SYNC_IMMEDIATE=false
+#sync_immediate = false
 
 # Sets the limit when the Raft will trigger the creation of a new
 # state machine snapshot and purge all logs that are included in
@@ -177,63 +248,162 @@ HQL_DATA_DIR={data_dir}
 # situations but will end up in more disk usage and longer
 # snapshot creations / log purges.
 # default: 10000
-HQL_LOGS_UNTIL_SNAPSHOT=10000
-
-# If given, these keys / certificates will be used to establish
-# TLS connections between nodes.
-#HQL_TLS_RAFT_KEY=tls/key.pem
-#HQL_TLS_RAFT_CERT=tls/cert-chain.pem
-#HQL_TLS_RAFT_DANGER_TLS_NO_VERIFY=true
-
-#HQL_TLS_API_KEY=tls/key.pem
-#HQL_TLS_API_CERT=tls/cert-chain.pem
-#HQL_TLS_API_DANGER_TLS_NO_VERIFY=true
+logs_until_snapshot = 10000
+
+# The compression for snapshots sent to other nodes over the network.
+# Can be one of 'none', 'zstd' or 'lz4'. 'zstd' has the best ratio,
+# 'lz4' is the fastest. Nodes will always accept any compression.
+# default: "none"
+#snapshot_compression = "none"
+
+# If set, limits the bandwidth in bytes per second for sending
+# snapshots to other nodes. The limit applies to the compressed data
+# and is shared between all nodes receiving a snapshot at the same
+# time, so that the normal replication traffic is not starved.
+# It must be high enough to send one snapshot chunk to each other
+# node within the `install_snapshot_timeout`.
+# default: not set
+#snapshot_bandwidth_limit = 10485760
 
 # Secrets for Raft internal authentication as well as for the API.
 # These must be at least 16 characters long and you should provide
 # different ones for both variables.
-HQL_SECRET_RAFT={secret_raft}
-HQL_SECRET_API={secret_api}
-
-# You can either parse `ENC_KEYS` and `ENC_KEY_ACTIVE` from the
-# environment with setting this value to `env`, or parse them from
-# a file on disk with `file:path/to/enc/keys/file`
-# default: env
-#HQL_ENC_KEYS_FROM=env
+secret_raft = "{secret_raft}"
+secret_api = "{secret_api}"
+
+# Additional secrets, which will be accepted for incoming connections.
+# Nodes and clients always use `secret_raft` / `secret_api` themselves.
+# This makes it possible to rotate secrets with rolling restarts and
+# without any downtime:
+# 1. add the new secret to the accepted ones on all nodes
+# 2. swap it with the current secret and keep the old one in the list
+# 3. remove the old secret from the list
+# default: []
+#secret_raft_accepted = []
+#secret_api_accepted = []
+
+# All cluster member nodes. Add one `[[nodes]]` table for each member.
+#
+# You can add an optional election `priority` (0 - 255) to each node.
+# The leader will periodically move the leadership to the healthy
+# voter with the highest priority, for instance the one closest to
+# your application servers. Nodes without a value have a priority of 0.
+#
+# Permanent learners should be marked with `role = "learner"`. Node 1
+# needs this information to only count voters for the quorum it waits
+# for during the very first cluster init.
+#
+# The env var override `HQL_NODES` uses one node per line in the
+# format `id addr_raft addr_api priority`, with an optional additional
+# `learner` value at the end.
+[[nodes]]
+id = 1
+addr_raft = "localhost:8100"
+addr_api = "localhost:8200"
+#priority = 0
+
+#[[nodes]]
+#id = 2
+#addr_raft = "localhost:8101"
+#addr_api = "localhost:8201"
+#role = "voter"
 
+# If given, these keys / certificates will be used to establish
+# TLS connections between nodes.
+# Both files are checked for changes every 30 seconds and a new
+# certificate will be loaded without a restart. You can trigger an
+# immediate reload with a `SIGHUP`.
+#[tls.raft]
+#key = "tls/key.pem"
+#cert = "tls/cert-chain.pem"
+#danger_tls_no_verify = true
+
+#[tls.api]
+#key = "tls/key.pem"
+#cert = "tls/cert-chain.pem"
+#danger_tls_no_verify = true
+
+[backup]
 # When the auto-backup task should run.
 # Accepts cron syntax:
 # "sec min hour day_of_month month day_of_week year"
 # default: "0 30 2 * * * *"
-#HQL_BACKUP_CRON="0 30 2 * * * *"
+#cron = "0 30 2 * * * *"
 
 # Backups older than the configured days will be cleaned up on S3
-# after the backup cron job `HQL_BACKUP_CRON`.
+# and inside `dir` after the backup cron job.
 # default: 30
-HQL_BACKUP_KEEP_DAYS=30
+keep_days = 30
+
+# Instead of the flat `keep_days`, you can use a grandfather-father-son
+# retention for backups on S3 and inside `dir`. For each of the last N
+# days, weeks and months, the newest backup will be kept. As soon as one
+# of these values is set, `keep_days` only applies to the log archive.
+# default: not set
+#keep_daily = 7
+#keep_weekly = 4
+#keep_monthly = 12
+
+# Backups are pushed to the S3 bucket below, if it is configured.
+# Additionally, or instead of S3, you can push backups into a local
+# directory, which should be a mounted network volume like NFS in most
+# cases. Retention with `keep_days` or the GFS values above applies
+# to both.
+# default: not set
+#dir = "/mnt/backups/hiqlite"
+# Encrypts backups inside `dir` with the same keys as S3 backups.
+# default: true
+#dir_encrypt = true
+# Set to `true` if `dir` is shared between all nodes, like a network
+# volume. Only then, backups inside `dir` can be used for an online
+# restore with `Client::restore_backup()`, because each node fetches
+# the backup on its own.
+# default: false
+#dir_shared = false
+
+# Includes a snapshot of all caches in each backup. It can be restored
+# on a running cluster with `Client::restore_cache()`, which is useful
+# for long-living cache data that is expensive to recompute after a
+# full outage. Only has an effect with the `cache` feature.
+# default: false
+#cache = false
 
 # Backups older than the configured days will be cleaned up locally
-# after each `Client::backup()` and the cron job `HQL_BACKUP_CRON`.
+# after each `Client::backup()` and the backup cron job.
 # default: 3
-HQL_BACKUP_KEEP_DAYS_LOCAL=3
+keep_days_local = 3
+
+# Continuously archives the committed SQLite Raft logs for a
+# point-in-time recovery. Can be either `s3` for the configured S3
+# bucket (encrypted), or a directory with the prefix `file:`.
+# The leader uploads a new segment every `log_archive_interval_secs`,
+# which is the max data loss in case of a disaster. Segments are
+# cleaned up after `keep_days`.
+# default: not set
+#log_archive = "s3"
+# default: 60
+#log_archive_interval_secs = 60
 
 # Access values for the S3 bucket where backups will be pushed to.
-#HQL_S3_URL=https://s3.example.com
-#HQL_S3_BUCKET=my_bucket
-#HQL_S3_REGION=example
-#HQL_S3_KEY=s3_key
-#HQL_S3_SECRET=s3_secret
+#[s3]
+#url = "https://s3.example.com"
+#bucket = "my_bucket"
+#region = "example"
+#path_style = true
+#key = "s3_key"
+#secret = "s3_secret"
+
+[encryption]
+# You can either use the `keys` and `key_active` below, or parse
+# `ENC_KEYS` and `ENC_KEY_ACTIVE` from a file on disk with
+# `file:path/to/enc/keys/file`
+# default: "env"
+#keys_from = "env"
 
 # You need to define at least one valid encryption key.
 # These keys are used to encrypt the database backups that will
 # be pushed to S3 storage.
 #
-# The format must match:
-# ENC_KEYS="
-# q6u26onRvXVG4427/M0NFQzhSSldCY01rckJNa1JYZ3g2NUFtSnNOVGdoU0E=
-# bVCyTsGaggVy5yqQ/UzluN29DZW41M3hTSkx6Y3NtZmRuQkR2TnJxUTYzcjQ=
-# "
-#
 # The first part until the first `/` is the key ID.
 # The ID must match '[a-zA-Z0-9]{{2,20}}'
 #
@@ -248,22 +418,27 @@ HQL_BACKUP_KEEP_DAYS_LOCAL=3
 # rotation work. Be careful with removing old keys. Make sure
 # that all secrets have been migrated beforehand.
 # You can find a utility in the Admin UI to do this for you.
-#
-ENC_KEYS="
-{enc_keys_trimmed}
-"
+keys = [
+{enc_keys_toml}
+]
 
-# This identifies the key ID from the `ENC_KEYS` list, that
+# This identifies the key ID from the `keys` list, that
 # should actively be used for new encryptions.
-ENC_KEY_ACTIVE={enc_key_active}
+key_active = "{enc_key_active}"
 
+[dashboard]
 # The password for the dashboard as b64 encoded Argon2ID hash
-HQL_PASSWORD_DASHBOARD={password_dashboard_b64}
+password = "{password_dashboard_b64}"
 
 # Can be set to `true` during local dev and testing to issue
 # insecure cookies
 # default: false
-HQL_INSECURE_COOKIE={insecure_cookie}
+insecure_cookie = {insecure_cookie}
+
+#[proxy]
+# The port the proxy will listen on.
+# default: 8200
+#listen_port = 8200
 "#,
     ))
 }
@@ -0,0 +1,70 @@
+use crate::server::args::{ArgsExport, ArgsImport};
+use crate::server::config;
+use crate::{Error, ExportFormat};
+use tokio::{fs, io};
+
+pub async fn export(args: ArgsExport) -> Result<(), Error> {
+    let client = config::build_remote_client(args.config_file).await?;
+    let tables = args.table.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let rows = match args.out {
+        // CSV and JSONL can only hold a single table per file
+        Some(dir) if args.format != ExportFormat::Sql && tables.len() != 1 => {
+            let tables = if tables.is_empty() {
+                client.export_table_names().await?
+            } else {
+                args.table.clone()
+            };
+
+            fs::create_dir_all(&dir).await?;
+            let mut rows = 0;
+            for table in tables {
+                let path = format!("{}/{}.{}", dir, table, args.format.extension());
+                let mut file = fs::File::create(&path).await?;
+                rows += client
+                    .export(args.format, &[table.as_str()], &mut file)
+                    .await?;
+                eprintln!("Exported table {} into {}", table, path);
+            }
+            rows
+        }
+
+        Some(path) => {
+            let mut file = fs::File::create(&path).await?;
+            client.export(args.format, &tables, &mut file).await?
+        }
+
+        None => {
+            if args.format != ExportFormat::Sql && tables.len() != 1 {
+                return Err(Error::Config(
+                    "CSV and JSONL exports of multiple tables need an output directory".into(),
+                ));
+            }
+            let mut stdout = io::stdout();
+            client.export(args.format, &tables, &mut stdout).await?
+        }
+    };
+
+    eprintln!("Exported {} rows", rows);
+    Ok(())
+}
+
+pub async fn import(args: ArgsImport) -> Result<(), Error> {
+    let client = config::build_remote_client(args.config_file).await?;
+    let table = args.table.as_deref();
+
+    let rows = match args.input {
+        Some(path) => {
+            let file = fs::File::open(&path).await?;
+            let mut reader = io::BufReader::new(file);
+            client.import(args.format, table, &mut reader).await?
+        }
+        None => {
+            let mut reader = io::BufReader::new(io::stdin());
+            client.import(args.format, table, &mut reader).await?
+        }
+    };
+
+    eprintln!("Imported {} rows", rows);
+    Ok(())
+}
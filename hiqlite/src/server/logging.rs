@@ -8,3 +8,13 @@ pub fn init_logging(level: &LogLevel) {
         .with_env_filter(EnvFilter::new(level.as_str()))
         .init();
 }
+
+/// For CLI commands, which may write their output to stdout
+pub fn init_logging_stderr(level: &LogLevel) {
+    tracing_subscriber::fmt()
+        .with_target(true)
+        .with_level(true)
+        .with_env_filter(EnvFilter::new(level.as_str()))
+        .with_writer(std::io::stderr)
+        .init();
+}
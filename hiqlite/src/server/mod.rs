@@ -6,10 +6,14 @@ use tracing::info;
 
 mod args;
 mod cache;
+mod cluster;
 pub mod config;
+mod export;
 mod logging;
 mod password;
 mod proxy;
+mod shell;
+mod table;
 
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -30,7 +34,7 @@ pub async fn server() -> Result<(), Error> {
             logging::init_logging(&args.log_level);
             info!("Hiqlite Proxy v{}", APP_VERSION);
 
-            let config = Config::parse(args.config_file);
+            let config = Config::parse(args.config_file)?;
             config.is_valid()?;
 
             proxy::start_proxy(config).await?;
@@ -40,6 +44,26 @@ pub async fn server() -> Result<(), Error> {
             logging::init_logging(&LogLevel::Info);
             config::generate(args).await?;
         }
+
+        Args::Export(args) => {
+            logging::init_logging_stderr(&args.log_level);
+            export::export(args).await?;
+        }
+
+        Args::Import(args) => {
+            logging::init_logging_stderr(&args.log_level);
+            export::import(args).await?;
+        }
+
+        Args::Shell(args) => {
+            logging::init_logging_stderr(&args.log_level);
+            shell::shell(args).await?;
+        }
+
+        Args::Cluster(args) => {
+            logging::init_logging_stderr(&args.log_level);
+            cluster::cluster(args).await?;
+        }
     }
 
     Ok(())
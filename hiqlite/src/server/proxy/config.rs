@@ -1,5 +1,5 @@
 use crate::s3::EncKeysFrom;
-use crate::{Error, Node, ServerTlsConfig};
+use crate::{config, config_file, server, Error, Node, ServerTlsConfig};
 use cryptr::EncKeys;
 use spow::pow::Pow;
 use std::env;
@@ -11,23 +11,27 @@ pub struct Config {
     pub nodes: Vec<String>,
     pub tls_config: Option<ServerTlsConfig>,
     pub secret_api: String,
+    pub secret_api_accepted: Vec<String>,
     // pub password_dashboard: String,
 }
 
 impl Config {
-    pub fn parse(filename: String) -> Self {
-        if dotenvy::from_filename("config").is_err() {
-            debug!("config file './config' not found");
+    pub fn parse(filename: String) -> Result<Self, Error> {
+        let filename = server::config::resolve_config_path(filename)?;
+        if config_file::is_toml(&filename) {
+            dotenvy::dotenv().ok();
+            config_file::load(&filename)?;
+        } else {
+            if dotenvy::from_filename("config").is_err() {
+                debug!("config file './config' not found");
+            }
+            if dotenvy::from_filename_override(&filename).is_err() {
+                debug!("config file '{}' not found", filename);
+            }
+            dotenvy::dotenv_override().ok();
         }
-        if dotenvy::from_filename_override(&filename).is_err() {
-            debug!("config file '{}' not found", filename);
-        }
-        dotenvy::dotenv_override().ok();
 
-        let listen_port = env::var("LISTEN_PORT")
-            .unwrap_or_else(|_| "8200".to_string())
-            .parse::<u16>()
-            .expect("Cannot parse LISTEN_PORT to u16");
+        let listen_port = config::env_parse("LISTEN_PORT")?.unwrap_or(8200);
 
         let enc_keys_from = env::var("HQL_ENC_KEYS_FROM")
             .map(|v| {
@@ -43,27 +47,29 @@ impl Config {
             EncKeysFrom::Env => EncKeys::from_env(),
             EncKeysFrom::File(path) => EncKeys::read_from_file(&path),
         }
-        .expect("ENC_KEYS not configured correctly")
-        .init()
-        .unwrap();
+        .and_then(|keys| keys.init())
+        .map_err(|err| {
+            Error::Config(format!("ENC_KEYS not configured correctly: {}", err).into())
+        })?;
 
-        let enc_key_active = EncKeys::get_key_active().unwrap();
+        let enc_key_active = EncKeys::get_key_active()?;
         Pow::init_bytes(enc_key_active);
 
         // let b64 =
         //     env::var("HQL_PASSWORD_DASHBOARD").expect("HQL_PASSWORD_DASHBOARD does not exist");
         // let password_dashboard = String::from_utf8(b64_decode(&b64).unwrap()).unwrap();
 
-        Self {
+        Ok(Self {
             listen_port,
-            nodes: Node::all_from_env()
+            nodes: Node::all_from_str(&config::env_required("HQL_NODES")?)?
                 .into_iter()
                 .map(|n| n.addr_api)
                 .collect::<Vec<_>>(),
-            tls_config: ServerTlsConfig::from_env("API"),
-            secret_api: env::var("HQL_SECRET_API").expect("HQL_SECRET_API not found"),
+            tls_config: ServerTlsConfig::parse_env("API")?,
+            secret_api: config::env_required("HQL_SECRET_API")?,
+            secret_api_accepted: config::env_list("HQL_SECRET_API_ACCEPTED"),
             // password_dashboard,
-        }
+        })
     }
 
     pub fn is_valid(&self) -> Result<(), Error> {
@@ -71,7 +77,7 @@ impl Config {
             return Err(Error::Config("'nodes' must not be empty".into()));
         }
 
-        if self.secret_api.len() < 16 {
+        if self.secret_api.len() < 16 || self.secret_api_accepted.iter().any(|s| s.len() < 16) {
             return Err(Error::Config(
                 "'secret_raft' and 'secret_api' should be at least 16 characters long".into(),
             ));
@@ -85,7 +85,7 @@ fn validate_secret(state: &AppStateExt, headers: &HeaderMap) -> Result<(), Error
     match headers.get(HEADER_NAME_SECRET) {
         None => Err(Error::Token("API Secret missing".into())),
         Some(secret) => {
-            if state.secret_api.as_bytes() != secret.as_bytes() {
+            if !state.secrets_api().contains(&secret.as_bytes()) {
                 Err(Error::Token("Invalid API Secret".into()))
             } else {
                 Ok(())
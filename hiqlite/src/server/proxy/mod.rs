@@ -6,6 +6,7 @@ use config::Config;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::sync::watch;
 use tracing::info;
 
 pub mod config;
@@ -41,6 +42,7 @@ pub async fn start_proxy(config: Config) -> Result<(), Error> {
     let state = Arc::new(AppStateProxy {
         client,
         secret_api: config.secret_api,
+        secret_api_accepted: config.secret_api_accepted,
         tx_notify,
         // dashboard_password: config.password_dashboard,
     });
@@ -68,7 +70,9 @@ pub async fn start_proxy(config: Config) -> Result<(), Error> {
     let addr = SocketAddr::from_str(&addr).expect("valid socket address");
 
     if let Some(config) = &config.tls_config {
-        let tls_config = config.server_config().await;
+        // the proxy runs until the process exits, which drops the sender and stops the reload
+        let (_tx_shutdown, rx_shutdown) = watch::channel(false);
+        let tls_config = config.server_config_reloading("proxy", rx_shutdown).await?;
 
         axum_server::bind_rustls(addr, tls_config)
             .serve(router.into_make_service())
@@ -1,9 +1,18 @@
+use crate::network::handshake::accepted_secrets;
 use crate::store::state_machine::memory::notify_handler::NotifyRequest;
 use crate::Client;
 
 pub struct AppStateProxy {
     pub client: Client,
     pub secret_api: String,
+    pub secret_api_accepted: Vec<String>,
     pub tx_notify: flume::Sender<NotifyRequest>,
     // pub dashboard_password: String,
 }
+
+impl AppStateProxy {
+    /// All secrets that are valid for incoming client connections
+    pub fn secrets_api(&self) -> Vec<&[u8]> {
+        accepted_secrets(&self.secret_api, &self.secret_api_accepted)
+    }
+}
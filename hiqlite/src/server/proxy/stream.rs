@@ -18,7 +18,7 @@ pub async fn handle_socket(
     let mut ws = socket.await?;
     ws.set_auto_close(true);
 
-    if let Err(err) = HandshakeSecret::server(&mut ws, state.secret_api.as_bytes()).await {
+    if let Err(err) = HandshakeSecret::server(&mut ws, &state.secrets_api()).await {
         error!("Error during WebSocket handshake: {}", err);
         ws.write_frame(Frame::close(1000, b"Invalid Handshake"))
             .await?;
@@ -0,0 +1,275 @@
+use crate::export::SqlSplitter;
+use crate::query::rows::{RowOwned, ValueOwned};
+use crate::server::args::ArgsShell;
+use crate::server::table::print_table;
+use crate::server::{config, APP_VERSION};
+use crate::{params, Client, Error, Param, Params, Row};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::time::Instant;
+use tokio::runtime::Handle;
+use tokio::{fs, task};
+
+const PROMPT: &str = "hiqlite> ";
+const PROMPT_CONTINUE: &str = "   ...> ";
+/// Longer values will be cut off in the table output
+const MAX_CELL_WIDTH: usize = 64;
+
+const HELP: &str = r#"Statements must be terminated with a `;` and may span multiple lines.
+Ctrl+C discards the current input, Ctrl+D exits.
+
+.consistent [on|off]  Show or toggle consistent reads through the leader
+.exit / .quit         Exit the shell
+.help                 Show this help
+.schema [table]       Show the schema of all objects or of a single table
+.tables               List all tables and views
+"#;
+
+struct Shell {
+    client: Client,
+    consistent: bool,
+}
+
+pub async fn shell(args: ArgsShell) -> Result<(), Error> {
+    let client = config::build_remote_client(args.config_file).await?;
+    let shell = Shell {
+        client,
+        consistent: args.consistent,
+    };
+
+    let config_dir = config::default_config_dir()?;
+    let _ = fs::create_dir_all(&config_dir).await;
+    let path_history = format!("{}/shell_history", config_dir);
+
+    // The terminal is blocking and the editor must stay on the same thread.
+    let rt = Handle::current();
+    task::spawn_blocking(move || repl(shell, rt, path_history)).await?
+}
+
+fn repl(mut shell: Shell, rt: Handle, path_history: String) -> Result<(), Error> {
+    let mut editor = DefaultEditor::new()
+        .map_err(|err| Error::Error(format!("Cannot open the terminal: {}", err).into()))?;
+    let _ = editor.load_history(&path_history);
+
+    println!(
+        "Hiqlite Shell v{} - enter `.help` for usage hints",
+        APP_VERSION
+    );
+
+    let mut splitter = SqlSplitter::default();
+    loop {
+        let prompt = if splitter.is_empty() {
+            PROMPT
+        } else {
+            PROMPT_CONTINUE
+        };
+
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                splitter = SqlSplitter::default();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(Error::Error(err.to_string().into())),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        if splitter.is_empty() && line.trim_start().starts_with('.') {
+            if rt.block_on(shell.meta_command(line.trim())) {
+                continue;
+            }
+            break;
+        }
+
+        for stmt in splitter.push(&format!("{}\n", line)) {
+            rt.block_on(shell.run(stmt));
+        }
+    }
+
+    let _ = editor.save_history(&path_history);
+    Ok(())
+}
+
+impl Shell {
+    /// Returns `false` if the shell should exit.
+    async fn meta_command(&mut self, cmd: &str) -> bool {
+        let mut parts = cmd.split_whitespace();
+
+        match parts.next().unwrap_or_default() {
+            ".exit" | ".quit" => return false,
+
+            ".help" => print!("{}", HELP),
+
+            ".tables" => {
+                let sql = "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') \
+                    AND name NOT LIKE 'sqlite_%' ORDER BY name";
+                match self.query(sql.to_string(), params!()).await {
+                    Ok(rows) => {
+                        for mut row in rows {
+                            println!("{}", row.get::<String>("name"));
+                        }
+                    }
+                    Err(err) => eprintln!("Error: {}", err),
+                }
+            }
+
+            ".schema" => {
+                let res = match parts.next() {
+                    Some(table) => {
+                        self.query(
+                            "SELECT sql FROM sqlite_master WHERE tbl_name = $1 AND sql IS NOT NULL \
+                            ORDER BY type DESC, name"
+                                .to_string(),
+                            params!(table),
+                        )
+                        .await
+                    }
+                    None => {
+                        self.query(
+                            "SELECT sql FROM sqlite_master WHERE sql IS NOT NULL \
+                            AND name NOT LIKE 'sqlite_%' ORDER BY tbl_name, type DESC, name"
+                                .to_string(),
+                            params!(),
+                        )
+                        .await
+                    }
+                };
+                match res {
+                    Ok(rows) => {
+                        for mut row in rows {
+                            println!("{};", row.get::<String>("sql"));
+                        }
+                    }
+                    Err(err) => eprintln!("Error: {}", err),
+                }
+            }
+
+            ".consistent" => {
+                match parts.next() {
+                    None => {}
+                    Some("on") => self.consistent = true,
+                    Some("off") => self.consistent = false,
+                    Some(_) => {
+                        eprintln!("Usage: .consistent [on|off]");
+                        return true;
+                    }
+                }
+                let state = if self.consistent { "on" } else { "off" };
+                println!("consistent reads: {}", state);
+            }
+
+            other => {
+                eprintln!("Unknown command {} - enter `.help` for usage hints", other);
+            }
+        }
+
+        true
+    }
+
+    async fn run(&self, stmt: String) {
+        let start = Instant::now();
+
+        let res = if is_read(&stmt) {
+            self.query(stmt, params!()).await
+        } else if stmt.to_uppercase().contains("RETURNING") {
+            self.client
+                .execute_returning(stmt, params!())
+                .await
+                .and_then(|rows| rows.into_iter().collect::<Result<Vec<_>, _>>())
+        } else {
+            match self.client.execute(stmt, params!()).await {
+                Ok(rows_affected) => {
+                    println!(
+                        "{} rows affected ({} ms)",
+                        rows_affected,
+                        start.elapsed().as_millis()
+                    );
+                }
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            return;
+        };
+
+        match res {
+            Ok(rows) => {
+                let count = rows.len();
+                print_rows(rows);
+                println!("({} rows, {} ms)", count, start.elapsed().as_millis());
+            }
+            Err(err) => eprintln!("Error: {}", err),
+        }
+    }
+
+    async fn query(&self, sql: String, params: Params) -> Result<Vec<Row<'_>>, Error> {
+        if self.consistent {
+            self.client.query_consistent(sql, params).await
+        } else {
+            self.client.query_raw(sql, params).await
+        }
+    }
+}
+
+fn is_read(stmt: &str) -> bool {
+    let keyword = stmt
+        .trim_start()
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    matches!(
+        keyword.as_str(),
+        "SELECT" | "WITH" | "EXPLAIN" | "VALUES" | "PRAGMA"
+    )
+}
+
+fn print_rows(rows: Vec<Row>) {
+    let rows = rows
+        .into_iter()
+        .map(|row| match row {
+            Row::Owned(row) => row,
+            Row::Borrowed(_) => unreachable!("remote rows are always owned"),
+        })
+        .collect::<Vec<RowOwned>>();
+    let Some(first) = rows.first() else {
+        return;
+    };
+
+    let header = first
+        .columns
+        .iter()
+        .map(|col| col.name.as_str())
+        .collect::<Vec<_>>();
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.columns
+                .iter()
+                .map(|col| fmt_value(&col.value))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    print_table(&header, &cells);
+}
+
+fn fmt_value(value: &ValueOwned) -> String {
+    let value = match value {
+        ValueOwned::Null => return "NULL".to_string(),
+        ValueOwned::Integer(i) => return i.to_string(),
+        ValueOwned::Real(r) => return r.to_string(),
+        ValueOwned::Text(t) => t.replace('\n', "\\n"),
+        ValueOwned::Blob(b) => format!("x'{}'", hex::encode(b)),
+    };
+
+    if value.chars().count() > MAX_CELL_WIDTH {
+        let mut cut = value.chars().take(MAX_CELL_WIDTH - 3).collect::<String>();
+        cut.push_str("...");
+        cut
+    } else {
+        value
+    }
+}
@@ -0,0 +1,34 @@
+/// Prints the rows as a plain text table with a column width fitting the longest value.
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths = header
+        .iter()
+        .map(|name| name.chars().count())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let line = |values: Vec<&str>| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+
+    println!("{}", line(header.to_vec()));
+    println!(
+        "{}",
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("-+-")
+    );
+    for row in rows {
+        println!("{}", line(row.iter().map(String::as_str).collect()));
+    }
+}
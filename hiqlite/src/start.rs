@@ -1,7 +1,7 @@
 use crate::app_state::AppState;
 use crate::network::raft_server_split;
-use crate::network::{api, management};
-use crate::{init, split_brain_check, store, Client, Error, NodeConfig};
+use crate::network::{api, management, prometheus};
+use crate::{init, leader_placement, split_brain_check, store, Client, Error, NodeConfig};
 use axum::routing::{get, post};
 use axum::Router;
 use serde::{Deserialize, Serialize};
@@ -9,16 +9,19 @@ use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::task;
 use tracing::{debug, info};
 
-#[cfg(feature = "backup")]
-use crate::backup;
 #[cfg(feature = "dashboard")]
 use crate::dashboard;
 #[cfg(feature = "s3")]
 use crate::s3;
+#[cfg(feature = "cache")]
+use crate::store::state_machine::memory::state_machine::StateMachineMemory;
+#[cfg(feature = "backup")]
+use crate::{backup, log_archive};
 
 #[allow(clippy::extra_unused_type_parameters)]
 pub async fn start_node_inner<C>(node_config: NodeConfig) -> Result<Client, Error>
@@ -54,14 +57,26 @@ where
 
     #[cfg(all(feature = "backup", feature = "sqlite"))]
     let backup_applied = backup::restore_backup_start(&node_config).await?;
+    #[cfg(feature = "backup")]
+    let backup_targets = backup::backup_targets(&node_config);
 
     let raft_config = Arc::new(node_config.raft_config.clone().validate().unwrap());
 
+    // created upfront, because backups may include a snapshot of the caches
+    #[cfg(feature = "cache")]
+    let state_machine_cache = Arc::new(StateMachineMemory::new::<C>().await?);
+
     #[cfg(feature = "sqlite")]
-    let raft_db = store::start_raft_db(node_config.clone(), raft_config.clone()).await?;
+    let raft_db = store::start_raft_db(
+        node_config.clone(),
+        raft_config.clone(),
+        #[cfg(all(feature = "backup", feature = "cache"))]
+        state_machine_cache.clone(),
+    )
+    .await?;
     #[cfg(feature = "cache")]
     let (is_pristine_cache_node_1, raft_cache) =
-        store::start_raft_cache::<C>(node_config.clone(), raft_config).await?;
+        store::start_raft_cache::<C>(node_config.clone(), raft_config, state_machine_cache).await?;
 
     let (api_addr, rpc_addr) = {
         let node = node_config
@@ -94,6 +109,24 @@ where
         raft_cache,
         secret_api: node_config.secret_api,
         secret_raft: node_config.secret_raft,
+        secret_api_accepted: node_config.secret_api_accepted,
+        secret_raft_accepted: node_config.secret_raft_accepted,
+        idempotency_window: Duration::from_secs(node_config.idempotency_window_secs),
+        api_client: reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .danger_accept_invalid_certs(
+                node_config
+                    .tls_api
+                    .as_ref()
+                    .map(|c| c.danger_tls_no_verify)
+                    .unwrap_or(false),
+            )
+            .build()?,
+        api_scheme: if node_config.tls_api.is_some() {
+            "https"
+        } else {
+            "http"
+        },
         #[cfg(feature = "sqlite")]
         client_buffers_db: Default::default(),
         #[cfg(feature = "cache")]
@@ -115,9 +148,17 @@ where
         node_config.tls_api.is_some(),
     );
 
+    #[cfg(any(feature = "sqlite", feature = "cache"))]
+    leader_placement::spawn(state.clone(), node_config.node_priorities.clone());
+
     #[cfg(all(feature = "backup", feature = "sqlite"))]
     if backup_applied {
-        backup::restore_backup_finish(&state).await;
+        backup::restore_backup_finish(&state).await?;
+    }
+
+    #[cfg(feature = "backup")]
+    if let Some(archive) = &state.raft_db.log_archive {
+        log_archive::spawn(state.clone(), archive.clone());
     }
 
     let (tx_shutdown, rx_shutdown) = tokio::sync::watch::channel(false);
@@ -134,7 +175,11 @@ where
     info!("rpc internal listening on {}", &rpc_addr);
 
     let tls_config = if let Some(config) = &node_config.tls_raft {
-        Some(config.server_config().await)
+        Some(
+            config
+                .server_config_reloading("raft", rx_shutdown.clone())
+                .await?,
+        )
     } else {
         None
     };
@@ -159,18 +204,23 @@ where
         }
     });
 
-    let default_routes = Router::new()
-        .nest(
-            "/cluster",
-            Router::new()
-                .route("/add_learner/:raft_type", post(management::add_learner))
-                .route("/become_member/:raft_type", post(management::become_member))
-                .route(
-                    "/membership/:raft_type",
-                    get(management::get_membership).post(management::post_membership),
-                )
-                .route("/metrics/:raft_type", get(management::metrics)),
+    let cluster_routes = Router::new()
+        .route("/add_learner/:raft_type", post(management::add_learner))
+        .route("/become_member/:raft_type", post(management::become_member))
+        .route(
+            "/membership/:raft_type",
+            get(management::get_membership).post(management::post_membership),
         )
+        .route("/metrics/:raft_type", get(management::metrics))
+        .route("/snapshot/:raft_type", post(management::snapshot))
+        .route("/elect/:raft_type", post(management::elect));
+    #[cfg(feature = "sqlite")]
+    let cluster_routes = cluster_routes.route("/query_stats", get(management::query_stats));
+    #[cfg(feature = "backup")]
+    let cluster_routes = cluster_routes.route("/restore_src", get(management::restore_src));
+
+    let default_routes = Router::new()
+        .nest("/cluster", cluster_routes)
         // TODO
         // .route("/execute", post(api::execute))
         // TODO
@@ -180,6 +230,7 @@ where
         .route("/listen", get(api::listen))
         .route("/stream/:raft_type", get(api::stream))
         .route("/health", get(api::health))
+        .route("/metrics", get(prometheus::metrics))
         .route("/ping", get(api::ping));
 
     #[cfg(not(feature = "dashboard"))]
@@ -198,6 +249,7 @@ where
                             .route("/metrics", get(dashboard::handlers::get_metrics))
                             .route("/pow", get(dashboard::handlers::get_pow))
                             .route("/query", post(dashboard::handlers::post_query))
+                            .route("/query_stats", get(dashboard::handlers::get_query_stats))
                             .route(
                                 "/session",
                                 get(dashboard::handlers::get_session)
@@ -219,7 +271,11 @@ where
 
     info!("api external listening on {}", &api_addr);
     let tls_config = if let Some(config) = &node_config.tls_api {
-        Some(config.server_config().await)
+        Some(
+            config
+                .server_config_reloading("api", rx_shutdown.clone())
+                .await?,
+        )
     } else {
         None
     };
@@ -253,6 +309,7 @@ where
                 st,
                 &crate::app_state::RaftType::Sqlite,
                 node_config.node_id,
+                node_config.role,
                 &nodes,
                 false,
                 node_config.raft_config.election_timeout_max,
@@ -273,6 +330,7 @@ where
                 st,
                 &crate::app_state::RaftType::Cache,
                 node_config.node_id,
+                node_config.role,
                 &nodes,
                 is_pristine_cache_node_1,
                 node_config.raft_config.election_timeout_max,
@@ -299,9 +357,9 @@ where
     )
     .await;
 
-    #[cfg(all(feature = "backup", feature = "s3"))]
-    if let Some(s3_config) = node_config.s3_config {
-        backup::start_cron(client.clone(), s3_config, node_config.backup_config);
+    #[cfg(feature = "backup")]
+    if !backup_targets.is_empty() {
+        backup::start_cron(client.clone(), backup_targets, node_config.backup_config);
     }
 
     Ok(client)
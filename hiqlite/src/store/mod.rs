@@ -2,6 +2,7 @@
 
 use crate::app_state::{AppState, RaftType};
 use crate::init::IsPristineNode1;
+use crate::network::snapshot_transfer::{SnapshotMetrics, SnapshotThrottle};
 use crate::network::NetworkStreaming;
 use crate::{init, Error, NodeConfig, NodeId, RaftConfig};
 use num_traits::ToPrimitive;
@@ -23,7 +24,9 @@ use crate::{
 #[cfg(feature = "sqlite")]
 use crate::{
     app_state::StateRaftDB,
+    query::stats::QueryStats,
     store::state_machine::sqlite::{
+        metrics::SqliteMetrics,
         state_machine::{SqlitePool, StateMachineSqlite},
         writer::WriterRequest,
         TypeConfigSqlite,
@@ -39,19 +42,51 @@ pub type StorageResult<T> = Result<T, StorageError<NodeId>>;
 pub(crate) async fn start_raft_db(
     node_config: NodeConfig,
     raft_config: Arc<RaftConfig>,
+    #[cfg(all(feature = "backup", feature = "cache"))] state_machine_cache: Arc<StateMachineMemory>,
 ) -> Result<StateRaftDB, Error> {
     let log_store =
         logs::rocksdb::LogStoreRocksdb::new(&node_config.data_dir, node_config.sync_immediate)
             .await;
+    let query_stats = Arc::new(QueryStats::new(node_config.slow_query_threshold_ms));
+    let metrics = Arc::new(SqliteMetrics::default());
+    #[cfg(feature = "backup")]
+    let log_archive = node_config
+        .backup_config
+        .log_archive
+        .clone()
+        .map(|config| {
+            crate::log_archive::LogArchive::new(
+                config,
+                &node_config.s3_config,
+                &node_config.data_dir,
+                node_config.backup_config.keep_days,
+            )
+        })
+        .transpose()?;
+    #[cfg(feature = "backup")]
+    let backup_targets = crate::backup::backup_targets(&node_config);
+    #[cfg(feature = "backup")]
+    let online_restore = Arc::new(crate::backup::OnlineRestore::new(&node_config)?);
     let state_machine_store = StateMachineSqlite::new(
         &node_config.data_dir,
         &node_config.filename_db,
         node_config.node_id,
         node_config.log_statements,
+        query_stats.clone(),
+        metrics.clone(),
         node_config.prepared_statement_cache_capacity,
         node_config.read_pool_size,
-        #[cfg(feature = "s3")]
-        node_config.s3_config,
+        #[cfg(feature = "backup")]
+        backup_targets.clone(),
+        #[cfg(feature = "backup")]
+        log_archive.clone(),
+        #[cfg(feature = "backup")]
+        online_restore.clone(),
+        #[cfg(all(feature = "backup", feature = "cache"))]
+        node_config
+            .backup_config
+            .cache
+            .then_some(state_machine_cache),
     )
     .await
     .unwrap();
@@ -59,6 +94,10 @@ pub(crate) async fn start_raft_db(
     let logs_writer = log_store.tx_writer.clone();
     let sql_writer = state_machine_store.write_tx.clone();
     let read_pool = state_machine_store.read_pool.clone();
+    #[cfg(feature = "backup")]
+    let path_backups = state_machine_store.path_backups.clone();
+
+    let snapshot_metrics = Arc::new(SnapshotMetrics::default());
 
     // Create the network layer that will connect and communicate the raft instances and
     // will be used in conjunction with the store created above.
@@ -68,6 +107,11 @@ pub(crate) async fn start_raft_db(
         secret_raft: node_config.secret_raft.as_bytes().to_vec(),
         raft_type: RaftType::Sqlite,
         heartbeat_interval: node_config.raft_config.heartbeat_interval,
+        snapshot_compression: node_config.snapshot_compression,
+        snapshot_throttle: node_config
+            .snapshot_bandwidth_limit
+            .map(|limit| Arc::new(SnapshotThrottle::new(limit))),
+        snapshot_metrics: snapshot_metrics.clone(),
     };
 
     // Create a local raft instance.
@@ -83,8 +127,12 @@ pub(crate) async fn start_raft_db(
 
     init::init_pristine_node_1_db(
         &raft,
+        &sql_writer,
+        &node_config.data_dir,
+        node_config.seed_db.as_deref(),
         node_config.node_id,
         &node_config.nodes,
+        &node_config.node_learners,
         &node_config.secret_api,
         node_config.tls_api.is_some(),
         node_config
@@ -102,6 +150,17 @@ pub(crate) async fn start_raft_db(
         sql_writer,
         read_pool,
         log_statements: node_config.log_statements,
+        query_stats,
+        metrics,
+        snapshot_metrics,
+        #[cfg(feature = "backup")]
+        log_archive,
+        #[cfg(feature = "backup")]
+        backup_targets,
+        #[cfg(feature = "backup")]
+        path_backups,
+        #[cfg(feature = "backup")]
+        online_restore,
     })
 }
 
@@ -109,12 +168,13 @@ pub(crate) async fn start_raft_db(
 pub(crate) async fn start_raft_cache<C>(
     node_config: NodeConfig,
     raft_config: Arc<RaftConfig>,
+    state_machine_store: Arc<StateMachineMemory>,
 ) -> Result<(IsPristineNode1, StateRaftCache), Error>
 where
     C: Debug + Serialize + for<'a> Deserialize<'a> + IntoEnumIterator + ToPrimitive,
 {
     let log_store = logs::memory::LogStoreMemory::new();
-    let state_machine_store = Arc::new(StateMachineMemory::new::<C>().await?);
+    let snapshot_metrics = Arc::new(SnapshotMetrics::default());
 
     let network = NetworkStreaming {
         node_id: node_config.node_id,
@@ -122,9 +182,15 @@ where
         secret_raft: node_config.secret_raft.as_bytes().to_vec(),
         raft_type: RaftType::Cache,
         heartbeat_interval: node_config.raft_config.heartbeat_interval,
+        snapshot_compression: node_config.snapshot_compression,
+        snapshot_throttle: node_config
+            .snapshot_bandwidth_limit
+            .map(|limit| Arc::new(SnapshotThrottle::new(limit))),
+        snapshot_metrics: snapshot_metrics.clone(),
     };
 
     let tx_caches = state_machine_store.tx_caches.clone();
+    let cache_names = C::iter().map(|c| format!("{:?}", c)).collect();
     #[cfg(feature = "listen_notify")]
     let tx_notify = state_machine_store.tx_notify.clone();
     #[cfg(feature = "listen_notify")]
@@ -147,6 +213,7 @@ where
         &raft,
         node_config.node_id,
         &node_config.nodes,
+        &node_config.node_learners,
         &node_config.secret_api,
         node_config.tls_api.is_some(),
         node_config
@@ -163,6 +230,8 @@ where
             raft,
             lock: Default::default(),
             tx_caches,
+            cache_names,
+            snapshot_metrics,
             #[cfg(feature = "listen_notify")]
             tx_notify,
             #[cfg(feature = "listen_notify")]
@@ -21,6 +21,8 @@ pub enum LockRequest {
     Await(LockAwaitPayload),
     SnapshotBuild(oneshot::Sender<HashMap<String, LockQueue>>),
     SnapshotInstall((HashMap<String, LockQueue>, oneshot::Sender<()>)),
+    /// Returns the amount of currently held locks and queued lock requests
+    Count(oneshot::Sender<(usize, usize)>),
 }
 
 pub struct LockRequestPayload {
@@ -40,7 +42,7 @@ pub struct LockAwaitPayload {
     pub ack: oneshot::Sender<LockState>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LockState {
     Locked(u64),
     Queued(u64),
@@ -202,6 +204,16 @@ async fn handler(rx: flume::Receiver<LockRequest>) {
                 locks = data;
                 ack.send(()).unwrap()
             }
+
+            LockRequest::Count(ack) => {
+                let now = Utc::now().timestamp();
+                let held = locks
+                    .values()
+                    .filter(|l| l.current_ticket.is_some() && l.exp >= now)
+                    .count();
+                let queued = locks.values().map(|l| l.queue.len()).sum();
+                let _ = ack.send((held, queued));
+            }
         }
     }
 
@@ -20,6 +20,7 @@ pub enum CacheRequestHandler {
     Clear,
     SnapshotBuild(oneshot::Sender<BTreeMap<String, Vec<u8>>>),
     SnapshotInstall((BTreeMap<String, Vec<u8>>, oneshot::Sender<()>)),
+    Len(oneshot::Sender<usize>),
 }
 
 pub fn spawn<C: Debug>(cache: C) -> flume::Sender<CacheRequestHandler> {
@@ -60,6 +61,9 @@ async fn kv_handler(cache_name: String, rx: flume::Receiver<CacheRequestHandler>
                 data = kvs;
                 ack.send(()).unwrap();
             }
+            CacheRequestHandler::Len(ack) => {
+                let _ = ack.send(data.len());
+            }
         }
     }
 
@@ -1,3 +1,4 @@
+use crate::idempotency::{Idempotency, IdempotencyStore};
 use crate::store::state_machine::memory::cache_ttl_handler::TtlRequest;
 use crate::store::state_machine::memory::kv_handler::CacheRequestHandler;
 use crate::store::state_machine::memory::{cache_ttl_handler, kv_handler, TypeConfigKV};
@@ -21,7 +22,7 @@ use std::sync::Arc;
 use strum::IntoEnumIterator;
 use tokio::fs;
 use tokio::sync::{oneshot, Mutex, RwLock};
-use tracing::info;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 #[cfg(feature = "dlock")]
@@ -36,7 +37,30 @@ type SnapshotData = Cursor<Vec<u8>>;
 type SnapshotKVs = Vec<BTreeMap<String, Vec<u8>>>;
 type SnapshotTTLs = Vec<BTreeMap<i64, String>>;
 type SnapshotLocks = Vec<u8>;
-type SnapshotDataInner = (SnapshotKVs, SnapshotTTLs, SnapshotLocks);
+type SnapshotIdempotency = Vec<(String, i64, CacheResponse)>;
+// The idempotency keys were added as the last element. bincode encodes tuples without any
+// length prefix, which means older nodes simply ignore them as trailing bytes, and snapshots
+// from older nodes are read with `deserialize_snapshot()`.
+type SnapshotDataInner = (
+    SnapshotKVs,
+    SnapshotTTLs,
+    SnapshotLocks,
+    SnapshotIdempotency,
+);
+
+/// Deserializes a snapshot, which may have been created by an older version without
+/// idempotency keys.
+fn deserialize_snapshot(bytes: &[u8]) -> bincode::Result<SnapshotDataInner> {
+    let mut reader = Cursor::new(bytes);
+    let (kvs, ttls, locks) =
+        bincode::deserialize_from::<_, (SnapshotKVs, SnapshotTTLs, SnapshotLocks)>(&mut reader)?;
+    let idempotency = if reader.position() < bytes.len() as u64 {
+        bincode::deserialize_from::<_, SnapshotIdempotency>(&mut reader)?
+    } else {
+        Vec::default()
+    };
+    Ok((kvs, ttls, locks, idempotency))
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CacheRequest {
@@ -66,9 +90,48 @@ pub enum CacheRequest {
     LockAwait((Cow<'static, str>, u64)),
     #[cfg(feature = "dlock")]
     LockRelease((Cow<'static, str>, u64)),
+    /// Wraps any of the other variants with an idempotency key to deduplicate retries.
+    Idempotent((Idempotency, Box<CacheRequest>)),
+    /// Replaces all caches with a serialized snapshot from a backup.
+    #[cfg(feature = "backup")]
+    Restore(Vec<u8>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl CacheRequest {
+    /// Attaches the given `Idempotency`, if there is any.
+    #[inline]
+    pub(crate) fn idempotent(self, idempotency: Option<Idempotency>) -> Self {
+        match idempotency {
+            Some(idempotency) => Self::Idempotent((idempotency, Box::new(self))),
+            None => self,
+        }
+    }
+
+    /// Rejects requests from remote clients, which must only be created on the server side.
+    /// `Idempotent` is stamped by the leader, `Restore` is only valid after the checks of
+    /// `restore_cache()`, and reads never go through the Raft.
+    pub(crate) fn check_remote(&self) -> Result<(), Error> {
+        match self {
+            Self::Idempotent(_) => Err(Error::BadRequest(
+                "CacheRequest::Idempotent cannot be sent by a client".into(),
+            )),
+            #[cfg(feature = "backup")]
+            Self::Restore(_) => Err(Error::BadRequest(
+                "CacheRequest::Restore cannot be sent by a client".into(),
+            )),
+            Self::Get { .. } => Err(Error::BadRequest(
+                "CacheRequest::Get cannot be written through the Raft".into(),
+            )),
+            #[cfg(feature = "dlock")]
+            Self::LockAwait(_) => Err(Error::BadRequest(
+                "CacheRequest::LockAwait cannot be written through the Raft".into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CacheResponse {
     Empty,
     Ok,
@@ -81,6 +144,7 @@ pub enum CacheResponse {
 pub struct StateMachineData {
     last_applied_log_id: Option<LogId<NodeId>>,
     last_membership: StoredMembership<NodeId, Node>,
+    idempotency: IdempotencyStore<CacheResponse>,
 }
 
 /// This is a full in-memory state machine acting as a cache.
@@ -109,45 +173,7 @@ impl RaftSnapshotBuilder<TypeConfigKV> for Arc<StateMachineMemory> {
         let (last_log_id, last_membership, snapshot_bytes) = {
             let data = self.data.read().await;
 
-            // TODO should we include notifications in snapshots as well? -> unsure if it makes sense or not
-
-            let mut ttls = Vec::with_capacity(self.tx_ttls.len());
-            for tx in &self.tx_ttls {
-                let (ack, rx) = oneshot::channel();
-                tx.send(TtlRequest::SnapshotBuild(ack))
-                    .expect("ttl handler to always be running");
-                let snap = rx
-                    .await
-                    .expect("to always receive an answer from ttl handler");
-                ttls.push(snap);
-            }
-
-            let mut caches = Vec::with_capacity(self.tx_caches.len());
-            for tx in &self.tx_caches {
-                let (ack, rx) = oneshot::channel();
-                tx.send(CacheRequestHandler::SnapshotBuild(ack))
-                    .expect("kv handler to always be running");
-                let snap = rx
-                    .await
-                    .expect("to always receive an answer from kv handler");
-                caches.push(snap);
-            }
-
-            #[cfg(feature = "dlock")]
-            let locks_bytes = {
-                let (ack, rx) = oneshot::channel();
-                self.tx_dlock
-                    .send(LockRequest::SnapshotBuild(ack))
-                    .expect("locks handler to always be running");
-                let locks = rx
-                    .await
-                    .expect("to always receive an answer from locks handler");
-                bincode::serialize(&locks).unwrap()
-            };
-            #[cfg(not(feature = "dlock"))]
-            let locks_bytes: Vec<u8> = Vec::default();
-
-            let snap: SnapshotDataInner = (caches, ttls, locks_bytes);
+            let snap = self.snapshot_data(data.idempotency.snapshot()).await;
             let snapshot_bytes = bincode::serialize(&snap)
                 .map_err(|err| StorageIOError::read_state_machine(&err))?;
 
@@ -230,6 +256,242 @@ impl StateMachineMemory {
             tx_dlock,
         })
     }
+
+    async fn snapshot_data(&self, idempotency: SnapshotIdempotency) -> SnapshotDataInner {
+        // TODO should we include notifications in snapshots as well? -> unsure if it makes sense or not
+
+        let mut ttls = Vec::with_capacity(self.tx_ttls.len());
+        for tx in &self.tx_ttls {
+            let (ack, rx) = oneshot::channel();
+            tx.send(TtlRequest::SnapshotBuild(ack))
+                .expect("ttl handler to always be running");
+            let snap = rx
+                .await
+                .expect("to always receive an answer from ttl handler");
+            ttls.push(snap);
+        }
+
+        let mut caches = Vec::with_capacity(self.tx_caches.len());
+        for tx in &self.tx_caches {
+            let (ack, rx) = oneshot::channel();
+            tx.send(CacheRequestHandler::SnapshotBuild(ack))
+                .expect("kv handler to always be running");
+            let snap = rx
+                .await
+                .expect("to always receive an answer from kv handler");
+            caches.push(snap);
+        }
+
+        #[cfg(feature = "dlock")]
+        let locks_bytes = {
+            let (ack, rx) = oneshot::channel();
+            self.tx_dlock
+                .send(LockRequest::SnapshotBuild(ack))
+                .expect("locks handler to always be running");
+            let locks = rx
+                .await
+                .expect("to always receive an answer from locks handler");
+            bincode::serialize(&locks).unwrap()
+        };
+        #[cfg(not(feature = "dlock"))]
+        let locks_bytes: Vec<u8> = Vec::default();
+
+        (caches, ttls, locks_bytes, idempotency)
+    }
+
+    /// Replaces the content of all caches and their TTLs.
+    async fn install_caches(&self, kvs: SnapshotKVs, ttls: SnapshotTTLs) {
+        for (idx, kv_data) in kvs.into_iter().enumerate() {
+            let (ack, rx) = oneshot::channel();
+            self.tx_caches
+                .get(idx)
+                .unwrap()
+                .send(CacheRequestHandler::SnapshotInstall((kv_data, ack)))
+                .expect("kv handler to always be running");
+            rx.await
+                .expect("to always receive an answer from the kv handler");
+        }
+
+        for (idx, kv_data) in ttls.into_iter().enumerate() {
+            let (ack, rx) = oneshot::channel();
+            self.tx_ttls
+                .get(idx)
+                .unwrap()
+                .send(TtlRequest::SnapshotInstall((kv_data, ack)))
+                .expect("ttl handler to always be running");
+            rx.await
+                .expect("to always receive an answer from the ttl handler");
+        }
+    }
+
+    /// Serializes the current content of all caches for a backup. It has the same format as a
+    /// Raft snapshot, without idempotency keys, which would be expired after a restore anyway.
+    #[cfg(feature = "backup")]
+    pub(crate) async fn backup_snapshot(&self) -> Vec<u8> {
+        let snap = self.snapshot_data(Vec::default()).await;
+        bincode::serialize(&snap).unwrap()
+    }
+
+    /// Makes sure a cache snapshot from a backup can be restored with the current cache index.
+    #[cfg(feature = "backup")]
+    pub(crate) fn check_backup_snapshot(bytes: &[u8], caches: usize) -> Result<(), Error> {
+        let (kvs, ttls, _, _) = deserialize_snapshot(bytes)?;
+        if kvs.len() != caches || ttls.len() != caches {
+            return Err(Error::Config(
+                format!(
+                    "The cache snapshot contains {} caches, while the Cache Index enum has {}",
+                    kvs.len(),
+                    caches
+                )
+                .into(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn apply_request(
+        &self,
+        req: CacheRequest,
+        last_applied_log_id: Option<LogId<NodeId>>,
+    ) -> CacheResponse {
+        match req {
+            CacheRequest::Get { .. } => {
+                unreachable!("a CacheRequest::Get should never come thorugh the Raft")
+            }
+
+            CacheRequest::Put {
+                cache_idx,
+                key,
+                value,
+                expires,
+            } => {
+                if let Some(exp) = expires {
+                    self.tx_ttls
+                        .get(cache_idx)
+                        .unwrap()
+                        .send(TtlRequest::Ttl((exp, key.to_string())))
+                        .expect("cache ttl handler to always be running");
+                }
+
+                self.tx_caches
+                    .get(cache_idx)
+                    .unwrap()
+                    .send(CacheRequestHandler::Put((key.to_string(), value)))
+                    .expect("cache ttl handler to always be running");
+
+                CacheResponse::Ok
+            }
+
+            CacheRequest::Delete { cache_idx, key } => {
+                self.tx_caches
+                    .get(cache_idx)
+                    .unwrap()
+                    .send(CacheRequestHandler::Delete(key.to_string()))
+                    .expect("cache ttl handler to always be running");
+
+                CacheResponse::Ok
+            }
+
+            CacheRequest::Clear { cache_idx } => {
+                self.tx_caches
+                    .get(cache_idx)
+                    .unwrap()
+                    .send(CacheRequestHandler::Clear)
+                    .expect("cache ttl handler to always be running");
+
+                CacheResponse::Ok
+            }
+
+            CacheRequest::ClearAll => {
+                for tx in &self.tx_caches {
+                    tx.send(CacheRequestHandler::Clear)
+                        .expect("cache ttl handler to always be running");
+                }
+
+                CacheResponse::Ok
+            }
+
+            #[cfg(feature = "listen_notify")]
+            CacheRequest::Notify(payload) => {
+                self.tx_notify
+                    .send(NotifyRequest::Notify(payload))
+                    // this channel can never be closed - we have both sides
+                    .unwrap();
+                CacheResponse::Ok
+            }
+
+            #[cfg(feature = "dlock")]
+            CacheRequest::Lock((key, id)) => {
+                let (ack, rx) = oneshot::channel();
+
+                // the id will be Some(_) in case this request is coming in after awaiting a queue
+                if let Some(log_id) = id {
+                    self.tx_dlock
+                        .send(LockRequest::Acquire(LockRequestPayload {
+                            key,
+                            log_id,
+                            ack,
+                        }))
+                        // this channel can never be closed - we have both sides
+                        .unwrap();
+                } else {
+                    let log_id = id.unwrap_or(last_applied_log_id.unwrap().index);
+                    self.tx_dlock
+                        .send(LockRequest::Lock(LockRequestPayload { key, log_id, ack }))
+                        // this channel can never be closed - we have both sides
+                        .unwrap();
+                }
+
+                let state = rx
+                    .await
+                    .expect("To always get a response from dlock handler");
+
+                CacheResponse::Lock(state)
+            }
+
+            #[cfg(feature = "dlock")]
+            CacheRequest::LockAwait(..) => {
+                unreachable!("Lock Awaits should never come through the Raft")
+            }
+
+            #[cfg(feature = "dlock")]
+            CacheRequest::LockRelease((key, id)) => {
+                self.tx_dlock
+                    .send(LockRequest::Release(LockReleasePayload { key, id }))
+                    // this channel can never be closed - we have both sides
+                    .unwrap();
+
+                // we can return early without waiting for answer, release should never fail anyway
+                CacheResponse::Lock(LockState::Released)
+            }
+
+            // The API rejects nested requests, but a log entry must never crash the apply.
+            CacheRequest::Idempotent((_, req)) => {
+                warn!("Applying a nested CacheRequest::Idempotent without deduplication");
+                Box::pin(self.apply_request(*req, last_applied_log_id)).await
+            }
+
+            #[cfg(feature = "backup")]
+            CacheRequest::Restore(bytes) => {
+                // The leader has checked the snapshot before, so this can only fail with a
+                // different Cache Index enum on this node.
+                // Locks are skipped on purpose: their owners do not exist anymore after an outage.
+                match deserialize_snapshot(&bytes) {
+                    Ok((kvs, ttls, _, _)) if kvs.len() == self.tx_caches.len() => {
+                        info!("Restoring {} caches from a backup", kvs.len());
+                        self.install_caches(kvs, ttls).await;
+                    }
+                    Ok((kvs, _, _, _)) => error!(
+                        "Cannot restore {} caches from a backup with {} caches configured",
+                        kvs.len(),
+                        self.tx_caches.len()
+                    ),
+                    Err(err) => error!("Cannot deserialize cache snapshot: {}", err),
+                }
+                CacheResponse::Ok
+            }
+        }
+    }
 }
 
 impl RaftStateMachine<TypeConfigKV> for Arc<StateMachineMemory> {
@@ -262,117 +524,19 @@ impl RaftStateMachine<TypeConfigKV> for Arc<StateMachineMemory> {
             let resp_value = match entry.payload {
                 EntryPayload::Blank => CacheResponse::Empty,
 
-                EntryPayload::Normal(req) => match req {
-                    CacheRequest::Get { .. } => {
-                        unreachable!("a CacheRequest::Get should never come thorugh the Raft")
-                    }
-
-                    CacheRequest::Put {
-                        cache_idx,
-                        key,
-                        value,
-                        expires,
-                    } => {
-                        if let Some(exp) = expires {
-                            self.tx_ttls
-                                .get(cache_idx)
-                                .unwrap()
-                                .send(TtlRequest::Ttl((exp, key.to_string())))
-                                .expect("cache ttl handler to always be running");
+                EntryPayload::Normal(CacheRequest::Idempotent((idempotency, req))) => {
+                    data.idempotency.purge(idempotency.ts);
+                    match data.idempotency.get(&idempotency) {
+                        Some(resp) => resp,
+                        None => {
+                            let resp = self.apply_request(*req, last_applied_log_id).await;
+                            data.idempotency.insert(idempotency, resp.clone());
+                            resp
                         }
-
-                        self.tx_caches
-                            .get(cache_idx)
-                            .unwrap()
-                            .send(CacheRequestHandler::Put((key.to_string(), value)))
-                            .expect("cache ttl handler to always be running");
-
-                        CacheResponse::Ok
                     }
+                }
 
-                    CacheRequest::Delete { cache_idx, key } => {
-                        self.tx_caches
-                            .get(cache_idx)
-                            .unwrap()
-                            .send(CacheRequestHandler::Delete(key.to_string()))
-                            .expect("cache ttl handler to always be running");
-
-                        CacheResponse::Ok
-                    }
-
-                    CacheRequest::Clear { cache_idx } => {
-                        self.tx_caches
-                            .get(cache_idx)
-                            .unwrap()
-                            .send(CacheRequestHandler::Clear)
-                            .expect("cache ttl handler to always be running");
-
-                        CacheResponse::Ok
-                    }
-
-                    CacheRequest::ClearAll => {
-                        for tx in &self.tx_caches {
-                            tx.send(CacheRequestHandler::Clear)
-                                .expect("cache ttl handler to always be running");
-                        }
-
-                        CacheResponse::Ok
-                    }
-
-                    #[cfg(feature = "listen_notify")]
-                    CacheRequest::Notify(payload) => {
-                        self.tx_notify
-                            .send(NotifyRequest::Notify(payload))
-                            // this channel can never be closed - we have both sides
-                            .unwrap();
-                        CacheResponse::Ok
-                    }
-
-                    #[cfg(feature = "dlock")]
-                    CacheRequest::Lock((key, id)) => {
-                        let (ack, rx) = oneshot::channel();
-
-                        // the id will be Some(_) in case this request is coming in after awaiting a queue
-                        if let Some(log_id) = id {
-                            self.tx_dlock
-                                .send(LockRequest::Acquire(LockRequestPayload {
-                                    key,
-                                    log_id,
-                                    ack,
-                                }))
-                                // this channel can never be closed - we have both sides
-                                .unwrap();
-                        } else {
-                            let log_id = id.unwrap_or(last_applied_log_id.unwrap().index);
-                            self.tx_dlock
-                                .send(LockRequest::Lock(LockRequestPayload { key, log_id, ack }))
-                                // this channel can never be closed - we have both sides
-                                .unwrap();
-                        }
-
-                        let state = rx
-                            .await
-                            .expect("To always get a response from dlock handler");
-
-                        CacheResponse::Lock(state)
-                    }
-
-                    #[cfg(feature = "dlock")]
-                    CacheRequest::LockAwait(..) => {
-                        unreachable!("Lock Awaits should never come through the Raft")
-                    }
-
-                    #[cfg(feature = "dlock")]
-                    CacheRequest::LockRelease((key, id)) => {
-                        self.tx_dlock
-                            .send(LockRequest::Release(LockReleasePayload { key, id }))
-                            // this channel can never be closed - we have both sides
-                            .unwrap();
-
-                        // we can return early without waiting for answer, release should never fail anyway
-                        CacheResponse::Lock(LockState::Released)
-                    }
-                },
+                EntryPayload::Normal(req) => self.apply_request(req, last_applied_log_id).await,
 
                 EntryPayload::Membership(mem) => {
                     data.last_membership = StoredMembership::new(Some(entry.log_id), mem);
@@ -405,33 +569,13 @@ impl RaftStateMachine<TypeConfigKV> for Arc<StateMachineMemory> {
     ) -> Result<(), StorageError<NodeId>> {
         let mut current_snapshot = self.snapshot.lock().await;
 
-        let (kvs, ttls, locks) = bincode::deserialize::<SnapshotDataInner>(snapshot.get_ref())
+        let (kvs, ttls, locks, idempotency) = deserialize_snapshot(snapshot.get_ref())
             .map_err(|e| StorageIOError::read_snapshot(Some(meta.signature()), &e))?;
 
         // make sure to hold the metadata lock the whole time
         let mut data = self.data.write().await;
 
-        for (idx, kv_data) in kvs.into_iter().enumerate() {
-            let (ack, rx) = oneshot::channel();
-            self.tx_caches
-                .get(idx)
-                .unwrap()
-                .send(CacheRequestHandler::SnapshotInstall((kv_data, ack)))
-                .expect("kv handler to always be running");
-            rx.await
-                .expect("to always receive an answer from the kv handler");
-        }
-
-        for (idx, kv_data) in ttls.into_iter().enumerate() {
-            let (ack, rx) = oneshot::channel();
-            self.tx_ttls
-                .get(idx)
-                .unwrap()
-                .send(TtlRequest::SnapshotInstall((kv_data, ack)))
-                .expect("ttl handler to always be running");
-            rx.await
-                .expect("to always receive an answer from the ttl handler");
-        }
+        self.install_caches(kvs, ttls).await;
 
         #[cfg(feature = "dlock")]
         {
@@ -452,6 +596,7 @@ impl RaftStateMachine<TypeConfigKV> for Arc<StateMachineMemory> {
 
         data.last_applied_log_id = meta.last_log_id;
         data.last_membership = meta.last_membership.clone();
+        data.idempotency.install_snapshot(idempotency);
 
         *current_snapshot = Some(snapshot_new);
 
@@ -464,3 +609,42 @@ impl RaftStateMachine<TypeConfigKV> for Arc<StateMachineMemory> {
         Ok(self.snapshot.lock().await.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_snapshot_compat() {
+        let kvs: SnapshotKVs = vec![BTreeMap::from([("key".to_string(), b"value".to_vec())])];
+        let ttls: SnapshotTTLs = vec![BTreeMap::new()];
+        let locks: SnapshotLocks = vec![1, 2, 3];
+
+        // snapshots from older versions without idempotency keys
+        let bytes = bincode::serialize(&(kvs.clone(), ttls.clone(), locks.clone())).unwrap();
+        let (kvs_de, ttls_de, locks_de, idempotency) = deserialize_snapshot(&bytes).unwrap();
+        assert_eq!(kvs_de, kvs);
+        assert_eq!(ttls_de, ttls);
+        assert_eq!(locks_de, locks);
+        assert!(idempotency.is_empty());
+
+        let snap: SnapshotDataInner = (
+            kvs.clone(),
+            ttls.clone(),
+            locks.clone(),
+            vec![("id".to_string(), 13, CacheResponse::Ok)],
+        );
+        let bytes = bincode::serialize(&snap).unwrap();
+        let (kvs_de, _, _, idempotency) = deserialize_snapshot(&bytes).unwrap();
+        assert_eq!(kvs_de, kvs);
+        assert_eq!(idempotency.len(), 1);
+        assert_eq!(idempotency[0].0, "id");
+
+        // older versions must be able to read the new format as well
+        let (kvs_de, ttls_de, locks_de) =
+            bincode::deserialize::<(SnapshotKVs, SnapshotTTLs, SnapshotLocks)>(&bytes).unwrap();
+        assert_eq!(kvs_de, kvs);
+        assert_eq!(ttls_de, ttls);
+        assert_eq!(locks_de, locks);
+    }
+}
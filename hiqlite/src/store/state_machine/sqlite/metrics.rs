@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Counters for the SQLite state machine, which will be exposed on the `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub(crate) struct SqliteMetrics {
+    pub apply_entries: AtomicU64,
+    pub apply_micros: AtomicU64,
+    pub backups_failed: AtomicU64,
+    pub backup_verifications_failed: AtomicU64,
+    /// Unix timestamp of the last successful local backup
+    pub backup_last_success: AtomicI64,
+    /// Unix timestamp of the last successful backup push for each `BackupTarget::name()`
+    pub backup_last_success_targets: Mutex<BTreeMap<String, i64>>,
+    /// Set while this node replaces its database during an online restore
+    pub maintenance: AtomicBool,
+}
+
+impl SqliteMetrics {
+    #[inline]
+    pub fn record_apply(&self, entries: u64, elapsed: Duration) {
+        self.apply_entries.fetch_add(entries, Ordering::Relaxed);
+        self.apply_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "backup")]
+    pub fn record_backup_target_success(&self, target: &str) {
+        self.backup_last_success_targets
+            .lock()
+            .unwrap()
+            .insert(target.to_string(), chrono::Utc::now().timestamp());
+    }
+}
@@ -2,10 +2,13 @@ use crate::store::state_machine::sqlite::state_machine::QueryWrite;
 use crate::Node;
 use crate::Response;
 
+pub mod metrics;
 pub mod param;
 pub mod reader;
+pub(crate) mod seed;
 pub mod snapshot_builder;
 pub mod state_machine;
+pub mod verify;
 pub mod writer;
 
 openraft::declare_raft_types!(
@@ -1,6 +1,8 @@
 use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use rusqlite::types::{ToSqlOutput, Value};
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::ToSql;
 use serde::{Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Param {
@@ -16,22 +18,49 @@ pub enum Param {
     Blob(Vec<u8>),
 }
 
-// impl ToSql for Param {
-//     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-//         todo!()
-//     }
-// }
+/// A typed JSON column.
+///
+/// Any `T: Serialize` can be used as a `Param` wrapped inside `Json`, and any
+/// `T: DeserializeOwned` can be read back with `row.get::<Json<T>>()`.
+///
+/// ```rust, notest
+/// client
+///     .execute(
+///         "INSERT INTO config (id, data) VALUES ($1, $2)",
+///         params!(1, Json(MyConfig { retries: 3 })),
+///     )
+///     .await?;
+///
+/// let config: Json<MyConfig> = row.get("data");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Json<T>(pub T);
 
-impl Param {
-    pub(crate) fn into_sql<'a>(self) -> ToSqlOutput<'a> {
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl ToSql for Param {
+    #[inline]
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
         let value = match self {
-            Param::Null => Value::Null,
-            Param::Integer(i) => Value::Integer(i),
-            Param::Real(r) => Value::Real(r),
-            Param::Text(t) => Value::Text(t),
-            Param::Blob(b) => Value::Blob(b),
+            Param::Null => ValueRef::Null,
+            Param::Integer(i) => ValueRef::Integer(*i),
+            Param::Real(r) => ValueRef::Real(*r),
+            Param::Text(t) => ValueRef::Text(t.as_bytes()),
+            Param::Blob(b) => ValueRef::Blob(b),
         };
-        ToSqlOutput::Owned(value)
+        Ok(ToSqlOutput::Borrowed(value))
     }
 }
 
@@ -56,25 +85,63 @@ impl From<isize> for Param {
     }
 }
 
-// #[cfg(feature = "i128_blob")]
-// #[cfg_attr(docsrs, doc(cfg(feature = "i128_blob")))]
-// impl From<i128> for Param {
-//     #[inline]
-//     fn from(i: i128) -> Param {
-//         // We store these biased (e.g. with the most significant bit flipped)
-//         // so that comparisons with negative numbers work properly.
-//         Param::Blob(i128::to_be_bytes(i ^ (1_i128 << 127)).to_vec())
-//     }
-// }
+impl From<u64> for Param {
+    /// Values up to `i64::MAX` are stored as `INTEGER`. Bigger ones do not fit into an SQLite
+    /// integer and are stored as an 8 byte big endian `BLOB`. A `BLOB` is never converted by
+    /// the column affinity, so the value can be read back without any loss from any column.
+    /// SQLite sorts all `INTEGER`s before `BLOB`s, which means the numeric ordering is kept.
+    #[inline]
+    fn from(i: u64) -> Param {
+        match i64::try_from(i) {
+            Ok(i) => Param::Integer(i),
+            Err(_) => Param::Blob(i.to_be_bytes().to_vec()),
+        }
+    }
+}
+
+impl From<i128> for Param {
+    /// `i128` is stored as a 16 byte big endian `BLOB`. It is biased (the most significant bit is
+    /// flipped), so that comparisons and ordering work properly, even with negative numbers.
+    ///
+    /// This is the same format `rusqlite` uses with its `i128_blob` feature.
+    #[inline]
+    fn from(i: i128) -> Param {
+        Param::Blob(i128::to_be_bytes(i ^ (1_i128 << 127)).to_vec())
+    }
+}
+
+impl From<rust_decimal::Decimal> for Param {
+    /// `Decimal` is stored as `TEXT` to not lose any precision.
+    ///
+    /// Use a `TEXT` column for it. A column with `NUMERIC` affinity, like `DECIMAL`, converts the
+    /// value into a `REAL`, which can only be read back, as long as it has at most 15 significant
+    /// digits.
+    #[inline]
+    fn from(d: rust_decimal::Decimal) -> Param {
+        Param::Text(d.to_string())
+    }
+}
 
 impl From<uuid::Uuid> for Param {
+    /// A `Uuid` is stored as its 16 raw bytes in a `BLOB`. The bytes are big endian, which means
+    /// the ordering in the database matches the ordering of the `Uuid` itself, and time-based
+    /// UUIDs like v7 stay sortable.
+    ///
+    /// If you prefer a readable `TEXT` column, use `Uuid::hyphenated()` instead.
     #[inline]
     fn from(id: uuid::Uuid) -> Param {
-        // TODO need to be converted to BE bytes for correct ordering -> feature flag
         Param::Blob(id.as_bytes().to_vec())
     }
 }
 
+impl From<uuid::fmt::Hyphenated> for Param {
+    /// Stores the `Uuid` as `TEXT` in the form of `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    #[inline]
+    fn from(id: uuid::fmt::Hyphenated) -> Param {
+        Param::Text(id.to_string())
+    }
+}
+
 macro_rules! from_i64(
     ($t:ty) => (
         impl From<$t> for Param {
@@ -208,6 +275,18 @@ impl From<serde_json::Value> for Param {
     }
 }
 
+impl<T: Serialize> From<Json<T>> for Param {
+    /// Serializes the inner value into a JSON `TEXT`.
+    ///
+    /// # Panics
+    /// If the `Serialize` impl for `T` fails, which is only possible for custom impls or maps
+    /// with non-string keys.
+    #[inline]
+    fn from(value: Json<T>) -> Self {
+        Param::Text(serde_json::to_string(&value.0).expect("Json<T> to always serialize"))
+    }
+}
+
 impl<T> From<Option<T>> for Param
 where
     T: Into<Param>,
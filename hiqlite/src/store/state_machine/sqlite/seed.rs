@@ -0,0 +1,197 @@
+use crate::store::state_machine::sqlite::state_machine::{
+    PathSnapshots, StateMachineData, StateMachineSqlite,
+};
+use crate::store::state_machine::sqlite::writer::WriterRequest;
+use crate::store::state_machine::sqlite::TypeConfigSqlite;
+use crate::Error;
+use rusqlite::OpenFlags;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::{fs, task, time};
+use tracing::info;
+
+/// Replaces the empty database of a pristine node 1 with a copy of the existing SQLite file at
+/// `path_seed`. The original file will not be modified.
+pub(crate) async fn apply_seed(
+    sql_writer: &flume::Sender<WriterRequest>,
+    data_dir: &str,
+    path_seed: &str,
+) -> Result<(), Error> {
+    let (_, _, PathSnapshots(path_snapshots), _) =
+        StateMachineSqlite::build_folders(data_dir, false).await;
+    let path_tmp = format!("{}/seed.sqlite", path_snapshots);
+    let _ = fs::remove_file(&path_tmp).await;
+
+    let src = path_seed.to_string();
+    let dst = path_tmp.clone();
+    task::spawn_blocking(move || prepare_seed(&src, &dst)).await??;
+
+    // the seed is applied like a snapshot, which keeps all open connections valid
+    let (ack, rx) = oneshot::channel();
+    sql_writer
+        .send_async(WriterRequest::SnapshotApply((path_tmp.clone(), ack)))
+        .await
+        .map_err(|err| Error::Error(err.to_string().into()))?;
+    rx.await
+        .map_err(|err| Error::Error(err.to_string().into()))?;
+
+    fs::remove_file(&path_tmp).await?;
+    info!("Database has been seeded from {}", path_seed);
+    Ok(())
+}
+
+/// Creates a compact copy of `path_seed` at `path_out` and adds the Hiqlite metadata to it.
+fn prepare_seed(path_seed: &str, path_out: &str) -> Result<(), Error> {
+    let conn = rusqlite::Connection::open_with_flags(
+        path_seed,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+
+    let check: String = conn.query_row("PRAGMA quick_check", (), |row| row.get(0))?;
+    if check != "ok" {
+        return Err(Error::Config(
+            format!("The seed database {} is corrupted: {}", path_seed, check).into(),
+        ));
+    }
+
+    let is_hiqlite: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_metadata')",
+        (),
+        |row| row.get(0),
+    )?;
+    if is_hiqlite {
+        return Err(Error::Config(
+            format!(
+                "The seed database {} is a Hiqlite database already - use HQL_BACKUP_RESTORE \
+                to restore a backup",
+                path_seed
+            )
+            .into(),
+        ));
+    }
+
+    // `VACUUM INTO` creates a consistent copy, even if the source is in WAL mode
+    conn.execute("VACUUM INTO $1", [path_out])?;
+    drop(conn);
+
+    let conn = rusqlite::Connection::open(path_out)?;
+    conn.execute(
+        r#"
+CREATE TABLE _metadata
+(
+    key  TEXT    NOT NULL
+        CONSTRAINT _metadata_pk
+            PRIMARY KEY,
+    data BLOB    NOT NULL
+)"#,
+        (),
+    )?;
+    let meta = bincode::serialize(&StateMachineData::default())?;
+    conn.execute(
+        "INSERT INTO _metadata (key, data) VALUES ('meta', $1)",
+        [meta],
+    )?;
+
+    Ok(())
+}
+
+/// Builds a snapshot right after the init of a seeded node 1 and purges the logs up to it.
+/// This makes sure that all other nodes will receive the seeded database via snapshot install
+/// instead of only replicating the empty logs.
+///
+/// Finishes an initial snapshot, which has been interrupted by a crash, as well. Only returns
+/// after the logs have been purged.
+pub(crate) async fn build_initial_snapshot(
+    raft: &openraft::Raft<TypeConfigSqlite>,
+) -> Result<(), Error> {
+    while raft.metrics().borrow().last_applied.is_none() {
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    if raft.metrics().borrow().snapshot.is_none() {
+        info!("Building the initial snapshot for the seeded database");
+        raft.trigger().snapshot().await?;
+    }
+
+    let index = loop {
+        let snapshot = raft.metrics().borrow().snapshot;
+        if let Some(snapshot) = snapshot {
+            break snapshot.index;
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    };
+
+    info!(
+        "Purging the logs up to the initial snapshot at index {}",
+        index
+    );
+    raft.trigger().purge_log(index).await?;
+    let purged = async {
+        while !raft
+            .metrics()
+            .borrow()
+            .purged
+            .is_some_and(|log_id| log_id.index >= index)
+        {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    time::timeout(Duration::from_secs(10), purged)
+        .await
+        .map_err(|_| {
+            Error::Timeout("Timeout waiting for the logs to be purged after seeding".into())
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_seed() {
+        let dir = format!(
+            "{}/hiqlite_test_seed_{}",
+            std::env::temp_dir().display(),
+            std::process::id()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path_seed = format!("{}/app.sqlite", dir);
+        let path_out = format!("{}/seed.sqlite", dir);
+
+        let conn = rusqlite::Connection::open(&path_seed).unwrap();
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            INSERT INTO users (name) VALUES ('alice'), ('bob');
+            "#,
+        )
+        .unwrap();
+
+        prepare_seed(&path_seed, &path_out).unwrap();
+
+        let out = rusqlite::Connection::open(&path_out).unwrap();
+        let count: i64 = out
+            .query_row("SELECT count(*) FROM users", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+        let meta: Vec<u8> = out
+            .query_row("SELECT data FROM _metadata WHERE key = 'meta'", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+        let meta: StateMachineData = bincode::deserialize(&meta).unwrap();
+        assert!(meta.last_applied_log_id.is_none());
+
+        // a Hiqlite database must be restored as a backup instead
+        let path_out_2 = format!("{}/seed_2.sqlite", dir);
+        assert!(prepare_seed(&path_out, &path_out_2).is_err());
+
+        drop(conn);
+        drop(out);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,21 @@
 #![allow(clippy::upper_case_acronyms)]
 
 use crate::helpers::set_path_access;
+use crate::idempotency::Idempotency;
 use crate::migration::Migration;
-use crate::query::rows::RowOwned;
+use crate::query::rows::{Row, RowOwned};
+use crate::query::stats::QueryStats;
+use crate::store::state_machine::sqlite::metrics::SqliteMetrics;
 use crate::store::state_machine::sqlite::param::Param;
 use crate::store::state_machine::sqlite::snapshot_builder::SQLiteSnapshotBuilder;
+use crate::store::state_machine::sqlite::verify::ContentChecksum;
 use crate::store::state_machine::sqlite::writer::WriterRequest::MetadataRead;
 use crate::store::state_machine::sqlite::writer::{
     self, MetaPersistRequest, SqlBatch, SqlTransaction, WriterRequest,
 };
 use crate::store::state_machine::sqlite::{reader, TypeConfigSqlite};
 use crate::store::{logs, StorageResult};
-use crate::{Error, Node, NodeId};
+use crate::{Error, Node, NodeId, TraceContext};
 use openraft::storage::RaftStateMachine;
 use openraft::{
     EntryPayload, LogId, OptionalSend, Snapshot, SnapshotId, SnapshotMeta, StorageError,
@@ -21,12 +25,13 @@ use rusqlite::{OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::clone::Clone;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 use tokio::{fs, task, time};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, debug_span, error, field, info, warn, Instrument};
 use uuid::Uuid;
 
 type Entry = openraft::Entry<TypeConfigSqlite>;
@@ -41,6 +46,8 @@ pub struct PathBackups(pub String);
 pub struct PathSnapshots(pub String);
 pub struct PathLockFile(pub String);
 
+/// The payload for the SQLite Raft log. New variants must always be appended at the end to keep
+/// existing logs compatible.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum QueryWrite {
     Execute(Query),
@@ -51,6 +58,34 @@ pub enum QueryWrite {
     #[cfg(feature = "backup")]
     Backup(NodeId),
     RTT,
+    /// Wraps any of the other variants with the trace context of the client request.
+    Traced((TraceContext, Box<QueryWrite>)),
+    TransactionGuarded(Vec<TxnStep>),
+    /// Wraps any of the other variants with an idempotency key to deduplicate retries.
+    Idempotent((Idempotency, Box<QueryWrite>)),
+    /// Replaces the database on each node with the given backup, which must match the checksum.
+    #[cfg(feature = "backup")]
+    Restore((crate::backup::BackupSource, ContentChecksum, Node)),
+}
+
+impl QueryWrite {
+    /// Attaches the given `Idempotency`, if there is any. Must be called before `traced()`.
+    #[inline]
+    pub(crate) fn idempotent(self, idempotency: Option<Idempotency>) -> Self {
+        match idempotency {
+            Some(idempotency) => Self::Idempotent((idempotency, Box::new(self))),
+            None => self,
+        }
+    }
+
+    /// Attaches the `TraceContext` of the current task, if there is any.
+    #[inline]
+    pub(crate) fn traced(self) -> Self {
+        match TraceContext::current() {
+            Some(ctx) => Self::Traced((ctx, Box::new(self))),
+            None => self,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,12 +94,110 @@ pub struct Query {
     pub params: Params,
 }
 
+/// A single step of a guarded transaction, see `Client::txn_guarded()` and
+/// `Client::txn_returning()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TxnStep {
+    /// Executes the query.
+    Execute(Query),
+    /// Executes the query and aborts the transaction if the affected rows do not match.
+    ExecuteExpect((Query, usize)),
+    /// Aborts the transaction if the query does not return at least one row.
+    AssertExists(Query),
+    /// Aborts the transaction if the query returns any row.
+    AssertNotExists(Query),
+    /// Executes a query with a `RETURNING` clause and returns the rows.
+    ExecuteReturning(Query),
+}
+
+impl TxnStep {
+    pub fn execute<S: Into<Cow<'static, str>>>(sql: S, params: Params) -> Self {
+        Self::Execute(Query {
+            sql: sql.into(),
+            params,
+        })
+    }
+
+    pub fn execute_expect<S: Into<Cow<'static, str>>>(
+        sql: S,
+        params: Params,
+        rows_affected: usize,
+    ) -> Self {
+        Self::ExecuteExpect((
+            Query {
+                sql: sql.into(),
+                params,
+            },
+            rows_affected,
+        ))
+    }
+
+    pub fn assert_exists<S: Into<Cow<'static, str>>>(sql: S, params: Params) -> Self {
+        Self::AssertExists(Query {
+            sql: sql.into(),
+            params,
+        })
+    }
+
+    pub fn assert_not_exists<S: Into<Cow<'static, str>>>(sql: S, params: Params) -> Self {
+        Self::AssertNotExists(Query {
+            sql: sql.into(),
+            params,
+        })
+    }
+
+    pub fn execute_returning<S: Into<Cow<'static, str>>>(sql: S, params: Params) -> Self {
+        Self::ExecuteReturning(Query {
+            sql: sql.into(),
+            params,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn query(&self) -> &Query {
+        match self {
+            Self::Execute(q)
+            | Self::AssertExists(q)
+            | Self::AssertNotExists(q)
+            | Self::ExecuteReturning(q) => q,
+            Self::ExecuteExpect((q, _)) => q,
+        }
+    }
+}
+
+/// The result of a single `TxnStep` as it is sent back to the client.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TxnStepOutput {
+    pub rows_affected: usize,
+    pub rows: Vec<RowOwned>,
+}
+
+/// The result of a single `TxnStep` from `Client::txn_returning()`.
+#[derive(Debug)]
+pub struct TxnStepResult {
+    /// The affected rows for an `Execute` or `ExecuteExpect`, the amount of returned rows for
+    /// an `ExecuteReturning` and always `0` for assertions.
+    pub rows_affected: usize,
+    /// The rows from a `RETURNING` clause. Always empty for all other steps.
+    pub rows: Vec<Row<'static>>,
+}
+
+impl From<TxnStepOutput> for TxnStepResult {
+    fn from(value: TxnStepOutput) -> Self {
+        Self {
+            rows_affected: value.rows_affected,
+            rows: value.rows.into_iter().map(Row::Owned).collect(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Empty,
     Execute(ResponseExecute),
     ExecuteReturning(ResponseExecuteReturning),
     Transaction(Result<Vec<Result<usize, Error>>, Error>),
+    TransactionGuarded(Result<Vec<TxnStepOutput>, Error>),
     Batch(ResponseBatch),
     Migrate(Result<(), Error>),
     Backup(Result<(), Error>),
@@ -86,6 +219,65 @@ pub struct ResponseBatch {
     pub result: Result<Vec<Result<usize, Error>>, Error>,
 }
 
+/// A response from the SQL writer which has not been received yet.
+#[derive(Debug)]
+enum PendingResponse {
+    Blank,
+    Execute(oneshot::Receiver<Result<usize, Error>>),
+    ExecuteReturning(oneshot::Receiver<Result<Vec<Result<RowOwned, Error>>, Error>>),
+    Transaction(oneshot::Receiver<Result<Vec<Result<usize, Error>>, Error>>),
+    TransactionGuarded(oneshot::Receiver<Result<Vec<TxnStepOutput>, Error>>),
+    Batch(oneshot::Receiver<Result<Vec<Result<usize, Error>>, Error>>),
+    #[cfg(feature = "backup")]
+    Backup(oneshot::Receiver<Result<(), Error>>),
+    #[cfg(feature = "backup")]
+    Restore(oneshot::Receiver<Result<(), Error>>),
+    Migrate(oneshot::Receiver<Result<(), Error>>),
+    RTT(oneshot::Receiver<()>),
+    Membership(oneshot::Receiver<()>),
+}
+
+impl PendingResponse {
+    async fn recv(self) -> Result<Response, StorageError<NodeId>> {
+        const ERR: &str = "to always get a response from sql writer";
+
+        let resp = match self {
+            Self::Blank => Response::Empty,
+            Self::Execute(rx) => Response::Execute(ResponseExecute {
+                result: rx.await.expect(ERR),
+            }),
+            Self::ExecuteReturning(rx) => Response::ExecuteReturning(ResponseExecuteReturning {
+                result: rx.await.expect(ERR),
+            }),
+            Self::Transaction(rx) => Response::Transaction(rx.await.expect(ERR)),
+            Self::TransactionGuarded(rx) => Response::TransactionGuarded(rx.await.expect(ERR)),
+            Self::Batch(rx) => Response::Batch(ResponseBatch {
+                result: rx.await.expect(ERR),
+            }),
+            #[cfg(feature = "backup")]
+            Self::Backup(rx) => Response::Backup(rx.await.expect(ERR)),
+            #[cfg(feature = "backup")]
+            Self::Restore(rx) => {
+                // this node cannot continue with a different state than the others
+                rx.await
+                    .expect(ERR)
+                    .map_err(|err| StorageIOError::write_state_machine(&err))?;
+                Response::Empty
+            }
+            Self::Migrate(rx) => Response::Migrate(rx.await.expect(ERR)),
+            Self::RTT(rx) => {
+                rx.await.expect(ERR);
+                Response::RTT
+            }
+            Self::Membership(rx) => {
+                rx.await.expect(ERR);
+                Response::Empty
+            }
+        };
+        Ok(resp)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredSnapshot {
     pub meta: SnapshotMeta<NodeId, Node>,
@@ -99,31 +291,60 @@ pub struct StateMachineData {
     pub last_snapshot_id: Option<String>,
 }
 
+/// Stored inside each backup to know where a log replay needs to start.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupMeta {
+    /// The log index of the backup request itself. Everything up to this index is included.
+    pub log_index: u64,
+    /// Unix timestamp in millis
+    pub timestamp: i64,
+    /// The log archive generation at the time of the backup, `0` without a log archive.
+    pub archive_generation: u64,
+    /// The content of the live database at `log_index`, used to verify the backup.
+    pub content: ContentChecksum,
+}
+
 #[derive(Debug, Clone)]
 pub struct StateMachineSqlite {
     // pub data: StateMachineData,
     this_node: NodeId,
     path_snapshots: String,
     #[cfg(feature = "backup")]
-    path_backups: String,
+    pub(crate) path_backups: String,
     path_lock_file: String,
 
-    #[cfg(feature = "s3")]
-    s3_config: Option<Arc<crate::s3::S3Config>>,
+    #[cfg(feature = "backup")]
+    backup_targets: Vec<Arc<dyn crate::backup::BackupTarget>>,
+    #[cfg(feature = "backup")]
+    log_archive: Option<Arc<crate::log_archive::LogArchive>>,
+    #[cfg(feature = "backup")]
+    online_restore: Arc<crate::backup::OnlineRestore>,
+    /// Only set, if caches should be included in backups
+    #[cfg(all(feature = "backup", feature = "cache"))]
+    cache: Option<Arc<crate::store::state_machine::memory::state_machine::StateMachineMemory>>,
 
     pub read_pool: SqlitePool,
     pub(crate) write_tx: flume::Sender<WriterRequest>,
+    metrics: Arc<SqliteMetrics>,
 }
 
 impl StateMachineSqlite {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new(
         data_dir: &str,
         filename_db: &str,
         this_node: NodeId,
         log_statements: bool,
+        query_stats: Arc<QueryStats>,
+        metrics: Arc<SqliteMetrics>,
         prepared_statement_cache_capacity: usize,
         read_pool_size: usize,
-        #[cfg(feature = "s3")] s3_config: Option<Arc<crate::s3::S3Config>>,
+        #[cfg(feature = "backup")] backup_targets: Vec<Arc<dyn crate::backup::BackupTarget>>,
+        #[cfg(feature = "backup")] log_archive: Option<Arc<crate::log_archive::LogArchive>>,
+        #[cfg(feature = "backup")] online_restore: Arc<crate::backup::OnlineRestore>,
+        #[cfg(all(feature = "backup", feature = "cache"))] cache: Option<
+            Arc<crate::store::state_machine::memory::state_machine::StateMachineMemory>,
+        >,
     ) -> Result<StateMachineSqlite, StorageError<NodeId>> {
         // IMPORTANT: Do NOT change the order of the db exists check!
         // DB recovery will fail otherwise!
@@ -150,8 +371,14 @@ impl StateMachineSqlite {
         .map_err(|err| StorageError::IO {
             source: StorageIOError::write(&err),
         })?;
-        let write_tx =
-            writer::spawn_writer(conn, this_node, path_lock_file.clone(), log_statements);
+        let write_tx = writer::spawn_writer(
+            conn,
+            this_node,
+            path_lock_file.clone(),
+            log_statements,
+            query_stats,
+            metrics.clone(),
+        );
 
         let read_pool = Self::connect_read_pool(
             path_db.as_ref(),
@@ -171,10 +398,17 @@ impl StateMachineSqlite {
             #[cfg(feature = "backup")]
             path_backups,
             path_lock_file,
-            #[cfg(feature = "s3")]
-            s3_config,
+            #[cfg(feature = "backup")]
+            backup_targets,
+            #[cfg(feature = "backup")]
+            log_archive,
+            #[cfg(feature = "backup")]
+            online_restore,
+            #[cfg(all(feature = "backup", feature = "cache"))]
+            cache,
             read_pool,
             write_tx,
+            metrics,
         };
 
         if !db_exists {
@@ -577,18 +811,50 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
             .expect("We always expect an upper bound to entries in apply()")
             - bound_lower
             + 1;
-        let mut replies = Vec::with_capacity(entries_len);
+        let mut pending = Vec::with_capacity(entries_len);
+        let start = Instant::now();
 
+        // All entries are sent to the writer first and the responses are awaited afterward.
+        // This makes it possible for the writer to group them into a single transaction.
         for entry in entries {
-            let last_applied_log_id = Some(entry.log_id);
+            let log_id = entry.log_id;
+            let last_applied_log_id = Some(log_id);
+
+            #[cfg(feature = "backup")]
+            if let Some(archive) = &self.log_archive {
+                match &entry.payload {
+                    EntryPayload::Normal(query) => archive.push(log_id.index, Some(query)),
+                    _ => archive.push(log_id.index, None),
+                }
+            }
 
-            // TODO if we always collect 1 in-flight req in a temp var to always have 1 req prepared
-            // before we await the rx before, we could probably improve the throughput here a bit
-            // in exchange for a more complicated logic -> test!
+            let (payload, trace) = match entry.payload {
+                EntryPayload::Normal(QueryWrite::Traced((trace, query))) => {
+                    (EntryPayload::Normal(*query), Some(trace))
+                }
+                payload => (payload, None),
+            };
+            let (payload, idempotency) = match payload {
+                EntryPayload::Normal(QueryWrite::Idempotent((idempotency, query))) => {
+                    (EntryPayload::Normal(*query), Some(idempotency))
+                }
+                payload => (payload, None),
+            };
+            let span = debug_span!(
+                "sqlite_apply",
+                log_index = log_id.index,
+                trace_id = field::Empty
+            );
+            if let Some(trace) = trace {
+                span.record("trace_id", field::display(trace));
+            }
 
-            let resp = match entry.payload {
+            let (mut req, resp) = match payload {
                 // TODO we probably need to update the log id in writer in case of ::Empty?
-                EntryPayload::Blank => Response::Empty,
+                EntryPayload::Blank => {
+                    pending.push(PendingResponse::Blank);
+                    continue;
+                }
 
                 EntryPayload::Normal(QueryWrite::Execute(Query { sql, params })) => {
                     let (tx, rx) = oneshot::channel();
@@ -598,17 +864,7 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         tx,
                     });
-
-                    self.write_tx
-                        .send_async(WriterRequest::Query(query))
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    let result = rx
-                        .await
-                        .expect("to always get a response from sql writer")
-                        .map_err(Error::from);
-                    Response::Execute(ResponseExecute { result })
+                    (WriterRequest::Query(query), PendingResponse::Execute(rx))
                 }
 
                 EntryPayload::Normal(QueryWrite::ExecuteReturning(Query { sql, params })) => {
@@ -619,17 +875,10 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         tx,
                     });
-
-                    self.write_tx
-                        .send_async(WriterRequest::Query(query))
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    let result = rx
-                        .await
-                        .expect("to always get a response from sql writer")
-                        .map_err(Error::from);
-                    Response::ExecuteReturning(ResponseExecuteReturning { result })
+                    (
+                        WriterRequest::Query(query),
+                        PendingResponse::ExecuteReturning(rx),
+                    )
                 }
 
                 EntryPayload::Normal(QueryWrite::Transaction(queries)) => {
@@ -639,26 +888,19 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         tx,
                     }));
+                    (req, PendingResponse::Transaction(rx))
+                }
 
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    let result = rx.await.expect("to always get a response from sql writer");
-
-                    let resp = match result {
-                        Ok(res) => {
-                            let mapped = res
-                                .into_iter()
-                                .map(|res| res.map_err(Error::from))
-                                .collect();
-                            Ok(mapped)
-                        }
-                        Err(err) => Err(err),
-                    };
-
-                    Response::Transaction(resp)
+                EntryPayload::Normal(QueryWrite::TransactionGuarded(steps)) => {
+                    let (tx, rx) = oneshot::channel();
+                    let req = WriterRequest::Query(writer::Query::TransactionGuarded(
+                        writer::SqlTransactionGuarded {
+                            steps,
+                            last_applied_log_id,
+                            tx,
+                        },
+                    ));
+                    (req, PendingResponse::TransactionGuarded(rx))
                 }
 
                 EntryPayload::Normal(QueryWrite::Batch(sql)) => {
@@ -668,35 +910,61 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         tx,
                     }));
-
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    let result = rx.await.expect("to always get a response from sql writer");
-                    Response::Batch(ResponseBatch { result })
+                    (req, PendingResponse::Batch(rx))
                 }
 
                 #[cfg(feature = "backup")]
                 EntryPayload::Normal(QueryWrite::Backup(node_id)) => {
+                    #[cfg(feature = "cache")]
+                    let cache_snapshot = match &self.cache {
+                        Some(cache) => Some(cache.backup_snapshot().await),
+                        None => None,
+                    };
+                    #[cfg(not(feature = "cache"))]
+                    let cache_snapshot = None;
+
                     let (ack, rx) = oneshot::channel();
                     let req = WriterRequest::Backup(writer::BackupRequest {
                         node_id,
                         target_folder: self.path_backups.clone(),
-                        #[cfg(feature = "s3")]
-                        s3_config: self.s3_config.clone(),
+                        backup_targets: self.backup_targets.clone(),
+                        archive_generation: self
+                            .log_archive
+                            .as_ref()
+                            .map(|archive| archive.generation())
+                            .unwrap_or(0),
+                        cache_snapshot,
                         last_applied_log_id,
                         ack,
                     });
+                    (req, PendingResponse::Backup(rx))
+                }
 
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
+                #[cfg(feature = "backup")]
+                EntryPayload::Normal(QueryWrite::Restore((src, content, leader))) => {
+                    // The writer resets the flag after the restore. Without a successful
+                    // fetch, this node cannot continue with the same state as the others.
+                    self.metrics.maintenance.store(true, Ordering::Relaxed);
+                    self.online_restore.set_pending(log_id.index, src.clone());
+                    let path = crate::backup::fetch_restore(
+                        &self.backup_targets,
+                        &self.path_backups,
+                        &self.online_restore,
+                        self.this_node,
+                        &src,
+                        &content,
+                        &leader,
+                    )
+                    .await
+                    .map_err(|err| StorageIOError::write_state_machine(&err))?;
 
-                    let result = rx.await.expect("to always get a response from sql writer");
-                    Response::Backup(result)
+                    let (ack, rx) = oneshot::channel();
+                    let req = WriterRequest::BackupApply(writer::BackupApplyRequest {
+                        path,
+                        last_applied_log_id,
+                        ack,
+                    });
+                    (req, PendingResponse::Restore(rx))
                 }
 
                 EntryPayload::Normal(QueryWrite::Migration(migrations)) => {
@@ -706,14 +974,7 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         tx,
                     });
-
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    let result = rx.await.expect("to always get a response from sql writer");
-                    Response::Migrate(result)
+                    (req, PendingResponse::Migrate(rx))
                 }
 
                 EntryPayload::Normal(QueryWrite::RTT) => {
@@ -722,38 +983,53 @@ impl RaftStateMachine<TypeConfigSqlite> for StateMachineSqlite {
                         last_applied_log_id,
                         ack,
                     });
+                    (req, PendingResponse::RTT(rx))
+                }
 
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
+                EntryPayload::Normal(QueryWrite::Traced(_)) => {
+                    unreachable!("QueryWrite::Traced is never nested")
+                }
 
-                    rx.await.expect("to always get a response from sql writer");
-                    Response::RTT
+                EntryPayload::Normal(QueryWrite::Idempotent(_)) => {
+                    unreachable!("QueryWrite::Idempotent is never nested")
                 }
 
                 EntryPayload::Membership(mem) => {
                     let (ack, rx) = oneshot::channel();
                     let req = WriterRequest::MetadataMembership(writer::MetaMembershipRequest {
-                        last_membership: StoredMembership::new(Some(entry.log_id), mem),
+                        last_membership: StoredMembership::new(Some(log_id), mem),
                         last_applied_log_id,
                         ack,
                     });
-
-                    self.write_tx
-                        .send_async(req)
-                        .await
-                        .expect("sql writer to always be listening");
-
-                    rx.await.expect("to always get a response from sql writer");
-
-                    Response::Empty
+                    (req, PendingResponse::Membership(rx))
                 }
             };
 
-            replies.push(resp);
+            // Only queries are deduplicated. Migrations are idempotent anyway.
+            if let Some(idempotency) = idempotency {
+                if let WriterRequest::Query(query) = req {
+                    req = WriterRequest::Query(writer::Query::Idempotent((
+                        idempotency,
+                        Box::new(query),
+                    )));
+                }
+            }
+
+            self.write_tx
+                .send_async(req)
+                .instrument(span)
+                .await
+                .expect("sql writer to always be listening");
+            pending.push(resp);
+        }
+
+        let mut replies = Vec::with_capacity(pending.len());
+        for resp in pending {
+            replies.push(resp.recv().await?);
         }
 
+        self.metrics
+            .record_apply(replies.len() as u64, start.elapsed());
         Ok(replies)
     }
 
@@ -0,0 +1,167 @@
+use crate::Error;
+use rusqlite::types::ValueRef;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Row counts and a content checksum over all tables, apart from the hiqlite `_metadata`.
+///
+/// The checksum does not depend on the row order, because a `VACUUM` may re-assign the rowid
+/// of tables without an explicit `INTEGER PRIMARY KEY`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentChecksum {
+    pub tables: u32,
+    pub rows: u64,
+    /// Hex-encoded SHA256
+    pub checksum: String,
+}
+
+impl ContentChecksum {
+    pub fn calculate(conn: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let tables = {
+            let mut stmt = conn.prepare(
+                r#"SELECT name FROM sqlite_master
+                WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_metadata'
+                ORDER BY name"#,
+            )?;
+            let rows = stmt.query_map((), |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut hasher = Sha256::new();
+        let mut rows_total = 0;
+
+        for table in &tables {
+            let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\"", table))?;
+            let columns = stmt.column_count();
+            let mut rows = stmt.query(())?;
+
+            let mut count = 0u64;
+            let mut sum = 0u128;
+            while let Some(row) = rows.next()? {
+                let mut row_hasher = Sha256::new();
+                for i in 0..columns {
+                    hash_value(&mut row_hasher, row.get_ref(i)?);
+                }
+                let digest = row_hasher.finalize();
+                sum = sum.wrapping_add(u128::from_le_bytes(digest[..16].try_into().unwrap()));
+                count += 1;
+            }
+
+            hasher.update(table.as_bytes());
+            hasher.update(count.to_le_bytes());
+            hasher.update(sum.to_le_bytes());
+            rows_total += count;
+        }
+
+        Ok(Self {
+            tables: tables.len() as u32,
+            rows: rows_total,
+            checksum: hex::encode(hasher.finalize()),
+        })
+    }
+}
+
+#[inline]
+fn hash_value(hasher: &mut Sha256, value: ValueRef) {
+    match value {
+        ValueRef::Null => hasher.update([0]),
+        ValueRef::Integer(i) => {
+            hasher.update([1]);
+            hasher.update(i.to_le_bytes());
+        }
+        ValueRef::Real(f) => {
+            hasher.update([2]);
+            hasher.update(f.to_bits().to_le_bytes());
+        }
+        ValueRef::Text(t) => {
+            hasher.update([3]);
+            hasher.update((t.len() as u64).to_le_bytes());
+            hasher.update(t);
+        }
+        ValueRef::Blob(b) => {
+            hasher.update([4]);
+            hasher.update((b.len() as u64).to_le_bytes());
+            hasher.update(b);
+        }
+    }
+}
+
+/// Runs `PRAGMA integrity_check` and returns an error with all found problems.
+pub fn integrity_check(conn: &rusqlite::Connection) -> Result<(), Error> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if rows.len() == 1 && rows[0] == "ok" {
+        Ok(())
+    } else {
+        Err(Error::Backup(
+            format!("integrity check failed: {}", rows.join(", ")).into(),
+        ))
+    }
+}
+
+/// Verifies a written database copy with an integrity check and compares its content with the
+/// `expected` checksum.
+pub fn verify(conn: &rusqlite::Connection, expected: &ContentChecksum) -> Result<(), Error> {
+    integrity_check(conn)?;
+
+    let actual = ContentChecksum::calculate(conn)?;
+    if &actual != expected {
+        return Err(Error::Backup(
+            format!(
+                "content mismatch - expected {} tables / {} rows / checksum {}, \
+                found {} tables / {} rows / checksum {}",
+                expected.tables,
+                expected.rows,
+                expected.checksum,
+                actual.tables,
+                actual.rows,
+                actual.checksum
+            )
+            .into(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE _metadata (key TEXT PRIMARY KEY, data BLOB);
+            CREATE TABLE test (name TEXT, value REAL, data BLOB);
+            INSERT INTO test VALUES ('a', 1.5, x'01'), ('b', NULL, NULL);
+            INSERT INTO _metadata VALUES ('meta', x'01');
+            "#,
+        )
+        .unwrap();
+
+        let expected = ContentChecksum::calculate(&conn).unwrap();
+        assert_eq!(expected.tables, 1);
+        assert_eq!(expected.rows, 2);
+        verify(&conn, &expected).unwrap();
+
+        // the row order and the metadata must not matter
+        conn.execute_batch(
+            r#"
+            DELETE FROM test WHERE name = 'a';
+            INSERT INTO test VALUES ('a', 1.5, x'01');
+            UPDATE _metadata SET data = x'02';
+            "#,
+        )
+        .unwrap();
+        verify(&conn, &expected).unwrap();
+
+        conn.execute("UPDATE test SET value = 2.5 WHERE name = 'a'", ())
+            .unwrap();
+        assert!(verify(&conn, &expected).is_err());
+    }
+}
@@ -1,19 +1,27 @@
+use crate::idempotency::Idempotency;
 use crate::migration::Migration;
 use crate::query::rows::{ColumnOwned, RowOwned, ValueOwned};
+use crate::query::stats::{QueryStats, StatementKind};
 use crate::store::logs;
+use crate::store::state_machine::sqlite::metrics::SqliteMetrics;
 use crate::store::state_machine::sqlite::state_machine;
 use crate::store::state_machine::sqlite::state_machine::{
-    Params, StateMachineData, StateMachineSqlite, StoredSnapshot,
+    BackupMeta, Params, StateMachineData, StateMachineSqlite, StoredSnapshot, TxnStep,
+    TxnStepOutput,
 };
+use crate::store::state_machine::sqlite::verify::{self, ContentChecksum};
 use crate::{AppliedMigration, Error, Node, NodeId};
 use chrono::Utc;
 use flume::RecvError;
 use openraft::{LogId, SnapshotMeta, StorageError, StorageIOError, StoredMembership};
 use rusqlite::backup::Progress;
-use rusqlite::{Batch, DatabaseName, Transaction};
+use rusqlite::{Batch, DatabaseName, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::default::Default;
 use std::ops::Sub;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
@@ -32,7 +40,7 @@ pub enum WriterRequest {
     MetadataRead(oneshot::Sender<StateMachineData>),
     MetadataMembership(MetaMembershipRequest),
     Backup(BackupRequest),
-    // BackupApply(BackupApplyRequest),
+    BackupApply(BackupApplyRequest),
     Shutdown(oneshot::Sender<()>),
     #[allow(clippy::upper_case_acronyms)]
     RTT(RTTRequest),
@@ -43,7 +51,9 @@ pub enum Query {
     Execute(SqlExecute),
     ExecuteReturning(SqlExecuteReturning),
     Transaction(SqlTransaction),
+    TransactionGuarded(SqlTransactionGuarded),
     Batch(SqlBatch),
+    Idempotent((Idempotency, Box<Query>)),
 }
 
 #[derive(Debug)]
@@ -69,6 +79,13 @@ pub struct SqlTransaction {
     pub tx: oneshot::Sender<Result<Vec<Result<usize, Error>>, Error>>,
 }
 
+#[derive(Debug)]
+pub struct SqlTransactionGuarded {
+    pub steps: Vec<TxnStep>,
+    pub last_applied_log_id: Option<LogId<NodeId>>,
+    pub tx: oneshot::Sender<Result<Vec<TxnStepOutput>, Error>>,
+}
+
 #[derive(Debug)]
 pub struct SqlBatch {
     pub sql: Cow<'static, str>,
@@ -114,8 +131,11 @@ pub struct MetaMembershipRequest {
 pub struct BackupRequest {
     pub node_id: NodeId,
     pub target_folder: String,
-    #[cfg(feature = "s3")]
-    pub s3_config: Option<std::sync::Arc<crate::s3::S3Config>>,
+    #[cfg(feature = "backup")]
+    pub backup_targets: Vec<Arc<dyn crate::backup::BackupTarget>>,
+    pub archive_generation: u64,
+    /// A serialized snapshot of all caches, which will be stored inside the backup
+    pub cache_snapshot: Option<Vec<u8>>,
     pub last_applied_log_id: Option<LogId<NodeId>>,
     pub ack: oneshot::Sender<Result<(), Error>>,
 }
@@ -126,11 +146,13 @@ pub struct RTTRequest {
     pub ack: oneshot::Sender<()>,
 }
 
-// #[derive(Debug)]
-// pub struct BackupApplyRequest {
-//     pub src: String,
-//     pub ack: oneshot::Sender<Result<(), Error>>,
-// }
+#[derive(Debug)]
+pub struct BackupApplyRequest {
+    /// A local copy of the backup, which has been verified already
+    pub path: String,
+    pub last_applied_log_id: Option<LogId<NodeId>>,
+    pub ack: oneshot::Sender<Result<(), Error>>,
+}
 
 #[allow(clippy::blocks_in_conditions)]
 pub fn spawn_writer(
@@ -138,6 +160,8 @@ pub fn spawn_writer(
     this_node: NodeId,
     path_lock_file: String,
     log_statements: bool,
+    query_stats: Arc<QueryStats>,
+    metrics: Arc<SqliteMetrics>,
 ) -> flume::Sender<WriterRequest> {
     let (tx, rx) = flume::bounded::<WriterRequest>(2);
 
@@ -161,244 +185,36 @@ CREATE TABLE IF NOT EXISTS _metadata
             (),
         )
         .expect("_metadata table creation to always succeed");
+        create_idempotency_table(&conn).expect("_idempotency table creation to always succeed");
 
-        'main: while let Ok(req) = rx.recv() {
-            match req {
-                WriterRequest::Query(query) => match query {
-                    Query::Execute(q) => {
-                        sm_data.last_applied_log_id = q.last_applied_log_id;
+        let mut group = WriteGroup::new();
 
-                        if log_statements {
-                            info!("Query::Execute:\n{}\n{:?}", q.sql, q.params);
-                        }
-
-                        let res = {
-                            let mut stmt = match conn.prepare_cached(q.sql.as_ref()) {
-                                Ok(stmt) => stmt,
-                                Err(err) => {
-                                    error!("Preparing cached query {}: {:?}", q.sql, err);
-                                    q.tx.send(Err(Error::PrepareStatement(err.to_string().into())))
-                                        .expect("oneshot tx to never be dropped");
-                                    continue;
-                                }
-                            };
-
-                            // let params_len = q.params.len();
-                            let mut params_err = None;
-                            let mut idx = 1;
-                            for param in q.params {
-                                if let Err(err) = stmt.raw_bind_parameter(idx, param.into_sql()) {
-                                    error!(
-                                        "Error binding param on position {} to query {}: {:?}",
-                                        idx, q.sql, err
-                                    );
-                                    params_err = Some(Error::QueryParams(err.to_string().into()));
-                                    break;
-                                }
-
-                                idx += 1;
-                            }
-
-                            if let Some(err) = params_err {
-                                q.tx.send(Err(err)).expect("oneshot tx to never be dropped");
-                                continue;
-                            }
+        while let Ok(req) = rx.recv() {
+            let req = match req {
+                WriterRequest::Query(query) if query.is_groupable() => {
+                    sm_data.last_applied_log_id = query.last_applied_log_id();
+                    group.apply(&conn, query, &query_stats, log_statements);
 
-                            stmt.raw_execute().map_err(Error::from)
-                        };
-
-                        q.tx.send(res).expect("oneshot tx to never be dropped");
+                    // Under load, the queue will never be empty and we can keep on adding
+                    // entries to the current transaction until we hit one of the limits.
+                    if group.is_full() || rx.is_empty() {
+                        group.commit(&conn);
                     }
+                    continue;
+                }
+                req => {
+                    // everything else must always see a committed state
+                    group.commit(&conn);
+                    req
+                }
+            };
 
-                    Query::ExecuteReturning(q) => {
-                        sm_data.last_applied_log_id = q.last_applied_log_id;
-
-                        if log_statements {
-                            info!("Query::ExecuteReturning:\n{}\n{:?}", q.sql, q.params);
-                        }
-
-                        let res = {
-                            let mut stmt = match conn.prepare_cached(q.sql.as_ref()) {
-                                Ok(stmt) => stmt,
-                                Err(err) => {
-                                    error!("Preparing cached query {}: {:?}", q.sql, err);
-                                    q.tx.send(Err(Error::PrepareStatement(err.to_string().into())))
-                                        .expect("oneshot tx to never be dropped");
-                                    continue;
-                                }
-                            };
-
-                            let columns = match ColumnOwned::mapping_cols_from_stmt(stmt.columns())
-                            {
-                                Ok(c) => c,
-                                Err(err) => {
-                                    q.tx.send(Err(Error::PrepareStatement(err.to_string().into())))
-                                        .expect("oneshot tx to never be dropped");
-                                    continue;
-                                }
-                            };
-
-                            // let params_len = q.params.len();
-                            let mut params_err = None;
-                            let mut idx = 1;
-                            for param in q.params {
-                                if let Err(err) = stmt.raw_bind_parameter(idx, param.into_sql()) {
-                                    error!(
-                                        "Error binding param on position {} to query {}: {:?}",
-                                        idx, q.sql, err
-                                    );
-                                    params_err = Some(Error::QueryParams(err.to_string().into()));
-                                    break;
-                                }
-
-                                idx += 1;
-                            }
-
-                            if let Some(err) = params_err {
-                                q.tx.send(Err(err)).expect("oneshot tx to never be dropped");
-                                continue;
-                            }
-
-                            let mut rows = stmt.raw_query();
-                            let mut res = Vec::new();
-                            loop {
-                                match rows.next() {
-                                    Ok(Some(row)) => {
-                                        res.push(Ok(RowOwned::from_row_column(row, &columns)));
-                                    }
-                                    Ok(None) => {
-                                        break;
-                                    }
-                                    Err(err) => {
-                                        res.push(Err(Error::Sqlite(err.to_string().into())));
-                                    }
-                                }
-                            }
-
-                            Ok(res)
-                        };
-
-                        q.tx.send(res).expect("oneshot tx to never be dropped");
-                    }
-
-                    Query::Transaction(req) => {
-                        sm_data.last_applied_log_id = req.last_applied_log_id;
-
-                        let txn = match conn.transaction() {
-                            Ok(txn) => txn,
-                            Err(err) => {
-                                error!("Opening database transaction: {:?}", err);
-                                req.tx
-                                    .send(Err(Error::Transaction(err.to_string().into())))
-                                    .expect("oneshot tx to never be dropped");
-                                continue;
-                            }
-                        };
-
-                        let mut results = Vec::with_capacity(req.queries.len());
-                        let mut query_err = None;
-
-                        'outer: for state_machine::Query { sql, params } in req.queries {
-                            if log_statements {
-                                info!("Query::Transaction:\n{}\n{:?}", sql, params);
-                            }
-
-                            let mut stmt = match txn.prepare_cached(sql.as_ref()) {
-                                Ok(stmt) => stmt,
-                                Err(err) => {
-                                    let err = format!("Preparing cached query {}: {:?}", sql, err);
-                                    query_err =
-                                        Some(Error::PrepareStatement(err.to_string().into()));
-                                    break;
-                                }
-                            };
-
-                            let mut idx = 1;
-                            for param in params {
-                                if let Err(err) = stmt.raw_bind_parameter(idx, param.into_sql()) {
-                                    let err = format!(
-                                        "Error binding param on position {} to query {}: {:?}",
-                                        idx, sql, err
-                                    );
-                                    query_err = Some(Error::QueryParams(err.to_string().into()));
-                                    break 'outer;
-                                }
-
-                                idx += 1;
-                            }
-
-                            let res = stmt.raw_execute().map_err(Error::from);
-                            match res {
-                                Ok(r) => results.push(Ok(r)),
-                                Err(err) => {
-                                    query_err = Some(Error::Transaction(err.to_string().into()));
-                                    break;
-                                }
-                            }
-                        }
-
-                        if let Some(err) = query_err {
-                            if let Err(e) = txn.rollback() {
-                                error!("Error during txn rollback: {:?}", e);
-                            }
-                            req.tx
-                                .send(Err(err))
-                                .expect("oneshot tx to never be dropped");
-                        } else {
-                            match txn.commit() {
-                                Ok(()) => {
-                                    req.tx
-                                        .send(Ok(results))
-                                        .expect("oneshot tx to never be dropped");
-                                }
-                                Err(err) => {
-                                    req.tx
-                                        .send(Err(Error::Transaction(err.to_string().into())))
-                                        .expect("oneshot tx to never be dropped");
-                                }
-                            }
-                        }
-                    }
-
-                    Query::Batch(req) => {
-                        sm_data.last_applied_log_id = req.last_applied_log_id;
-
-                        if log_statements {
-                            info!("Query::Batch:\n{}", req.sql);
-                        }
-
-                        let mut batch = Batch::new(&conn, req.sql.as_ref());
-                        // we can at least assume 2 statements in a batch execute
-                        let mut res = Vec::with_capacity(2);
-
-                        let mut err = None;
-
-                        loop {
-                            match batch.next() {
-                                Ok(Some(mut stmt)) => {
-                                    res.push(stmt.execute([]).map_err(Error::from));
-                                }
-                                Ok(None) => break,
-                                Err(e) => {
-                                    // The `Batch` iterator can't recover from errors
-                                    // -> exit early and do not commit the txn
-                                    err = Some(Error::Sqlite(e.to_string().into()));
-                                    break;
-                                }
-                            }
-                        }
-
-                        if let Some(err) = err {
-                            req.tx
-                                .send(Err(err))
-                                .expect("oneshot tx to never be dropped");
-                        } else {
-                            req.tx
-                                .send(Ok(res))
-                                .expect("oneshot tx to never be dropped");
-                        }
-                    }
-                },
+            match req {
+                WriterRequest::Query(query) => {
+                    sm_data.last_applied_log_id = query.last_applied_log_id();
+                    let res = query.apply(&conn, Some(&query_stats), log_statements);
+                    query.ack(res);
+                }
 
                 WriterRequest::Migrate(req) => {
                     sm_data.last_applied_log_id = req.last_applied_log_id;
@@ -478,6 +294,10 @@ CREATE TABLE IF NOT EXISTS _metadata
                         })
                         .expect("Metadata query to always succeed");
 
+                    // the snapshot may come from an older version without this table
+                    create_idempotency_table(&conn)
+                        .expect("_idempotency table creation to always succeed");
+
                     ack.send(()).unwrap()
                 }
 
@@ -533,21 +353,31 @@ CREATE TABLE IF NOT EXISTS _metadata
                     }
 
                     // only the current leader should push the backup
-                    #[cfg(feature = "s3")]
-                    let s3_config = if this_node == req.node_id {
-                        req.s3_config
+                    #[cfg(feature = "backup")]
+                    let backup_targets = if this_node == req.node_id {
+                        req.backup_targets
                     } else {
-                        None
+                        Vec::default()
                     };
 
+                    let backup_meta = BackupMeta {
+                        log_index: req.last_applied_log_id.map(|id| id.index).unwrap_or(0),
+                        timestamp: now.timestamp_millis(),
+                        archive_generation: req.archive_generation,
+                        ..Default::default()
+                    };
                     if let Err(err) = create_backup(
                         &conn,
                         req.node_id,
                         req.target_folder.clone(),
-                        #[cfg(feature = "s3")]
-                        s3_config,
+                        backup_meta,
+                        req.cache_snapshot,
+                        #[cfg(feature = "backup")]
+                        backup_targets,
+                        metrics.clone(),
                     ) {
                         error!("Error creating backup: {:?}", err);
+                        metrics.backups_failed.fetch_add(1, Ordering::Relaxed);
                         req.ack.send(Err(err));
                         continue;
                     }
@@ -566,9 +396,46 @@ CREATE TABLE IF NOT EXISTS _metadata
                     }
 
                     ts_last_backup = Some(now);
+                    metrics
+                        .backup_last_success
+                        .store(now.timestamp(), Ordering::Relaxed);
                     req.ack.send(Ok(()));
                 }
 
+                WriterRequest::BackupApply(req) => {
+                    let start = Instant::now();
+                    info!("Starting online restore from backup {}", req.path);
+                    if let Err(err) =
+                        conn.restore(DatabaseName::Main, &req.path, None::<fn(Progress)>)
+                    {
+                        error!("Error during online restore from {}: {}", req.path, err);
+                        let _ = req.ack.send(Err(Error::from(err)));
+                        continue;
+                    }
+
+                    // The backup only contains reset metadata. Everything Raft related must be
+                    // kept, the restore is just another applied log entry.
+                    sm_data.last_applied_log_id = req.last_applied_log_id;
+                    persist_metadata(&conn, &sm_data).expect("Metadata persist to never fail");
+                    // the backup may come from an older version without this table
+                    create_idempotency_table(&conn)
+                        .expect("_idempotency table creation to always succeed");
+
+                    if let Err(err) = conn.execute("PRAGMA optimize", []) {
+                        error!("Error during 'PRAGMA optimize': {}", err);
+                    }
+                    if let Err(err) = std::fs::remove_file(&req.path) {
+                        error!("Error removing restored backup {}: {}", req.path, err);
+                    }
+
+                    info!(
+                        "Online restore finished after {} ms",
+                        start.elapsed().as_millis()
+                    );
+                    metrics.maintenance.store(false, Ordering::Relaxed);
+                    req.ack.send(Ok(())).unwrap();
+                }
+
                 WriterRequest::RTT(req) => {
                     sm_data.last_applied_log_id = req.last_applied_log_id;
                     req.ack.send(()).unwrap();
@@ -581,6 +448,7 @@ CREATE TABLE IF NOT EXISTS _metadata
             }
         }
 
+        group.commit(&conn);
         warn!("SQL writer is shutting down");
 
         // make sure metadata is persisted before shutting down
@@ -596,6 +464,655 @@ CREATE TABLE IF NOT EXISTS _metadata
     tx
 }
 
+/// The max amount of entries the writer will apply inside a single transaction.
+const GROUP_MAX_ENTRIES: usize = 128;
+/// The max time a write transaction will be kept open. Responses are only sent after the commit,
+/// so this is the max additional latency a single write can get under heavy load.
+const GROUP_MAX_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Serialize, Deserialize)]
+enum QueryResult {
+    Execute(Result<usize, Error>),
+    ExecuteReturning(Result<Vec<Result<RowOwned, Error>>, Error>),
+    Many(Result<Vec<Result<usize, Error>>, Error>),
+    Guarded(Result<Vec<TxnStepOutput>, Error>),
+}
+
+impl QueryResult {
+    #[inline]
+    fn is_err(&self) -> bool {
+        match self {
+            QueryResult::Execute(res) => res.is_err(),
+            QueryResult::ExecuteReturning(res) => res.is_err(),
+            QueryResult::Many(res) => res.is_err(),
+            QueryResult::Guarded(res) => res.is_err(),
+        }
+    }
+}
+
+impl Query {
+    #[inline]
+    fn last_applied_log_id(&self) -> Option<LogId<NodeId>> {
+        match self {
+            Query::Execute(q) => q.last_applied_log_id,
+            Query::ExecuteReturning(q) => q.last_applied_log_id,
+            Query::Transaction(q) => q.last_applied_log_id,
+            Query::TransactionGuarded(q) => q.last_applied_log_id,
+            Query::Batch(q) => q.last_applied_log_id,
+            Query::Idempotent((_, q)) => q.last_applied_log_id(),
+        }
+    }
+
+    /// Returns `true` if this query can be applied inside a shared write transaction.
+    /// A batch is never grouped, because it may contain anything.
+    #[inline]
+    fn is_groupable(&self) -> bool {
+        match self {
+            Query::Execute(q) => !needs_autocommit(&q.sql),
+            Query::ExecuteReturning(q) => !needs_autocommit(&q.sql),
+            Query::Transaction(_) | Query::TransactionGuarded(_) => true,
+            Query::Batch(_) => false,
+            Query::Idempotent((_, q)) => q.is_groupable(),
+        }
+    }
+
+    /// Applies this query without sending the result.
+    /// A `Query::Transaction` and `Query::TransactionGuarded` expect to be wrapped in a
+    /// `SAVEPOINT` by the caller.
+    fn apply(
+        &self,
+        conn: &rusqlite::Connection,
+        query_stats: Option<&QueryStats>,
+        log_statements: bool,
+    ) -> QueryResult {
+        match self {
+            Query::Idempotent((idempotency, query)) => {
+                match idempotent_result(conn, idempotency) {
+                    Ok(Some(res)) if query.matches(&res) => {
+                        debug!(
+                            "Returning the existing result for idempotency key {}",
+                            idempotency.key
+                        );
+                        return res;
+                    }
+                    Ok(Some(_)) => {
+                        return query.err(Error::BadRequest(
+                            format!(
+                                "idempotency key {} has been used for another kind of write",
+                                idempotency.key
+                            )
+                            .into(),
+                        ));
+                    }
+                    Ok(None) => {}
+                    Err(err) => return query.err(err),
+                }
+
+                // Failed writes are rolled back and not remembered, so they can be retried.
+                let res = query.apply(conn, query_stats, log_statements);
+                if !res.is_err() {
+                    if let Err(err) = idempotent_result_insert(conn, idempotency, &res) {
+                        return query.err(err);
+                    }
+                }
+                res
+            }
+
+            Query::Execute(q) => {
+                if log_statements {
+                    info!("Query::Execute:\n{}\n{:?}", q.sql, q.params);
+                }
+
+                let start = Instant::now();
+                let res = execute(conn, &q.sql, &q.params);
+                if let Some(stats) = query_stats {
+                    stats.record(
+                        StatementKind::Write,
+                        &q.sql,
+                        &q.params,
+                        start.elapsed(),
+                        res.as_ref().copied(),
+                    );
+                }
+                QueryResult::Execute(res)
+            }
+
+            Query::ExecuteReturning(q) => {
+                if log_statements {
+                    info!("Query::ExecuteReturning:\n{}\n{:?}", q.sql, q.params);
+                }
+
+                let start = Instant::now();
+                let res = execute_returning(conn, &q.sql, &q.params);
+                if let Some(stats) = query_stats {
+                    stats.record(
+                        StatementKind::Write,
+                        &q.sql,
+                        &q.params,
+                        start.elapsed(),
+                        res.as_ref().map(|rows| rows.len()),
+                    );
+                }
+                QueryResult::ExecuteReturning(res)
+            }
+
+            Query::Transaction(req) => {
+                let mut results = Vec::with_capacity(req.queries.len());
+
+                for state_machine::Query { sql, params } in &req.queries {
+                    if log_statements {
+                        info!("Query::Transaction:\n{}\n{:?}", sql, params);
+                    }
+
+                    let start = Instant::now();
+                    let res = execute(conn, sql, params);
+                    if let Some(stats) = query_stats {
+                        stats.record(
+                            StatementKind::Write,
+                            sql,
+                            params,
+                            start.elapsed(),
+                            res.as_ref().copied(),
+                        );
+                    }
+                    match res {
+                        Ok(r) => results.push(Ok(r)),
+                        Err(err) => {
+                            return QueryResult::Many(Err(Error::Transaction(
+                                err.to_string().into(),
+                            )));
+                        }
+                    }
+                }
+
+                QueryResult::Many(Ok(results))
+            }
+
+            Query::TransactionGuarded(req) => {
+                let mut results = Vec::with_capacity(req.steps.len());
+
+                for (step, txn_step) in req.steps.iter().enumerate() {
+                    let state_machine::Query { sql, params } = txn_step.query();
+                    if log_statements {
+                        info!("Query::TransactionGuarded:\n{}\n{:?}", sql, params);
+                    }
+
+                    let start = Instant::now();
+                    let res = match txn_step {
+                        TxnStep::Execute(_) | TxnStep::ExecuteExpect(_) => {
+                            execute(conn, sql, params).map(|rows_affected| TxnStepOutput {
+                                rows_affected,
+                                rows: Vec::default(),
+                            })
+                        }
+                        TxnStep::AssertExists(_) | TxnStep::AssertNotExists(_) => {
+                            exists(conn, sql, params).map(|exists| TxnStepOutput {
+                                rows_affected: usize::from(exists),
+                                rows: Vec::default(),
+                            })
+                        }
+                        TxnStep::ExecuteReturning(_) => {
+                            match execute_returning(conn, sql, params) {
+                                Ok(rows) => rows.into_iter().collect::<Result<Vec<_>, _>>(),
+                                Err(err) => Err(err),
+                            }
+                            .map(|rows| TxnStepOutput {
+                                rows_affected: rows.len(),
+                                rows,
+                            })
+                        }
+                    };
+                    if let Some(stats) = query_stats {
+                        stats.record(
+                            StatementKind::Write,
+                            sql,
+                            params,
+                            start.elapsed(),
+                            res.as_ref().map(|out| out.rows_affected),
+                        );
+                    }
+
+                    let mut output = match res {
+                        Ok(output) => output,
+                        Err(err) => {
+                            return QueryResult::Guarded(Err(Error::Transaction(
+                                err.to_string().into(),
+                            )));
+                        }
+                    };
+                    let rows = output.rows_affected;
+
+                    let reason: Option<Cow<'static, str>> = match txn_step {
+                        TxnStep::Execute(_) | TxnStep::ExecuteReturning(_) => None,
+                        TxnStep::ExecuteExpect((_, expected)) => (rows != *expected).then(|| {
+                            format!("expected {} affected rows, got {}", expected, rows).into()
+                        }),
+                        TxnStep::AssertExists(_) => {
+                            (rows == 0).then_some("query returned no rows".into())
+                        }
+                        TxnStep::AssertNotExists(_) => {
+                            (rows > 0).then_some("query returned rows".into())
+                        }
+                    };
+                    if let Some(reason) = reason {
+                        return QueryResult::Guarded(Err(Error::GuardFailed { step, reason }));
+                    }
+
+                    if matches!(
+                        txn_step,
+                        TxnStep::AssertExists(_) | TxnStep::AssertNotExists(_)
+                    ) {
+                        output.rows_affected = 0;
+                    }
+                    results.push(output);
+                }
+
+                QueryResult::Guarded(Ok(results))
+            }
+
+            Query::Batch(req) => {
+                if log_statements {
+                    info!("Query::Batch:\n{}", req.sql);
+                }
+
+                let start = Instant::now();
+                let mut batch = Batch::new(conn, req.sql.as_ref());
+                // we can at least assume 2 statements in a batch execute
+                let mut res = Vec::with_capacity(2);
+
+                let mut err = None;
+
+                loop {
+                    match batch.next() {
+                        Ok(Some(mut stmt)) => {
+                            res.push(stmt.execute([]).map_err(Error::from));
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            // The `Batch` iterator can't recover from errors -> exit early
+                            err = Some(Error::Sqlite(e.to_string().into()));
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(stats) = query_stats {
+                    let rows = match &err {
+                        Some(err) => Err(err),
+                        None => Ok(res.iter().filter_map(|r| r.as_ref().ok()).sum()),
+                    };
+                    stats.record(
+                        StatementKind::Write,
+                        &req.sql,
+                        &Vec::new(),
+                        start.elapsed(),
+                        rows,
+                    );
+                }
+
+                match err {
+                    Some(err) => QueryResult::Many(Err(err)),
+                    None => QueryResult::Many(Ok(res)),
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the result belongs to this kind of query.
+    #[inline]
+    fn matches(&self, res: &QueryResult) -> bool {
+        matches!(
+            (self, res),
+            (Query::Execute(_), QueryResult::Execute(_))
+                | (Query::ExecuteReturning(_), QueryResult::ExecuteReturning(_))
+                | (Query::Transaction(_), QueryResult::Many(_))
+                | (Query::TransactionGuarded(_), QueryResult::Guarded(_))
+                | (Query::Batch(_), QueryResult::Many(_))
+        )
+    }
+
+    #[inline]
+    fn err(&self, err: Error) -> QueryResult {
+        match self {
+            Query::Execute(_) => QueryResult::Execute(Err(err)),
+            Query::ExecuteReturning(_) => QueryResult::ExecuteReturning(Err(err)),
+            Query::Transaction(_) | Query::Batch(_) => QueryResult::Many(Err(err)),
+            Query::TransactionGuarded(_) => QueryResult::Guarded(Err(err)),
+            Query::Idempotent((_, q)) => q.err(err),
+        }
+    }
+
+    fn ack(self, res: QueryResult) {
+        let sent = match (self, res) {
+            (Query::Idempotent((_, q)), res) => return q.ack(res),
+            (Query::Execute(q), QueryResult::Execute(res)) => q.tx.send(res).is_ok(),
+            (Query::ExecuteReturning(q), QueryResult::ExecuteReturning(res)) => {
+                q.tx.send(res).is_ok()
+            }
+            (Query::Transaction(q), QueryResult::Many(res)) => q.tx.send(res).is_ok(),
+            (Query::TransactionGuarded(q), QueryResult::Guarded(res)) => q.tx.send(res).is_ok(),
+            (Query::Batch(q), QueryResult::Many(res)) => q.tx.send(res).is_ok(),
+            _ => unreachable!("QueryResult must always match the Query"),
+        };
+        assert!(sent, "oneshot tx to never be dropped");
+    }
+
+    fn ack_err(self, err: Error) {
+        let res = self.err(err);
+        self.ack(res);
+    }
+}
+
+/// Statements, which control transactions themselves or cannot run inside of one.
+#[inline]
+fn needs_autocommit(sql: &str) -> bool {
+    let keyword = skip_comments(sql)
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+
+    [
+        "BEGIN",
+        "COMMIT",
+        "END",
+        "ROLLBACK",
+        "SAVEPOINT",
+        "RELEASE",
+        "VACUUM",
+        "ATTACH",
+        "DETACH",
+        "PRAGMA",
+    ]
+    .iter()
+    .any(|kw| keyword.eq_ignore_ascii_case(kw))
+}
+
+/// Skips leading whitespace and `--` / `/* */` comments, which SQLite allows before any statement.
+fn skip_comments(mut sql: &str) -> &str {
+    loop {
+        sql = sql.trim_start();
+        if let Some(rest) = sql.strip_prefix("--") {
+            sql = rest
+                .split_once('\n')
+                .map(|(_, rest)| rest)
+                .unwrap_or_default();
+        } else if let Some(rest) = sql.strip_prefix("/*") {
+            sql = rest
+                .split_once("*/")
+                .map(|(_, rest)| rest)
+                .unwrap_or_default();
+        } else {
+            return sql;
+        }
+    }
+}
+
+/// Consecutive queries applied inside a single transaction to avoid the commit overhead for each
+/// of them. Each query gets its own `SAVEPOINT`, so a failing one will not affect the others.
+///
+/// The results are only sent out after the commit. Otherwise, a client could read from the
+/// pool before its own write is visible.
+struct WriteGroup {
+    entries: Vec<(Query, QueryResult)>,
+    started: Instant,
+}
+
+impl WriteGroup {
+    fn new() -> Self {
+        Self {
+            entries: Vec::with_capacity(GROUP_MAX_ENTRIES),
+            started: Instant::now(),
+        }
+    }
+
+    #[inline]
+    fn is_full(&self) -> bool {
+        self.entries.len() >= GROUP_MAX_ENTRIES || self.started.elapsed() >= GROUP_MAX_DELAY
+    }
+
+    fn apply(
+        &mut self,
+        conn: &rusqlite::Connection,
+        query: Query,
+        query_stats: &QueryStats,
+        log_statements: bool,
+    ) {
+        if self.entries.is_empty() {
+            if let Err(err) = conn.execute_batch("BEGIN") {
+                error!("Error starting write transaction: {}", err);
+                query.ack_err(Error::Transaction(err.to_string().into()));
+                return;
+            }
+            self.started = Instant::now();
+        }
+
+        let res = match Self::apply_savepoint(conn, &query, Some(query_stats), log_statements) {
+            Ok(res) => res,
+            Err(err) => {
+                self.rollback(conn, query, err);
+                return;
+            }
+        };
+
+        if conn.is_autocommit() {
+            // Some conflict resolutions like `INSERT OR ROLLBACK` roll back the whole
+            // transaction, which would silently discard all other entries of this group.
+            warn!(
+                "Entry {:?} has rolled back the write transaction - re-applying {} entries",
+                query.last_applied_log_id(),
+                self.entries.len()
+            );
+            if let Err(err) = conn.execute_batch("BEGIN") {
+                self.rollback(conn, query, err);
+                return;
+            }
+            let reapplied = self.entries.iter_mut().try_for_each(|(query, res)| {
+                *res = Self::apply_savepoint(conn, query, None, false)?;
+                Ok(())
+            });
+            if let Err(err) = reapplied {
+                self.rollback(conn, query, err);
+                return;
+            }
+        }
+
+        self.entries.push((query, res));
+    }
+
+    /// Applies a single query inside its own `SAVEPOINT`. Returns an `Err` only if the
+    /// transaction control itself fails, which makes the whole group unusable.
+    #[inline]
+    fn apply_savepoint(
+        conn: &rusqlite::Connection,
+        query: &Query,
+        query_stats: Option<&QueryStats>,
+        log_statements: bool,
+    ) -> Result<QueryResult, rusqlite::Error> {
+        conn.execute_batch("SAVEPOINT entry")?;
+
+        let res = query.apply(conn, query_stats, log_statements);
+
+        if !conn.is_autocommit() {
+            let sql = if res.is_err() {
+                "ROLLBACK TO entry; RELEASE entry"
+            } else {
+                "RELEASE entry"
+            };
+            conn.execute_batch(sql)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Rolls back the whole group after a failed transaction control statement, like `RELEASE`
+    /// on a full disk, and acks all entries including the current `query` with the error.
+    fn rollback(&mut self, conn: &rusqlite::Connection, query: Query, err: rusqlite::Error) {
+        error!("Error in write transaction - rolling back: {}", err);
+        query.ack_err(Error::Transaction(err.to_string().into()));
+        self.rollback_all(conn, err);
+    }
+
+    fn rollback_all(&mut self, conn: &rusqlite::Connection, err: rusqlite::Error) {
+        if !conn.is_autocommit() {
+            if let Err(err) = conn.execute_batch("ROLLBACK") {
+                error!("Error during txn rollback: {}", err);
+            }
+        }
+        for (query, _) in self.entries.drain(..) {
+            query.ack_err(Error::Transaction(err.to_string().into()));
+        }
+    }
+
+    fn commit(&mut self, conn: &rusqlite::Connection) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        match conn.execute_batch("COMMIT") {
+            Ok(_) => {
+                for (query, res) in self.entries.drain(..) {
+                    query.ack(res);
+                }
+            }
+            Err(err) => {
+                error!("Error committing write transaction: {}", err);
+                self.rollback_all(conn, err);
+            }
+        }
+    }
+}
+
+#[inline]
+fn bind_params(
+    stmt: &mut rusqlite::CachedStatement<'_>,
+    sql: &str,
+    params: &Params,
+) -> Result<(), Error> {
+    for (idx, param) in (1..).zip(params.iter()) {
+        if let Err(err) = stmt.raw_bind_parameter(idx, param) {
+            error!(
+                "Error binding param on position {} to query {}: {:?}",
+                idx, sql, err
+            );
+            return Err(Error::QueryParams(err.to_string().into()));
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn execute(conn: &rusqlite::Connection, sql: &str, params: &Params) -> Result<usize, Error> {
+    let mut stmt = match conn.prepare_cached(sql) {
+        Ok(stmt) => stmt,
+        Err(err) => {
+            error!("Preparing cached query {}: {:?}", sql, err);
+            return Err(Error::PrepareStatement(err.to_string().into()));
+        }
+    };
+    bind_params(&mut stmt, sql, params)?;
+    stmt.raw_execute().map_err(Error::from)
+}
+
+/// Returns `true` if the query returns at least one row.
+#[inline]
+fn exists(conn: &rusqlite::Connection, sql: &str, params: &Params) -> Result<bool, Error> {
+    let mut stmt = match conn.prepare_cached(sql) {
+        Ok(stmt) => stmt,
+        Err(err) => {
+            error!("Preparing cached query {}: {:?}", sql, err);
+            return Err(Error::PrepareStatement(err.to_string().into()));
+        }
+    };
+    bind_params(&mut stmt, sql, params)?;
+
+    let mut rows = stmt.raw_query();
+    Ok(rows.next()?.is_some())
+}
+
+#[inline]
+fn execute_returning(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &Params,
+) -> Result<Vec<Result<RowOwned, Error>>, Error> {
+    let mut stmt = match conn.prepare_cached(sql) {
+        Ok(stmt) => stmt,
+        Err(err) => {
+            error!("Preparing cached query {}: {:?}", sql, err);
+            return Err(Error::PrepareStatement(err.to_string().into()));
+        }
+    };
+    let columns = ColumnOwned::mapping_cols_from_stmt(stmt.columns())
+        .map_err(|err| Error::PrepareStatement(err.to_string().into()))?;
+    bind_params(&mut stmt, sql, params)?;
+
+    let mut rows = stmt.raw_query();
+    let mut res = Vec::new();
+    loop {
+        match rows.next() {
+            Ok(Some(row)) => {
+                res.push(Ok(RowOwned::from_row_column(row, &columns)));
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(err) => {
+                res.push(Err(Error::Sqlite(err.to_string().into())));
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+fn create_idempotency_table(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        r#"
+CREATE TABLE IF NOT EXISTS _idempotency
+(
+    key     TEXT    NOT NULL
+        CONSTRAINT _idempotency_pk
+            PRIMARY KEY,
+    expires INTEGER NOT NULL,
+    result  BLOB    NOT NULL
+);
+CREATE INDEX IF NOT EXISTS _idempotency_expires_index ON _idempotency (expires);
+"#,
+    )
+}
+
+/// Removes all expired results and returns the one for the given key, if it exists.
+fn idempotent_result(
+    conn: &rusqlite::Connection,
+    idempotency: &Idempotency,
+) -> Result<Option<QueryResult>, Error> {
+    conn.prepare_cached("DELETE FROM _idempotency WHERE expires < $1")?
+        .execute([idempotency.ts])?;
+
+    let bytes: Option<Vec<u8>> = conn
+        .prepare_cached("SELECT result FROM _idempotency WHERE key = $1")?
+        .query_row([idempotency.key.as_ref()], |row| row.get(0))
+        .optional()?;
+
+    match bytes {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+fn idempotent_result_insert(
+    conn: &rusqlite::Connection,
+    idempotency: &Idempotency,
+    res: &QueryResult,
+) -> Result<(), Error> {
+    let bytes = bincode::serialize(res)?;
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO _idempotency (key, expires, result) VALUES ($1, $2, $3)",
+    )?
+    .execute((idempotency.key.as_ref(), idempotency.expires, bytes))?;
+    Ok(())
+}
+
 #[inline]
 fn persist_metadata(
     conn: &rusqlite::Connection,
@@ -618,39 +1135,94 @@ fn create_backup(
     conn: &rusqlite::Connection,
     node_id: NodeId,
     target_folder: String,
-    #[cfg(feature = "s3")] s3_config: Option<std::sync::Arc<crate::s3::S3Config>>,
+    mut backup_meta: BackupMeta,
+    cache_snapshot: Option<Vec<u8>>,
+    #[cfg(feature = "backup")] backup_targets: Vec<Arc<dyn crate::backup::BackupTarget>>,
+    metrics: Arc<SqliteMetrics>,
 ) -> Result<(), Error> {
     // - build target db file name with node id and timestamp
     // - vacuum into target file
-    // - connect to vacuumed db and reset metadata
-    // - if we have an s3 target, encrypt and push it
+    // - connect to vacuumed db, reset metadata and remember the backup log index
+    // - verify the backup against the content of the live db
+    // - push it to all backup targets, which will encrypt it
 
     let file = format!("backup_node_{}_{}.sqlite", node_id, Utc::now().timestamp());
     let path_full = format!("{}/{}", target_folder, file);
     info!("Creating database backup into {}", path_full);
 
     conn.execute(&format!("VACUUM main INTO '{}'", path_full), ())?;
+    // no write can happen in between, so this is the state at the backup log index
+    backup_meta.content = ContentChecksum::calculate(conn)?;
 
     // connect to the backup and reset metadata
     // make sure connection is dropped before starting encrypt + push
-    {
+    let res = {
         let conn_bkp = rusqlite::Connection::open(&path_full)?;
         persist_metadata(&conn_bkp, &StateMachineData::default());
+        conn_bkp.execute(
+            "REPLACE INTO _metadata (key, data) VALUES ('backup', $1)",
+            [bincode::serialize(&backup_meta).unwrap()],
+        )?;
+        // a restored backup may contain an old cache snapshot, which must never be carried over
+        match cache_snapshot {
+            Some(bytes) => conn_bkp.execute(
+                "REPLACE INTO _metadata (key, data) VALUES ('cache', $1)",
+                [bytes],
+            )?,
+            None => conn_bkp.execute("DELETE FROM _metadata WHERE key = 'cache'", ())?,
+        };
+
+        let start = Instant::now();
+        let res = verify::verify(&conn_bkp, &backup_meta.content);
+        info!(
+            "Backup verification finished after {} ms",
+            start.elapsed().as_millis()
+        );
+        res
+    };
+    if let Err(err) = res {
+        error!("Backup {} failed verification: {}", path_full, err);
+        metrics
+            .backup_verifications_failed
+            .fetch_add(1, Ordering::Relaxed);
+        let _ = std::fs::remove_file(&path_full);
+        return Err(err);
     }
 
-    info!("Database backup finished");
+    info!(
+        "Database backup finished and verified: {} tables / {} rows",
+        backup_meta.content.tables, backup_meta.content.rows
+    );
 
-    #[cfg(feature = "s3")]
-    if let Some(s3) = s3_config {
+    // the metadata next to the backup makes the catalogue work without opening each backup
+    #[cfg(feature = "backup")]
+    let (file_meta, path_meta) = {
+        let file_meta = format!("{}{}", file, crate::backup::catalogue::META_SUFFIX);
+        let path_meta = format!("{}/{}", target_folder, file_meta);
+        std::fs::write(&path_meta, serde_json::to_vec(&backup_meta).unwrap())?;
+        (file_meta, path_meta)
+    };
+
+    #[cfg(feature = "backup")]
+    if !backup_targets.is_empty() {
         task::spawn(async move {
-            info!("Background task for database encryption and S3 backup task has been started");
-
-            match s3.push(&path_full, &file).await {
-                Ok(_) => {
-                    info!("Push backup to S3 has been finished");
-                }
-                Err(err) => {
-                    error!("Error pushing Backup to S3: {}", err);
+            info!("Background task for pushing the backup to its targets has been started");
+
+            for target in backup_targets {
+                // the metadata is pushed last, because it marks a complete backup
+                let res = match target.upload(&path_full, &file).await {
+                    Ok(_) => target.upload(&path_meta, &file_meta).await,
+                    Err(err) => Err(err),
+                };
+                match res {
+                    Ok(_) => {
+                        info!("Push backup to {} has been finished", target.name());
+                        metrics.record_backup_target_success(target.name());
+                    }
+                    Err(err) => {
+                        error!("Error pushing Backup to {}: {}", target.name(), err);
+                        metrics.backups_failed.fetch_add(1, Ordering::Relaxed);
+                    }
                 }
             }
         });
@@ -824,3 +1396,161 @@ fn apply_migration(txn: rusqlite::Transaction, migration: Migration) -> Result<(
     txn.commit()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{params, Param};
+
+    fn execute_req(
+        sql: &'static str,
+        params: Params,
+    ) -> (Query, oneshot::Receiver<Result<usize, Error>>) {
+        let (tx, rx) = oneshot::channel();
+        let query = Query::Execute(SqlExecute {
+            sql: sql.into(),
+            params,
+            last_applied_log_id: None,
+            tx,
+        });
+        (query, rx)
+    }
+
+    #[test]
+    fn test_write_group() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY)")
+            .unwrap();
+        let stats = QueryStats::new(None);
+        let mut group = WriteGroup::new();
+
+        let mut acks = Vec::new();
+        for (sql, params) in [
+            ("INSERT INTO test VALUES ($1)", params!(1)),
+            // unique constraint -> must only fail this single entry
+            ("INSERT INTO test VALUES ($1)", params!(1)),
+            ("INSERT INTO test VALUES ($1)", params!(2)),
+            // rolls back the whole transaction -> the group must be re-applied
+            ("INSERT OR ROLLBACK INTO test VALUES ($1)", params!(2)),
+            ("INSERT INTO test VALUES ($1)", params!(3)),
+        ] {
+            let (query, rx) = execute_req(sql, params);
+            assert!(query.is_groupable());
+            group.apply(&conn, query, &stats, false);
+            acks.push(rx);
+        }
+        assert!(!conn.is_autocommit());
+
+        // nothing must be sent before the commit
+        assert!(acks[0].try_recv().is_err());
+        group.commit(&conn);
+        assert!(conn.is_autocommit());
+
+        let results = acks
+            .into_iter()
+            .map(|mut rx| rx.try_recv().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &1);
+        assert!(results[3].is_err());
+        assert_eq!(results[4].as_ref().unwrap(), &1);
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM test", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+
+        for sql in [
+            "VACUUM",
+            "-- maintenance\nVACUUM",
+            "/* settings */ PRAGMA user_version = 1",
+            "  /* a */ -- b\n /* c\n */ vacuum",
+        ] {
+            let (query, _rx) = execute_req(sql, params!());
+            assert!(!query.is_groupable(), "{}", sql);
+        }
+        let (query, _rx) = execute_req("-- VACUUM\nINSERT INTO test VALUES (4)", params!());
+        assert!(query.is_groupable());
+    }
+
+    #[test]
+    fn test_write_group_txn_control_error() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY)")
+            .unwrap();
+        let stats = QueryStats::new(None);
+        let mut group = WriteGroup::new();
+
+        // a failing `RELEASE` must roll back and fail the whole group instead of panicking
+        let (query, mut rx_1) = execute_req("INSERT INTO test VALUES ($1)", params!(1));
+        group.apply(&conn, query, &stats, false);
+        let (query, mut rx_2) = execute_req("RELEASE entry", params!());
+        group.apply(&conn, query, &stats, false);
+        assert!(conn.is_autocommit());
+        assert!(matches!(
+            rx_1.try_recv().unwrap(),
+            Err(Error::Transaction(_))
+        ));
+        assert!(matches!(
+            rx_2.try_recv().unwrap(),
+            Err(Error::Transaction(_))
+        ));
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM test", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // a failing `BEGIN` must only fail the new entry
+        conn.execute_batch("BEGIN").unwrap();
+        let (query, mut rx) = execute_req("INSERT INTO test VALUES ($1)", params!(2));
+        group.apply(&conn, query, &stats, false);
+        assert!(matches!(rx.try_recv().unwrap(), Err(Error::Transaction(_))));
+        conn.execute_batch("ROLLBACK").unwrap();
+
+        // the group can be used again afterward
+        let (query, mut rx) = execute_req("INSERT INTO test VALUES ($1)", params!(3));
+        group.apply(&conn, query, &stats, false);
+        group.commit(&conn);
+        assert_eq!(rx.try_recv().unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_idempotent_query() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY)")
+            .unwrap();
+        create_idempotency_table(&conn).unwrap();
+        let stats = QueryStats::new(None);
+
+        let idempotent = |ts: i64| {
+            let (query, rx) = execute_req("INSERT INTO test VALUES ($1)", params!(1));
+            let idempotency = Idempotency {
+                key: "key".into(),
+                ts,
+                expires: ts + 100,
+            };
+            (Query::Idempotent((idempotency, Box::new(query))), rx)
+        };
+
+        for ts in [0, 50] {
+            let (query, mut rx) = idempotent(ts);
+            let mut group = WriteGroup::new();
+            group.apply(&conn, query, &stats, false);
+            group.commit(&conn);
+            assert_eq!(rx.try_recv().unwrap().unwrap(), 1);
+        }
+
+        // the result has expired -> the insert must be applied again and fail
+        let (query, mut rx) = idempotent(101);
+        let res = query.apply(&conn, None, false);
+        query.ack(res);
+        assert!(rx.try_recv().unwrap().is_err());
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM _idempotency", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Error;
+use crate::{config, Error};
 use axum_server::tls_rustls::RustlsConfig;
 use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
 use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
@@ -7,8 +7,15 @@ use std::borrow::Cow;
 use std::env;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::{fs, task, time};
 use tokio_rustls::client::TlsStream;
+use tracing::{debug, error, info, warn};
+
+/// The interval in which the key and cert files will be checked for changes.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub struct ServerTlsConfig {
@@ -27,34 +34,80 @@ impl ServerTlsConfig {
     }
 
     pub fn from_env(variant: &str) -> Option<Self> {
+        Self::parse_env(variant).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn parse_env(variant: &str) -> Result<Option<Self>, Error> {
         let key = env::var(format!("HQL_TLS_{}_KEY", variant)).ok();
         let cert = env::var(format!("HQL_TLS_{}_CERT", variant)).ok();
-        let no_verify = env::var(format!("HQL_TLS_{}_DANGER_TLS_NO_VERIFY", variant))
-            .ok()
-            .map(|v| {
-                v.parse::<bool>()
-                    .expect("Cannot parse HQL_TLS_*_DANGER_TLS_NO_VERIFY to bool")
-            });
-
-        #[allow(clippy::unnecessary_unwrap)]
-        if key.is_some() && cert.is_some() {
-            Some(Self {
-                key: key.unwrap().into(),
-                cert: cert.unwrap().into(),
+        let no_verify =
+            config::env_parse::<bool>(&format!("HQL_TLS_{}_DANGER_TLS_NO_VERIFY", variant))?;
+
+        if let (Some(key), Some(cert)) = (key, cert) {
+            Ok(Some(Self {
+                key: key.into(),
+                cert: cert.into(),
                 danger_tls_no_verify: no_verify.unwrap_or(false),
-            })
+            }))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub async fn server_config(&self) -> axum_server::tls_rustls::RustlsConfig {
+    pub async fn server_config(&self) -> Result<RustlsConfig, Error> {
         RustlsConfig::from_pem_file(
             PathBuf::from(self.cert.as_ref()),
             PathBuf::from(self.key.as_ref()),
         )
         .await
-        .expect("valid TLS certificate")
+        .map_err(|err| {
+            Error::Config(
+                format!(
+                    "Cannot load TLS key {} / cert {}: {}",
+                    self.key, self.cert, err
+                )
+                .into(),
+            )
+        })
+    }
+
+    /// Builds the server config and spawns a task, which reloads the certificate as soon as the
+    /// key or cert file changes on disk, or when the process receives a `SIGHUP`. This makes it
+    /// possible to rotate certificates, for instance via cert-manager, without any restart.
+    /// Existing connections keep their session and only new handshakes use the new certificate.
+    ///
+    /// The task stops as soon as `rx_shutdown` changes or its sender is dropped.
+    pub(crate) async fn server_config_reloading(
+        &self,
+        name: &'static str,
+        mut rx_shutdown: watch::Receiver<bool>,
+    ) -> Result<RustlsConfig, Error> {
+        let config = self.server_config().await?;
+        let tls = self.clone();
+        let config_reload = config.clone();
+        task::spawn(async move {
+            tokio::select! {
+                _ = rx_shutdown.changed() => {
+                    debug!("Stopping the TLS {} certificate reload", name);
+                }
+                _ = reload_watcher(tls, config_reload, name) => {}
+            }
+        });
+        Ok(config)
+    }
+
+    async fn files_modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let key = fs::metadata(self.key.as_ref())
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        let cert = fs::metadata(self.cert.as_ref())
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        Some((key, cert))
     }
 
     pub fn client_config(&self) -> Arc<ClientConfig> {
@@ -62,6 +115,71 @@ impl ServerTlsConfig {
     }
 }
 
+async fn reload_watcher(tls: ServerTlsConfig, config: RustlsConfig, name: &'static str) {
+    let mut modified = tls.files_modified().await;
+
+    #[cfg(unix)]
+    let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(err) => {
+            warn!(
+                "Cannot listen for SIGHUP to reload the TLS {} certificate: {}",
+                name, err
+            );
+            None
+        }
+    };
+
+    loop {
+        #[cfg(unix)]
+        let forced = match sighup.as_mut() {
+            Some(signal) => match time::timeout(RELOAD_CHECK_INTERVAL, signal.recv()).await {
+                Ok(Some(_)) => true,
+                Ok(None) => {
+                    // the signal driver is gone -> fall back to the file checks only
+                    sighup = None;
+                    false
+                }
+                Err(_) => false,
+            },
+            None => {
+                time::sleep(RELOAD_CHECK_INTERVAL).await;
+                false
+            }
+        };
+        #[cfg(not(unix))]
+        let forced = {
+            time::sleep(RELOAD_CHECK_INTERVAL).await;
+            false
+        };
+
+        let modified_new = tls.files_modified().await;
+        if !forced && modified_new == modified {
+            continue;
+        }
+
+        match config
+            .reload_from_pem_file(
+                PathBuf::from(tls.cert.as_ref()),
+                PathBuf::from(tls.key.as_ref()),
+            )
+            .await
+        {
+            Ok(_) => {
+                info!("Reloaded TLS {} certificate from {}", name, tls.cert);
+                modified = modified_new;
+            }
+            Err(err) => {
+                // cert-manager may not have written both files yet -> retry with the next tick
+                error!(
+                    "Error reloading TLS {} certificate - keeping the current one: {}",
+                    name, err
+                );
+            }
+        }
+    }
+}
+
 pub fn build_tls_config(tls_no_verify: bool) -> Arc<ClientConfig> {
     #[allow(unused_mut)]
     let mut root_store = tokio_rustls::rustls::RootCertStore::empty();
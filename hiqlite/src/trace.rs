@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+
+tokio::task_local! {
+    static TRACE_CONTEXT: TraceContext;
+}
+
+/// A minimal W3C Trace Context, which is propagated from the `Client` to the Raft leader and
+/// from there inside the Raft log to all other nodes.
+///
+/// Run your `Client` calls inside [TraceContext::scope] to attach a trace to them. The
+/// `trace_id` will then show up as a field in the `tracing` spans for forwarded requests,
+/// Raft commits and the SQLite apply on each node, which makes it possible to correlate
+/// a single write across the whole cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.trace_id)
+    }
+}
+
+impl TraceContext {
+    /// Creates a new context with random IDs.
+    pub fn new() -> Self {
+        let mut buf = [0u8; 24];
+        getrandom::getrandom(&mut buf).expect("Cannot get random bytes from the OS");
+        Self {
+            trace_id: u128::from_be_bytes(buf[..16].try_into().unwrap()),
+            span_id: u64::from_be_bytes(buf[16..].try_into().unwrap()),
+        }
+    }
+
+    /// Parses a W3C `traceparent` header value like
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let _flags = parts.next()?;
+
+        if version.len() != 2 || trace_id.len() != 32 || span_id.len() != 16 {
+            return None;
+        }
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        // all zero IDs are invalid per spec
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self { trace_id, span_id })
+    }
+
+    /// Formats this context as a W3C `traceparent` header value.
+    pub fn traceparent(&self) -> String {
+        format!("00-{:032x}-{:016x}-01", self.trace_id, self.span_id)
+    }
+
+    /// Returns the context of the current task, if any has been set with [TraceContext::scope].
+    pub fn current() -> Option<Self> {
+        TRACE_CONTEXT.try_with(|ctx| *ctx).ok()
+    }
+
+    /// Runs the given future with this context attached. Every `Client` request made from
+    /// inside `fut` will propagate it.
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        TRACE_CONTEXT.scope(self, fut).await
+    }
+}
+
+impl Default for TraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs `fut` inside the given context, or as it is, if there is none.
+pub(crate) async fn scope_opt<F: Future>(trace: Option<TraceContext>, fut: F) -> F::Output {
+    match trace {
+        Some(ctx) => ctx.scope(fut).await,
+        None => fut.await,
+    }
+}
+
+/// Span around a write to the SQLite Raft, from proposing the entry until it has been applied
+/// on the leader.
+#[cfg(feature = "sqlite")]
+pub(crate) fn commit_span() -> tracing::Span {
+    let span = tracing::info_span!("raft_commit", trace_id = tracing::field::Empty);
+    if let Some(ctx) = TraceContext::current() {
+        span.record("trace_id", tracing::field::display(ctx));
+    }
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trace_context() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let ctx = TraceContext::from_traceparent(header).unwrap();
+        assert_eq!(ctx.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(ctx.span_id, 0x00f067aa0ba902b7);
+        assert_eq!(ctx.traceparent(), header);
+
+        assert!(TraceContext::from_traceparent("00-0-0-01").is_none());
+        assert!(TraceContext::from_traceparent(
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01"
+        )
+        .is_none());
+
+        assert!(TraceContext::current().is_none());
+        let inner = ctx.scope(async { TraceContext::current() }).await;
+        assert_eq!(inner, Some(ctx));
+        assert!(TraceContext::current().is_none());
+    }
+}
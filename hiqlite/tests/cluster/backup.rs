@@ -20,6 +20,17 @@ pub async fn test_backup(client_1: &Client) -> Result<(), Error> {
     let leader = metrics.current_leader.unwrap();
     let path = find_backup_file(leader).await;
 
+    log("Check that the backup shows up in the catalogue with its metadata");
+    let (_, backup_name) = path.rsplit_once('/').unwrap();
+    let backups = client_1.list_backups().await?;
+    let info = backups
+        .iter()
+        .find(|info| info.name == backup_name && info.target == "local")
+        .expect("backup to be listed in the catalogue");
+    assert_eq!(info.node_id, leader);
+    assert!(info.verified);
+    assert!(info.log_index.unwrap() > 0);
+
     // copy the file into a 2nd location for later restore from file testing
     fs::copy(&path, BACKUP_PATH_FILE).await?;
 
@@ -71,10 +82,13 @@ pub async fn find_backup_file(node_id: u64) -> String {
     let path_base = format!("{}/node_{}/state_machine/backups", TEST_DATA_DIR, node_id);
     let mut ls = fs::read_dir(&path_base).await.unwrap();
 
-    if let Some(file) = ls.next_entry().await.unwrap() {
+    while let Some(file) = ls.next_entry().await.unwrap() {
         let file_name = file.file_name();
         let name = file_name.to_str().unwrap();
-        return format!("{}/{}", path_base, name);
+        // skip the backup metadata next to it
+        if name.ends_with(".sqlite") {
+            return format!("{}/{}", path_base, name);
+        }
     }
     panic!("Backup folder is empty when it should not be");
 }
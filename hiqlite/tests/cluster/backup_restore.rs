@@ -1,10 +1,11 @@
 use crate::backup::BACKUP_PATH_FILE;
 use crate::execute_query::TestData;
 use crate::start::build_config;
-use crate::{backup, log, Cache};
-use hiqlite::{params, start_node_with_cache, Client, Error, Param};
+use crate::{backup, log, Cache, TEST_DATA_DIR};
+use hiqlite::{params, start_node_with_cache, BackupSource, Client, Error, Param};
 use std::env;
-use tokio::task;
+use std::time::Duration;
+use tokio::{fs, task, time};
 
 pub async fn start_test_cluster_with_backup(
     from_fs: bool,
@@ -32,6 +33,100 @@ pub async fn start_test_cluster_with_backup(
     Ok((client_1, client_2, client_3))
 }
 
+pub async fn test_online_restore(
+    client_1: &Client,
+    client_2: &Client,
+    client_3: &Client,
+) -> Result<(), Error> {
+    log("Change current database as preparation for online restore tests");
+    backup::test_backup_restore_prerequisites(client_1).await?;
+
+    log("Make sure a local file is rejected, because each node fetches the backup on its own");
+    let path = backup::find_backup_file(1).await;
+    let res = client_2
+        .restore_backup(BackupSource::File(path.clone()))
+        .await;
+    assert!(matches!(res, Err(Error::BadRequest(_))));
+
+    log("Restore S3 backup through a follower client without any restart");
+    let (_path, backup_name) = path.rsplit_once('/').unwrap();
+    client_2
+        .restore_backup(BackupSource::S3(backup_name.to_string()))
+        .await?;
+
+    log("Make sure an invalid backup is rejected before anything is replicated");
+    let res = client_3
+        .restore_backup(BackupSource::S3("backup_node_1_0.sqlite".to_string()))
+        .await;
+    assert!(res.is_err());
+
+    // followers apply the restore in the background
+    time::sleep(Duration::from_millis(1000)).await;
+
+    log("Make sure databases are correctly restored online");
+    test_db_is_healthy_after_restore(client_1).await?;
+    test_db_is_healthy_after_restore(client_2).await?;
+    test_db_is_healthy_after_restore(client_3).await?;
+
+    log("Make sure the leader keeps its checked copy for nodes which cannot fetch the backup");
+    let leader = client_1.metrics_db().await?.current_leader.unwrap();
+    let path_src = format!(
+        "{}/node_{}/state_machine/backups/restore_online_src.sqlite",
+        TEST_DATA_DIR, leader
+    );
+    assert!(fs::try_exists(&path_src).await?);
+
+    log("Make sure writes after the online restore work on all nodes");
+    client_3
+        .execute(
+            "INSERT INTO test VALUES ($1, $2, $3)",
+            params!(1001, 1001, "after online restore"),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn test_cache_restore(
+    client_1: &Client,
+    client_2: &Client,
+    client_3: &Client,
+) -> Result<(), Error> {
+    log("Create a backup which includes all caches");
+    client_1
+        .put(Cache::One, "cache_backup", &"from backup".to_string(), None)
+        .await?;
+    client_1.backup().await?;
+    time::sleep(Duration::from_millis(500)).await;
+
+    log("Clear all caches and restore them from the backup");
+    client_1.clear_cache_all().await?;
+
+    // the catalogue lists local backups of the leader
+    let leader = client_1.metrics_db().await?.current_leader.unwrap();
+    let backup = client_1
+        .list_backups()
+        .await?
+        .into_iter()
+        .find(|info| info.target == "local")
+        .expect("a local backup to exist");
+    let path = format!(
+        "{}/node_{}/state_machine/backups/{}",
+        TEST_DATA_DIR, leader, backup.name
+    );
+    client_1.restore_cache(BackupSource::File(path)).await?;
+
+    time::sleep(Duration::from_millis(100)).await;
+
+    log("Make sure the cache has been restored on all nodes");
+    for client in [client_1, client_2, client_3] {
+        let value: Option<String> = client.get(Cache::One, "cache_backup").await?;
+        assert_eq!(value.as_deref(), Some("from backup"));
+    }
+
+    Ok(())
+}
+
 pub async fn test_db_is_healthy_after_restore(client: &Client) -> Result<(), Error> {
     log("Check old data still exists");
     let res: TestData = client
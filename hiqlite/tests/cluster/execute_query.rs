@@ -1,6 +1,6 @@
 use crate::log;
 use chrono::Utc;
-use hiqlite::{params, Client, Error, Param};
+use hiqlite::{params, Client, Error, Param, TraceContext};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use tokio::time;
@@ -60,6 +60,16 @@ pub async fn test_execute_query(
     assert_eq!(res.ts, data.ts);
     assert_eq!(res.description, data.description);
 
+    log("Checking query stats");
+    let stats = client_2.query_stats().await?;
+    let stat = stats
+        .iter()
+        .find(|s| s.sql == "SELECT * FROM test WHERE id = $1")
+        .expect("stats for the query to exist");
+    assert_eq!(stat.kind, hiqlite::StatementKind::Read);
+    assert!(stat.count >= 1);
+    assert!(stat.rows >= 1);
+
     log("Making sure the same insert and read works on the other nodes as well");
 
     let data = TestData {
@@ -96,11 +106,12 @@ pub async fn test_execute_query(
         ts: Utc::now().timestamp(),
         description: None,
     };
-    let rows_affected = client_3
-        .execute(
+    // the trace context travels inside the Raft log and must not change the result
+    let rows_affected = TraceContext::new()
+        .scope(client_3.execute(
             "INSERT INTO test VALUES ($1, $2, $3)",
             params!(data.id, data.ts, data.description.clone()),
-        )
+        ))
         .await?;
     assert_eq!(rows_affected, 1);
 
@@ -0,0 +1,197 @@
+use crate::log;
+use hiqlite::{params, Client, Error, ExportFormat, Param};
+use std::time::Duration;
+use tokio::time;
+
+// more than a single export page and import chunk
+const ROWS: i64 = 2500;
+
+pub async fn test_export_import(client_1: &Client, client_2: &Client) -> Result<(), Error> {
+    log("Create a table with data for the export");
+    client_1
+        .batch(format!(
+            r#"
+        CREATE TABLE export_test
+        (
+            id    INTEGER NOT NULL PRIMARY KEY,
+            name  TEXT,
+            score REAL,
+            data  BLOB
+        );
+        CREATE INDEX export_test_name ON export_test (name);
+
+        INSERT INTO export_test (id, name, score, data)
+        WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < {ROWS})
+        SELECT x, 'name ' || x, x * 1.5, randomblob(8) FROM c;
+
+        INSERT INTO export_test (id, name, score, data) VALUES
+            ({}, 'with "quotes", commas; and
+a line break', -0.1, x''),
+            ({}, NULL, NULL, NULL),
+            ({}, '', 0, x'00ff');
+        "#,
+            ROWS + 1,
+            ROWS + 2,
+            ROWS + 3,
+        ))
+        .await?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    // a BLOB which is no valid UTF-8
+    client_1
+        .execute(
+            "INSERT INTO export_test (id, name, score, data) VALUES ($1, $2, $3, $4)",
+            params!(ROWS + 4, "binary", 1.0, vec![0u8, 159, 146, 150, 255]),
+        )
+        .await?;
+    let total = ROWS as u64 + 4;
+
+    // the export reads from the local replica
+    time::sleep(Duration::from_millis(100)).await;
+
+    log("SQL export and import");
+    let mut dump = Vec::new();
+    let rows = client_2
+        .export(ExportFormat::Sql, &["export_test"], &mut dump)
+        .await?;
+    assert_eq!(rows, total);
+    let dump_str = String::from_utf8(dump.clone()).unwrap();
+    assert!(dump_str.contains("CREATE TABLE export_test"));
+    assert!(dump_str.contains("CREATE INDEX export_test_name"));
+
+    client_1
+        .execute(
+            "ALTER TABLE export_test RENAME TO export_test_orig",
+            params!(),
+        )
+        .await?;
+    // the index would conflict with the one from the dump
+    client_1
+        .execute("DROP INDEX export_test_name", params!())
+        .await?;
+    let rows = client_1
+        .import(ExportFormat::Sql, None, &mut dump.as_slice())
+        .await?;
+    assert_eq!(rows, total);
+    assert_same_data(client_1, "export_test", total).await?;
+
+    log("CSV export and import");
+    let mut csv = Vec::new();
+    let rows = client_1
+        .export(ExportFormat::Csv, &["export_test"], &mut csv)
+        .await?;
+    assert_eq!(rows, total);
+    assert!(csv.starts_with(b"id,name,score,data\n"));
+
+    create_target_table(client_1, "export_test_csv").await?;
+    let rows = client_1
+        .import(
+            ExportFormat::Csv,
+            Some("export_test_csv"),
+            &mut csv.as_slice(),
+        )
+        .await?;
+    assert_eq!(rows, total);
+    assert_same_data(client_1, "export_test_csv", total).await?;
+
+    log("JSONL export and import");
+    let mut jsonl = Vec::new();
+    let rows = client_1
+        .export(ExportFormat::Jsonl, &["export_test"], &mut jsonl)
+        .await?;
+    assert_eq!(rows, total);
+    assert!(jsonl.starts_with(br#"{"id":1,"name":"name 1","score":1.5,"data":""#));
+
+    create_target_table(client_1, "export_test_jsonl").await?;
+    let rows = client_1
+        .import(
+            ExportFormat::Jsonl,
+            Some("export_test_jsonl"),
+            &mut jsonl.as_slice(),
+        )
+        .await?;
+    assert_eq!(rows, total);
+    assert_same_data(client_1, "export_test_jsonl", total).await?;
+
+    log("Invalid exports and imports");
+    let mut buf = Vec::new();
+    let res = client_1.export(ExportFormat::Csv, &[], &mut buf).await;
+    assert!(res.is_err());
+    let res = client_1
+        .export(ExportFormat::Sql, &["does_not_exist"], &mut buf)
+        .await;
+    assert!(res.is_err());
+    let res = client_1
+        .import(ExportFormat::Jsonl, None, &mut b"{}".as_slice())
+        .await;
+    assert!(res.is_err());
+    let res = client_1
+        .import(
+            ExportFormat::Csv,
+            Some("export_test_csv"),
+            &mut b"id,name\n1,2,3\n".as_slice(),
+        )
+        .await;
+    assert!(res.is_err());
+    let res = client_1
+        .import(
+            ExportFormat::Csv,
+            Some("export_test_csv"),
+            &mut b"id,data\n9999,no hex\n".as_slice(),
+        )
+        .await;
+    assert!(res.is_err());
+
+    for table in [
+        "export_test",
+        "export_test_orig",
+        "export_test_csv",
+        "export_test_jsonl",
+    ] {
+        client_1
+            .execute(format!("DROP TABLE {}", table), params!())
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn create_target_table(client: &Client, name: &str) -> Result<(), Error> {
+    client
+        .execute(
+            format!(
+                "CREATE TABLE {} (id INTEGER NOT NULL PRIMARY KEY, name TEXT, score REAL, \
+                data BLOB)",
+                name
+            ),
+            params!(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Compares `table` with the original data in `export_test_orig`.
+async fn assert_same_data(client: &Client, table: &str, total: u64) -> Result<(), Error> {
+    // client_1 may be a follower
+    time::sleep(Duration::from_millis(100)).await;
+
+    let mut row = client
+        .query_raw_one(
+            format!(
+                r#"
+            SELECT count(*) AS count FROM export_test_orig o
+            JOIN {} t ON o.id = t.id
+                AND o.name IS t.name
+                AND o.score IS t.score
+                AND o.data IS t.data
+                AND typeof(o.name) = typeof(t.name)
+                AND typeof(o.data) = typeof(t.data)
+            "#,
+                table
+            ),
+            params!(),
+        )
+        .await?;
+    assert_eq!(row.get::<i64>("count"), total as i64);
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use crate::execute_query::TestData;
+use crate::{log, Cache};
+use chrono::Utc;
+use hiqlite::{params, Client, Error, Param};
+use std::time::Duration;
+use tokio::time;
+
+pub async fn test_idempotency(
+    client_1: &Client,
+    client_2: &Client,
+    client_3: &Client,
+) -> Result<(), Error> {
+    // we re-use the test table from the simple insert / query tests here
+    log("Retry an insert with the same idempotency key");
+
+    let sql = "INSERT INTO test VALUES ($1, $2, $3)";
+    let now = Utc::now().timestamp();
+
+    let rows_affected = client_1
+        .with_idempotency_key("insert-31")
+        .execute(sql, params!(31, now, "Idempotent"))
+        .await?;
+    assert_eq!(rows_affected, 1);
+
+    // without the key, this would fail with a unique constraint violation
+    let rows_affected = client_1
+        .with_idempotency_key("insert-31")
+        .execute(sql, params!(31, now, "Idempotent"))
+        .await?;
+    assert_eq!(rows_affected, 1);
+
+    log("Retry from a remote node returns the same result");
+    let rows_affected = client_2
+        .with_idempotency_key("insert-31")
+        .execute(sql, params!(31, now, "Idempotent"))
+        .await?;
+    assert_eq!(rows_affected, 1);
+
+    let res = client_3.execute(sql, params!(31, now, "Idempotent")).await;
+    assert!(res.is_err());
+
+    let data: Vec<TestData> = client_3
+        .query_map("SELECT * FROM test WHERE id = $1", params!(31))
+        .await?;
+    assert_eq!(data.len(), 1);
+
+    log("Re-using a key for another kind of write fails");
+    let res = client_3
+        .with_idempotency_key("insert-31")
+        .txn([(sql, params!(32, now, "Idempotent"))])
+        .await;
+    assert!(matches!(res, Err(Error::BadRequest(_))));
+
+    log("Failed writes are not remembered and can be retried");
+    let client = client_2.with_idempotency_key("insert-32");
+    let res = client.execute(sql, params!(31, now, "Duplicate")).await;
+    assert!(res.is_err());
+    let rows_affected = client.execute(sql, params!(32, now, "Retried")).await?;
+    assert_eq!(rows_affected, 1);
+
+    log("Retry a cache put with the same idempotency key");
+    let key = "idempotent key";
+    let value = "idempotent value".to_string();
+    let client = client_3.with_idempotency_key("put-1");
+    client.put(Cache::One, key, &value, None).await?;
+    client_1.delete(Cache::One, key).await?;
+
+    // must not put the value a 2nd time
+    client.put(Cache::One, key, &value, None).await?;
+    time::sleep(Duration::from_millis(10)).await;
+    let res: Option<String> = client_1.get(Cache::One, key).await?;
+    assert!(res.is_none());
+    let res: Option<String> = client_3.get(Cache::One, key).await?;
+    assert!(res.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::{log, start, Cache, TEST_DATA_DIR};
+use hiqlite::{start_node_with_cache, Client, Error, Node, NodeConfig, NodeRole};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::{fs, time};
+
+const LEARNER_ID: u64 = 4;
+
+fn node_learner() -> Node {
+    Node {
+        id: LEARNER_ID,
+        addr_raft: "127.0.0.1:32004".to_string(),
+        addr_api: "127.0.0.1:31004".to_string(),
+    }
+}
+
+async fn build_config_learner() -> NodeConfig {
+    let data_dir = format!("{}/node_{}", TEST_DATA_DIR, LEARNER_ID);
+    fs::create_dir_all(&data_dir).await.unwrap();
+
+    let mut nodes = start::nodes();
+    nodes.push(node_learner());
+
+    NodeConfig {
+        node_id: LEARNER_ID,
+        nodes,
+        node_learners: BTreeSet::from([LEARNER_ID]),
+        role: NodeRole::Learner,
+        data_dir: data_dir.into(),
+        ..start::build_config(1).await
+    }
+}
+
+/// Starts node 4 as a permanent learner and makes sure that it is never promoted to a voter,
+/// even when another node re-joins with `become_member` in the meantime.
+///
+/// A non-leader voter is restarted during this test, which is why the clients for node 2 and 3
+/// are taken by value and handed back afterward.
+pub async fn test_learner(
+    client_1: &Client,
+    client_2: Client,
+    client_3: Client,
+) -> Result<(Client, Client), Error> {
+    log("Start node 4 as a permanent learner");
+    let client_4 = start_node_with_cache::<Cache>(build_config_learner().await).await?;
+    wait_for_healthy(&client_4).await;
+    time::sleep(Duration::from_millis(500)).await;
+    is_learner_everywhere(&[client_1, &client_2, &client_3, &client_4]).await?;
+
+    // writes on a learner are forwarded to the leader
+    client_4.batch("SELECT 1;").await?;
+
+    // A restarted voter always re-joins the in-memory cache raft with `add_learner` and
+    // `become_member`, which must only promote the re-joining node.
+    let leader = client_1.metrics_db().await?.current_leader.unwrap();
+    let (client_2, client_3) = if leader == 3 {
+        (restart_node(2, client_2).await?, client_3)
+    } else {
+        (client_2, restart_node(3, client_3).await?)
+    };
+    time::sleep(Duration::from_millis(500)).await;
+    is_learner_everywhere(&[client_1, &client_2, &client_3, &client_4]).await?;
+
+    log("Remove the learner node 4 again");
+    client_4.shutdown().await?;
+    client_1.remove_node(LEARNER_ID).await?;
+    time::sleep(Duration::from_millis(500)).await;
+    for client in [client_1, &client_2, &client_3] {
+        let metrics = client.metrics_db().await?;
+        assert_eq!(metrics.membership_config.nodes().count(), 3);
+        let metrics = client.metrics_cache().await?;
+        assert_eq!(metrics.membership_config.nodes().count(), 3);
+    }
+
+    start::wait_for_healthy_cluster(client_1, &client_2, &client_3).await?;
+
+    Ok((client_2, client_3))
+}
+
+async fn is_learner_everywhere(clients: &[&Client]) -> Result<(), Error> {
+    for client in clients {
+        for metrics in [client.metrics_db().await?, client.metrics_cache().await?] {
+            let membership = metrics.membership_config.membership();
+            assert!(membership.learner_ids().any(|id| id == LEARNER_ID));
+            assert!(!membership.voter_ids().any(|id| id == LEARNER_ID));
+            assert_eq!(membership.voter_ids().count(), 3);
+            assert_ne!(metrics.current_leader, Some(LEARNER_ID));
+        }
+    }
+    Ok(())
+}
+
+async fn restart_node(node_id: u64, client: Client) -> Result<Client, Error> {
+    log(format!(
+        "Restart node {} to re-join the cache raft with 'become_member'",
+        node_id
+    ));
+    client.shutdown().await?;
+    time::sleep(Duration::from_millis(1000)).await;
+
+    let client = start_node_with_cache::<Cache>(start::build_config(node_id).await).await?;
+    wait_for_healthy(&client).await;
+    Ok(client)
+}
+
+async fn wait_for_healthy(client: &Client) {
+    loop {
+        time::sleep(Duration::from_secs(1)).await;
+        match client.is_healthy_db().await {
+            Ok(_) => break,
+            Err(err) => log(format!("Waiting for node to become healthy: {}", err)),
+        }
+    }
+}
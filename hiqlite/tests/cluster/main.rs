@@ -16,6 +16,10 @@ mod backup_restore;
 mod batch;
 mod check;
 mod execute_query;
+mod export_import;
+mod idempotency;
+mod learner;
+mod membership;
 mod migration;
 mod self_heal;
 mod start;
@@ -97,10 +101,18 @@ async fn exec_tests() -> Result<(), Error> {
     batch::test_batch(&client_1, &client_2, &client_3).await?;
     log("Batch tests finished");
 
+    log("Starting idempotency tests");
+    idempotency::test_idempotency(&client_1, &client_2, &client_3).await?;
+    log("Idempotency tests finished");
+
     log("Starting SQL type conversion tests");
     type_conversions::test_type_conversions(&client_1).await?;
     log("SQL type conversion tests finished");
 
+    log("Starting export / import tests");
+    export_import::test_export_import(&client_1, &client_2).await?;
+    log("Export / import tests finished");
+
     log("Test cache operations");
     cache::test_cache(&client_1, &client_2, &client_3).await?;
     log("Cache operations finished");
@@ -113,6 +125,14 @@ async fn exec_tests() -> Result<(), Error> {
     dlock::test_dlock(&client_1, &client_2, &client_3).await?;
     log("Distributed locks tests finished");
 
+    log("Test runtime membership changes");
+    membership::test_membership(&client_1, &client_2, &client_3).await?;
+    log("Runtime membership changes finished");
+
+    log("Test permanent learner nodes");
+    let (client_2, client_3) = learner::test_learner(&client_1, client_2, client_3).await?;
+    log("Permanent learner tests finished");
+
     log("Test remote-only client");
     remote_only::test_remote_only_client().await?;
     log("Remote-only client tests finished");
@@ -205,6 +225,14 @@ async fn exec_tests() -> Result<(), Error> {
     backup_restore::test_db_is_healthy_after_restore(&client_2).await?;
     backup_restore::test_db_is_healthy_after_restore(&client_3).await?;
 
+    log("Test online restore through the Raft");
+    backup_restore::test_online_restore(&client_1, &client_2, &client_3).await?;
+    log("Online restore tests finished");
+
+    log("Test cache restore from a backup");
+    backup_restore::test_cache_restore(&client_1, &client_2, &client_3).await?;
+    log("Cache restore tests finished");
+
     // we need to wait a bit until all backup nodes have created a new snapshot
     time::sleep(Duration::from_millis(1000)).await;
 
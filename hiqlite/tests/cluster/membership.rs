@@ -0,0 +1,67 @@
+use crate::{log, start};
+use hiqlite::{Client, Error};
+use std::time::Duration;
+use tokio::time;
+
+pub async fn test_membership(
+    client_1: &Client,
+    client_2: &Client,
+    client_3: &Client,
+) -> Result<(), Error> {
+    log("Invalid membership changes are rejected");
+    let node_3 = start::nodes().remove(2);
+    let res = client_1.replace_node(3, node_3.clone()).await;
+    assert!(matches!(res, Err(Error::BadRequest(_))));
+    let res = client_2.replace_node(4, node_3).await;
+    assert!(matches!(res, Err(Error::BadRequest(_))));
+
+    log("Removing a non-member is a no-op");
+    client_3.remove_node(4).await?;
+
+    // we do not want to remove the leader here to keep the test stable
+    let leader = client_1.metrics_db().await?.current_leader.unwrap();
+    let id = if leader == 3 { 2 } else { 3 };
+    let node = start::nodes().remove(id as usize - 1);
+
+    log(format!("Remove node {} at runtime", id));
+    client_1.remove_node(id).await?;
+    time::sleep(Duration::from_millis(500)).await;
+    let metrics = client_1.metrics_db().await?;
+    assert!(!metrics.membership_config.voter_ids().any(|nid| nid == id));
+    assert_eq!(metrics.membership_config.nodes().count(), 2);
+    let metrics = client_1.metrics_cache().await?;
+    assert_eq!(metrics.membership_config.nodes().count(), 2);
+
+    log(format!("Add node {} back at runtime", id));
+    client_2.add_node(node).await?;
+
+    time::sleep(Duration::from_millis(500)).await;
+    for client in [client_1, client_2, client_3] {
+        let metrics = client.metrics_db().await?;
+        assert_eq!(metrics.membership_config.voter_ids().count(), 3);
+        let metrics = client.metrics_cache().await?;
+        assert_eq!(metrics.membership_config.voter_ids().count(), 3);
+    }
+
+    start::wait_for_healthy_cluster(client_1, client_2, client_3).await?;
+
+    log("Transfer the leadership to another node and back");
+    let to = if leader == 1 { 2 } else { 1 };
+    client_3.transfer_leadership(Some(to)).await?;
+    time::sleep(Duration::from_millis(500)).await;
+    let metrics = client_1.metrics_db().await?;
+    assert_eq!(metrics.current_leader, Some(to));
+    let metrics = client_1.metrics_cache().await?;
+    assert_eq!(metrics.current_leader, Some(to));
+
+    // writes must work right away with the new leader
+    client_3.batch("SELECT 1;").await?;
+
+    let res = client_2.transfer_leadership(Some(4)).await;
+    assert!(matches!(res, Err(Error::BadRequest(_))));
+
+    client_2.transfer_leadership(Some(leader)).await?;
+    start::wait_for_healthy_cluster(client_1, client_2, client_3).await?;
+
+    Ok(())
+}
@@ -34,6 +34,17 @@ pub async fn test_remote_only_client() -> Result<(), Error> {
     let res = client_2.listen::<TestData>().await?;
     assert_eq!(res, msg);
 
+    log("Trigger snapshots with a remote client");
+    let metrics = client_1.metrics_db().await?;
+    let last_applied = metrics.last_applied.unwrap().index;
+    let snapshot = client_1.trigger_snapshot_db().await?.unwrap();
+    assert!(snapshot.index >= last_applied);
+    let metrics = client_1.metrics_db().await?;
+    assert!(metrics.snapshot.unwrap().index >= last_applied);
+
+    let snapshot = client_2.trigger_snapshot_cache().await?;
+    assert!(snapshot.is_some());
+
     Ok(())
 }
 
@@ -1,5 +1,5 @@
 use crate::{log, Cache, TEST_DATA_DIR};
-use hiqlite::{start_node_with_cache, Client, Error, Node, NodeConfig};
+use hiqlite::{start_node_with_cache, BackupConfig, Client, Error, Node, NodeConfig};
 use std::time::Duration;
 use tokio::{fs, task, time};
 
@@ -81,7 +81,7 @@ pub async fn build_config(node_id: u64) -> NodeConfig {
         tls_api: None,
         secret_raft: "asdasdasdasdasdasd".to_string(),
         secret_api: SECRET_API.to_string(),
-        backup_config: Default::default(),
+        backup_config: BackupConfig::default().with_cache(true),
         enc_keys_from: hiqlite::s3::EncKeysFrom::Env,
         s3_config: hiqlite::s3::S3Config::try_from_env(),
         #[cfg(feature = "dashboard")]
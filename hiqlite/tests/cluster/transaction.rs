@@ -1,7 +1,7 @@
 use crate::execute_query::TestData;
 use crate::log;
 use chrono::Utc;
-use hiqlite::{params, Client, Error, Param};
+use hiqlite::{params, Client, Error, Param, TxnStep};
 use std::time::Duration;
 use tokio::time;
 
@@ -101,5 +101,106 @@ pub async fn test_transactions(
     assert_eq!(data[2].ts, now);
     assert_eq!(data[2].description, None);
 
+    log("Guarded transaction with a failing precondition");
+    let res = client_2
+        .txn_guarded([
+            TxnStep::execute(
+                "UPDATE test SET description = $1 WHERE id = $2",
+                params!("guarded", 13),
+            ),
+            TxnStep::assert_not_exists("SELECT 1 FROM test WHERE id = $1", params!(12)),
+        ])
+        .await;
+    match res {
+        Err(Error::GuardFailed { step, .. }) => assert_eq!(step, 1),
+        res => panic!("expected Error::GuardFailed, got: {:?}", res),
+    }
+    let res = client_3
+        .txn_guarded([TxnStep::execute_expect(
+            "UPDATE test SET description = $1 WHERE id = $2",
+            params!("guarded", 99),
+            1,
+        )])
+        .await;
+    match res {
+        Err(Error::GuardFailed { step, .. }) => assert_eq!(step, 0),
+        res => panic!("expected Error::GuardFailed, got: {:?}", res),
+    }
+
+    // the update from the first step must have been rolled back
+    let data: TestData = client_1
+        .query_map_one("SELECT * FROM test WHERE id = $1", params!(13))
+        .await?;
+    assert_eq!(data.description, None);
+
+    log("Guarded transaction with all preconditions met");
+    let res = client_2
+        .txn_guarded([
+            TxnStep::assert_exists(
+                "SELECT 1 FROM test WHERE id = $1 AND description IS NULL",
+                params!(13),
+            ),
+            TxnStep::execute_expect("UPDATE test SET ts = $1 WHERE id = $2", params!(now, 13), 1),
+        ])
+        .await?;
+    assert_eq!(res, vec![0, 1]);
+
+    log("Transaction with RETURNING statements");
+    let res = client_3
+        .txn_returning([
+            TxnStep::execute_returning(
+                "INSERT INTO test_2 (ts, description) VALUES ($1, $2) RETURNING id",
+                params!(now, "parent"),
+            ),
+            TxnStep::execute(
+                "INSERT INTO test_2 (ts, description) VALUES ($1, 'child of ' || last_insert_rowid())",
+                params!(now),
+            ),
+            TxnStep::execute_returning(
+                "UPDATE test_2 SET ts = $1 WHERE ts = $1 RETURNING id, description",
+                params!(now),
+            ),
+        ])
+        .await?;
+    assert_eq!(res.len(), 3);
+
+    assert_eq!(res[0].rows_affected, 1);
+    assert_eq!(res[0].rows.len(), 1);
+    assert_eq!(res[1].rows_affected, 1);
+    assert!(res[1].rows.is_empty());
+    assert_eq!(res[2].rows_affected, 2);
+
+    let mut res = res.into_iter();
+    let parent_id: i64 = res.next().unwrap().rows.swap_remove(0).get("id");
+    let mut rows = res
+        .nth(1)
+        .unwrap()
+        .rows
+        .into_iter()
+        .map(|mut row| (row.get::<i64>("id"), row.get::<String>("description")))
+        .collect::<Vec<_>>();
+    rows.sort();
+    assert_eq!(rows[0], (parent_id, "parent".to_string()));
+    assert_eq!(rows[1].1, format!("child of {}", parent_id));
+
+    log("Failing transaction with RETURNING statements must be rolled back");
+    let res = client_1
+        .txn_returning([
+            TxnStep::execute_returning(
+                "INSERT INTO test_2 (ts, description) VALUES ($1, $2) RETURNING id",
+                params!(now, "rolled back"),
+            ),
+            TxnStep::execute_returning(
+                "INSERT INTO test_2 (id, ts) VALUES ($1, $2) RETURNING id",
+                params!(parent_id, now),
+            ),
+        ])
+        .await;
+    assert!(res.is_err());
+    let data: Vec<TestData> = client_2
+        .query_map("SELECT * FROM test_2", params!())
+        .await?;
+    assert_eq!(data.len(), 2);
+
     Ok(())
 }